@@ -0,0 +1,87 @@
+use crate::AppState;
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct NewCollection {
+    pub id: String,
+    pub title: String,
+    pub description: Option<String>,
+}
+
+#[derive(Deserialize)]
+pub struct CollectionSnapshots {
+    pub snapshot_ids: Vec<Uuid>,
+}
+
+/// POST /api/v1/collections
+/// Creates a collection, or updates the title and description of an existing one
+pub async fn upsert_collection(
+    State(state): State<Arc<AppState>>,
+    Json(collection): Json<NewCollection>,
+) -> impl IntoResponse {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO collections (id, title, description)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (id) DO UPDATE SET title = EXCLUDED.title, description = EXCLUDED.description
+        "#,
+    )
+    .bind(&collection.id)
+    .bind(&collection.title)
+    .bind(&collection.description)
+    .execute(&state.pool)
+    .await;
+
+    match result {
+        Ok(_) => Json(serde_json::json!({"id": collection.id})).into_response(),
+        Err(e) => {
+            tracing::error!("Collection upsert error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Collection update failed",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// POST /api/v1/collections/:id/snapshots
+/// Adds snapshots to a collection; ids already present are ignored
+pub async fn add_snapshots(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Json(body): Json<CollectionSnapshots>,
+) -> impl IntoResponse {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO collection_snapshots (collection_id, snapshot_id)
+        SELECT c.id, s.id
+        FROM collections c, snapshots s
+        WHERE c.id = $1 AND s.id = ANY($2)
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .bind(&id)
+    .bind(&body.snapshot_ids)
+    .execute(&state.pool)
+    .await;
+
+    match result {
+        Ok(done) => Json(serde_json::json!({"added": done.rows_affected()})).into_response(),
+        Err(e) => {
+            tracing::error!("Collection snapshots error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Collection update failed",
+            )
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,246 @@
+use crate::AppState;
+use archive_crawler::lease::{LeaseConfig, LeaseManager};
+use archive_crawler::region::RegionRouter;
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct DeadLetterQuery {
+    pub domain: Option<String>,
+    pub error_class: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// Selects dead-lettered URLs for requeue or purge. At least one field must be set.
+#[derive(Deserialize)]
+pub struct DeadLetterSelection {
+    pub urls: Option<Vec<String>>,
+    pub domain: Option<String>,
+    pub error_class: Option<String>,
+}
+
+impl DeadLetterSelection {
+    fn is_empty(&self) -> bool {
+        self.urls.is_none() && self.domain.is_none() && self.error_class.is_none()
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct DeadLetterUrl {
+    pub url: String,
+    pub domain: String,
+    pub depth: Option<i32>,
+    pub fetch_attempts: i32,
+    pub error_class: String,
+    pub last_error: Option<String>,
+    pub dead_lettered_at: Option<DateTime<Utc>>,
+}
+
+/// GET /api/v1/frontier/dead-letters
+/// Lists URLs that exhausted their retry budget, most recent first
+pub async fn list_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<DeadLetterQuery>,
+) -> impl IntoResponse {
+    let result = sqlx::query_as::<_, DeadLetterUrl>(
+        r#"
+        SELECT url, domain, depth, fetch_attempts, error_class, last_error, dead_lettered_at
+        FROM dead_letter_urls
+        WHERE ($1::text IS NULL OR domain = $1)
+          AND ($2::text IS NULL OR error_class = $2)
+        ORDER BY dead_lettered_at DESC
+        LIMIT $3 OFFSET $4
+        "#,
+    )
+    .bind(&params.domain)
+    .bind(&params.error_class)
+    .bind(params.limit.unwrap_or(100).min(1000))
+    .bind(params.offset.unwrap_or(0))
+    .fetch_all(&state.pool)
+    .await;
+
+    match result {
+        Ok(urls) => Json(urls).into_response(),
+        Err(e) => {
+            tracing::error!("Dead-letter list error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Dead-letter list failed").into_response()
+        }
+    }
+}
+
+/// POST /api/v1/frontier/dead-letters/requeue
+/// Moves the selected URLs back into the frontier with a fresh retry budget
+pub async fn requeue_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Json(selection): Json<DeadLetterSelection>,
+) -> impl IntoResponse {
+    if selection.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Provide urls, domain or error_class",
+        )
+            .into_response();
+    }
+
+    let result = async {
+        // The ring the crawlers route with, so requeued URLs land where they would
+        let router = LeaseManager::new(state.pool.clone(), LeaseConfig::from_env())
+            .region_router()
+            .await?;
+        requeue(&state.pool, &router, &selection).await
+    }
+    .await;
+    match result {
+        Ok(requeued) => Json(serde_json::json!({ "requeued": requeued })).into_response(),
+        Err(e) => {
+            tracing::error!("Dead-letter requeue error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Requeue failed").into_response()
+        }
+    }
+}
+
+/// Moves the selection back into the frontier, each URL in the region the
+/// crawlers' ring assigns its domain
+async fn requeue(
+    pool: &PgPool,
+    router: &RegionRouter,
+    selection: &DeadLetterSelection,
+) -> anyhow::Result<u64> {
+    let mut tx = pool.begin().await?;
+    let moved: Vec<(String, String, Option<i32>, Option<i32>)> = sqlx::query_as(
+        r#"
+        DELETE FROM dead_letter_urls
+        WHERE ($1::text[] IS NULL OR url = ANY($1))
+          AND ($2::text IS NULL OR domain = $2)
+          AND ($3::text IS NULL OR error_class = $3)
+        RETURNING url, domain, priority, depth
+        "#,
+    )
+    .bind(&selection.urls)
+    .bind(&selection.domain)
+    .bind(&selection.error_class)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut urls = Vec::with_capacity(moved.len());
+    let mut domains = Vec::with_capacity(moved.len());
+    let mut priorities = Vec::with_capacity(moved.len());
+    let mut depths = Vec::with_capacity(moved.len());
+    let mut regions = Vec::with_capacity(moved.len());
+    for (url, domain, priority, depth) in moved {
+        regions.push(router.route_domain(&domain).to_string());
+        urls.push(url);
+        domains.push(domain);
+        priorities.push(priority);
+        depths.push(depth);
+    }
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO url_frontier (url, domain, priority, depth, preferred_region, next_fetch_at, fetch_attempts)
+        SELECT url, domain, priority, depth, region, NOW(), 0
+        FROM UNNEST($1::text[], $2::text[], $3::int[], $4::int[], $5::text[]) AS m(url, domain, priority, depth, region)
+        ON CONFLICT (url) DO UPDATE SET next_fetch_at = NOW(), fetch_attempts = 0, last_error = NULL, last_error_class = NULL,
+            preferred_region = EXCLUDED.preferred_region
+        "#,
+    )
+    .bind(&urls)
+    .bind(&domains)
+    .bind(&priorities)
+    .bind(&depths)
+    .bind(&regions)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(result.rows_affected())
+}
+
+/// POST /api/v1/frontier/dead-letters/purge
+/// Permanently drops the selected URLs
+pub async fn purge_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Json(selection): Json<DeadLetterSelection>,
+) -> impl IntoResponse {
+    if selection.is_empty() {
+        return (
+            StatusCode::BAD_REQUEST,
+            "Provide urls, domain or error_class",
+        )
+            .into_response();
+    }
+
+    let result = sqlx::query(
+        r#"
+        DELETE FROM dead_letter_urls
+        WHERE ($1::text[] IS NULL OR url = ANY($1))
+          AND ($2::text IS NULL OR domain = $2)
+          AND ($3::text IS NULL OR error_class = $3)
+        "#,
+    )
+    .bind(&selection.urls)
+    .bind(&selection.domain)
+    .bind(&selection.error_class)
+    .execute(&state.pool)
+    .await;
+
+    match result {
+        Ok(r) => Json(serde_json::json!({ "purged": r.rows_affected() })).into_response(),
+        Err(e) => {
+            tracing::error!("Dead-letter purge error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Purge failed").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archive_common::testing::scratch_pool;
+    use archive_crawler::region::Region;
+
+    #[tokio::test]
+    async fn test_requeue_routes_urls_with_the_crawler_ring() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO dead_letter_urls (url, domain, priority, depth, fetch_attempts, error_class)
+            VALUES ('https://example.com/a', 'example.com', 5, 1, 6, 'http_5xx')
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let router = RegionRouter::with_regions(vec![Region::new("ap-south-1")]);
+        let selection = DeadLetterSelection {
+            urls: None,
+            domain: Some("example.com".to_string()),
+            error_class: None,
+        };
+        assert_eq!(requeue(&pool, &router, &selection).await.unwrap(), 1);
+
+        let (region, attempts, priority): (String, i32, i32) = sqlx::query_as(
+            "SELECT preferred_region, fetch_attempts, priority FROM url_frontier WHERE url = 'https://example.com/a'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!((region.as_str(), attempts, priority), ("ap-south-1", 0, 5));
+        let left: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM dead_letter_urls")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(left, 0);
+    }
+}
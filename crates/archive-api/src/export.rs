@@ -0,0 +1,517 @@
+use crate::snapshots::{like_prefix, timestamp_range};
+use crate::AppState;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use flate2::{write::GzEncoder, Compression};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::collections::HashSet;
+use std::io::Write;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Revisit profile of captures stored from a `304 Not Modified`
+pub(crate) const PROFILE_SERVER_NOT_MODIFIED: &str =
+    "http://netpreserve.org/warc/1.1/revisit/server-not-modified";
+/// Revisit profile of captures whose payload digest matched an archived one
+pub(crate) const PROFILE_IDENTICAL_PAYLOAD: &str =
+    "http://netpreserve.org/warc/1.1/revisit/identical-payload-digest";
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    /// Every URL starting with this prefix
+    pub prefix: Option<String>,
+    /// Every URL on this host
+    pub host: Option<String>,
+    /// Every capture tagged with this crawl job
+    pub job: Option<String>,
+    /// Every capture in this collection
+    pub collection: Option<String>,
+    /// Timestamp prefix (`YYYY` up to `YYYYMMDDHHMMSS`), inclusive
+    pub from: Option<String>,
+    /// Timestamp prefix, inclusive of the whole period it names
+    pub to: Option<String>,
+    /// Record ID (snapshot id) of the last complete record already received
+    pub resume_after: Option<Uuid>,
+}
+
+/// A capture as selected for export
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ExportCapture {
+    pub id: Uuid,
+    pub url: String,
+    pub timestamp: DateTime<Utc>,
+    pub warc_file: String,
+    pub offset: i64,
+    pub length: i64,
+    pub sha256: String,
+    pub status_code: i16,
+    pub content_type: String,
+    pub revisit_of: Option<Uuid>,
+    pub revisit_profile: Option<String>,
+}
+
+/// Which captures an export or package contains
+#[derive(Debug, Clone, Default)]
+pub struct Selection {
+    pub prefix: Option<String>,
+    pub host: Option<String>,
+    pub job: Option<String>,
+    pub collection: Option<String>,
+    pub from: Option<DateTime<Utc>>,
+    /// Exclusive
+    pub to: Option<DateTime<Utc>>,
+}
+
+impl Selection {
+    /// Validates the selectors and timestamp prefixes of a query
+    pub fn parse(
+        prefix: Option<String>,
+        host: Option<String>,
+        job: Option<String>,
+        collection: Option<String>,
+        from: Option<&str>,
+        to: Option<&str>,
+    ) -> Result<Self, &'static str> {
+        if prefix.is_none() && host.is_none() && job.is_none() && collection.is_none() {
+            return Err("One of prefix, host, job or collection is required");
+        }
+        let from = match from.map(timestamp_range) {
+            Some(None) => return Err("Invalid from timestamp"),
+            range => range.flatten().map(|(start, _)| start),
+        };
+        let to = match to.map(timestamp_range) {
+            Some(None) => return Err("Invalid to timestamp"),
+            range => range.flatten().map(|(_, end)| end),
+        };
+        Ok(Self {
+            prefix,
+            host,
+            job,
+            collection,
+            from,
+            to,
+        })
+    }
+
+    pub fn describe(&self) -> String {
+        [
+            ("prefix", self.prefix.as_deref()),
+            ("host", self.host.as_deref()),
+            ("job", self.job.as_deref()),
+            ("collection", self.collection.as_deref()),
+        ]
+        .iter()
+        .filter_map(|(name, value)| value.map(|v| format!("{}={}", name, v)))
+        .chain(self.from.map(|t| format!("from={}", t.to_rfc3339())))
+        .chain(self.to.map(|t| format!("to={}", t.to_rfc3339())))
+        .collect::<Vec<_>>()
+        .join(" ")
+    }
+
+    /// Selected captures oldest first, with the responses their revisits refer to
+    pub async fn plan(&self, pool: &PgPool) -> anyhow::Result<Vec<PlannedRecord>> {
+        let selected = sqlx::query_as::<_, ExportCapture>(&format!(
+            r#"
+            SELECT {CAPTURE_COLUMNS} FROM snapshots
+            WHERE ($1::text IS NULL OR url LIKE $1)
+              AND ($2::text IS NULL OR lower(substring(url from '^[A-Za-z][A-Za-z0-9+.-]*://(?:[^/@]*@)?([^/:?#]+)')) = lower($2))
+              AND ($3::text IS NULL OR job_id = $3)
+              AND ($4::text IS NULL OR id IN (
+                  SELECT snapshot_id FROM collection_snapshots WHERE collection_id = $4
+              ))
+              AND ($5::timestamptz IS NULL OR timestamp >= $5)
+              AND ($6::timestamptz IS NULL OR timestamp < $6)
+            ORDER BY timestamp ASC, id ASC
+            "#
+        ))
+        .bind(self.prefix.as_deref().map(like_prefix))
+        .bind(&self.host)
+        .bind(&self.job)
+        .bind(&self.collection)
+        .bind(self.from)
+        .bind(self.to)
+        .fetch_all(pool)
+        .await?;
+
+        let revisited = selected
+            .iter()
+            .filter_map(|c| c.revisit_of)
+            .collect::<Vec<_>>();
+        let originals = if revisited.is_empty() {
+            Vec::new()
+        } else {
+            sqlx::query_as::<_, ExportCapture>(&format!(
+                "SELECT {CAPTURE_COLUMNS} FROM snapshots WHERE id = ANY($1)"
+            ))
+            .bind(&revisited)
+            .fetch_all(pool)
+            .await?
+        };
+
+        Ok(export_plan(selected, &originals))
+    }
+}
+
+/// A capture to write, and the response it revisits
+pub type PlannedRecord = (ExportCapture, Option<ExportCapture>);
+
+/// Records in the order they are written: each revisit is preceded by the
+/// response it refers to, unless that response was already written
+pub fn export_plan(
+    selected: Vec<ExportCapture>,
+    originals: &[ExportCapture],
+) -> Vec<PlannedRecord> {
+    let mut written = HashSet::new();
+    let mut plan = Vec::with_capacity(selected.len());
+    for capture in selected {
+        let original = capture
+            .revisit_of
+            .and_then(|id| originals.iter().find(|o| o.id == id))
+            .cloned();
+        if let Some(original) = &original {
+            if written.insert(original.id) {
+                plan.push((original.clone(), None));
+            }
+        }
+        if written.insert(capture.id) {
+            plan.push((capture, original));
+        }
+    }
+    plan
+}
+
+/// One WARC/1.1 record with block digest, gzipped as its own member so
+/// readers can seek to any record
+pub fn gzip_record(headers: &[(&str, String)], block: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut record = String::from("WARC/1.1\r\n");
+    for (name, value) in headers {
+        record.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    record.push_str(&format!(
+        "WARC-Block-Digest: sha256:{:x}\r\n",
+        Sha256::digest(block)
+    ));
+    record.push_str(&format!("Content-Length: {}\r\n\r\n", block.len()));
+
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(record.as_bytes())?;
+    encoder.write_all(block)?;
+    encoder.write_all(b"\r\n\r\n")?;
+    encoder.finish()
+}
+
+fn warc_date(timestamp: DateTime<Utc>) -> String {
+    timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+pub(crate) fn warcinfo_record(filename: &str, description: &str) -> std::io::Result<Vec<u8>> {
+    let fields = format!(
+        "software: ArchiveStream/{}\r\nformat: WARC File Format 1.1\r\n\
+         conformsTo: https://iipc.github.io/warc-specifications/specifications/warc-format/warc-1.1/\r\n\
+         description: {}\r\n",
+        env!("CARGO_PKG_VERSION"),
+        description
+    );
+    gzip_record(
+        &[
+            ("WARC-Type", "warcinfo".to_string()),
+            ("WARC-Record-ID", format!("<urn:uuid:{}>", Uuid::new_v4())),
+            ("WARC-Date", warc_date(Utc::now())),
+            ("WARC-Filename", filename.to_string()),
+            ("Content-Type", "application/warc-fields".to_string()),
+        ],
+        fields.as_bytes(),
+    )
+}
+
+/// Response record, or a revisit pointing at `original`. Record IDs are the
+/// snapshot ids, so a client can resume after the last complete record.
+pub(crate) fn capture_record(
+    capture: &ExportCapture,
+    original: Option<&ExportCapture>,
+    http: &[u8],
+) -> std::io::Result<Vec<u8>> {
+    let mut headers = vec![
+        (
+            "WARC-Type",
+            if original.is_some() {
+                "revisit"
+            } else {
+                "response"
+            }
+            .to_string(),
+        ),
+        ("WARC-Record-ID", format!("<urn:uuid:{}>", capture.id)),
+        ("WARC-Date", warc_date(capture.timestamp)),
+        ("WARC-Target-URI", capture.url.clone()),
+        ("WARC-Payload-Digest", format!("sha256:{}", capture.sha256)),
+    ];
+    if let Some(original) = original {
+        // Revisits stored before profiles were recorded came from 304s
+        let profile = capture
+            .revisit_profile
+            .as_deref()
+            .unwrap_or(PROFILE_SERVER_NOT_MODIFIED);
+        headers.push(("WARC-Profile", profile.to_string()));
+        headers.push(("WARC-Refers-To", format!("<urn:uuid:{}>", original.id)));
+        headers.push(("WARC-Refers-To-Target-URI", original.url.clone()));
+        headers.push(("WARC-Refers-To-Date", warc_date(original.timestamp)));
+    }
+    headers.push((
+        "Content-Type",
+        "application/http; msgtype=response".to_string(),
+    ));
+    gzip_record(&headers, http)
+}
+
+/// Stored records end with the record separator; the export writes its own
+pub(crate) fn strip_separator(block: &[u8]) -> &[u8] {
+    block.strip_suffix(b"\r\n\r\n").unwrap_or(block)
+}
+
+const CAPTURE_COLUMNS: &str = "id, url, timestamp, warc_file, \"offset\", length, sha256, status_code, content_type, revisit_of, revisit_profile";
+
+/// GET /api/v1/export/warc?prefix=|host=|job=|collection=&from=&to=&resume_after=
+/// Streams the selected captures as a gzipped WARC, oldest first
+pub async fn export_warc(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ExportQuery>,
+) -> impl IntoResponse {
+    let selection = match Selection::parse(
+        params.prefix,
+        params.host,
+        params.job,
+        params.collection,
+        params.from.as_deref(),
+        params.to.as_deref(),
+    ) {
+        Ok(selection) => selection,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+
+    let mut plan = match selection.plan(&state.pool).await {
+        Ok(plan) => plan,
+        Err(e) => {
+            tracing::error!("Export selection error: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Export failed").into_response();
+        }
+    };
+    // The selection fingerprint lets a resuming client check nothing moved
+    let etag = {
+        let mut hasher = Sha256::new();
+        for (capture, _) in &plan {
+            hasher.update(capture.id.as_bytes());
+        }
+        format!("\"{:x}\"", hasher.finalize())
+    };
+
+    let resuming = params.resume_after.is_some();
+    if let Some(after) = params.resume_after {
+        match plan.iter().position(|(c, _)| c.id == after) {
+            Some(index) => {
+                plan.drain(..=index);
+            }
+            None => {
+                return (
+                    StatusCode::BAD_REQUEST,
+                    "resume_after is not in this export",
+                )
+                    .into_response()
+            }
+        }
+    }
+
+    let filename = format!(
+        "archivestream-export-{}.warc.gz",
+        Utc::now().format("%Y%m%d%H%M%S")
+    );
+    let description = format!(
+        "{} captures selected by {}",
+        plan.len(),
+        selection.describe()
+    );
+    let warcinfo = if resuming {
+        None
+    } else {
+        match warcinfo_record(&filename, &description) {
+            Ok(record) => Some(record),
+            Err(e) => {
+                tracing::error!("Export warcinfo error: {}", e);
+                return (StatusCode::INTERNAL_SERVER_ERROR, "Export failed").into_response();
+            }
+        }
+    };
+
+    let stream = futures::stream::unfold(
+        (state, warcinfo, plan.into_iter()),
+        |(state, warcinfo, mut plan)| async move {
+            if let Some(record) = warcinfo {
+                return Some((Ok(Bytes::from(record)), (state, None, plan)));
+            }
+            let (capture, original) = plan.next()?;
+            let record = match state
+                .warc_reader
+                .read_record(&capture.warc_file, capture.offset, capture.length)
+                .await
+            {
+                Ok(http) => capture_record(&capture, original.as_ref(), strip_separator(&http)),
+                Err(e) => Err(std::io::Error::other(e.to_string())),
+            };
+            match record {
+                Ok(record) => Some((Ok(Bytes::from(record)), (state, None, plan))),
+                Err(e) => {
+                    // Ending the body early lets the client resume from the last record
+                    tracing::error!("Export of {} failed: {}", capture.id, e);
+                    Some((Err(e), (state, None, Vec::new().into_iter())))
+                }
+            }
+        },
+    );
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/warc+gzip")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{}\"", filename),
+        )
+        .header(header::ETAG, etag)
+        .body(Body::from_stream(stream))
+        .unwrap()
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archive_common::testing::scratch_pool;
+    use flate2::read::MultiGzDecoder;
+    use std::io::Read;
+
+    fn capture(n: u128, revisit_of: Option<u128>) -> ExportCapture {
+        ExportCapture {
+            id: Uuid::from_u128(n),
+            url: "https://example.com/".to_string(),
+            timestamp: DateTime::from_timestamp(1_700_000_000 + n as i64, 0).unwrap(),
+            warc_file: "a.warc".to_string(),
+            offset: 0,
+            length: 0,
+            sha256: format!("{:064x}", n),
+            status_code: 200,
+            content_type: "text/html".to_string(),
+            revisit_of: revisit_of.map(Uuid::from_u128),
+            revisit_profile: None,
+        }
+    }
+
+    #[test]
+    fn test_revisits_follow_the_response_they_refer_to() {
+        // 1 is outside the selection; 2 and 4 revisit it, 3 is a plain response
+        let plan = export_plan(
+            vec![capture(2, Some(1)), capture(3, None), capture(4, Some(1))],
+            &[capture(1, None)],
+        );
+        let order = plan
+            .iter()
+            .map(|(c, o)| (c.id.as_u128(), o.as_ref().map(|o| o.id.as_u128())))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            order,
+            vec![(1, None), (2, Some(1)), (3, None), (4, Some(1))]
+        );
+    }
+
+    #[test]
+    fn test_members_concatenate_into_a_readable_warc() {
+        let http = b"HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\nhello";
+        let mut file = warcinfo_record("x.warc.gz", "test").unwrap();
+        file.extend(capture_record(&capture(1, None), None, http).unwrap());
+        file.extend(capture_record(&capture(2, Some(1)), Some(&capture(1, None)), b"").unwrap());
+
+        let mut text = String::new();
+        MultiGzDecoder::new(file.as_slice())
+            .read_to_string(&mut text)
+            .unwrap();
+        assert_eq!(text.matches("WARC/1.1\r\n").count(), 3);
+        assert!(text.contains("WARC-Type: warcinfo"));
+        assert!(text.contains(&format!(
+            "Content-Length: {}\r\n\r\nHTTP/1.1 200 OK",
+            http.len()
+        )));
+        assert!(text.contains(&format!(
+            "WARC-Refers-To: <urn:uuid:{}>",
+            Uuid::from_u128(1)
+        )));
+    }
+
+    fn warc_text(record: &[u8]) -> String {
+        let mut text = String::new();
+        MultiGzDecoder::new(record)
+            .read_to_string(&mut text)
+            .unwrap();
+        text
+    }
+
+    #[tokio::test]
+    async fn test_plan_carries_each_revisits_profile_in_postgres() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let original = Uuid::from_u128(1);
+        let rows = [
+            (original, None, None, None),
+            (
+                Uuid::from_u128(2),
+                Some(original),
+                Some(PROFILE_IDENTICAL_PAYLOAD),
+                Some("job-a"),
+            ),
+            // Stored before revisits recorded their profile
+            (Uuid::from_u128(3), Some(original), None, Some("job-a")),
+        ];
+        for (n, (id, revisit_of, profile, job)) in rows.into_iter().enumerate() {
+            sqlx::query(
+                r#"
+                INSERT INTO snapshots (id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type, revisit_of, revisit_profile, job_id)
+                VALUES ($1, 'https://example.com/', $2, 'a.warc', 0, 100, 'abc', 200, 'text/html', $3, $4, $5)
+                "#,
+            )
+            .bind(id)
+            .bind(DateTime::from_timestamp(1_700_000_000 + n as i64, 0).unwrap())
+            .bind(revisit_of)
+            .bind(profile)
+            .bind(job)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let selection = Selection {
+            job: Some("job-a".to_string()),
+            ..Default::default()
+        };
+        let plan = selection.plan(&pool).await.unwrap();
+        let ids = plan.iter().map(|(c, _)| c.id.as_u128()).collect::<Vec<_>>();
+        assert_eq!(ids, vec![1, 2, 3]);
+
+        let profiles = plan[1..]
+            .iter()
+            .map(|(capture, original)| {
+                warc_text(&capture_record(capture, original.as_ref(), b"").unwrap())
+            })
+            .collect::<Vec<_>>();
+        assert!(profiles[0].contains(&format!("WARC-Profile: {}\r\n", PROFILE_IDENTICAL_PAYLOAD)));
+        assert!(profiles[1].contains(&format!(
+            "WARC-Profile: {}\r\n",
+            PROFILE_SERVER_NOT_MODIFIED
+        )));
+    }
+}
@@ -1,25 +1,35 @@
+use crate::replication;
+use crate::snapshots::Cursor;
 use crate::AppState;
+use archive_federation::health::HealthConfig;
+use archive_federation::identity::{NODE_ID_HEADER, SIGNATURE_HEADER, TIMESTAMP_HEADER};
+use archive_federation::policy::{Direction, ReplicationFilter};
+use archive_federation::search::merge;
+use archive_federation::{HandshakeHello, HandshakeProof, Rejection};
 use axum::{
     extract::{Json, Path, Query, State},
-    http::StatusCode,
-    response::IntoResponse,
+    http::{HeaderMap, Method, StatusCode, Uri},
+    response::{IntoResponse, Response},
 };
-use std::sync::Arc;
-// use archive_federation::Peer;
 use serde::Deserialize;
+use serde_json::json;
+use std::sync::Arc;
 use uuid::Uuid;
 
-#[derive(Deserialize)]
-pub struct HandshakeRequest {
-    pub node_id: String,
-    pub endpoint: String,
-}
-
 #[derive(Deserialize)]
 pub struct FederatedSearchRequest {
     pub q: String,
+    /// Most peers to ask; all active peers when unset
+    pub max_instances: Option<usize>,
+    /// Per-peer timeout
+    pub timeout_ms: Option<u64>,
+    pub limit: Option<usize>,
 }
 
+/// Longest per-peer timeout a query can ask for
+const MAX_SEARCH_TIMEOUT_MS: u64 = 10_000;
+const MAX_SEARCH_RESULTS: usize = 200;
+
 /// GET /api/v1/federation/peers
 /// Returns the list of known active peers
 pub async fn get_peers(State(state): State<Arc<AppState>>) -> impl IntoResponse {
@@ -27,88 +37,315 @@ pub async fn get_peers(State(state): State<Arc<AppState>>) -> impl IntoResponse
     Json(peers_json)
 }
 
-/// GET /api/v1/federation/search
-/// Performs a search across local and federated instances
+/// GET /api/v1/federation/search?q=&max_instances=&timeout_ms=&limit=
+/// Searches locally and on peers, merging hits by normalized score and
+/// deduplicating captures held by several nodes
 pub async fn search_federated(
     State(state): State<Arc<AppState>>,
     Query(params): Query<FederatedSearchRequest>,
 ) -> impl IntoResponse {
-    // 1. Local Search
-    // Note: search_service.search returns Vec<SearchResult> or similar
-    let local_results = state
-        .search_service
-        .search(&params.q)
-        .await
-        .unwrap_or_default();
-
-    // 2. Federated Search
     let fed_query = archive_federation::FederatedQuery {
         query: params.q.clone(),
-        max_instances: None,
+        max_instances: params.max_instances,
+        timeout_ms: Some(
+            params
+                .timeout_ms
+                .unwrap_or(archive_federation::DEFAULT_SEARCH_TIMEOUT_MS)
+                .min(MAX_SEARCH_TIMEOUT_MS),
+        ),
     };
+    let (local, peers) = tokio::join!(
+        state.search_service.search(&params.q),
+        state.peer_manager.broadcast_search(&fed_query)
+    );
 
-    let peer_results = state.peer_manager.broadcast_search(&fed_query).await;
-
-    // 3. Return Combined
-    Json(serde_json::json!({
-        "local": local_results,
-        "federated": peer_results
+    let local_status = match &local {
+        Ok(results) => json!({"status": "ok", "results": results.len()}),
+        Err(e) => {
+            tracing::error!("Local search error: {}", e);
+            json!({"status": "error", "results": 0})
+        }
+    };
+    let reports: Vec<_> = peers
+        .iter()
+        .map(|peer| {
+            json!({
+                "node_id": peer.source_node_id,
+                "endpoint": peer.endpoint,
+                "status": peer.status,
+                "latency_ms": peer.latency_ms,
+                "results": peer.results.len(),
+                "error": peer.error,
+            })
+        })
+        .collect();
+
+    let mut sources = vec![(state.config.node_id.clone(), local.unwrap_or_default())];
+    sources.extend(
+        peers
+            .into_iter()
+            .map(|peer| (peer.source_node_id, peer.results)),
+    );
+    let limit = params.limit.unwrap_or(50).min(MAX_SEARCH_RESULTS);
+
+    Json(json!({
+        "query": params.q,
+        "results": merge(sources, limit),
+        "local": local_status,
+        "peers": reports,
     }))
 }
 
+fn rejection_response(rejection: Rejection) -> Response {
+    let status = match rejection {
+        Rejection::Denied => StatusCode::FORBIDDEN,
+        Rejection::Unauthenticated(_) => StatusCode::UNAUTHORIZED,
+        Rejection::InvalidEndpoint(_) => StatusCode::BAD_REQUEST,
+        Rejection::Busy => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    (status, rejection.to_string()).into_response()
+}
+
+/// Authenticate a bodiless request signed by a known peer; the error response otherwise
+fn reject_unsigned(
+    state: &AppState,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+) -> Option<Response> {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(node_id), Some(timestamp), Some(signature)) = (
+        header(NODE_ID_HEADER),
+        header(TIMESTAMP_HEADER).and_then(|t| t.parse::<i64>().ok()),
+        header(SIGNATURE_HEADER),
+    ) else {
+        return Some((StatusCode::UNAUTHORIZED, "Signed peer request required").into_response());
+    };
+    let path = uri
+        .path_and_query()
+        .map(|p| p.as_str())
+        .unwrap_or_else(|| uri.path());
+    state
+        .peer_manager
+        .verify_request(node_id, method.as_str(), path, timestamp, b"", signature)
+        .err()
+        .map(|rejection| {
+            tracing::warn!("Rejected request from {}: {}", node_id, rejection);
+            rejection_response(rejection)
+        })
+}
+
 /// POST /api/v1/federation/handshake
-/// Accepts a handshake from another node
+/// First step of a handshake: proves our key over the caller's challenge and
+/// returns a challenge for the caller to sign
 pub async fn handle_handshake(
     State(state): State<Arc<AppState>>,
-    Json(payload): Json<HandshakeRequest>,
+    Json(hello): Json<HandshakeHello>,
 ) -> impl IntoResponse {
-    // In a real implementation, we would verify the node_id and challenge
-    // For now, we trust the handshake and add the peer
-    state
-        .peer_manager
-        .add_peer(payload.node_id.clone(), payload.endpoint.clone());
+    let node_id = hello.node_id.clone();
+    match state.peer_manager.begin_handshake(hello).await {
+        Ok(challenge) => Json(challenge).into_response(),
+        Err(rejection) => {
+            tracing::warn!("Refused handshake from {}: {}", node_id, rejection);
+            rejection_response(rejection)
+        }
+    }
+}
+
+/// POST /api/v1/federation/handshake/verify
+/// Second step: accepts the caller as a peer once it has signed our challenge
+pub async fn verify_handshake(
+    State(state): State<Arc<AppState>>,
+    Json(proof): Json<HandshakeProof>,
+) -> impl IntoResponse {
+    match state.peer_manager.complete_handshake(&proof).await {
+        Ok(peer) => {
+            tracing::info!("Accepted handshake from peer: {}", peer.id);
+            Json(serde_json::json!({
+                "status": "accepted",
+                "local_node_id": state.config.node_id,
+            }))
+            .into_response()
+        }
+        Err(rejection) => {
+            tracing::warn!(
+                "Refused handshake proof from {}: {}",
+                proof.node_id,
+                rejection
+            );
+            rejection_response(rejection)
+        }
+    }
+}
 
-    tracing::info!("Accepted handshake from peer: {}", payload.node_id);
+/// Largest manifest page a peer can ask for
+const MAX_MANIFEST_PAGE: usize = 1000;
+/// Rows examined per filtered page, as a multiple of the page size
+const MANIFEST_SCAN_FACTOR: usize = 10;
 
-    Json(serde_json::json!({
-        "status": "accepted",
-        "local_node_id": state.config.node_id // We need to add this to AppConfig
-    }))
+#[derive(sqlx::FromRow)]
+struct ManifestRow {
+    #[sqlx(flatten)]
+    snapshot: archive_common::Snapshot,
+    collections: Vec<String>,
+}
+
+/// Node id of a request that passed [`reject_unsigned`]
+fn signer(headers: &HeaderMap) -> &str {
+    headers
+        .get(NODE_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default()
 }
 
-/// GET /api/v1/federation/manifest
-/// Returns available snapshots for sync
+/// GET /api/v1/federation/manifest?from=&to=&limit=&after=&filter=
+/// Pages through snapshots oldest first for sync; `next` resumes the listing.
+/// Only captures both the requested filter and our push policy for the
+/// caller admit are listed
 pub async fn get_manifest(
     State(state): State<Arc<AppState>>,
     Query(params): Query<archive_federation::ManifestRequest>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
-    // Default to last 24 hours if not specified
-    let from_ts = params
-        .from
-        .unwrap_or_else(|| chrono::Utc::now() - chrono::Duration::hours(24));
-    let to_ts = params.to.unwrap_or_else(chrono::Utc::now);
-    let limit = params.limit.unwrap_or(100) as i64;
-
-    let result = sqlx::query_as::<_, archive_common::Snapshot>(
+    if let Some(response) = reject_unsigned(&state, &method, &uri, &headers) {
+        return response;
+    }
+
+    let after = match params.after.as_deref().map(Cursor::decode) {
+        Some(None) => return (StatusCode::BAD_REQUEST, "Invalid cursor").into_response(),
+        after => after.flatten(),
+    };
+    let requested: ReplicationFilter = match params.filter.as_deref().map(serde_json::from_str) {
+        Some(Ok(filter)) => filter,
+        Some(Err(_)) => return (StatusCode::BAD_REQUEST, "Invalid filter").into_response(),
+        None => ReplicationFilter::default(),
+    };
+    let push = match replication::policy_for(&state.pool, signer(&headers), Direction::Push).await {
+        Ok(policy) => policy.map(|p| p.filter).unwrap_or_default(),
+        Err(e) => {
+            tracing::error!("Manifest policy error: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Manifest failed").into_response();
+        }
+    };
+    let filters: Vec<_> = [requested, push]
+        .into_iter()
+        .filter(|f| !f.is_empty())
+        .collect();
+
+    let limit = params.limit.unwrap_or(100).clamp(1, MAX_MANIFEST_PAGE);
+    let scan = if filters.is_empty() {
+        limit
+    } else {
+        limit * MANIFEST_SCAN_FACTOR
+    };
+    // Date bounds of the filters narrow the query itself
+    let from = filters
+        .iter()
+        .filter_map(|f| f.from)
+        .chain(params.from)
+        .max();
+    let to = filters.iter().filter_map(|f| f.to).chain(params.to).min();
+
+    // Oldest first, so a peer can resume from the last capture it saw
+    let result = sqlx::query_as::<_, ManifestRow>(
         r#"
-        SELECT id, url, timestamp, warc_file, offset, length, sha256, status_code, content_type, payload_hash
-        FROM snapshots
-        WHERE timestamp >= $1 AND timestamp <= $2
-        ORDER BY timestamp DESC
-        LIMIT $3
+        SELECT s.id, s.url, s.timestamp, s.warc_file, s.offset, s.length, s.sha256, s.status_code, s.content_type, s.payload_hash,
+               ARRAY(SELECT cs.collection_id FROM collection_snapshots cs WHERE cs.snapshot_id = s.id) AS collections
+        FROM snapshots s
+        WHERE ($1::timestamptz IS NULL OR s.timestamp >= $1)
+          AND ($2::timestamptz IS NULL OR s.timestamp <= $2)
+          AND ($3::timestamptz IS NULL OR (s.timestamp, s.id) > ($3, $4))
+        ORDER BY s.timestamp ASC, s.id ASC
+        LIMIT $5
         "#
     )
-    .bind(from_ts)
-    .bind(to_ts)
-    .bind(limit)
+    .bind(from)
+    .bind(to)
+    .bind(after.as_ref().map(|c| c.timestamp))
+    .bind(after.as_ref().map(|c| c.id))
+    .bind(scan as i64 + 1)
     .fetch_all(&state.pool)
     .await;
 
-    match result {
-        Ok(snapshots) => Json(archive_federation::ManifestResponse { snapshots }).into_response(),
+    let rows = match result {
+        Ok(rows) => rows,
         Err(e) => {
             tracing::error!("Manifest error: {}", e);
-            (StatusCode::INTERNAL_SERVER_ERROR, "Manifest failed").into_response()
+            return (StatusCode::INTERNAL_SERVER_ERROR, "Manifest failed").into_response();
+        }
+    };
+
+    // `next` follows the last row examined, so a page may come back short
+    // or empty while more captures remain
+    let mut snapshots = Vec::new();
+    let mut last = None;
+    let mut more = false;
+    for (i, row) in rows.into_iter().enumerate() {
+        if snapshots.len() == limit || i == scan {
+            more = true;
+            break;
+        }
+        last = Some(Cursor {
+            timestamp: row.snapshot.timestamp,
+            id: row.snapshot.id,
+        });
+        if filters
+            .iter()
+            .all(|f| f.admits(&row.snapshot, Some(&row.collections)))
+        {
+            snapshots.push(row.snapshot);
+        }
+    }
+    let next = last.filter(|_| more).map(|c| c.encode());
+    Json(archive_federation::ManifestResponse { snapshots, next }).into_response()
+}
+
+/// Refuse a download outside the caller's push policy or over its budget
+async fn enforce_push_policy(
+    state: &AppState,
+    peer_id: &str,
+    snapshot: &archive_common::Snapshot,
+) -> Option<Response> {
+    let checked = async {
+        let Some(policy) = replication::policy_for(&state.pool, peer_id, Direction::Push).await?
+        else {
+            return anyhow::Ok(None);
+        };
+        let collections: Option<Vec<String>> = if policy.filter.collections.is_empty() {
+            None
+        } else {
+            Some(
+                sqlx::query_scalar(
+                    "SELECT collection_id FROM collection_snapshots WHERE snapshot_id = $1",
+                )
+                .bind(snapshot.id)
+                .fetch_all(&state.pool)
+                .await?,
+            )
+        };
+        if !policy.filter.admits(snapshot, collections.as_deref()) {
+            return Ok(Some((
+                StatusCode::FORBIDDEN,
+                "Outside the replication policy",
+            )));
+        }
+        let allowed = replication::consume_bandwidth(
+            &state.pool,
+            peer_id,
+            Direction::Push,
+            snapshot.length,
+            policy.bandwidth_bytes_per_hour,
+        )
+        .await?;
+        Ok((!allowed).then_some((StatusCode::TOO_MANY_REQUESTS, "Bandwidth budget spent")))
+    };
+    match checked.await {
+        Ok(refusal) => refusal.map(IntoResponse::into_response),
+        Err(e) => {
+            tracing::error!("Push policy error: {}", e);
+            Some((StatusCode::INTERNAL_SERVER_ERROR, "Policy check failed").into_response())
         }
     }
 }
@@ -118,9 +355,16 @@ pub async fn get_manifest(
 pub async fn download_snapshot(
     Path(id): Path<Uuid>,
     State(state): State<Arc<AppState>>,
+    method: Method,
+    uri: Uri,
+    headers: HeaderMap,
 ) -> impl IntoResponse {
+    if let Some(response) = reject_unsigned(&state, &method, &uri, &headers) {
+        return response;
+    }
+
     let snapshot = match sqlx::query_as::<_, archive_common::Snapshot>(
-        "SELECT id, url, timestamp, warc_file, offset, length, sha256, status_code, content_type, payload_hash FROM snapshots WHERE id = $1"
+        "SELECT id, url, timestamp, warc_file, \"offset\", length, sha256, status_code, content_type, payload_hash FROM snapshots WHERE id = $1"
     )
     .bind(id)
     .fetch_optional(&state.pool)
@@ -129,6 +373,9 @@ pub async fn download_snapshot(
         Ok(None) => return (StatusCode::NOT_FOUND, "Snapshot not found").into_response(),
         Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "DB error").into_response(),
     };
+    if let Some(response) = enforce_push_policy(&state, signer(&headers), &snapshot).await {
+        return response;
+    }
 
     let data = match state
         .warc_reader
@@ -150,118 +397,65 @@ pub async fn download_snapshot(
         .into_response()
 }
 
-// --- Sync Worker ---
+// --- Health Worker ---
 
-pub struct SyncWorker {
+/// Keeps the peer registry current: probes peers, handshakes with configured
+/// seeds until they are known, and discovers peers through gossip
+pub struct HealthWorker {
     state: Arc<AppState>,
+    config: HealthConfig,
+    seeds: Vec<String>,
+    public_endpoint: String,
+    /// New peers handshaken per gossip round; 0 disables gossip
+    gossip_limit: usize,
 }
 
-impl SyncWorker {
+impl HealthWorker {
     pub fn new(state: Arc<AppState>) -> Self {
-        Self { state }
+        let seeds = std::env::var("FEDERATION_SEEDS")
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(|s| s.trim_end_matches('/').to_string())
+            .collect();
+        Self {
+            state,
+            config: HealthConfig::from_env(),
+            seeds,
+            public_endpoint: std::env::var("FEDERATION_PUBLIC_ENDPOINT")
+                .unwrap_or_else(|_| "http://localhost:3001".into()),
+            gossip_limit: std::env::var("FEDERATION_GOSSIP_LIMIT")
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(5),
+        }
     }
 
     pub async fn run_loop(&self) {
-        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60)); // Poll every minute
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            self.config.interval_secs.max(1) as u64,
+        ));
         loop {
             interval.tick().await;
-            if let Err(e) = self.sync_cycle().await {
-                tracing::error!("Sync cycle error: {}", e);
-            }
-        }
-    }
-
-    async fn sync_cycle(&self) -> anyhow::Result<()> {
-        let peers = self.state.peer_manager.get_active_peers();
-        if peers.is_empty() {
-            return Ok(());
-        }
+            let peers = &self.state.peer_manager;
 
-        let client = reqwest::Client::new();
-
-        for peer in peers {
-            // 1. Get Manifest
-            let manifest_url = format!("{}/api/v1/federation/manifest?limit=50", peer.endpoint); // simplified
-            let resp = client.get(&manifest_url).send().await?;
-            if !resp.status().is_success() {
-                continue;
-            }
-
-            let manifest: archive_federation::ManifestResponse = resp.json().await?;
-
-            // 2. Process Snapshots
-            for remote_snap in manifest.snapshots {
-                // Check if we have it
-                let exists = sqlx::query("SELECT 1 FROM snapshots WHERE sha256 = $1")
-                    .bind(remote_snap.sha256.clone())
-                    .fetch_optional(&self.state.pool)
-                    .await?
-                    .is_some();
-
-                if !exists {
-                    tracing::info!(
-                        "Syncing missing snapshot {} from peer {}",
-                        remote_snap.id,
-                        peer.id
-                    );
-                    self.download_and_save(&client, &peer, &remote_snap).await?;
+            // Seeds must prove their keys like any other peer
+            for seed in &self.seeds {
+                if peers.knows_endpoint(seed) {
+                    continue;
+                }
+                match peers.connect(seed, &self.public_endpoint).await {
+                    Ok(peer) => tracing::info!("Federated with seed {} ({})", seed, peer.id),
+                    Err(e) => tracing::warn!("Handshake with seed {} failed: {}", seed, e),
                 }
             }
-        }
-        Ok(())
-    }
-
-    async fn download_and_save(
-        &self,
-        client: &reqwest::Client,
-        peer: &archive_federation::Peer,
-        snap: &archive_common::Snapshot,
-    ) -> anyhow::Result<()> {
-        let url = format!("{}/api/v1/snapshot/{}/download", peer.endpoint, snap.id);
-        let resp = client.get(&url).send().await?;
-        let bytes = resp.bytes().await?;
-
-        // Write to local WARC
-        // For MVP, we just append to a dedicated sync.warc file
-
-        // Improve: reconstruct WarcRecord or write raw bytes if WarcWriter supported raw write
-        // Since WarcWriter expects WarcRecord, we need to parse the raw bytes or extend WarcWriter.
-        // For now, let's assume we implement a write_raw method or parse it.
-        // Actually, easier: The API returns the raw record (headers + body).
-        // We can just append it to the file directly.
-
-        let path = "data/archive/sync.warc";
-        if let Some(parent) = std::path::Path::new(path).parent() {
-            tokio::fs::create_dir_all(parent).await?;
-        }
 
-        let mut file = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(path)?;
+            peers.probe_due(&self.config).await;
 
-        let offset = file.metadata()?.len();
-        std::io::Write::write_all(&mut file, &bytes)?;
-        let length = bytes.len() as u64;
-
-        // Save to DB
-        sqlx::query(
-            "INSERT INTO snapshots (id, url, timestamp, warc_file, offset, length, sha256, status_code, content_type, payload_hash) 
-             VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)"
-        )
-        .bind(uuid::Uuid::new_v4())
-        .bind(&snap.url)
-        .bind(snap.timestamp)
-        .bind("sync.warc")
-        .bind(offset as i64)
-        .bind(length as i64)
-        .bind(&snap.sha256)
-        .bind(snap.status_code as i32)
-        .bind(&snap.content_type)
-        .bind(&snap.payload_hash)
-        .execute(&self.state.pool)
-        .await?;
-
-        Ok(())
+            if self.gossip_limit > 0 {
+                peers.gossip(&self.public_endpoint, self.gossip_limit).await;
+            }
+        }
     }
 }
@@ -0,0 +1,686 @@
+use crate::export::strip_separator;
+use crate::import::parse::HttpResponse;
+use crate::timestamp;
+use crate::AppState;
+use anyhow::{anyhow, bail, Result};
+use archive_common::merkle::{self, Hash};
+use archive_federation::identity::manifest_message;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use chrono::{DateTime, SecondsFormat, SubsecRound, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Fixity settings
+#[derive(Clone)]
+pub struct FixityConfig {
+    pub check_interval: Duration,
+    /// Captures rehashed per check
+    pub batch_size: i64,
+    /// A capture is rehashed again once its last check is this old
+    pub recheck_days: i32,
+    pub manifest_interval: Duration,
+    /// Most captures one manifest covers; a backlog spreads over several
+    pub manifest_max_leaves: i64,
+    /// Where failed checks are POSTed, if anywhere
+    pub alert_webhook: Option<String>,
+}
+
+impl FixityConfig {
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+        Self {
+            check_interval: Duration::from_secs(var("FIXITY_CHECK_INTERVAL_SECS", 600)),
+            batch_size: var("FIXITY_BATCH_SIZE", 200),
+            recheck_days: var("FIXITY_RECHECK_DAYS", 30),
+            manifest_interval: Duration::from_secs(var("MERKLE_MANIFEST_INTERVAL_SECS", 3600)),
+            manifest_max_leaves: var("MERKLE_MAX_LEAVES", 10_000),
+            alert_webhook: std::env::var("FIXITY_ALERT_WEBHOOK")
+                .ok()
+                .filter(|url| !url.is_empty()),
+        }
+    }
+}
+
+/// Times in signed messages and proofs, exactly as they were hashed
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Micros, true)
+}
+
+/// What rehashing a capture's stored HTTP response found
+#[derive(Debug, PartialEq)]
+struct Check {
+    /// `ok`, `mismatch` or `unreadable`
+    outcome: &'static str,
+    actual_sha256: Option<String>,
+    detail: Option<String>,
+}
+
+impl Check {
+    fn unreadable(detail: String) -> Self {
+        Self {
+            outcome: "unreadable",
+            actual_sha256: None,
+            detail: Some(detail),
+        }
+    }
+}
+
+/// Compare the payload digest of a stored HTTP response with the one
+/// recorded at capture time
+fn check_payload(http: &[u8], expected: &str) -> Check {
+    let Some(response) = HttpResponse::parse(strip_separator(http)) else {
+        return Check::unreadable("stored record is not an HTTP response".into());
+    };
+    let actual = format!("{:x}", Sha256::digest(response.body));
+    Check {
+        outcome: if actual.eq_ignore_ascii_case(expected) {
+            "ok"
+        } else {
+            "mismatch"
+        },
+        actual_sha256: Some(actual),
+        detail: None,
+    }
+}
+
+/// A capture due for a rehash
+#[derive(sqlx::FromRow)]
+struct Due {
+    id: Uuid,
+    url: String,
+    warc_file: String,
+    offset: i64,
+    length: i64,
+    sha256: String,
+}
+
+/// A check that did not come back `ok`
+#[derive(Serialize, sqlx::FromRow)]
+struct Failure {
+    snapshot_id: Uuid,
+    url: String,
+    warc_file: String,
+    outcome: String,
+    expected_sha256: String,
+    actual_sha256: Option<String>,
+    detail: Option<String>,
+    checked_at: DateTime<Utc>,
+}
+
+/// Rehashes stored captures on a schedule, alerting on any that no longer
+/// match their recorded digest, and signs Merkle manifests over new captures
+pub struct FixityWorker {
+    state: Arc<AppState>,
+    config: FixityConfig,
+    client: reqwest::Client,
+}
+
+impl FixityWorker {
+    pub fn new(state: Arc<AppState>, config: FixityConfig) -> Self {
+        Self {
+            state,
+            config,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn run_loop(&self) {
+        let mut check = tokio::time::interval(self.config.check_interval);
+        let mut manifest = tokio::time::interval(self.config.manifest_interval);
+        loop {
+            tokio::select! {
+                _ = check.tick() => match self.check_due().await {
+                    Ok((0, _)) => {}
+                    Ok((checked, failures)) => {
+                        tracing::info!("Fixity checked {} captures, {} failed", checked, failures.len());
+                        if !failures.is_empty() {
+                            self.alert(&failures).await;
+                        }
+                    }
+                    Err(e) => tracing::error!("Fixity check failed: {}", e),
+                },
+                _ = manifest.tick() => {
+                    match self.sign_manifest().await {
+                        Ok(None) => {}
+                        Ok(Some((id, size))) => tracing::info!("Signed Merkle manifest {} over {} captures", id, size),
+                        Err(e) => tracing::error!("Merkle manifest failed: {}", e),
+                    }
+                    if let Some(tsa) = &self.state.tsa {
+                        match timestamp::stamp_manifests(&self.state.pool, tsa).await {
+                            Ok(0) => {}
+                            Ok(count) => tracing::info!("Timestamped {} Merkle manifests", count),
+                            Err(e) => tracing::error!("Timestamping manifests failed: {}", e),
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Claim a batch of captures never checked or checked too long ago and
+    /// rehash them. Revisits share their original's bytes and are skipped
+    async fn check_due(&self) -> Result<(usize, Vec<Failure>)> {
+        let due = sqlx::query_as::<_, Due>(
+            r#"
+            UPDATE snapshots SET fixity_checked_at = NOW()
+            WHERE id IN (
+                SELECT id FROM snapshots
+                WHERE revisit_of IS NULL
+                  AND (fixity_checked_at IS NULL OR fixity_checked_at < NOW() - make_interval(days => $1))
+                ORDER BY fixity_checked_at NULLS FIRST
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, url, warc_file, "offset", length, sha256
+            "#,
+        )
+        .bind(self.config.recheck_days)
+        .bind(self.config.batch_size)
+        .fetch_all(&self.state.pool)
+        .await?;
+
+        let mut failures = Vec::new();
+        for capture in &due {
+            let check = match self
+                .state
+                .warc_reader
+                .read_record(&capture.warc_file, capture.offset, capture.length)
+                .await
+            {
+                Ok(http) => check_payload(&http, &capture.sha256),
+                Err(e) => Check::unreadable(e.to_string()),
+            };
+            let checked_at: DateTime<Utc> = sqlx::query_scalar(
+                r#"
+                INSERT INTO fixity_events (snapshot_id, outcome, expected_sha256, actual_sha256, detail)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING checked_at
+                "#,
+            )
+            .bind(capture.id)
+            .bind(check.outcome)
+            .bind(&capture.sha256)
+            .bind(&check.actual_sha256)
+            .bind(&check.detail)
+            .fetch_one(&self.state.pool)
+            .await?;
+
+            if check.outcome != "ok" {
+                tracing::error!(
+                    "Fixity {} for snapshot {} ({} in {}): {}",
+                    check.outcome,
+                    capture.id,
+                    capture.url,
+                    capture.warc_file,
+                    check.detail.as_deref().unwrap_or("payload digest changed")
+                );
+                failures.push(Failure {
+                    snapshot_id: capture.id,
+                    url: capture.url.clone(),
+                    warc_file: capture.warc_file.clone(),
+                    outcome: check.outcome.to_string(),
+                    expected_sha256: capture.sha256.clone(),
+                    actual_sha256: check.actual_sha256,
+                    detail: check.detail,
+                    checked_at,
+                });
+            }
+        }
+        Ok((due.len(), failures))
+    }
+
+    /// One alert per check round, listing every failure in it
+    async fn alert(&self, failures: &[Failure]) {
+        let Some(webhook) = &self.config.alert_webhook else {
+            return;
+        };
+        let payload = serde_json::json!({
+            "event": "fixity_failure",
+            "node_id": self.state.config.node_id,
+            "failures": failures,
+        });
+        let result = self.client.post(webhook).json(&payload).send().await;
+        match result.map(|r| r.error_for_status()) {
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) | Err(e) => tracing::error!("Fixity alert webhook failed: {}", e),
+        }
+    }
+
+    /// Cover captures not yet in any manifest with a new signed root,
+    /// chained to the previous one
+    pub(crate) async fn sign_manifest(&self) -> Result<Option<(i64, usize)>> {
+        let mut tx = self.state.pool.begin().await?;
+        // Manifests are chained, so only one node may append at a time
+        sqlx::query("LOCK TABLE merkle_manifests IN EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await?;
+
+        let captures: Vec<(Uuid, String, DateTime<Utc>, String)> = sqlx::query_as(
+            r#"
+            SELECT id, url, timestamp, sha256 FROM snapshots s
+            WHERE NOT EXISTS (SELECT 1 FROM merkle_leaves l WHERE l.snapshot_id = s.id)
+            ORDER BY timestamp, id
+            LIMIT $1
+            "#,
+        )
+        .bind(self.config.manifest_max_leaves)
+        .fetch_all(&mut *tx)
+        .await?;
+        if captures.is_empty() {
+            return Ok(None);
+        }
+
+        let leaves: Vec<Hash> = captures
+            .iter()
+            .map(|(id, url, timestamp, sha256)| {
+                merkle::leaf_hash(&merkle::snapshot_leaf(*id, url, *timestamp, sha256))
+            })
+            .collect();
+        let root = hex::encode(merkle::root(&leaves));
+        let previous_root: Option<String> =
+            sqlx::query_scalar("SELECT root FROM merkle_manifests ORDER BY id DESC LIMIT 1")
+                .fetch_optional(&mut *tx)
+                .await?;
+
+        let identity = self.state.peer_manager.identity();
+        let created_at = Utc::now().trunc_subsecs(0);
+        let signature = identity.sign(&manifest_message(
+            &identity.node_id(),
+            &format_time(created_at),
+            leaves.len() as i64,
+            &root,
+            previous_root.as_deref().unwrap_or_default(),
+        ));
+        let id: i64 = sqlx::query_scalar(
+            r#"
+            INSERT INTO merkle_manifests (root, previous_root, tree_size, node_id, public_key, signature, created_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7)
+            RETURNING id
+            "#,
+        )
+        .bind(&root)
+        .bind(&previous_root)
+        .bind(leaves.len() as i32)
+        .bind(identity.node_id())
+        .bind(identity.public_key())
+        .bind(&signature)
+        .bind(created_at)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT INTO merkle_leaves (snapshot_id, manifest_id, position, leaf_hash)
+            SELECT snapshot_id, $1, position - 1, leaf_hash
+            FROM UNNEST($2::uuid[], $3::text[]) WITH ORDINALITY AS l(snapshot_id, leaf_hash, position)
+            "#,
+        )
+        .bind(id)
+        .bind(captures.iter().map(|c| c.0).collect::<Vec<_>>())
+        .bind(leaves.iter().map(hex::encode).collect::<Vec<_>>())
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(Some((id, leaves.len())))
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct ManifestRow {
+    id: i64,
+    root: String,
+    previous_root: Option<String>,
+    tree_size: i32,
+    node_id: String,
+    public_key: String,
+    signature: String,
+    created_at: DateTime<Utc>,
+    tsa_time: Option<DateTime<Utc>>,
+}
+
+const MANIFEST_COLUMNS: &str =
+    "id, root, previous_root, tree_size, node_id, public_key, signature, created_at, tsa_time";
+
+/// A signed manifest as published; `created_at` is the string that was signed
+#[derive(Serialize)]
+pub(crate) struct Manifest {
+    pub(crate) id: i64,
+    pub(crate) root: String,
+    previous_root: Option<String>,
+    pub(crate) tree_size: i32,
+    node_id: String,
+    public_key: String,
+    signature: String,
+    created_at: String,
+    /// When a Time Stamping Authority countersigned the root
+    tsa_time: Option<DateTime<Utc>>,
+}
+
+impl From<ManifestRow> for Manifest {
+    fn from(row: ManifestRow) -> Self {
+        Self {
+            id: row.id,
+            root: row.root,
+            previous_root: row.previous_root,
+            tree_size: row.tree_size,
+            node_id: row.node_id,
+            public_key: row.public_key,
+            signature: row.signature,
+            created_at: format_time(row.created_at),
+            tsa_time: row.tsa_time,
+        }
+    }
+}
+
+async fn fetch_manifest(pool: &sqlx::PgPool, id: Option<i64>) -> Result<Option<Manifest>> {
+    let row = sqlx::query_as::<_, ManifestRow>(&format!(
+        "SELECT {} FROM merkle_manifests WHERE $1::bigint IS NULL OR id = $1 ORDER BY id DESC LIMIT 1",
+        MANIFEST_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(Manifest::from))
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct FixityEvent {
+    outcome: String,
+    expected_sha256: String,
+    actual_sha256: Option<String>,
+    detail: Option<String>,
+    checked_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct FixityStatus {
+    /// Captures with bytes of their own, i.e. not revisits
+    captures: i64,
+    never_checked: i64,
+    /// Captures whose latest check did not come back `ok`
+    failing: i64,
+    recent_failures: Vec<Failure>,
+    /// Captures not yet covered by a signed manifest
+    unsigned: i64,
+    last_manifest: Option<Manifest>,
+}
+
+/// GET /api/v1/fixity — check coverage, failures and the latest manifest
+pub async fn get_fixity_status(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let result = async {
+        let (captures, never_checked): (i64, i64) = sqlx::query_as(
+            r#"
+            SELECT COUNT(*), COUNT(*) FILTER (WHERE fixity_checked_at IS NULL)
+            FROM snapshots WHERE revisit_of IS NULL
+            "#,
+        )
+        .fetch_one(&state.pool)
+        .await?;
+        let failing: i64 = sqlx::query_scalar(
+            r#"
+            SELECT COUNT(*) FROM (
+                SELECT DISTINCT ON (snapshot_id) outcome FROM fixity_events
+                ORDER BY snapshot_id, id DESC
+            ) latest
+            WHERE outcome <> 'ok'
+            "#,
+        )
+        .fetch_one(&state.pool)
+        .await?;
+        let recent_failures = sqlx::query_as::<_, Failure>(
+            r#"
+            SELECT e.snapshot_id, s.url, s.warc_file, e.outcome, e.expected_sha256,
+                   e.actual_sha256, e.detail, e.checked_at
+            FROM fixity_events e
+            JOIN snapshots s ON s.id = e.snapshot_id
+            WHERE e.outcome <> 'ok'
+            ORDER BY e.id DESC
+            LIMIT 50
+            "#,
+        )
+        .fetch_all(&state.pool)
+        .await?;
+        let unsigned: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM snapshots s WHERE NOT EXISTS (SELECT 1 FROM merkle_leaves l WHERE l.snapshot_id = s.id)",
+        )
+        .fetch_one(&state.pool)
+        .await?;
+        anyhow::Ok(FixityStatus {
+            captures,
+            never_checked,
+            failing,
+            recent_failures,
+            unsigned,
+            last_manifest: fetch_manifest(&state.pool, None).await?,
+        })
+    }
+    .await;
+
+    match result {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => {
+            tracing::error!("Fixity status error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error fetching fixity status",
+            )
+                .into_response()
+        }
+    }
+}
+
+/// GET /api/v1/fixity/manifests/:id — one signed manifest, for walking the chain
+pub async fn get_manifest(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> impl IntoResponse {
+    match fetch_manifest(&state.pool, Some(id)).await {
+        Ok(Some(manifest)) => Json(manifest).into_response(),
+        Ok(None) => (StatusCode::NOT_FOUND, "Manifest not found").into_response(),
+        Err(e) => {
+            tracing::error!("Manifest fetch error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error fetching manifest").into_response()
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct InclusionProof {
+    pub(crate) snapshot_id: Uuid,
+    pub(crate) url: String,
+    /// Capture time as hashed into the leaf
+    pub(crate) timestamp: String,
+    pub(crate) sha256: String,
+    pub(crate) leaf_hash: String,
+    pub(crate) leaf_index: i32,
+    /// Sibling hashes from the leaf up to the root
+    pub(crate) audit_path: Vec<String>,
+    pub(crate) manifest: Manifest,
+    /// Latest rehash of the stored bytes
+    fixity: Option<FixityEvent>,
+}
+
+pub(crate) async fn inclusion_proof(
+    pool: &sqlx::PgPool,
+    id: Uuid,
+) -> Result<Option<InclusionProof>> {
+    let Some((url, timestamp, sha256, manifest_id, position)) =
+        sqlx::query_as::<_, (String, DateTime<Utc>, String, i64, i32)>(
+            r#"
+            SELECT s.url, s.timestamp, s.sha256, l.manifest_id, l.position
+            FROM merkle_leaves l JOIN snapshots s ON s.id = l.snapshot_id
+            WHERE l.snapshot_id = $1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await?
+    else {
+        return Ok(None);
+    };
+    let manifest = fetch_manifest(pool, Some(manifest_id))
+        .await?
+        .ok_or_else(|| anyhow!("manifest {} is missing", manifest_id))?;
+
+    let leaves = sqlx::query_scalar::<_, String>(
+        "SELECT leaf_hash FROM merkle_leaves WHERE manifest_id = $1 ORDER BY position",
+    )
+    .bind(manifest_id)
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(|leaf| {
+        hex::decode(leaf)?
+            .try_into()
+            .map_err(|_| anyhow!("leaf hash is not 32 bytes"))
+    })
+    .collect::<Result<Vec<Hash>>>()?;
+    if hex::encode(merkle::root(&leaves)) != manifest.root {
+        bail!(
+            "stored leaves of manifest {} no longer hash to its root",
+            manifest_id
+        );
+    }
+
+    let fixity = sqlx::query_as::<_, FixityEvent>(
+        r#"
+        SELECT outcome, expected_sha256, actual_sha256, detail, checked_at
+        FROM fixity_events WHERE snapshot_id = $1
+        ORDER BY id DESC LIMIT 1
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+
+    let index = position as usize;
+    Ok(Some(InclusionProof {
+        snapshot_id: id,
+        url,
+        timestamp: format_time(timestamp),
+        sha256,
+        leaf_hash: hex::encode(leaves[index]),
+        leaf_index: position,
+        audit_path: merkle::inclusion_proof(&leaves, index)
+            .iter()
+            .map(hex::encode)
+            .collect(),
+        manifest,
+        fixity,
+    }))
+}
+
+/// GET /api/v1/snapshot/:id/proof — the capture's Merkle inclusion proof
+/// against the signed manifest that covers it
+pub async fn get_inclusion_proof(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    match inclusion_proof(&state.pool, id).await {
+        Ok(Some(proof)) => Json(proof).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            "Snapshot is not covered by a signed manifest yet",
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Inclusion proof error for {}: {}", id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error building inclusion proof",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::import::response_record;
+    use crate::tests::test_state;
+    use archive_common::storage::{ArchiveStorage, FsStorage};
+    use archive_common::testing::scratch_pool;
+
+    #[test]
+    fn test_check_payload_rehashes_the_http_body() {
+        let http = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>hello</p>\r\n\r\n";
+        let digest = format!("{:x}", Sha256::digest(b"<p>hello</p>"));
+
+        let check = check_payload(http, &digest.to_uppercase());
+        assert_eq!(check.outcome, "ok");
+        assert_eq!(check.actual_sha256.as_deref(), Some(digest.as_str()));
+
+        let altered = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>hellO</p>\r\n\r\n";
+        assert_eq!(check_payload(altered, &digest).outcome, "mismatch");
+        assert_eq!(check_payload(b"garbage", &digest).outcome, "unreadable");
+    }
+
+    #[tokio::test]
+    async fn test_check_due_rehashes_stored_captures_in_postgres() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let dir = std::env::temp_dir().join(format!("archivestream-test-{}", Uuid::new_v4()));
+        let storage = Arc::new(FsStorage::new(&dir));
+        let http = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>hello</p>";
+        let digest = format!("{:x}", Sha256::digest(b"<p>hello</p>"));
+        let record = response_record("https://example.com/", Utc::now(), &digest, http);
+        let length = record.len() as i64;
+        storage
+            .put("a.warc", record.repeat(2).into())
+            .await
+            .unwrap();
+
+        // The second capture's recorded digest no longer matches its bytes;
+        // the revisit shares the first capture's bytes and is not checked
+        let (good, bad, revisit) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        for (id, offset, sha256, revisit_of) in [
+            (good, 0, digest.clone(), None),
+            (bad, length, "0".repeat(64), None),
+            (revisit, 0, digest.clone(), Some(good)),
+        ] {
+            sqlx::query(
+                r#"
+                INSERT INTO snapshots (id, url, warc_file, "offset", length, sha256, status_code, content_type, revisit_of)
+                VALUES ($1, 'https://example.com/', 'a.warc', $2, $3, $4, 200, 'text/html', $5)
+                "#,
+            )
+            .bind(id)
+            .bind(offset)
+            .bind(length)
+            .bind(sha256)
+            .bind(revisit_of)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let worker = FixityWorker::new(test_state(pool.clone(), storage), FixityConfig::from_env());
+        let (checked, failures) = worker.check_due().await.unwrap();
+        assert_eq!(checked, 2);
+        assert_eq!(failures.len(), 1);
+        assert_eq!(
+            (failures[0].snapshot_id, failures[0].outcome.as_str()),
+            (bad, "mismatch")
+        );
+        assert_eq!(failures[0].actual_sha256.as_deref(), Some(digest.as_str()));
+
+        // Both are checked now, so nothing is due until the recheck period
+        assert_eq!(worker.check_due().await.unwrap().0, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,1092 @@
+pub(crate) mod parse;
+
+use crate::export::{PROFILE_IDENTICAL_PAYLOAD, PROFILE_SERVER_NOT_MODIFIED};
+use crate::AppState;
+use archive_common::storage::ArchiveStorage;
+use axum::{
+    body::Body,
+    extract::{Json, Path, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use parse::{HttpResponse, RecordReader, SourceRecord};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use sqlx::{PgConnection, PgExecutor, PgPool, Postgres, Transaction};
+use std::collections::HashMap;
+use std::io::{Read, SeekFrom};
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use uuid::Uuid;
+
+/// Issues kept in a report; later ones are only counted
+const MAX_ISSUES: usize = 1000;
+/// Largest upload `POST /imports` accepts unless IMPORT_MAX_BYTES says otherwise
+pub const DEFAULT_MAX_BYTES: u64 = 4 << 30;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ImportIssue {
+    pub file: String,
+    /// Offset of the record in the decompressed source, when known
+    pub offset: Option<u64>,
+    pub message: String,
+}
+
+/// Outcome of one import
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct ImportReport {
+    pub files: usize,
+    pub records: usize,
+    /// Responses stored with a new payload
+    pub responses: usize,
+    /// Responses whose payload was already archived, stored as revisits
+    pub duplicates: usize,
+    /// Revisit records resolved to the capture they refer to
+    pub revisits: usize,
+    /// warcinfo, request, metadata and other non-capture records
+    pub skipped: usize,
+    pub errors: usize,
+    pub issues: Vec<ImportIssue>,
+}
+
+impl ImportReport {
+    fn issue(&mut self, file: &str, offset: Option<u64>, message: impl ToString) {
+        self.errors += 1;
+        if self.issues.len() < MAX_ISSUES {
+            self.issues.push(ImportIssue {
+                file: file.to_string(),
+                offset,
+                message: message.to_string(),
+            });
+        }
+    }
+}
+
+/// Location and metadata of an archived capture a revisit can point at
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub(crate) struct Original {
+    pub(crate) id: Uuid,
+    pub(crate) warc_file: String,
+    pub(crate) offset: i64,
+    pub(crate) length: i64,
+    pub(crate) sha256: String,
+    pub(crate) status_code: i16,
+    pub(crate) content_type: String,
+}
+
+/// A snapshot row about to be written
+pub(crate) struct NewSnapshot<'a> {
+    pub(crate) url: &'a str,
+    pub(crate) timestamp: DateTime<Utc>,
+    pub(crate) warc_file: &'a str,
+    pub(crate) offset: i64,
+    pub(crate) length: i64,
+    pub(crate) sha256: &'a str,
+    pub(crate) status_code: i16,
+    pub(crate) content_type: &'a str,
+    pub(crate) revisit_of: Option<Uuid>,
+    /// WARC-Profile of a revisit
+    pub(crate) revisit_profile: Option<&'a str>,
+}
+
+/// Earliest full capture of a payload already in `payloads`
+pub(crate) async fn archived_payload(
+    executor: impl PgExecutor<'_>,
+    sha256: &str,
+) -> anyhow::Result<Option<Original>> {
+    Ok(sqlx::query_as::<_, Original>(
+        r#"
+        SELECT s.id, s.warc_file, s.offset, s.length, s.sha256, s.status_code, s.content_type
+        FROM payloads p
+        JOIN snapshots s ON s.sha256 = p.hash AND s.revisit_of IS NULL
+        WHERE p.hash = $1
+        ORDER BY s.timestamp
+        LIMIT 1
+        "#,
+    )
+    .bind(sha256)
+    .fetch_optional(executor)
+    .await?)
+}
+
+/// Insert a snapshot tagged with `job_id` and queue it for indexing
+pub(crate) async fn insert_snapshot(
+    conn: &mut PgConnection,
+    snapshot: &NewSnapshot<'_>,
+    job_id: &str,
+) -> anyhow::Result<Uuid> {
+    let id = Uuid::new_v4();
+    sqlx::query(
+        r#"
+        INSERT INTO snapshots (id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type, payload_hash, revisit_of, revisit_profile, job_id)
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $7, $10, $11, $12)
+        "#,
+    )
+    .bind(id)
+    .bind(snapshot.url)
+    .bind(snapshot.timestamp)
+    .bind(snapshot.warc_file)
+    .bind(snapshot.offset)
+    .bind(snapshot.length)
+    .bind(snapshot.sha256)
+    .bind(snapshot.status_code)
+    .bind(snapshot.content_type)
+    .bind(snapshot.revisit_of)
+    .bind(snapshot.revisit_profile)
+    .bind(job_id)
+    .execute(&mut *conn)
+    .await?;
+
+    // The indexer only reads HTML
+    if snapshot.content_type.contains("html") {
+        sqlx::query("INSERT INTO index_queue (snapshot_id) VALUES ($1) ON CONFLICT DO NOTHING")
+            .bind(id)
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(id)
+}
+
+/// WARC/1.1 name of a source revisit's profile; WARC/1.0 names are upgraded
+fn revisit_profile(source: Option<&str>) -> Option<&str> {
+    let profile = source?;
+    if profile.ends_with("/revisit/identical-payload-digest") {
+        Some(PROFILE_IDENTICAL_PAYLOAD)
+    } else if profile.ends_with("/revisit/server-not-modified") {
+        Some(PROFILE_SERVER_NOT_MODIFIED)
+    } else {
+        Some(profile)
+    }
+}
+
+/// Re-serialize a record as WARC; ARC records become WARC/1.1 responses
+fn warc_bytes(record: &SourceRecord, sha256: &str) -> Vec<u8> {
+    if !record.header.starts_with(b"WARC/") {
+        return response_record(&record.url, record.date, sha256, &record.block);
+    }
+    let mut bytes = record.header.clone();
+    bytes.extend_from_slice(b"\r\n\r\n");
+    bytes.extend_from_slice(&record.block);
+    bytes.extend_from_slice(b"\r\n\r\n");
+    bytes
+}
+
+/// A WARC/1.1 response record around an HTTP message
+pub(crate) fn response_record(
+    url: &str,
+    date: DateTime<Utc>,
+    sha256: &str,
+    http: &[u8],
+) -> Vec<u8> {
+    let mut bytes = format!(
+        "WARC/1.1\r\n\
+        WARC-Type: response\r\n\
+        WARC-Record-ID: <urn:uuid:{}>\r\n\
+        WARC-Date: {}\r\n\
+        WARC-Target-URI: {}\r\n\
+        WARC-Payload-Digest: sha256:{}\r\n\
+        Content-Type: application/http; msgtype=response\r\n\
+        Content-Length: {}\r\n\r\n",
+        Uuid::new_v4(),
+        date.format("%Y-%m-%dT%H:%M:%SZ"),
+        url,
+        sha256,
+        http.len()
+    )
+    .into_bytes();
+    bytes.extend_from_slice(http);
+    bytes.extend_from_slice(b"\r\n\r\n");
+    bytes
+}
+
+/// Records handled per chunk of an import
+const CHUNK_RECORDS: usize = 1000;
+
+/// Part of an import that is committed on its own: a transaction and the
+/// WARC its new snapshots point into
+struct Chunk {
+    tx: Transaction<'static, Postgres>,
+    warc_name: String,
+    /// Local file the WARC is assembled in until it is stored
+    staging: PathBuf,
+    warc: tokio::fs::File,
+    warc_len: u64,
+    records: usize,
+}
+
+impl Chunk {
+    async fn open(pool: &PgPool, warc_name: String) -> anyhow::Result<Self> {
+        let staging = std::env::temp_dir().join(format!("archivestream-{}", warc_name));
+        let warc = tokio::fs::File::create(&staging).await?;
+        Ok(Self {
+            tx: pool.begin().await?,
+            warc_name,
+            staging,
+            warc,
+            warc_len: 0,
+            records: 0,
+        })
+    }
+
+    /// Append a record to the WARC; returns its offset
+    async fn append(&mut self, bytes: &[u8]) -> anyhow::Result<u64> {
+        let offset = self.warc_len;
+        self.warc_len += bytes.len() as u64;
+        self.warc.write_all(bytes).await?;
+        Ok(offset)
+    }
+
+    /// Cut the WARC back to `len`, dropping what a failed record wrote
+    async fn truncate(&mut self, len: u64) -> anyhow::Result<()> {
+        if self.warc_len > len {
+            self.warc.flush().await?;
+            self.warc.set_len(len).await?;
+            self.warc.seek(SeekFrom::Start(len)).await?;
+            self.warc_len = len;
+        }
+        Ok(())
+    }
+
+    /// Put the WARC in archive storage, then commit the snapshots that point
+    /// into it. Nothing is committed if storing fails
+    async fn store(self, storage: &dyn ArchiveStorage) -> anyhow::Result<()> {
+        let Chunk {
+            tx,
+            warc_name,
+            staging,
+            mut warc,
+            warc_len,
+            ..
+        } = self;
+        let stored = async {
+            warc.flush().await?;
+            if warc_len > 0 {
+                storage.put_file(&warc_name, &staging).await?;
+            }
+            tx.commit().await?;
+            anyhow::Ok(())
+        }
+        .await;
+        drop(warc);
+        let _ = tokio::fs::remove_file(&staging).await;
+        stored
+    }
+
+    /// Roll the chunk back and remove its WARC
+    async fn discard(self) {
+        let Chunk { tx, staging, .. } = self;
+        let _ = tx.rollback().await;
+        let _ = tokio::fs::remove_file(&staging).await;
+    }
+}
+
+/// Copies records from WARC, ARC and WACZ files into new WARCs in the archive
+/// and creates their snapshots. Records are handled in chunks of
+/// `CHUNK_RECORDS`; each chunk has its own transaction and WARC, and commits
+/// only once that WARC is in storage
+pub struct Importer {
+    pool: PgPool,
+    storage: Arc<dyn ArchiveStorage>,
+    pub id: Uuid,
+    /// Open chunk; after a commit the next record opens a new one
+    chunk: Option<Chunk>,
+    chunks: usize,
+    chunk_records: usize,
+    /// A chunk could not be stored; the import stops there
+    failed: bool,
+    report: ImportReport,
+    /// Captures stored by this import, by WARC-Record-ID, `(url, date)` and
+    /// source payload digest, so revisits within the import resolve without
+    /// the database
+    by_record_id: HashMap<String, Original>,
+    by_capture: HashMap<(String, DateTime<Utc>), Original>,
+    by_digest: HashMap<String, Original>,
+    by_sha256: HashMap<String, Original>,
+    /// Revisits whose original was not seen yet
+    pending: Vec<(String, SourceRecord)>,
+}
+
+impl Importer {
+    pub async fn new(
+        pool: &PgPool,
+        storage: Arc<dyn ArchiveStorage>,
+        id: Uuid,
+    ) -> anyhow::Result<Self> {
+        let mut importer = Self {
+            pool: pool.clone(),
+            storage,
+            id,
+            chunk: None,
+            chunks: 0,
+            chunk_records: CHUNK_RECORDS,
+            failed: false,
+            report: ImportReport::default(),
+            by_record_id: HashMap::new(),
+            by_capture: HashMap::new(),
+            by_digest: HashMap::new(),
+            by_sha256: HashMap::new(),
+            pending: Vec::new(),
+        };
+        importer.chunk().await?;
+        Ok(importer)
+    }
+
+    /// Crawl job id the imported snapshots are tagged with, so exports can select them
+    pub fn job_id(&self) -> String {
+        format!("import-{}", self.id.simple())
+    }
+
+    /// `import-<id>.warc` for the first chunk, `import-<id>-<n>.warc` after it
+    fn warc_name(&self, chunk: usize) -> String {
+        match chunk {
+            0 => format!("{}.warc", self.job_id()),
+            n => format!("{}-{}.warc", self.job_id(), n),
+        }
+    }
+
+    /// The open chunk, opening a new one if the last was committed
+    async fn chunk(&mut self) -> anyhow::Result<&mut Chunk> {
+        if self.chunk.is_none() {
+            let warc_name = self.warc_name(self.chunks);
+            self.chunk = Some(Chunk::open(&self.pool, warc_name).await?);
+            self.chunks += 1;
+        }
+        Ok(self.chunk.as_mut().expect("chunk was just opened"))
+    }
+
+    /// Store and commit the open chunk; if that fails the import stops
+    async fn commit(&mut self) {
+        if let Some(chunk) = self.chunk.take() {
+            let warc_name = chunk.warc_name.clone();
+            if let Err(e) = chunk.store(&*self.storage).await {
+                self.report.issue(&warc_name, None, e);
+                self.failed = true;
+            }
+        }
+    }
+
+    /// Import every record of a WARC, ARC or WACZ file
+    pub async fn import_file(&mut self, path: &FsPath) -> anyhow::Result<()> {
+        if self.failed {
+            return Ok(());
+        }
+        let name = path.display().to_string();
+        let mut head = [0u8; 4];
+        let read = std::fs::File::open(path)?.read(&mut head)?;
+
+        if parse::is_zip(&head[..read]) {
+            let mut archive = zip::ZipArchive::new(std::fs::File::open(path)?)?;
+            let entries: Vec<String> = archive
+                .file_names()
+                .filter(|n| n.starts_with("archive/"))
+                .map(String::from)
+                .collect();
+            for entry in entries {
+                let label = format!("{}!{}", name, entry);
+                // Zip entries are read fully: records are imported across awaits
+                let mut content = Vec::new();
+                if let Err(e) = archive.by_name(&entry)?.read_to_end(&mut content) {
+                    self.report.issue(&label, None, e);
+                    continue;
+                }
+                self.import_stream(&label, content.as_slice()).await;
+            }
+        } else {
+            self.import_stream(&name, std::fs::File::open(path)?).await;
+        }
+        Ok(())
+    }
+
+    async fn import_stream(&mut self, file: &str, reader: impl Read + Send) {
+        self.report.files += 1;
+        let (format, stream) = match parse::open(reader) {
+            Ok(opened) => opened,
+            Err(e) => return self.report.issue(file, None, e),
+        };
+        let mut records = RecordReader::new(stream, format);
+        while !self.failed {
+            match records.next_record() {
+                Ok(Some(record)) => {
+                    self.report.records += 1;
+                    let offset = record.offset;
+                    if let Err(e) = self.import_record(file, record).await {
+                        self.report.issue(file, Some(offset), e);
+                    }
+                    if self
+                        .chunk
+                        .as_ref()
+                        .is_some_and(|chunk| chunk.records >= self.chunk_records)
+                    {
+                        self.commit().await;
+                    }
+                }
+                Ok(None) => break,
+                Err(e) => {
+                    // Record boundaries are lost; the rest of the file is unreadable
+                    self.report.issue(file, None, e);
+                    break;
+                }
+            }
+        }
+    }
+
+    async fn import_record(&mut self, file: &str, record: SourceRecord) -> anyhow::Result<()> {
+        let start = self.savepoint().await?;
+        let result = match record.record_type.as_str() {
+            "response" => self.import_response(record).await,
+            "revisit" => match self.import_revisit(&record).await {
+                Ok(false) => {
+                    self.pending.push((file.to_string(), record));
+                    Ok(())
+                }
+                resolved => resolved.map(|_| ()),
+            },
+            _ => {
+                self.report.skipped += 1;
+                Ok(())
+            }
+        };
+        self.release(result, start).await
+    }
+
+    /// A record's statements run under a savepoint, so a failed record does
+    /// not abort the rest of its chunk's transaction. Returns the length of
+    /// the WARC before the record
+    async fn savepoint(&mut self) -> anyhow::Result<u64> {
+        let chunk = self.chunk().await?;
+        sqlx::query("SAVEPOINT import_record")
+            .execute(&mut *chunk.tx)
+            .await?;
+        chunk.records += 1;
+        Ok(chunk.warc_len)
+    }
+
+    /// End the record's savepoint. A failed record is rolled back and its
+    /// bytes are cut from the WARC; if they cannot be, the import stops
+    async fn release<T>(&mut self, result: anyhow::Result<T>, start: u64) -> anyhow::Result<T> {
+        let chunk = self.chunk().await?;
+        if result.is_ok() {
+            sqlx::query("RELEASE SAVEPOINT import_record")
+                .execute(&mut *chunk.tx)
+                .await?;
+            return result;
+        }
+        sqlx::query("ROLLBACK TO SAVEPOINT import_record")
+            .execute(&mut *chunk.tx)
+            .await?;
+        if let Err(e) = chunk.truncate(start).await {
+            self.failed = true;
+            return Err(e.context("failed record could not be removed from the staging WARC"));
+        }
+        result
+    }
+
+    async fn import_response(&mut self, record: SourceRecord) -> anyhow::Result<()> {
+        let Some(http) = HttpResponse::parse(&record.block) else {
+            // dns:, whois: and similar captures have no HTTP message
+            self.report.skipped += 1;
+            return Ok(());
+        };
+        let sha256 = format!("{:x}", Sha256::digest(http.body));
+        let (status_code, content_type) = (http.status_code, http.content_type);
+
+        let original = match self.by_sha256.get(&sha256) {
+            Some(original) => Some(original.clone()),
+            None => self.archived_payload(&sha256).await?,
+        };
+        // Revisits of a duplicate point at the capture that holds the payload
+        let stored = match original {
+            Some(original) => {
+                self.insert_snapshot(&NewSnapshot {
+                    url: &record.url,
+                    timestamp: record.date,
+                    warc_file: &original.warc_file,
+                    offset: original.offset,
+                    length: original.length,
+                    sha256: &sha256,
+                    status_code,
+                    content_type: &content_type,
+                    revisit_of: Some(original.id),
+                    revisit_profile: Some(PROFILE_IDENTICAL_PAYLOAD),
+                })
+                .await?;
+                self.report.duplicates += 1;
+                original
+            }
+            None => {
+                let bytes = warc_bytes(&record, &sha256);
+                let chunk = self.chunk().await?;
+                let offset = chunk.append(&bytes).await?;
+                let warc_name = chunk.warc_name.clone();
+                sqlx::query(
+                    "INSERT INTO payloads (hash, warc_path, warc_offset, size) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+                )
+                .bind(&sha256)
+                .bind(&warc_name)
+                .bind(offset as i64)
+                .bind(http.body.len() as i64)
+                .execute(&mut *chunk.tx)
+                .await?;
+                let original = Original {
+                    id: Uuid::nil(),
+                    warc_file: warc_name,
+                    offset: offset as i64,
+                    length: bytes.len() as i64,
+                    sha256: sha256.clone(),
+                    status_code,
+                    content_type,
+                };
+                let id = self
+                    .insert_snapshot(&NewSnapshot {
+                        url: &record.url,
+                        timestamp: record.date,
+                        warc_file: &original.warc_file,
+                        offset: original.offset,
+                        length: original.length,
+                        sha256: &sha256,
+                        status_code,
+                        content_type: &original.content_type,
+                        revisit_of: None,
+                        revisit_profile: None,
+                    })
+                    .await?;
+                self.report.responses += 1;
+                let original = Original { id, ..original };
+                self.by_sha256.insert(sha256, original.clone());
+                original
+            }
+        };
+
+        if let Some(record_id) = &record.record_id {
+            self.by_record_id.insert(record_id.clone(), stored.clone());
+        }
+        if let Some(digest) = &record.payload_digest {
+            self.by_digest.insert(digest.clone(), stored.clone());
+        }
+        self.by_capture.insert((record.url, record.date), stored);
+        Ok(())
+    }
+
+    async fn archived_payload(&mut self, sha256: &str) -> anyhow::Result<Option<Original>> {
+        archived_payload(&mut *self.chunk().await?.tx, sha256).await
+    }
+
+    /// Store a revisit pointing at its original; `false` if the original is unknown
+    async fn import_revisit(&mut self, record: &SourceRecord) -> anyhow::Result<bool> {
+        let target_url = record.refers_to_url.as_deref().unwrap_or(&record.url);
+        let mut original = record
+            .refers_to
+            .as_ref()
+            .and_then(|id| self.by_record_id.get(id))
+            .or_else(|| {
+                let date = record.refers_to_date?;
+                self.by_capture.get(&(target_url.to_string(), date))
+            })
+            .or_else(|| {
+                record
+                    .payload_digest
+                    .as_ref()
+                    .and_then(|d| self.by_digest.get(d))
+            })
+            .cloned();
+
+        if original.is_none() {
+            if let Some(date) = record.refers_to_date {
+                original = sqlx::query_as::<_, Original>(
+                    r#"
+                    SELECT id, warc_file, "offset", length, sha256, status_code, content_type
+                    FROM snapshots
+                    WHERE url = $1 AND timestamp = $2 AND revisit_of IS NULL
+                    LIMIT 1
+                    "#,
+                )
+                .bind(target_url)
+                .bind(date)
+                .fetch_optional(&mut *self.chunk().await?.tx)
+                .await?;
+            }
+        }
+        if original.is_none() {
+            // Our own digests are hex SHA-256
+            let sha256 = record
+                .payload_digest
+                .as_deref()
+                .and_then(|d| d.strip_prefix("sha256:"))
+                .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()));
+            if let Some(sha256) = sha256 {
+                original = self.archived_payload(&sha256.to_ascii_lowercase()).await?;
+            }
+        }
+        let Some(original) = original else {
+            return Ok(false);
+        };
+
+        // The revisit's own headers carry its status; fall back to the original's
+        let http = HttpResponse::parse(&record.block);
+        self.insert_snapshot(&NewSnapshot {
+            url: &record.url,
+            timestamp: record.date,
+            warc_file: &original.warc_file,
+            offset: original.offset,
+            length: original.length,
+            sha256: &original.sha256,
+            status_code: http
+                .as_ref()
+                .map_or(original.status_code, |h| h.status_code),
+            content_type: &original.content_type,
+            revisit_of: Some(original.id),
+            revisit_profile: revisit_profile(record.profile.as_deref()),
+        })
+        .await?;
+        self.report.revisits += 1;
+        Ok(true)
+    }
+
+    async fn insert_snapshot(&mut self, snapshot: &NewSnapshot<'_>) -> anyhow::Result<Uuid> {
+        let job_id = self.job_id();
+        insert_snapshot(&mut self.chunk().await?.tx, snapshot, &job_id).await
+    }
+
+    /// Resolve revisits that preceded their original, then store and commit
+    /// the last chunk; returns the import's status
+    pub async fn finish(mut self) -> (&'static str, ImportReport) {
+        for (file, record) in std::mem::take(&mut self.pending) {
+            if self.failed {
+                break;
+            }
+            let resolved = match self.savepoint().await {
+                Ok(start) => {
+                    let resolved = self.import_revisit(&record).await;
+                    self.release(resolved, start).await
+                }
+                Err(e) => Err(e),
+            };
+            match resolved {
+                Ok(true) => {}
+                Ok(false) => self.report.issue(
+                    &file,
+                    Some(record.offset),
+                    format!(
+                        "revisit of {} refers to a capture that is not archived",
+                        record.url
+                    ),
+                ),
+                Err(e) => self.report.issue(&file, Some(record.offset), e),
+            }
+        }
+
+        if self.failed {
+            if let Some(chunk) = self.chunk.take() {
+                chunk.discard().await;
+            }
+            return ("failed", self.report);
+        }
+        self.commit().await;
+        let status = if self.failed { "failed" } else { "completed" };
+        (status, self.report)
+    }
+}
+
+/// Import `paths` as one import, recording its progress and report in `imports`
+pub async fn run(
+    pool: PgPool,
+    storage: Arc<dyn ArchiveStorage>,
+    id: Uuid,
+    source: &str,
+    paths: &[PathBuf],
+) -> ImportReport {
+    let started =
+        sqlx::query("INSERT INTO imports (id, source, status) VALUES ($1, $2, 'running')")
+            .bind(id)
+            .bind(source)
+            .execute(&pool)
+            .await;
+    if let Err(e) = started {
+        tracing::error!("Import {} could not be recorded: {}", id, e);
+    }
+
+    let (status, report) = match Importer::new(&pool, storage, id).await {
+        Ok(mut importer) => {
+            for path in paths {
+                if let Err(e) = importer.import_file(path).await {
+                    importer.report.issue(&path.display().to_string(), None, e);
+                }
+            }
+            importer.finish().await
+        }
+        Err(e) => {
+            let mut report = ImportReport::default();
+            report.issue(source, None, e);
+            ("failed", report)
+        }
+    };
+
+    let finished = sqlx::query(
+        "UPDATE imports SET status = $2, report = $3::jsonb, finished_at = NOW() WHERE id = $1",
+    )
+    .bind(id)
+    .bind(status)
+    .bind(serde_json::to_string(&report).unwrap_or_default())
+    .execute(&pool)
+    .await;
+    if let Err(e) = finished {
+        tracing::error!("Import {} report could not be saved: {}", id, e);
+    }
+    report
+}
+
+#[derive(Deserialize)]
+pub struct ImportUpload {
+    /// Original file name, recorded as the import source
+    pub filename: Option<String>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct ImportStatus {
+    pub id: Uuid,
+    pub source: String,
+    /// `running`, `completed` or `failed`
+    pub status: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    #[serde(skip)]
+    report_json: Option<String>,
+    #[sqlx(skip)]
+    pub report: Option<ImportReport>,
+}
+
+/// POST /api/v1/imports?filename=
+/// Accepts a WARC, ARC or WACZ file as the request body and imports it in the
+/// background; poll `GET /api/v1/imports/:id` for the report
+pub async fn create_import(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ImportUpload>,
+    body: Body,
+) -> impl IntoResponse {
+    let id = Uuid::new_v4();
+    let path = std::env::temp_dir().join(format!("archivestream-import-{}", id.simple()));
+
+    let max_bytes = state.config.import_max_bytes;
+    let upload = async {
+        let mut file = tokio::fs::File::create(&path).await?;
+        let mut stream = body.into_data_stream();
+        let mut written = 0u64;
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk?;
+            written += chunk.len() as u64;
+            if written > max_bytes {
+                return Ok(false);
+            }
+            file.write_all(&chunk).await?;
+        }
+        file.flush().await?;
+        anyhow::Ok(true)
+    };
+    match upload.await {
+        Ok(true) => {}
+        Ok(false) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            return (
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!("Upload exceeds {} bytes", max_bytes),
+            )
+                .into_response();
+        }
+        Err(e) => {
+            tracing::error!("Import upload error: {}", e);
+            let _ = tokio::fs::remove_file(&path).await;
+            return (StatusCode::BAD_REQUEST, "Upload failed").into_response();
+        }
+    }
+
+    let source = params.filename.unwrap_or_else(|| "upload".to_string());
+    let (pool, storage) = (state.pool.clone(), state.storage.clone());
+    tokio::spawn(async move {
+        let report = run(pool, storage, id, &source, std::slice::from_ref(&path)).await;
+        tracing::info!(
+            "Import {} finished: {} records, {} errors",
+            id,
+            report.records,
+            report.errors
+        );
+        let _ = tokio::fs::remove_file(&path).await;
+    });
+
+    (
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({"id": id, "status": "running"})),
+    )
+        .into_response()
+}
+
+/// GET /api/v1/imports/:id
+pub async fn get_import(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    let result = sqlx::query_as::<_, ImportStatus>(
+        "SELECT id, source, status, started_at, finished_at, report::text AS report_json FROM imports WHERE id = $1",
+    )
+    .bind(id)
+    .fetch_optional(&state.pool)
+    .await;
+
+    match result {
+        Ok(Some(mut import)) => {
+            import.report = import
+                .report_json
+                .take()
+                .and_then(|r| serde_json::from_str(&r).ok());
+            Json(import).into_response()
+        }
+        Ok(None) => (StatusCode::NOT_FOUND, "Import not found").into_response(),
+        Err(e) => {
+            tracing::error!("Import status error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error fetching import").into_response()
+        }
+    }
+}
+
+/// `archive-api import <file>...` imports files in one run and prints the report
+pub async fn cli(
+    pool: PgPool,
+    storage: Arc<dyn ArchiveStorage>,
+    args: &[String],
+) -> anyhow::Result<()> {
+    if args.is_empty() {
+        anyhow::bail!("usage: archive-api import <file.warc[.gz]|file.arc[.gz]|file.wacz>...");
+    }
+    let paths: Vec<PathBuf> = args.iter().map(PathBuf::from).collect();
+    let id = Uuid::new_v4();
+    let report = run(pool, storage, id, &args.join(" "), &paths).await;
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&serde_json::json!({"id": id, "report": report}))?
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archive_common::storage::FsStorage;
+    use archive_common::testing::scratch_pool;
+
+    async fn import_status(pool: &PgPool, id: Uuid) -> String {
+        sqlx::query_scalar("SELECT status FROM imports WHERE id = $1")
+            .bind(id)
+            .fetch_one(pool)
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_are_committed_only_once_the_warc_is_stored() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let dir = std::env::temp_dir().join(format!("archivestream-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let date = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let http = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>hi</p>";
+        let sha256 = format!("{:x}", Sha256::digest(b"<p>hi</p>"));
+        let mut warc = response_record("https://example.com/a", date, &sha256, http);
+        warc.extend(response_record(
+            "https://example.com/b",
+            date,
+            &sha256,
+            http,
+        ));
+        let first = dir.join("first.warc");
+        std::fs::write(&first, &warc).unwrap();
+
+        // Storage rooted at a regular file cannot take the WARC
+        let broken = Arc::new(FsStorage::new(&first));
+        let id = Uuid::new_v4();
+        run(
+            pool.clone(),
+            broken,
+            id,
+            "first.warc",
+            std::slice::from_ref(&first),
+        )
+        .await;
+        assert_eq!(import_status(&pool, id).await, "failed");
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM snapshots")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(count, 0);
+
+        let storage = Arc::new(FsStorage::new(dir.join("archive")));
+        let id = Uuid::new_v4();
+        let report = run(pool.clone(), storage.clone(), id, "first.warc", &[first]).await;
+        assert_eq!(import_status(&pool, id).await, "completed");
+        assert_eq!((report.responses, report.duplicates), (1, 1));
+        let stored = storage
+            .get_range(&format!("import-{}.warc", id.simple()), 0, 9)
+            .await
+            .unwrap();
+        assert_eq!(&stored[..], b"WARC/1.1\r");
+
+        // A second import's revisit resolves to the first import's capture
+        // through the database, by URL and date alone
+        let head = b"HTTP/1.1 304 Not Modified\r\n\r\n";
+        let mut revisit = format!(
+            "WARC/1.0\r\n\
+            WARC-Type: revisit\r\n\
+            WARC-Date: 2023-11-15T00:00:00Z\r\n\
+            WARC-Target-URI: https://example.com/a\r\n\
+            WARC-Profile: http://netpreserve.org/warc/1.0/revisit/server-not-modified\r\n\
+            WARC-Refers-To-Target-URI: https://example.com/a\r\n\
+            WARC-Refers-To-Date: {}\r\n\
+            Content-Length: {}\r\n\r\n",
+            date.format("%Y-%m-%dT%H:%M:%SZ"),
+            head.len()
+        )
+        .into_bytes();
+        revisit.extend_from_slice(head);
+        revisit.extend_from_slice(b"\r\n\r\n");
+        let second = dir.join("second.warc");
+        std::fs::write(&second, &revisit).unwrap();
+        let report = run(
+            pool.clone(),
+            storage,
+            Uuid::new_v4(),
+            "second.warc",
+            &[second],
+        )
+        .await;
+        assert_eq!((report.revisits, report.errors), (1, 0));
+
+        let rows: Vec<(String, i16, Option<String>, bool)> = sqlx::query_as(
+            r#"
+            SELECT url, status_code, revisit_profile, revisit_of IS NOT NULL
+            FROM snapshots ORDER BY timestamp, url
+            "#,
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let profile = |p: &str| Some(p.to_string());
+        assert_eq!(
+            rows,
+            vec![
+                ("https://example.com/a".to_string(), 200, None, false),
+                (
+                    "https://example.com/b".to_string(),
+                    200,
+                    profile(PROFILE_IDENTICAL_PAYLOAD),
+                    true
+                ),
+                (
+                    "https://example.com/a".to_string(),
+                    304,
+                    profile(PROFILE_SERVER_NOT_MODIFIED),
+                    true
+                ),
+            ]
+        );
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_chunks_commit_with_their_own_warc_and_drop_failed_records() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let dir = std::env::temp_dir().join(format!("archivestream-test-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let date = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let record = |url: &str, body: &str| {
+            let http = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: text/plain\r\n\r\n{}",
+                body
+            );
+            let sha256 = format!("{:x}", Sha256::digest(body));
+            response_record(url, date, &sha256, http.as_bytes())
+        };
+        let first = record("https://example.com/a", "a");
+        // Postgres text cannot hold NUL, so this snapshot's insert fails
+        // after its bytes were written
+        let broken = record("https://example.com/\0", "b");
+        let last = record("https://example.com/c", "c");
+        let source = dir.join("source.warc");
+        std::fs::write(&source, [first.clone(), broken, last.clone()].concat()).unwrap();
+
+        let storage = Arc::new(FsStorage::new(dir.join("archive")));
+        let id = Uuid::new_v4();
+        let mut importer = Importer::new(&pool, storage.clone(), id).await.unwrap();
+        importer.chunk_records = 2;
+        importer.import_file(&source).await.unwrap();
+        let (status, report) = importer.finish().await;
+        assert_eq!(status, "completed");
+        assert_eq!((report.responses, report.errors), (2, 1));
+
+        let rows: Vec<(String, String, i64)> =
+            sqlx::query_as(r#"SELECT url, warc_file, "offset" FROM snapshots ORDER BY url"#)
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        let (chunk0, chunk1) = (
+            format!("import-{}.warc", id.simple()),
+            format!("import-{}-1.warc", id.simple()),
+        );
+        assert_eq!(
+            rows,
+            vec![
+                ("https://example.com/a".to_string(), chunk0.clone(), 0),
+                ("https://example.com/c".to_string(), chunk1.clone(), 0),
+            ]
+        );
+        // The failed record's bytes were cut from the first chunk's WARC
+        let archive = dir.join("archive");
+        assert_eq!(std::fs::read(archive.join(&chunk0)).unwrap(), first);
+        assert_eq!(std::fs::read(archive.join(&chunk1)).unwrap(), last);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn test_uploads_over_the_limit_are_refused_and_removed() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let dir = std::env::temp_dir().join(format!("archivestream-test-{}", Uuid::new_v4()));
+        let storage = Arc::new(FsStorage::new(dir.clone()));
+        let mut state = Arc::into_inner(crate::tests::test_state(pool.clone(), storage)).unwrap();
+        state.config.import_max_bytes = 16;
+        let state = Arc::new(state);
+        // Uploads are spooled to archivestream-import-<id>, which unlike
+        // chunk staging files has no extension
+        let uploads = || {
+            std::fs::read_dir(std::env::temp_dir())
+                .unwrap()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    let name = entry.file_name().to_string_lossy().into_owned();
+                    name.starts_with("archivestream-import-") && !name.contains('.')
+                })
+                .count()
+        };
+        let before = uploads();
+
+        let response = create_import(
+            State(state),
+            Query(ImportUpload { filename: None }),
+            Body::from(vec![b'x'; 64]),
+        )
+        .await
+        .into_response();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(uploads(), before);
+        let imports: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM imports")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(imports, 0);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,365 @@
+use anyhow::{anyhow, bail, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use flate2::read::MultiGzDecoder;
+use std::io::{BufRead, BufReader, Read};
+
+/// Record formats the importer understands; WACZ packages hold either
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Warc,
+    Arc,
+}
+
+/// True for a zip container such as a WACZ package
+pub fn is_zip(head: &[u8]) -> bool {
+    head.starts_with(b"PK\x03\x04")
+}
+
+/// One archived record, normalized from WARC or ARC
+#[derive(Debug, Clone)]
+pub struct SourceRecord {
+    /// Position of the record in the decompressed source file
+    pub offset: u64,
+    pub record_type: String,
+    pub record_id: Option<String>,
+    pub url: String,
+    pub date: DateTime<Utc>,
+    /// `WARC-Payload-Digest` as written by the source (any algorithm)
+    pub payload_digest: Option<String>,
+    /// `WARC-Profile` of a revisit
+    pub profile: Option<String>,
+    pub refers_to: Option<String>,
+    pub refers_to_url: Option<String>,
+    pub refers_to_date: Option<DateTime<Utc>>,
+    /// Original header lines, without the trailing blank line
+    pub header: Vec<u8>,
+    pub block: Vec<u8>,
+}
+
+/// Status, content type and body of an HTTP response block
+pub struct HttpResponse<'a> {
+    pub status_code: i16,
+    pub content_type: String,
+    pub body: &'a [u8],
+}
+
+impl<'a> HttpResponse<'a> {
+    pub fn parse(block: &'a [u8]) -> Option<Self> {
+        // ARC files often use bare line feeds
+        let end = block
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .map(|end| (end, end + 4))
+            .or_else(|| {
+                block
+                    .windows(2)
+                    .position(|w| w == b"\n\n")
+                    .map(|end| (end, end + 2))
+            });
+        let (head, body) = match end {
+            Some((end, start)) => (&block[..end], &block[start..]),
+            // Headers only, e.g. the block of a revisit record
+            None => (block, &block[block.len()..]),
+        };
+        let head = String::from_utf8_lossy(head);
+        let mut lines = head.lines();
+        let status_code = lines
+            .next()?
+            .strip_prefix("HTTP/")?
+            .split_whitespace()
+            .nth(1)?
+            .parse()
+            .ok()?;
+        let content_type = lines
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-type"))
+            .map(|(_, value)| value.trim().to_string())
+            .unwrap_or_else(|| "application/octet-stream".to_string());
+        Some(Self {
+            status_code,
+            content_type,
+            body,
+        })
+    }
+}
+
+/// Decompress gzip transparently (record and whole-file compression both
+/// work) and detect the record format from the first bytes
+pub fn open<'a>(reader: impl Read + Send + 'a) -> Result<(Format, Box<dyn BufRead + Send + 'a>)> {
+    let mut reader = BufReader::new(reader);
+    let gzipped = reader.fill_buf()?.starts_with(&[0x1f, 0x8b]);
+    let mut reader: Box<dyn BufRead + Send + 'a> = if gzipped {
+        Box::new(BufReader::new(MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    };
+    let head = reader.fill_buf()?;
+    let format = if head.starts_with(b"WARC/") {
+        Format::Warc
+    } else if head.starts_with(b"filedesc://") {
+        Format::Arc
+    } else {
+        bail!("not a WARC or ARC file");
+    };
+    Ok((format, reader))
+}
+
+fn warc_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+fn arc_date(value: &str) -> Option<DateTime<Utc>> {
+    let digits = value.get(..14)?;
+    NaiveDateTime::parse_from_str(digits, "%Y%m%d%H%M%S")
+        .ok()
+        .map(|d| d.and_utc())
+}
+
+fn strip_angles(value: &str) -> String {
+    value
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>')
+        .to_string()
+}
+
+/// Reads records one at a time from a decompressed WARC or ARC stream
+pub struct RecordReader<R: BufRead> {
+    reader: R,
+    format: Format,
+    offset: u64,
+}
+
+impl<R: BufRead> RecordReader<R> {
+    pub fn new(reader: R, format: Format) -> Self {
+        Self {
+            reader,
+            format,
+            offset: 0,
+        }
+    }
+
+    fn read_line(&mut self, line: &mut Vec<u8>) -> Result<usize> {
+        line.clear();
+        let n = self.reader.read_until(b'\n', line)?;
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    fn read_block(&mut self, length: usize) -> Result<Vec<u8>> {
+        let mut block = vec![0u8; length];
+        self.reader
+            .read_exact(&mut block)
+            .map_err(|e| anyhow!("record block truncated: {}", e))?;
+        self.offset += length as u64;
+        Ok(block)
+    }
+
+    /// Skip blank lines between records; `false` at end of input
+    fn next_line(&mut self, line: &mut Vec<u8>) -> Result<bool> {
+        loop {
+            if self.read_line(line)? == 0 {
+                return Ok(false);
+            }
+            if !line.iter().all(u8::is_ascii_whitespace) {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// The next record; `Ok(None)` at end of input. A malformed record is an
+    /// error after which the stream cannot be resynchronized.
+    pub fn next_record(&mut self) -> Result<Option<SourceRecord>> {
+        match self.format {
+            Format::Warc => self.next_warc(),
+            Format::Arc => self.next_arc(),
+        }
+    }
+
+    fn next_warc(&mut self) -> Result<Option<SourceRecord>> {
+        let mut line = Vec::new();
+        if !self.next_line(&mut line)? {
+            return Ok(None);
+        }
+        let offset = self.offset - line.len() as u64;
+        if !line.starts_with(b"WARC/") {
+            bail!("expected a WARC version line at offset {}", offset);
+        }
+
+        let mut header = line.clone();
+        let mut fields = Vec::new();
+        loop {
+            if self.read_line(&mut line)? == 0 {
+                bail!("WARC header truncated at offset {}", offset);
+            }
+            if line == b"\r\n" || line == b"\n" {
+                break;
+            }
+            header.extend_from_slice(&line);
+            let text = String::from_utf8_lossy(&line);
+            if let Some((name, value)) = text.split_once(':') {
+                fields.push((name.trim().to_ascii_lowercase(), value.trim().to_string()));
+            }
+        }
+        while header.ends_with(b"\n") || header.ends_with(b"\r") {
+            header.pop();
+        }
+        let field = |name: &str| {
+            fields
+                .iter()
+                .find(|(n, _)| n == name)
+                .map(|(_, v)| v.clone())
+        };
+
+        let length = field("content-length")
+            .and_then(|v| v.parse::<usize>().ok())
+            .ok_or_else(|| anyhow!("WARC record at offset {} has no Content-Length", offset))?;
+        let block = self.read_block(length)?;
+
+        Ok(Some(SourceRecord {
+            offset,
+            record_type: field("warc-type").unwrap_or_default().to_ascii_lowercase(),
+            record_id: field("warc-record-id").map(|v| strip_angles(&v)),
+            url: field("warc-target-uri")
+                .map(|v| strip_angles(&v))
+                .unwrap_or_default(),
+            date: field("warc-date")
+                .and_then(|v| warc_date(&v))
+                .ok_or_else(|| {
+                    anyhow!("WARC record at offset {} has no valid WARC-Date", offset)
+                })?,
+            payload_digest: field("warc-payload-digest"),
+            profile: field("warc-profile"),
+            refers_to: field("warc-refers-to").map(|v| strip_angles(&v)),
+            refers_to_url: field("warc-refers-to-target-uri").map(|v| strip_angles(&v)),
+            refers_to_date: field("warc-refers-to-date").and_then(|v| warc_date(&v)),
+            header,
+            block,
+        }))
+    }
+
+    /// ARC v1 and v2 header lines both start with the URL, IP and date and end
+    /// with the record length
+    fn next_arc(&mut self) -> Result<Option<SourceRecord>> {
+        let mut line = Vec::new();
+        if !self.next_line(&mut line)? {
+            return Ok(None);
+        }
+        let offset = self.offset - line.len() as u64;
+        let text = String::from_utf8_lossy(&line).trim_end().to_string();
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        if parts.len() < 5 {
+            bail!("malformed ARC header at offset {}", offset);
+        }
+        let length = parts[parts.len() - 1]
+            .parse::<usize>()
+            .map_err(|_| anyhow!("malformed ARC length at offset {}", offset))?;
+        let block = self.read_block(length)?;
+
+        let record_type = if parts[0].starts_with("filedesc://") {
+            "warcinfo"
+        } else if parts[0].starts_with("http://") || parts[0].starts_with("https://") {
+            "response"
+        } else {
+            "resource"
+        };
+        Ok(Some(SourceRecord {
+            offset,
+            record_type: record_type.to_string(),
+            record_id: None,
+            url: parts[0].to_string(),
+            date: arc_date(parts[2])
+                .ok_or_else(|| anyhow!("malformed ARC date at offset {}", offset))?,
+            payload_digest: None,
+            profile: None,
+            refers_to: None,
+            refers_to_url: None,
+            refers_to_date: None,
+            header: text.into_bytes(),
+            block,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use std::io::Write;
+
+    fn warc(kind: &str, id: &str, extra: &str, block: &str) -> String {
+        format!(
+            "WARC/1.0\r\nWARC-Type: {}\r\nWARC-Record-ID: <urn:uuid:{}>\r\nWARC-Date: 2024-03-15T12:00:00Z\r\nWARC-Target-URI: https://example.com/\r\n{}Content-Length: {}\r\n\r\n{}\r\n\r\n",
+            kind,
+            id,
+            extra,
+            block.len(),
+            block
+        )
+    }
+
+    #[test]
+    fn test_reads_gzipped_warc_members_in_order() {
+        let http = "HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>hi</p>";
+        let mut file = Vec::new();
+        for record in [
+            warc("response", "a", "", http),
+            warc(
+                "revisit",
+                "b",
+                "WARC-Refers-To: <urn:uuid:a>\r\n",
+                "HTTP/1.1 200 OK\r\n\r\n",
+            ),
+        ] {
+            let mut gz = GzEncoder::new(Vec::new(), Compression::default());
+            gz.write_all(record.as_bytes()).unwrap();
+            file.extend(gz.finish().unwrap());
+        }
+        let (format, stream) = open(file.as_slice()).unwrap();
+        assert_eq!(format, Format::Warc);
+
+        let mut reader = RecordReader::new(stream, format);
+        let response = reader.next_record().unwrap().unwrap();
+        assert_eq!(response.record_type, "response");
+        assert_eq!(response.record_id.as_deref(), Some("urn:uuid:a"));
+        let parsed = HttpResponse::parse(&response.block).unwrap();
+        assert_eq!(parsed.status_code, 200);
+        assert_eq!(parsed.content_type, "text/html");
+        assert_eq!(parsed.body, b"<p>hi</p>");
+
+        let revisit = reader.next_record().unwrap().unwrap();
+        assert_eq!(revisit.refers_to.as_deref(), Some("urn:uuid:a"));
+        assert_eq!(revisit.offset, warc("response", "a", "", http).len() as u64);
+        assert!(reader.next_record().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_reads_arc_records() {
+        let http = "HTTP/1.0 404 Not Found\nContent-Type: text/plain\n\nnope";
+        let file = format!(
+            "filedesc://old.arc 0.0.0.0 20010101000000 text/plain 3\nabc\n\nhttp://example.com/x 1.2.3.4 20010203040506 text/plain {}\n{}\n",
+            http.len(),
+            http
+        );
+        let (format, stream) = open(file.as_bytes()).unwrap();
+        assert_eq!(format, Format::Arc);
+
+        let mut reader = RecordReader::new(stream, format);
+        assert_eq!(
+            reader.next_record().unwrap().unwrap().record_type,
+            "warcinfo"
+        );
+        let record = reader.next_record().unwrap().unwrap();
+        assert_eq!(record.url, "http://example.com/x");
+        assert_eq!(
+            record.date.format("%Y%m%d%H%M%S").to_string(),
+            "20010203040506"
+        );
+        assert_eq!(record.block, http.as_bytes());
+        let parsed = HttpResponse::parse(&record.block).unwrap();
+        assert_eq!((parsed.status_code, parsed.body), (404, &b"nope"[..]));
+        assert!(reader.next_record().unwrap().is_none());
+    }
+}
@@ -0,0 +1,203 @@
+use crate::AppState;
+use anyhow::Result;
+use archive_common::storage::IpfsArchiveStorage;
+use archive_ipfs::{IpfsObject, SnapshotManifest};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// IPFS settings, present when `ARCHIVE_STORAGE=ipfs`
+#[derive(Clone)]
+pub struct IpfsConfig {
+    pub storage: Arc<IpfsArchiveStorage>,
+    pub scan_interval: Duration,
+    pub publish_interval: Duration,
+    /// Kubo key the manifest is published under; the node's own when unset
+    pub ipns_key: Option<String>,
+}
+
+impl IpfsConfig {
+    pub fn from_env() -> Result<Option<Self>> {
+        if std::env::var("ARCHIVE_STORAGE").as_deref() != Ok("ipfs") {
+            return Ok(None);
+        }
+        let secs = |name: &str, default: u64| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+        Ok(Some(Self {
+            storage: Arc::new(IpfsArchiveStorage::from_env()?),
+            scan_interval: Duration::from_secs(secs("IPFS_SCAN_INTERVAL_SECS", 300)),
+            publish_interval: Duration::from_secs(secs("IPFS_PUBLISH_INTERVAL_SECS", 3600)),
+            ipns_key: std::env::var("IPFS_IPNS_KEY")
+                .ok()
+                .filter(|k| !k.is_empty()),
+        }))
+    }
+}
+
+/// Records the CIDs of WARC files in IPFS storage on their snapshots and
+/// periodically publishes a manifest of them to IPNS
+pub struct IpfsWorker {
+    state: Arc<AppState>,
+    config: IpfsConfig,
+}
+
+impl IpfsWorker {
+    pub fn new(state: Arc<AppState>, config: IpfsConfig) -> Self {
+        Self { state, config }
+    }
+
+    pub async fn run_loop(&self) {
+        let mut scan = tokio::time::interval(self.config.scan_interval);
+        let mut publish = tokio::time::interval(self.config.publish_interval);
+        // Let the first scan land before the first publication
+        publish.tick().await;
+        loop {
+            tokio::select! {
+                _ = scan.tick() => match self.record_cids().await {
+                    Ok(0) => {}
+                    Ok(count) => tracing::info!("Recorded CIDs of {} WARC files", count),
+                    Err(e) => tracing::error!("IPFS scan failed: {}", e),
+                },
+                _ = publish.tick() => if let Err(e) = self.publish().await {
+                    tracing::error!("IPNS publication failed: {}", e);
+                },
+            }
+        }
+    }
+
+    /// Record objects that are new or were replaced since the last scan
+    async fn record_cids(&self) -> Result<usize> {
+        let mut recorded = 0;
+        for object in self.config.storage.objects().await? {
+            let known: Option<String> =
+                sqlx::query_scalar("SELECT cid FROM ipfs_objects WHERE warc_file = $1")
+                    .bind(&object.name)
+                    .fetch_optional(&self.state.pool)
+                    .await?;
+            if known.as_deref() == Some(object.cid.as_str()) {
+                continue;
+            }
+
+            let mut tx = self.state.pool.begin().await?;
+            sqlx::query(
+                r#"
+                INSERT INTO ipfs_objects (warc_file, cid, size, uploaded_at)
+                VALUES ($1, $2, $3, NOW())
+                ON CONFLICT (warc_file) DO UPDATE
+                SET cid = EXCLUDED.cid, size = EXCLUDED.size, uploaded_at = NOW()
+                "#,
+            )
+            .bind(&object.name)
+            .bind(&object.cid)
+            .bind(object.size as i64)
+            .execute(&mut *tx)
+            .await?;
+            sqlx::query("UPDATE snapshots SET ipfs_cid = $2 WHERE warc_file = $1")
+                .bind(&object.name)
+                .bind(&object.cid)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+            recorded += 1;
+        }
+        Ok(recorded)
+    }
+
+    async fn publish(&self) -> Result<()> {
+        let files: Vec<(String, String, i64)> =
+            sqlx::query_as("SELECT warc_file, cid, size FROM ipfs_objects ORDER BY warc_file")
+                .fetch_all(&self.state.pool)
+                .await?;
+        if files.is_empty() {
+            return Ok(());
+        }
+
+        let manifest = SnapshotManifest {
+            version: "1".to_string(),
+            snapshots: Vec::new(),
+            files: files
+                .into_iter()
+                .map(|(name, cid, size)| IpfsObject {
+                    name,
+                    cid,
+                    size: size as u64,
+                })
+                .collect(),
+            created_at: Utc::now().to_rfc3339(),
+        };
+        let publication = self
+            .config
+            .storage
+            .client()
+            .publish_manifest(&manifest, self.config.ipns_key.as_deref())
+            .await?;
+
+        sqlx::query("INSERT INTO ipfs_publications (name, cid, files) VALUES ($1, $2, $3)")
+            .bind(&publication.name)
+            .bind(&publication.cid)
+            .bind(manifest.files.len() as i32)
+            .execute(&self.state.pool)
+            .await?;
+        tracing::info!(
+            "Published manifest {} of {} files to /ipns/{}",
+            publication.cid,
+            manifest.files.len(),
+            publication.name
+        );
+        Ok(())
+    }
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+struct PublicationRow {
+    name: String,
+    cid: String,
+    files: i32,
+    published_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct IpfsStatus {
+    objects: i64,
+    bytes: i64,
+    last_publication: Option<PublicationRow>,
+}
+
+/// GET /api/v1/ipfs — uploaded WARC files and the latest IPNS publication
+pub async fn get_ipfs_status(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let result = async {
+        let (objects, bytes): (i64, i64) =
+            sqlx::query_as("SELECT COUNT(*), COALESCE(SUM(size), 0)::bigint FROM ipfs_objects")
+                .fetch_one(&state.pool)
+                .await?;
+        let last_publication = sqlx::query_as::<_, PublicationRow>(
+            "SELECT name, cid, files, published_at FROM ipfs_publications ORDER BY id DESC LIMIT 1",
+        )
+        .fetch_optional(&state.pool)
+        .await?;
+        anyhow::Ok(IpfsStatus {
+            objects,
+            bytes,
+            last_publication,
+        })
+    }
+    .await;
+
+    match result {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => {
+            tracing::error!("IPFS status error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error fetching IPFS status",
+            )
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,58 @@
+use crate::AppState;
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Deserialize)]
+pub struct PredictionQuery {
+    pub url: String,
+    pub limit: Option<i64>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct CrawlPredictionRecord {
+    pub predicted_at: Option<DateTime<Utc>>,
+    pub next_fetch_at: DateTime<Utc>,
+    pub change_probability: f32,
+    pub change_rate_per_day: f32,
+    pub confidence: f32,
+    pub freshness_target: Option<f32>,
+    pub prior_alpha: Option<f64>,
+    pub prior_beta_secs: Option<f64>,
+    pub observations: i32,
+}
+
+/// GET /api/v1/frontier/predictions?url=
+/// Recrawl decisions made for a URL, most recent first
+pub async fn list_predictions(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<PredictionQuery>,
+) -> impl IntoResponse {
+    let result = sqlx::query_as::<_, CrawlPredictionRecord>(
+        r#"
+        SELECT predicted_at, next_fetch_at, change_probability, change_rate_per_day, confidence,
+               freshness_target, prior_alpha, prior_beta_secs, observations
+        FROM crawl_predictions
+        WHERE url = $1
+        ORDER BY predicted_at DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(&params.url)
+    .bind(params.limit.unwrap_or(50).min(500))
+    .fetch_all(&state.pool)
+    .await;
+
+    match result {
+        Ok(predictions) => Json(predictions).into_response(),
+        Err(e) => {
+            tracing::error!("Prediction list error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Prediction list failed").into_response()
+        }
+    }
+}
@@ -1,39 +1,25 @@
 use anyhow::{anyhow, Result};
+use archive_common::storage::ArchiveStorage;
 use bytes::Bytes;
-use reqwest::header::RANGE;
-use reqwest::Client;
+use std::sync::Arc;
 
 pub struct WarcReader {
-    client: Client,
-    base_url: String, // MinIO/S3 entry point
+    storage: Arc<dyn ArchiveStorage>,
 }
 
 impl WarcReader {
-    pub fn new(base_url: String) -> Self {
-        Self {
-            client: Client::new(),
-            base_url,
-        }
+    pub fn new(storage: Arc<dyn ArchiveStorage>) -> Self {
+        Self { storage }
     }
 
     pub async fn read_record(&self, filename: &str, offset: i64, length: i64) -> Result<Bytes> {
-        let url = format!("{}/{}", self.base_url, filename);
-
-        let response = self
-            .client
-            .get(&url)
-            .header(RANGE, format!("bytes={}-{}", offset, offset + length - 1))
-            .send()
-            .await?;
-
-        if !response.status().is_success() && response.status() != 206 {
-            return Err(anyhow!(
-                "Failed to fetch WARC record: status {}",
-                response.status()
-            ));
+        if offset < 0 || length <= 0 {
+            return Err(anyhow!("Invalid WARC record range"));
         }
-
-        let body = response.bytes().await?;
+        let body = self
+            .storage
+            .get_range(filename, offset as u64, length as u64)
+            .await?;
 
         // Basic parsing: Skip WARC headers to get the HTTP response
         // In a real implementation, we'd use a WARC parser crate to handle headers properly
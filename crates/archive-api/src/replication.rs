@@ -0,0 +1,303 @@
+use crate::AppState;
+use archive_federation::policy::{
+    within_budget, Direction, ReplicationFilter, ReplicationPolicy, DEFAULT_PEER,
+};
+use axum::{
+    extract::{Json, Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Duration, Utc};
+use serde::Deserialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+
+#[derive(sqlx::FromRow)]
+struct PolicyRow {
+    peer_id: String,
+    direction: String,
+    filter: String,
+    bandwidth_bytes_per_hour: Option<i64>,
+    storage_quota_bytes: Option<i64>,
+}
+
+impl PolicyRow {
+    fn into_policy(self) -> anyhow::Result<ReplicationPolicy> {
+        Ok(ReplicationPolicy {
+            peer_id: self.peer_id,
+            direction: Direction::parse(&self.direction)
+                .ok_or_else(|| anyhow::anyhow!("unknown direction {}", self.direction))?,
+            filter: serde_json::from_str(&self.filter)?,
+            bandwidth_bytes_per_hour: self.bandwidth_bytes_per_hour,
+            storage_quota_bytes: self.storage_quota_bytes,
+        })
+    }
+}
+
+const POLICY_COLUMNS: &str =
+    "peer_id, direction, filter::text AS filter, bandwidth_bytes_per_hour, storage_quota_bytes";
+
+/// The peer's own policy for `direction`, else the default one
+pub async fn policy_for(
+    pool: &PgPool,
+    peer_id: &str,
+    direction: Direction,
+) -> anyhow::Result<Option<ReplicationPolicy>> {
+    sqlx::query_as::<_, PolicyRow>(&format!(
+        "SELECT {} FROM federation_policies WHERE peer_id IN ($1, $2) AND direction = $3 ORDER BY peer_id = $2 LIMIT 1",
+        POLICY_COLUMNS
+    ))
+    .bind(peer_id)
+    .bind(DEFAULT_PEER)
+    .bind(direction.as_str())
+    .fetch_optional(pool)
+    .await?
+    .map(PolicyRow::into_policy)
+    .transpose()
+}
+
+/// Charge `bytes` to the peer's hourly budget; `false` if it is spent
+pub async fn consume_bandwidth(
+    pool: &PgPool,
+    peer_id: &str,
+    direction: Direction,
+    bytes: i64,
+    budget: Option<i64>,
+) -> anyhow::Result<bool> {
+    let mut tx = pool.begin().await?;
+    let window: Option<(DateTime<Utc>, i64)> = sqlx::query_as(
+        "SELECT window_start, bytes FROM federation_bandwidth WHERE peer_id = $1 AND direction = $2 FOR UPDATE",
+    )
+    .bind(peer_id)
+    .bind(direction.as_str())
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    let now = Utc::now();
+    let (window_start, used) = match window {
+        Some((start, used)) if now - start < Duration::hours(1) => (start, used),
+        _ => (now, 0),
+    };
+    if !within_budget(budget, used, bytes) {
+        return Ok(false);
+    }
+    sqlx::query(
+        r#"
+        INSERT INTO federation_bandwidth (peer_id, direction, window_start, bytes)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (peer_id, direction) DO UPDATE SET window_start = EXCLUDED.window_start, bytes = EXCLUDED.bytes
+        "#,
+    )
+    .bind(peer_id)
+    .bind(direction.as_str())
+    .bind(window_start)
+    .bind(used + bytes)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await?;
+    Ok(true)
+}
+
+/// GET /api/v1/federation/policies
+pub async fn list_policies(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let rows = sqlx::query_as::<_, PolicyRow>(&format!(
+        "SELECT {} FROM federation_policies ORDER BY peer_id, direction",
+        POLICY_COLUMNS
+    ))
+    .fetch_all(&state.pool)
+    .await;
+
+    match rows.map_err(anyhow::Error::from).and_then(|rows| {
+        rows.into_iter()
+            .map(PolicyRow::into_policy)
+            .collect::<anyhow::Result<Vec<_>>>()
+    }) {
+        Ok(policies) => Json(policies).into_response(),
+        Err(e) => {
+            tracing::error!("Policy list error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error listing policies").into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PolicyBody {
+    #[serde(default)]
+    pub filter: ReplicationFilter,
+    pub bandwidth_bytes_per_hour: Option<i64>,
+    pub storage_quota_bytes: Option<i64>,
+}
+
+/// PUT /api/v1/federation/policies/:peer_id/:direction
+/// Creates or replaces a policy; `*` as the peer sets the default
+pub async fn put_policy(
+    State(state): State<Arc<AppState>>,
+    Path((peer_id, direction)): Path<(String, String)>,
+    Json(body): Json<PolicyBody>,
+) -> impl IntoResponse {
+    let Some(direction) = Direction::parse(&direction) else {
+        return (StatusCode::BAD_REQUEST, "direction must be pull or push").into_response();
+    };
+    if [body.bandwidth_bytes_per_hour, body.storage_quota_bytes]
+        .iter()
+        .flatten()
+        .any(|limit| *limit <= 0)
+    {
+        return (StatusCode::BAD_REQUEST, "limits must be positive").into_response();
+    }
+    let policy = ReplicationPolicy {
+        peer_id,
+        direction,
+        filter: body.filter,
+        bandwidth_bytes_per_hour: body.bandwidth_bytes_per_hour,
+        storage_quota_bytes: body.storage_quota_bytes,
+    };
+    let filter = match serde_json::to_string(&policy.filter) {
+        Ok(filter) => filter,
+        Err(e) => return (StatusCode::BAD_REQUEST, e.to_string()).into_response(),
+    };
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO federation_policies (peer_id, direction, filter, bandwidth_bytes_per_hour, storage_quota_bytes, updated_at)
+        VALUES ($1, $2, $3::jsonb, $4, $5, NOW())
+        ON CONFLICT (peer_id, direction) DO UPDATE SET
+            filter = EXCLUDED.filter,
+            bandwidth_bytes_per_hour = EXCLUDED.bandwidth_bytes_per_hour,
+            storage_quota_bytes = EXCLUDED.storage_quota_bytes,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(&policy.peer_id)
+    .bind(policy.direction.as_str())
+    .bind(filter)
+    .bind(policy.bandwidth_bytes_per_hour)
+    .bind(policy.storage_quota_bytes)
+    .execute(&state.pool)
+    .await;
+
+    match result {
+        Ok(_) => Json(policy).into_response(),
+        Err(e) => {
+            tracing::error!("Policy save error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error saving policy").into_response()
+        }
+    }
+}
+
+/// DELETE /api/v1/federation/policies/:peer_id/:direction
+pub async fn delete_policy(
+    State(state): State<Arc<AppState>>,
+    Path((peer_id, direction)): Path<(String, String)>,
+) -> impl IntoResponse {
+    let result =
+        sqlx::query("DELETE FROM federation_policies WHERE peer_id = $1 AND direction = $2")
+            .bind(&peer_id)
+            .bind(&direction)
+            .execute(&state.pool)
+            .await;
+
+    match result {
+        Ok(done) if done.rows_affected() == 0 => {
+            (StatusCode::NOT_FOUND, "Policy not found").into_response()
+        }
+        Ok(_) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => {
+            tracing::error!("Policy delete error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Error deleting policy").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archive_common::testing::scratch_pool;
+
+    async fn insert_policy(pool: &PgPool, peer_id: &str, filter: &str, budget: Option<i64>) {
+        sqlx::query(
+            r#"
+            INSERT INTO federation_policies (peer_id, direction, filter, bandwidth_bytes_per_hour)
+            VALUES ($1, 'pull', $2::jsonb, $3)
+            "#,
+        )
+        .bind(peer_id)
+        .bind(filter)
+        .bind(budget)
+        .execute(pool)
+        .await
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_peer_policy_overrides_the_default() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        assert!(policy_for(&pool, "node-a", Direction::Pull)
+            .await
+            .unwrap()
+            .is_none());
+
+        insert_policy(&pool, DEFAULT_PEER, r#"{"hosts": ["example.com"]}"#, None).await;
+        insert_policy(
+            &pool,
+            "node-a",
+            r#"{"mime_types": ["text/html"]}"#,
+            Some(100),
+        )
+        .await;
+
+        let own = policy_for(&pool, "node-a", Direction::Pull)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(own.peer_id, "node-a");
+        assert_eq!(own.filter.mime_types, vec!["text/html".to_string()]);
+        assert_eq!(own.bandwidth_bytes_per_hour, Some(100));
+
+        let default = policy_for(&pool, "node-b", Direction::Pull)
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(default.peer_id, DEFAULT_PEER);
+        assert_eq!(default.filter.hosts, vec!["example.com".to_string()]);
+        assert!(policy_for(&pool, "node-b", Direction::Push)
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bandwidth_is_charged_per_hourly_window() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let consume = |bytes| consume_bandwidth(&pool, "node-a", Direction::Pull, bytes, Some(100));
+
+        // The first transfer of a window goes through even over budget
+        assert!(consume(150).await.unwrap());
+        assert!(!consume(1).await.unwrap());
+        sqlx::query("UPDATE federation_bandwidth SET window_start = NOW() - INTERVAL '2 hours'")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert!(consume(60).await.unwrap());
+        assert!(consume(40).await.unwrap());
+        assert!(!consume(1).await.unwrap());
+
+        // Budgets are kept per peer and direction
+        assert!(
+            consume_bandwidth(&pool, "node-a", Direction::Push, 100, Some(100))
+                .await
+                .unwrap()
+        );
+        let used: i64 = sqlx::query_scalar(
+            "SELECT bytes FROM federation_bandwidth WHERE peer_id = 'node-a' AND direction = 'pull'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(used, 100);
+    }
+}
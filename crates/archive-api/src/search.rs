@@ -52,6 +52,7 @@ impl SearchService {
                     "url": source["url"],
                     "title": source["title"],
                     "timestamp": source["timestamp"],
+                    "score": hit["_score"],
                     "snippet": highlight
                 })
             })
@@ -0,0 +1,434 @@
+use crate::AppState;
+use archive_common::Snapshot;
+use axum::{
+    extract::{Json, Query, State},
+    http::{HeaderValue, StatusCode},
+    response::IntoResponse,
+};
+use chrono::{DateTime, Months, NaiveDateTime, Utc};
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Response header carrying the cursor for the next page
+pub const NEXT_CURSOR_HEADER: &str = "x-next-cursor";
+
+#[derive(Deserialize)]
+pub struct SnapshotsQuery {
+    pub url: String,
+    #[serde(rename = "matchType", default)]
+    pub match_type: MatchType,
+    /// Wayback-style timestamp prefix (`2024`, `202403`, ... `20240315120000`), inclusive
+    pub from: Option<String>,
+    /// Wayback-style timestamp prefix, inclusive of the whole period it names
+    pub to: Option<String>,
+    /// Comma-separated status codes or classes, e.g. `200,3xx`
+    pub status: Option<String>,
+    /// MIME type prefix, e.g. `text/html` or `image/`
+    pub mime: Option<String>,
+    pub collapse: Option<Collapse>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchType {
+    #[default]
+    Exact,
+    /// Every URL starting with `url`
+    Prefix,
+    /// Every URL on the host of `url`
+    Host,
+    /// Every URL on the host of `url` or its subdomains
+    Domain,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Collapse {
+    /// Drop captures whose digest matches the previous capture of the same URL
+    Digest,
+}
+
+/// Position after the last row of a page, ordered by `(timestamp, id)` descending
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cursor {
+    pub timestamp: DateTime<Utc>,
+    pub id: Uuid,
+}
+
+impl Cursor {
+    /// Opaque, URL-safe form
+    pub fn encode(&self) -> String {
+        format!("{}.{}", self.timestamp.timestamp_micros(), self.id.simple())
+    }
+
+    pub fn decode(cursor: &str) -> Option<Self> {
+        let (micros, id) = cursor.split_once('.')?;
+        Some(Self {
+            timestamp: DateTime::from_timestamp_micros(micros.parse().ok()?)?,
+            id: Uuid::parse_str(id).ok()?,
+        })
+    }
+}
+
+/// Start (inclusive) and end (exclusive) of the period a timestamp prefix names
+pub fn timestamp_range(prefix: &str) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    if !prefix.chars().all(|c| c.is_ascii_digit())
+        || !matches!(prefix.len(), 4 | 6 | 8 | 10 | 12 | 14)
+    {
+        return None;
+    }
+    let padded = format!("{}{}", prefix, &"00000101000000"[prefix.len()..]);
+    let start = NaiveDateTime::parse_from_str(&padded, "%Y%m%d%H%M%S")
+        .ok()?
+        .and_utc();
+    let end = match prefix.len() {
+        4 => start.checked_add_months(Months::new(12))?,
+        6 => start.checked_add_months(Months::new(1))?,
+        8 => start + chrono::Duration::days(1),
+        10 => start + chrono::Duration::hours(1),
+        12 => start + chrono::Duration::minutes(1),
+        _ => start + chrono::Duration::seconds(1),
+    };
+    Some((start, end))
+}
+
+/// Inclusive status ranges from `200,3xx`
+fn status_ranges(filter: &str) -> Option<(Vec<i32>, Vec<i32>)> {
+    let mut lows = Vec::new();
+    let mut highs = Vec::new();
+    for part in filter.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (low, high) = match part.strip_suffix("xx") {
+            Some(class) => {
+                let class = class.parse::<i32>().ok().filter(|c| (1..=5).contains(c))?;
+                (class * 100, class * 100 + 99)
+            }
+            None => {
+                let code = part.parse::<i32>().ok()?;
+                (code, code)
+            }
+        };
+        lows.push(low);
+        highs.push(high);
+    }
+    Some((lows, highs))
+}
+
+/// Escape `LIKE` wildcards so a prefix matches literally
+pub(crate) fn like_prefix(prefix: &str) -> String {
+    let escaped = prefix
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    format!("{}%", escaped)
+}
+
+fn bad_request(message: &str) -> axum::response::Response {
+    (StatusCode::BAD_REQUEST, message.to_string()).into_response()
+}
+
+/// GET /api/v1/snapshots?url=&matchType=&from=&to=&status=&mime=&collapse=&cursor=&limit=
+/// Captures newest first. When more remain, the `X-Next-Cursor` header holds
+/// the `cursor` for the next page.
+pub async fn get_snapshots_v1(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<SnapshotsQuery>,
+) -> impl IntoResponse {
+    let limit = params.limit.unwrap_or(50).clamp(1, 1000);
+
+    let from = match params.from.as_deref().map(timestamp_range) {
+        Some(None) => return bad_request("Invalid from timestamp"),
+        range => range.flatten().map(|(start, _)| start),
+    };
+    let to = match params.to.as_deref().map(timestamp_range) {
+        Some(None) => return bad_request("Invalid to timestamp"),
+        range => range.flatten().map(|(_, end)| end),
+    };
+    let (status_lows, status_highs) = match params.status.as_deref().map(status_ranges) {
+        Some(None) => return bad_request("Invalid status filter"),
+        ranges => ranges.flatten().unwrap_or_default(),
+    };
+    let cursor = match params.cursor.as_deref().map(Cursor::decode) {
+        Some(None) => return bad_request("Invalid cursor"),
+        cursor => cursor.flatten(),
+    };
+
+    let host = match params.match_type {
+        MatchType::Host | MatchType::Domain => {
+            let host = url::Url::parse(&params.url)
+                .ok()
+                .and_then(|u| u.host_str().map(|h| h.to_ascii_lowercase()))
+                // A bare host name is accepted as well
+                .unwrap_or_else(|| params.url.trim_end_matches('/').to_ascii_lowercase());
+            Some(host)
+        }
+        _ => None,
+    };
+    // Subdomains of `host` are the hosts whose reversal starts with `.host` reversed
+    let subdomains = host
+        .as_deref()
+        .filter(|_| params.match_type == MatchType::Domain)
+        .map(|host| like_prefix(&format!(".{}", host).chars().rev().collect::<String>()));
+    let (exact, prefix) = match params.match_type {
+        MatchType::Exact => (Some(params.url.clone()), None),
+        MatchType::Prefix => (None, Some(like_prefix(&params.url))),
+        MatchType::Host | MatchType::Domain => (None, None),
+    };
+
+    // Filters apply before collapsing, as in CDX servers; the cursor applies after,
+    // so a page boundary does not change which captures are collapsed.
+    let result = sqlx::query_as::<_, Snapshot>(
+        r#"
+        WITH matched AS (
+            SELECT s.*
+            FROM snapshots s
+            WHERE ($1::text IS NULL OR s.url = $1)
+              AND ($2::text IS NULL OR s.url LIKE $2)
+              AND ($3::text IS NULL OR s.host = $3 OR reverse(s.host) LIKE $4)
+              AND ($5::timestamptz IS NULL OR s.timestamp >= $5)
+              AND ($6::timestamptz IS NULL OR s.timestamp < $6)
+              AND ($7::text IS NULL OR s.content_type LIKE $7)
+              AND ($8::int[] IS NULL OR EXISTS (
+                  SELECT 1 FROM unnest($8::int[], $9::int[]) AS r(low, high)
+                  WHERE s.status_code BETWEEN r.low AND r.high
+              ))
+        ),
+        filtered AS (
+            SELECT matched.*,
+                   LAG(sha256) OVER (PARTITION BY url ORDER BY timestamp, id) AS previous_sha256
+            FROM matched
+        )
+        SELECT id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type, payload_hash
+        FROM filtered
+        WHERE (NOT $10 OR previous_sha256 IS NULL OR previous_sha256 <> sha256)
+          AND ($11::timestamptz IS NULL OR (timestamp, id) < ($11, $12))
+        ORDER BY timestamp DESC, id DESC
+        LIMIT $13
+        "#,
+    )
+    .bind(exact)
+    .bind(prefix)
+    .bind(host)
+    .bind(subdomains)
+    .bind(from)
+    .bind(to)
+    .bind(params.mime.as_deref().map(like_prefix))
+    .bind((!status_lows.is_empty()).then_some(status_lows))
+    .bind(status_highs)
+    .bind(params.collapse == Some(Collapse::Digest))
+    .bind(cursor.as_ref().map(|c| c.timestamp))
+    .bind(cursor.as_ref().map(|c| c.id))
+    .bind(limit + 1)
+    .fetch_all(&state.pool)
+    .await;
+
+    match result {
+        Ok(mut snapshots) => {
+            let next = (snapshots.len() as i64 > limit).then(|| {
+                snapshots.truncate(limit as usize);
+                snapshots.last().map(|s| {
+                    Cursor {
+                        timestamp: s.timestamp,
+                        id: s.id,
+                    }
+                    .encode()
+                })
+            });
+
+            let mut response = Json(snapshots).into_response();
+            if let Some(Ok(value)) = next.flatten().map(|c| HeaderValue::from_str(&c)) {
+                response.headers_mut().insert(NEXT_CURSOR_HEADER, value);
+            }
+            response
+        }
+        Err(e) => {
+            tracing::error!("Snapshots error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error fetching snapshots",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_state;
+    use archive_common::storage::FsStorage;
+    use archive_common::testing::scratch_pool;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_timestamp_prefixes_cover_their_period() {
+        let (start, end) = timestamp_range("2024").unwrap();
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2025, 1, 1, 0, 0, 0).unwrap());
+
+        let (start, end) = timestamp_range("202402").unwrap();
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 2, 1, 0, 0, 0).unwrap());
+        assert_eq!(end, Utc.with_ymd_and_hms(2024, 3, 1, 0, 0, 0).unwrap());
+
+        let (start, _) = timestamp_range("20240315120000").unwrap();
+        assert_eq!(start, Utc.with_ymd_and_hms(2024, 3, 15, 12, 0, 0).unwrap());
+
+        assert!(timestamp_range("20241").is_none());
+        assert!(timestamp_range("2024-03").is_none());
+        assert!(timestamp_range("20241301").is_none());
+    }
+
+    #[test]
+    fn test_filters_and_cursor_round_trip() {
+        assert_eq!(
+            status_ranges("200, 3xx"),
+            Some((vec![200, 300], vec![200, 399]))
+        );
+        assert!(status_ranges("9xx").is_none());
+        assert_eq!(
+            like_prefix("https://a.com/100%_"),
+            "https://a.com/100\\%\\_%"
+        );
+
+        let cursor = Cursor {
+            timestamp: Utc.with_ymd_and_hms(2024, 3, 15, 12, 0, 0).unwrap(),
+            id: Uuid::new_v4(),
+        };
+        assert_eq!(Cursor::decode(&cursor.encode()), Some(cursor));
+        assert!(Cursor::decode("not-a-cursor").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_snapshots_query_pages_and_collapses_in_postgres() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let url = "https://example.com/";
+        for (hour, digest) in [(1, "a"), (2, "a"), (3, "b")] {
+            sqlx::query(
+                r#"
+                INSERT INTO snapshots (id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type)
+                VALUES ($1, $2, $3, 'crawl.warc', $4, 100, $5, 200, 'text/html')
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(url)
+            .bind(Utc.with_ymd_and_hms(2024, 3, 1, hour, 0, 0).unwrap())
+            .bind(hour as i64 * 100)
+            .bind(digest)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let state = test_state(pool, Arc::new(FsStorage::new(std::env::temp_dir())));
+        let query = |collapse, limit| SnapshotsQuery {
+            url: url.to_string(),
+            match_type: MatchType::Exact,
+            from: None,
+            to: None,
+            status: None,
+            mime: None,
+            collapse,
+            cursor: None,
+            limit: Some(limit),
+        };
+        let fetch = |params| {
+            let state = state.clone();
+            async move {
+                let response = get_snapshots_v1(State(state), Query(params))
+                    .await
+                    .into_response();
+                assert_eq!(response.status(), StatusCode::OK);
+                let next = response.headers().get(NEXT_CURSOR_HEADER).cloned();
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let snapshots: Vec<Snapshot> = serde_json::from_slice(&body).unwrap();
+                (snapshots, next)
+            }
+        };
+
+        let (page, next) = fetch(query(None, 2)).await;
+        assert_eq!(page.len(), 2);
+        assert_eq!((page[0].offset, page[1].offset), (300, 200));
+        assert!(next.is_some());
+
+        let (collapsed, next) = fetch(query(Some(Collapse::Digest), 10)).await;
+        let digests: Vec<_> = collapsed.iter().map(|s| s.sha256.as_str()).collect();
+        assert_eq!(digests, vec!["b", "a"]);
+        assert!(next.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_host_and_domain_matches_use_the_stored_host() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        for url in [
+            "https://example.com/a",
+            "HTTPS://WWW.Example.com/b",
+            "https://user@api.example.com:8443/c",
+            "https://notexample.com/d",
+            "https://example.org/e",
+        ] {
+            sqlx::query(
+                r#"
+                INSERT INTO snapshots (id, url, warc_file, "offset", length, sha256, status_code, content_type)
+                VALUES ($1, $2, 'crawl.warc', 0, 100, 'a', 200, 'text/html')
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(url)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+        let state = test_state(pool, Arc::new(FsStorage::new(std::env::temp_dir())));
+        let fetch = |url: &str, match_type| {
+            let state = state.clone();
+            let params = SnapshotsQuery {
+                url: url.to_string(),
+                match_type,
+                from: None,
+                to: None,
+                status: None,
+                mime: None,
+                collapse: None,
+                cursor: None,
+                limit: None,
+            };
+            async move {
+                let response = get_snapshots_v1(State(state), Query(params))
+                    .await
+                    .into_response();
+                assert_eq!(response.status(), StatusCode::OK);
+                let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let snapshots: Vec<Snapshot> = serde_json::from_slice(&body).unwrap();
+                let mut urls: Vec<_> = snapshots.into_iter().map(|s| s.url).collect();
+                urls.sort();
+                urls
+            }
+        };
+
+        assert_eq!(
+            fetch("https://Example.com/", MatchType::Host).await,
+            vec!["https://example.com/a"]
+        );
+        assert_eq!(
+            fetch("example.com", MatchType::Domain).await,
+            vec![
+                "HTTPS://WWW.Example.com/b",
+                "https://example.com/a",
+                "https://user@api.example.com:8443/c",
+            ]
+        );
+        assert_eq!(
+            fetch("www.example.com", MatchType::Host).await,
+            vec!["HTTPS://WWW.Example.com/b"]
+        );
+    }
+}
@@ -0,0 +1,736 @@
+use crate::export::{strip_separator, PROFILE_IDENTICAL_PAYLOAD};
+use crate::import::parse::HttpResponse;
+use crate::import::{self, NewSnapshot};
+use crate::replication;
+use crate::snapshots::Cursor;
+use crate::AppState;
+use anyhow::{anyhow, bail};
+use archive_common::warc::WarcWriter;
+use archive_common::Snapshot;
+use archive_federation::policy::{Direction, ReplicationFilter};
+use archive_federation::{ManifestResponse, Peer};
+use axum::{extract::State, http::StatusCode, response::IntoResponse, Json};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Manifest entries requested per page
+const PAGE_SIZE: usize = 200;
+/// Pages fetched from one peer per cycle, so a long backlog does not starve
+/// the other peers
+const MAX_PAGES_PER_CYCLE: usize = 20;
+/// Failed records are retried on later cycles up to this many attempts
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Check a downloaded record block against the manifest's payload digest and
+/// return the HTTP message to store
+pub fn verify_block<'a>(block: &'a [u8], sha256: &str) -> anyhow::Result<&'a [u8]> {
+    let http = strip_separator(block);
+    let response = HttpResponse::parse(http).ok_or_else(|| anyhow!("not an HTTP response"))?;
+    let digest = format!("{:x}", Sha256::digest(response.body));
+    if !digest.eq_ignore_ascii_case(sha256) {
+        bail!(
+            "payload digest mismatch: expected {}, got {}",
+            sha256,
+            digest
+        );
+    }
+    Ok(http)
+}
+
+/// What syncing one manifest entry did
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Outcome {
+    /// The capture was already held
+    Skipped,
+    /// Outside the pull policy
+    Filtered,
+    /// Stored as a revisit of a payload already in the archive
+    Deduplicated,
+    /// Downloaded, verified and added to the page's segment WARC, or a repeat
+    /// of a payload the segment already holds
+    Synced,
+    /// Left for a later cycle because a budget or quota is spent
+    Deferred(&'static str),
+}
+
+/// The pull policy for one peer and what has been used of it
+struct Pull {
+    filter: ReplicationFilter,
+    bandwidth_bytes_per_hour: Option<i64>,
+    storage_quota_bytes: Option<i64>,
+    /// Bytes of records stored from the peer so far
+    stored: i64,
+}
+
+/// Counters for one cycle against one peer, added to `federation_sync_state`
+#[derive(Debug, Default)]
+struct Progress {
+    synced: i64,
+    deduplicated: i64,
+    skipped: i64,
+    filtered: i64,
+    failed: i64,
+}
+
+impl Progress {
+    fn count(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Skipped => self.skipped += 1,
+            Outcome::Filtered => self.filtered += 1,
+            Outcome::Deduplicated => self.deduplicated += 1,
+            // Counted once their segment is stored
+            Outcome::Synced | Outcome::Deferred(_) => {}
+        }
+    }
+}
+
+/// A downloaded record waiting for its segment to be stored
+struct Capture {
+    snap: Snapshot,
+    offset: i64,
+    length: i64,
+    body_len: i64,
+    /// Index of the earlier capture in the segment whose record holds this
+    /// payload; such captures are stored as revisits of it
+    repeat_of: Option<usize>,
+}
+
+/// Records downloaded in one page, stored as one WARC before their snapshots
+/// are created
+struct Segment {
+    writer: WarcWriter,
+    captures: Vec<Capture>,
+    /// Capture holding the record written for each payload digest
+    written: HashMap<String, usize>,
+}
+
+impl Segment {
+    fn new(peer: &Peer) -> Self {
+        let name = format!(
+            "{}-{}.warc",
+            sync_job_id(peer),
+            Utc::now().format("%Y%m%d%H%M%S%3f")
+        );
+        Self {
+            writer: WarcWriter::new(name),
+            captures: Vec::new(),
+            written: HashMap::new(),
+        }
+    }
+}
+
+/// Pulls captures from active peers page by page, resuming from a stored
+/// cursor per peer
+pub struct SyncWorker {
+    state: Arc<AppState>,
+    client: reqwest::Client,
+}
+
+impl SyncWorker {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            state,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub async fn run_loop(&self) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60));
+        loop {
+            interval.tick().await;
+            self.sync_cycle().await;
+        }
+    }
+
+    async fn sync_cycle(&self) {
+        for peer in self.state.peer_manager.get_active_peers() {
+            let mut progress = Progress::default();
+            let result = self.sync_peer(&peer, &mut progress).await;
+            if let Err(e) = &result {
+                tracing::warn!("Sync with peer {} stopped: {}", peer.id, e);
+            }
+            let error = result.err().map(|e| e.to_string());
+            if let Err(e) = self.save_progress(&peer.id, &progress, error).await {
+                tracing::error!("Failed to save sync progress for {}: {}", peer.id, e);
+            }
+        }
+    }
+
+    /// Retry earlier failures, then walk the peer's manifest from the cursor
+    async fn sync_peer(&self, peer: &Peer, progress: &mut Progress) -> anyhow::Result<()> {
+        let mut pull = self.pull_policy(peer).await?;
+        self.retry_failures(peer, &mut pull, progress).await?;
+
+        let mut cursor: Option<String> =
+            sqlx::query_scalar("SELECT cursor FROM federation_sync_state WHERE peer_id = $1")
+                .bind(&peer.id)
+                .fetch_optional(&self.state.pool)
+                .await?
+                .flatten();
+
+        for _ in 0..MAX_PAGES_PER_CYCLE {
+            let mut url =
+                reqwest::Url::parse(&format!("{}/api/v1/federation/manifest", peer.endpoint))?;
+            url.query_pairs_mut()
+                .append_pair("limit", &PAGE_SIZE.to_string());
+            if let Some(cursor) = &cursor {
+                url.query_pairs_mut().append_pair("after", cursor);
+            }
+            // Older peers ignore the filter, so entries are checked again below
+            if !pull.filter.is_empty() {
+                url.query_pairs_mut()
+                    .append_pair("filter", &serde_json::to_string(&pull.filter)?);
+            }
+            let manifest: ManifestResponse = self
+                .state
+                .peer_manager
+                .signed_get(&self.client, url.as_str())?
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+            self.state.peer_manager.update_last_seen(&peer.id).await;
+
+            // Failed records live on in the failure table, so the cursor
+            // moves past them
+            let mut segment = Segment::new(peer);
+            for snap in &manifest.snapshots {
+                match self.sync_record(peer, snap, &mut pull, &mut segment).await {
+                    Ok(Outcome::Deferred(reason)) => {
+                        self.store_segment(peer, segment, progress).await?;
+                        self.save_cursor(peer, &cursor).await?;
+                        bail!(reason);
+                    }
+                    Ok(outcome) => progress.count(outcome),
+                    Err(e) => {
+                        tracing::warn!("Sync of {} from {} failed: {}", snap.id, peer.id, e);
+                        progress.failed += 1;
+                        self.record_failure(peer, snap, &e).await?;
+                    }
+                }
+                cursor = Some(
+                    Cursor {
+                        timestamp: snap.timestamp,
+                        id: snap.id,
+                    }
+                    .encode(),
+                );
+            }
+            self.store_segment(peer, segment, progress).await?;
+            // The peer may have examined entries past the last one it listed
+            if manifest.next.is_some() {
+                cursor = manifest.next.clone();
+            }
+            self.save_cursor(peer, &cursor).await?;
+
+            if manifest.next.is_none() {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    async fn pull_policy(&self, peer: &Peer) -> anyhow::Result<Pull> {
+        let policy = replication::policy_for(&self.state.pool, &peer.id, Direction::Pull).await?;
+        let stored: i64 = sqlx::query_scalar(
+            "SELECT COALESCE(SUM(length), 0)::bigint FROM snapshots WHERE job_id = $1 AND revisit_of IS NULL",
+        )
+        .bind(sync_job_id(peer))
+        .fetch_one(&self.state.pool)
+        .await?;
+        let (filter, bandwidth_bytes_per_hour, storage_quota_bytes) = policy
+            .map(|p| (p.filter, p.bandwidth_bytes_per_hour, p.storage_quota_bytes))
+            .unwrap_or_default();
+        Ok(Pull {
+            filter,
+            bandwidth_bytes_per_hour,
+            storage_quota_bytes,
+            stored,
+        })
+    }
+
+    async fn save_cursor(&self, peer: &Peer, cursor: &Option<String>) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO federation_sync_state (peer_id, cursor, updated_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (peer_id) DO UPDATE SET cursor = EXCLUDED.cursor, updated_at = NOW()
+            "#,
+        )
+        .bind(&peer.id)
+        .bind(cursor)
+        .execute(&self.state.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn retry_failures(
+        &self,
+        peer: &Peer,
+        pull: &mut Pull,
+        progress: &mut Progress,
+    ) -> anyhow::Result<()> {
+        let failures: Vec<String> = sqlx::query_scalar(
+            r#"
+            SELECT snapshot::text FROM federation_sync_failures
+            WHERE peer_id = $1 AND attempts < $2
+            ORDER BY last_attempt_at
+            LIMIT $3
+            "#,
+        )
+        .bind(&peer.id)
+        .bind(MAX_ATTEMPTS)
+        .bind(PAGE_SIZE as i64)
+        .fetch_all(&self.state.pool)
+        .await?;
+
+        let mut segment = Segment::new(peer);
+        let mut resolved = Vec::new();
+        let mut deferred = None;
+        for failure in failures {
+            let snap: Snapshot = serde_json::from_str(&failure)?;
+            match self.sync_record(peer, &snap, pull, &mut segment).await {
+                Ok(Outcome::Deferred(reason)) => {
+                    deferred = Some(reason);
+                    break;
+                }
+                Ok(outcome) => {
+                    progress.count(outcome);
+                    resolved.push(snap.id);
+                }
+                Err(e) => {
+                    progress.failed += 1;
+                    self.record_failure(peer, &snap, &e).await?;
+                }
+            }
+        }
+
+        self.store_segment(peer, segment, progress).await?;
+        sqlx::query(
+            "DELETE FROM federation_sync_failures WHERE peer_id = $1 AND remote_id = ANY($2)",
+        )
+        .bind(&peer.id)
+        .bind(&resolved)
+        .execute(&self.state.pool)
+        .await?;
+        match deferred {
+            Some(reason) => bail!(reason),
+            None => Ok(()),
+        }
+    }
+
+    /// Store the segment's WARC, then create its snapshots
+    async fn store_segment(
+        &self,
+        peer: &Peer,
+        segment: Segment,
+        progress: &mut Progress,
+    ) -> anyhow::Result<()> {
+        let Segment {
+            writer, captures, ..
+        } = segment;
+        let warc_file = writer.name().to_string();
+        writer.finish(self.state.storage.as_ref()).await?;
+
+        let mut conn = self.state.pool.acquire().await?;
+        let job_id = sync_job_id(peer);
+        let mut ids = Vec::with_capacity(captures.len());
+        for capture in &captures {
+            let snap = &capture.snap;
+            if let Some(original) = capture.repeat_of {
+                let id = import::insert_snapshot(
+                    &mut conn,
+                    &NewSnapshot {
+                        url: &snap.url,
+                        timestamp: snap.timestamp,
+                        warc_file: &warc_file,
+                        offset: capture.offset,
+                        length: capture.length,
+                        sha256: &snap.sha256,
+                        status_code: snap.status_code,
+                        content_type: &snap.content_type,
+                        revisit_of: Some(ids[original]),
+                        revisit_profile: Some(PROFILE_IDENTICAL_PAYLOAD),
+                    },
+                    &job_id,
+                )
+                .await?;
+                ids.push(id);
+                progress.deduplicated += 1;
+                continue;
+            }
+            sqlx::query(
+                "INSERT INTO payloads (hash, warc_path, warc_offset, size) VALUES ($1, $2, $3, $4) ON CONFLICT DO NOTHING",
+            )
+            .bind(&snap.sha256)
+            .bind(&warc_file)
+            .bind(capture.offset)
+            .bind(capture.body_len)
+            .execute(&mut *conn)
+            .await?;
+            let id = import::insert_snapshot(
+                &mut conn,
+                &NewSnapshot {
+                    url: &snap.url,
+                    timestamp: snap.timestamp,
+                    warc_file: &warc_file,
+                    offset: capture.offset,
+                    length: capture.length,
+                    sha256: &snap.sha256,
+                    status_code: snap.status_code,
+                    content_type: &snap.content_type,
+                    revisit_of: None,
+                    revisit_profile: None,
+                },
+                &job_id,
+            )
+            .await?;
+            ids.push(id);
+            progress.synced += 1;
+        }
+        Ok(())
+    }
+
+    async fn sync_record(
+        &self,
+        peer: &Peer,
+        snap: &Snapshot,
+        pull: &mut Pull,
+        segment: &mut Segment,
+    ) -> anyhow::Result<Outcome> {
+        if !pull.filter.admits(snap, None) {
+            return Ok(Outcome::Filtered);
+        }
+        let pool = &self.state.pool;
+        let held = sqlx::query(
+            "SELECT 1 FROM snapshots WHERE url = $1 AND timestamp = $2 AND sha256 = $3 LIMIT 1",
+        )
+        .bind(&snap.url)
+        .bind(snap.timestamp)
+        .bind(&snap.sha256)
+        .fetch_optional(pool)
+        .await?
+        .is_some();
+        if held {
+            return Ok(Outcome::Skipped);
+        }
+
+        let job_id = sync_job_id(peer);
+        if let Some(original) = import::archived_payload(pool, &snap.sha256).await? {
+            import::insert_snapshot(
+                &mut *pool.acquire().await?,
+                &NewSnapshot {
+                    url: &snap.url,
+                    timestamp: snap.timestamp,
+                    warc_file: &original.warc_file,
+                    offset: original.offset,
+                    length: original.length,
+                    sha256: &original.sha256,
+                    status_code: snap.status_code,
+                    content_type: &snap.content_type,
+                    revisit_of: Some(original.id),
+                    revisit_profile: Some(PROFILE_IDENTICAL_PAYLOAD),
+                },
+                &job_id,
+            )
+            .await?;
+            return Ok(Outcome::Deduplicated);
+        }
+
+        // A payload already downloaded for this segment is not fetched again
+        if let Some(&original) = segment.written.get(&snap.sha256) {
+            let Capture {
+                offset,
+                length,
+                body_len,
+                ..
+            } = segment.captures[original];
+            segment.captures.push(Capture {
+                snap: snap.clone(),
+                offset,
+                length,
+                body_len,
+                repeat_of: Some(original),
+            });
+            return Ok(Outcome::Synced);
+        }
+
+        if pull
+            .storage_quota_bytes
+            .is_some_and(|quota| pull.stored + snap.length > quota)
+        {
+            return Ok(Outcome::Deferred("storage quota reached"));
+        }
+        let allowed = replication::consume_bandwidth(
+            pool,
+            &peer.id,
+            Direction::Pull,
+            snap.length,
+            pull.bandwidth_bytes_per_hour,
+        )
+        .await?;
+        if !allowed {
+            return Ok(Outcome::Deferred("hourly bandwidth budget spent"));
+        }
+
+        let url = format!("{}/api/v1/snapshot/{}/download", peer.endpoint, snap.id);
+        let block = self
+            .state
+            .peer_manager
+            .signed_get(&self.client, &url)?
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let http = verify_block(&block, &snap.sha256)?;
+        let body_len = HttpResponse::parse(http).map_or(0, |h| h.body.len());
+
+        let record = import::response_record(&snap.url, snap.timestamp, &snap.sha256, http);
+        let (offset, length) = segment.writer.write_bytes(&record);
+        segment
+            .written
+            .insert(snap.sha256.clone(), segment.captures.len());
+        segment.captures.push(Capture {
+            snap: snap.clone(),
+            offset: offset as i64,
+            length: length as i64,
+            body_len: body_len as i64,
+            repeat_of: None,
+        });
+        pull.stored += record.len() as i64;
+        Ok(Outcome::Synced)
+    }
+
+    async fn record_failure(
+        &self,
+        peer: &Peer,
+        snap: &Snapshot,
+        error: &anyhow::Error,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO federation_sync_failures (peer_id, remote_id, snapshot, error)
+            VALUES ($1, $2, $3::jsonb, $4)
+            ON CONFLICT (peer_id, remote_id) DO UPDATE SET
+                error = EXCLUDED.error,
+                attempts = federation_sync_failures.attempts + 1,
+                last_attempt_at = NOW()
+            "#,
+        )
+        .bind(&peer.id)
+        .bind(snap.id)
+        .bind(serde_json::to_string(snap)?)
+        .bind(error.to_string())
+        .execute(&self.state.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn save_progress(
+        &self,
+        peer_id: &str,
+        progress: &Progress,
+        error: Option<String>,
+    ) -> anyhow::Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO federation_sync_state (peer_id, synced, deduplicated, skipped, filtered, failed, last_error, last_synced_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, CASE WHEN $7::text IS NULL THEN NOW() END, NOW())
+            ON CONFLICT (peer_id) DO UPDATE SET
+                synced = federation_sync_state.synced + EXCLUDED.synced,
+                deduplicated = federation_sync_state.deduplicated + EXCLUDED.deduplicated,
+                skipped = federation_sync_state.skipped + EXCLUDED.skipped,
+                filtered = federation_sync_state.filtered + EXCLUDED.filtered,
+                failed = federation_sync_state.failed + EXCLUDED.failed,
+                last_error = EXCLUDED.last_error,
+                last_synced_at = COALESCE(EXCLUDED.last_synced_at, federation_sync_state.last_synced_at),
+                updated_at = NOW()
+            "#,
+        )
+        .bind(peer_id)
+        .bind(progress.synced)
+        .bind(progress.deduplicated)
+        .bind(progress.skipped)
+        .bind(progress.filtered)
+        .bind(progress.failed)
+        .bind(error)
+        .execute(&self.state.pool)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Job id and WARC name for captures synced from a peer
+fn sync_job_id(peer: &Peer) -> String {
+    let id: String = peer
+        .id
+        .chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .take(16)
+        .collect();
+    format!("sync-{}", id)
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct SyncStatus {
+    pub peer_id: String,
+    pub endpoint: Option<String>,
+    pub cursor: Option<String>,
+    pub synced: i64,
+    pub deduplicated: i64,
+    pub skipped: i64,
+    pub filtered: i64,
+    pub failed: i64,
+    /// Records still to be retried
+    pub pending_retries: i64,
+    /// Records that used up their retries
+    pub abandoned: i64,
+    pub last_error: Option<String>,
+    pub last_synced_at: Option<DateTime<Utc>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// GET /api/v1/federation/sync
+/// Sync progress per peer
+pub async fn get_sync_status(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let result = sqlx::query_as::<_, SyncStatus>(
+        r#"
+        SELECT s.peer_id, p.endpoint, s.cursor, s.synced, s.deduplicated, s.skipped, s.filtered, s.failed,
+               COUNT(f.remote_id) FILTER (WHERE f.attempts < $1) AS pending_retries,
+               COUNT(f.remote_id) FILTER (WHERE f.attempts >= $1) AS abandoned,
+               s.last_error, s.last_synced_at, s.updated_at
+        FROM federation_sync_state s
+        LEFT JOIN federation_peers p ON p.id = s.peer_id
+        LEFT JOIN federation_sync_failures f ON f.peer_id = s.peer_id
+        GROUP BY s.peer_id, p.endpoint
+        ORDER BY s.peer_id
+        "#,
+    )
+    .bind(MAX_ATTEMPTS)
+    .fetch_all(&state.pool)
+    .await;
+
+    match result {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => {
+            tracing::error!("Sync status error: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error fetching sync status",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_state;
+    use archive_common::storage::FsStorage;
+    use archive_common::testing::scratch_pool;
+    use archive_federation::PeerStatus;
+    use axum::{extract::Path, routing::get, Router};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_verify_block_checks_the_payload_digest() {
+        let block = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>hi</p>\r\n\r\n";
+        let digest = format!("{:x}", Sha256::digest(b"<p>hi</p>"));
+
+        let http = verify_block(block, &digest).unwrap();
+        assert!(http.ends_with(b"<p>hi</p>"));
+        assert!(verify_block(block, &format!("{:x}", Sha256::digest(b"other"))).is_err());
+        assert!(verify_block(b"garbage", &digest).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_repeated_payloads_in_a_page_are_downloaded_and_written_once() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let block = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<p>hi</p>\r\n\r\n";
+        let sha256 = format!("{:x}", Sha256::digest(b"<p>hi</p>"));
+        let snapshots: Vec<Snapshot> = ["https://a.com/", "https://a.com/index.html"]
+            .into_iter()
+            .map(|url| Snapshot {
+                id: Uuid::new_v4(),
+                url: url.to_string(),
+                timestamp: Utc::now(),
+                warc_file: "peer.warc".to_string(),
+                offset: 0,
+                length: block.len() as i64,
+                sha256: sha256.clone(),
+                status_code: 200,
+                content_type: "text/html".to_string(),
+                payload_hash: None,
+            })
+            .collect();
+
+        // A peer listing both captures in one manifest page
+        let downloads = Arc::new(AtomicUsize::new(0));
+        let counter = downloads.clone();
+        let manifest = ManifestResponse {
+            snapshots: snapshots.clone(),
+            next: None,
+        };
+        let app = Router::new()
+            .route(
+                "/api/v1/federation/manifest",
+                get(move || async move { Json(manifest) }),
+            )
+            .route(
+                "/api/v1/snapshot/:id/download",
+                get(move |Path(_id): Path<Uuid>| async move {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    block.to_vec()
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let dir = std::env::temp_dir().join(format!("archivestream-test-{}", Uuid::new_v4()));
+        let storage = Arc::new(FsStorage::new(dir.clone()));
+        let worker = SyncWorker::new(test_state(pool.clone(), storage));
+        let peer = Peer {
+            id: "peer-a".to_string(),
+            endpoint,
+            last_seen: Utc::now(),
+            status: PeerStatus::Active,
+            capabilities: Vec::new(),
+            public_key: String::new(),
+            failures: 0,
+            next_probe_at: Utc::now(),
+        };
+        let mut progress = Progress::default();
+        worker.sync_peer(&peer, &mut progress).await.unwrap();
+        assert_eq!(downloads.load(Ordering::SeqCst), 1);
+        assert_eq!((progress.synced, progress.deduplicated), (1, 1));
+
+        let rows: Vec<(Uuid, String, String, i64, Option<Uuid>)> = sqlx::query_as(
+            r#"SELECT id, url, warc_file, "offset", revisit_of FROM snapshots ORDER BY url"#,
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        let (original, revisit) = (&rows[0], &rows[1]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(original.4, None);
+        assert_eq!(revisit.4, Some(original.0));
+        assert_eq!((&revisit.2, revisit.3), (&original.2, original.3));
+        let warc = std::fs::read(dir.join(&original.2)).unwrap();
+        let responses = warc
+            .windows(b"WARC-Type: response".len())
+            .filter(|w| *w == b"WARC-Type: response")
+            .count();
+        assert_eq!(responses, 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
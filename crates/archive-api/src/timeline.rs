@@ -0,0 +1,689 @@
+use crate::export::strip_separator;
+use crate::import::parse::HttpResponse;
+use crate::AppState;
+use archive_common::extractor::extract_text;
+use archive_common::simhash;
+use archive_semantic::{Classifier, SemanticCategory};
+use axum::{
+    extract::{Json, Query, State},
+    http::StatusCode,
+    response::IntoResponse,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+use sqlx::Row;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Heatmap floor, so unchanged captures still show up on the timeline
+const MIN_INTENSITY: f32 = 0.1;
+
+/// Categories that matter regardless of how little text moved
+const SIGNIFICANT_CHANGE_FLOOR: f32 = 0.5;
+
+#[derive(Deserialize)]
+pub struct TimelineQuery {
+    pub url: String,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+    /// Aggregate captures per day, week or month instead of listing them
+    pub bucket: Option<BucketSize>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BucketSize {
+    Day,
+    Week,
+    Month,
+}
+
+impl BucketSize {
+    /// Postgres `date_trunc` field
+    fn as_str(&self) -> &'static str {
+        match self {
+            BucketSize::Day => "day",
+            BucketSize::Week => "week",
+            BucketSize::Month => "month",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct TimelineResponse {
+    pub url: String,
+    /// Captures (or buckets) matching the range, before pagination
+    pub total: i64,
+    pub offset: i64,
+    pub limit: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshots: Option<Vec<TimelineSnapshot>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub buckets: Option<Vec<TimelineBucket>>,
+}
+
+#[derive(Serialize)]
+pub struct TimelineSnapshot {
+    pub timestamp: DateTime<Utc>,
+    pub status: u16,
+    pub digest: String,
+    pub intensity: f32, // Heatmap intensity 0.0 to 1.0
+    /// SimHash similarity to the previous snapshot, when both have one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub similarity: Option<f32>,
+    /// Precomputed change against the previous snapshot, once available
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub change: Option<ChangeMagnitude>,
+}
+
+#[derive(Serialize, sqlx::FromRow)]
+pub struct TimelineBucket {
+    pub start: DateTime<Utc>,
+    pub captures: i64,
+    pub changes: i64,
+    pub max_magnitude: Option<f32>,
+    pub avg_magnitude: Option<f32>,
+}
+
+/// How much a capture differs from the one before it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ChangeMagnitude {
+    /// Share of words added or removed, `0.0..=1.0`
+    pub text_diff_ratio: Option<f32>,
+    /// Screenshot difference, `0.0..=1.0`, written by the visual pipeline
+    pub visual_diff_score: Option<f32>,
+    pub semantic_category: Option<String>,
+    /// Combined score used for the heatmap
+    pub magnitude: f32,
+}
+
+impl ChangeMagnitude {
+    pub fn unchanged() -> Self {
+        Self {
+            text_diff_ratio: Some(0.0),
+            visual_diff_score: None,
+            semantic_category: None,
+            magnitude: 0.0,
+        }
+    }
+
+    /// The payload changed but could not be compared; it counts as a full
+    /// change, like any digest change without a magnitude
+    pub fn unknown() -> Self {
+        Self {
+            semantic_category: Some("Unknown".to_string()),
+            ..Self::combine(None, None, None)
+        }
+    }
+
+    /// Word-level diff and semantic category of two HTML or text payloads
+    pub fn between(previous: &str, current: &str, classifier: &Classifier) -> Self {
+        let previous = extract_text(previous).text_content;
+        let current = extract_text(current).text_content;
+        // Whitespace is not content; diffing it would make rewrites look similar
+        let previous = previous.split_whitespace().collect::<Vec<_>>();
+        let current = current.split_whitespace().collect::<Vec<_>>();
+        let diff = TextDiff::from_slices(&previous, &current);
+        let ratio = 1.0 - diff.ratio();
+
+        let mut added = String::new();
+        let mut removed = String::new();
+        for change in diff.iter_all_changes() {
+            let text = match change.tag() {
+                similar::ChangeTag::Insert => &mut added,
+                similar::ChangeTag::Delete => &mut removed,
+                similar::ChangeTag::Equal => continue,
+            };
+            text.push_str(change.value());
+            text.push(' ');
+        }
+        let category = (ratio > 0.0)
+            .then(|| classifier.classify(&added, &removed))
+            .and_then(|c| c.categories.into_iter().next());
+
+        Self::combine(Some(ratio), None, category)
+    }
+
+    /// Text and visual scores take the larger; significant categories lift small edits
+    pub fn combine(
+        text_diff_ratio: Option<f32>,
+        visual_diff_score: Option<f32>,
+        category: Option<SemanticCategory>,
+    ) -> Self {
+        let mut magnitude = match (text_diff_ratio, visual_diff_score) {
+            (None, None) => 1.0,
+            (text, visual) => text.unwrap_or(0.0).max(visual.unwrap_or(0.0)),
+        };
+        let significant = matches!(
+            category,
+            Some(
+                SemanticCategory::PrivacyPolicy
+                    | SemanticCategory::PriceChange
+                    | SemanticCategory::BreakingNews
+            )
+        );
+        if significant && magnitude > 0.0 {
+            magnitude = magnitude.max(SIGNIFICANT_CHANGE_FLOOR);
+        }
+
+        Self {
+            text_diff_ratio,
+            visual_diff_score,
+            semantic_category: category.map(|c| format!("{:?}", c)),
+            magnitude: magnitude.clamp(0.0, 1.0),
+        }
+    }
+}
+
+/// Heatmap intensity: the precomputed magnitude when there is one, else the
+/// SimHash distance, else whether the digest changed at all
+fn intensity(changed: bool, magnitude: Option<f32>, simhashes: Option<(u64, u64)>) -> f32 {
+    if !changed {
+        return MIN_INTENSITY;
+    }
+    match (magnitude, simhashes) {
+        (Some(m), _) => m.clamp(MIN_INTENSITY, 1.0),
+        (None, Some((a, b))) => {
+            (simhash::hamming_distance(a, b) as f32 / 32.0).clamp(MIN_INTENSITY, 1.0)
+        }
+        (None, None) => 1.0,
+    }
+}
+
+/// Captures of one URL with the digest and SimHash of the capture before each,
+/// so a page boundary does not lose the comparison
+const ORDERED_CAPTURES: &str = r#"
+    WITH captures AS (
+        SELECT s.id, s.timestamp, s.status_code, s.sha256,
+               COALESCE(s.simhash, (
+                   SELECT o.simhash FROM change_observations o
+                   WHERE o.url = s.url AND o.observed_at = s.timestamp
+                   LIMIT 1
+               )) AS simhash
+        FROM snapshots s
+        WHERE s.url = $1
+    ),
+    ordered AS (
+        SELECT captures.*,
+               LAG(sha256) OVER (ORDER BY timestamp) AS previous_sha256,
+               LAG(simhash) OVER (ORDER BY timestamp) AS previous_simhash
+        FROM captures
+    )
+"#;
+
+/// GET /api/v1/timeline?url=&from=&to=&bucket=&limit=&offset=
+pub async fn get_timeline(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<TimelineQuery>,
+) -> impl IntoResponse {
+    let limit = params.limit.unwrap_or(500).clamp(1, 5000);
+    let offset = params.offset.unwrap_or(0).max(0);
+
+    let result = match params.bucket {
+        Some(bucket) => timeline_buckets(&state, &params, bucket, limit, offset)
+            .await
+            .map(|(total, buckets)| (total, None, Some(buckets))),
+        None => timeline_snapshots(&state, &params, limit, offset)
+            .await
+            .map(|(total, snapshots)| (total, Some(snapshots), None)),
+    };
+
+    match result {
+        Ok((total, snapshots, buckets)) => Json(TimelineResponse {
+            url: params.url,
+            total,
+            offset,
+            limit,
+            snapshots,
+            buckets,
+        })
+        .into_response(),
+        Err(e) => {
+            tracing::error!("Timeline error: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, "Timeline failed").into_response()
+        }
+    }
+}
+
+async fn timeline_snapshots(
+    state: &AppState,
+    params: &TimelineQuery,
+    limit: i64,
+    offset: i64,
+) -> anyhow::Result<(i64, Vec<TimelineSnapshot>)> {
+    let total: i64 = sqlx::query_scalar(
+        r#"
+        SELECT COUNT(*) FROM snapshots
+        WHERE url = $1
+          AND ($2::timestamptz IS NULL OR timestamp >= $2)
+          AND ($3::timestamptz IS NULL OR timestamp <= $3)
+        "#,
+    )
+    .bind(&params.url)
+    .bind(params.from)
+    .bind(params.to)
+    .fetch_one(&state.pool)
+    .await?;
+
+    let rows = sqlx::query(&format!(
+        r#"
+        {ORDERED_CAPTURES}
+        SELECT o.timestamp, o.status_code, o.sha256, o.simhash, o.previous_sha256,
+               o.previous_simhash, c.text_diff_ratio, c.visual_diff_score,
+               c.semantic_category, c.magnitude
+        FROM ordered o
+        LEFT JOIN snapshot_changes c ON c.snapshot_id = o.id
+        WHERE ($2::timestamptz IS NULL OR o.timestamp >= $2)
+          AND ($3::timestamptz IS NULL OR o.timestamp <= $3)
+        ORDER BY o.timestamp ASC
+        LIMIT $4 OFFSET $5
+        "#
+    ))
+    .bind(&params.url)
+    .bind(params.from)
+    .bind(params.to)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.pool)
+    .await?;
+
+    let snapshots = rows
+        .into_iter()
+        .map(|row| {
+            let status_code: i16 = row.get("status_code");
+            let digest: String = row.get("sha256");
+            let previous_digest: Option<String> = row.get("previous_sha256");
+            let simhashes = row
+                .get::<Option<i64>, _>("previous_simhash")
+                .zip(row.get::<Option<i64>, _>("simhash"))
+                .map(|(a, b)| (a as u64, b as u64));
+            let change = row
+                .get::<Option<f32>, _>("magnitude")
+                .map(|magnitude| ChangeMagnitude {
+                    text_diff_ratio: row.get("text_diff_ratio"),
+                    visual_diff_score: row.get("visual_diff_score"),
+                    semantic_category: row.get("semantic_category"),
+                    magnitude,
+                });
+            let changed = previous_digest.is_some_and(|d| d != digest);
+
+            TimelineSnapshot {
+                timestamp: row.get("timestamp"),
+                status: status_code as u16,
+                intensity: intensity(changed, change.as_ref().map(|c| c.magnitude), simhashes),
+                similarity: simhashes.map(|(a, b)| simhash::similarity(a, b)),
+                digest,
+                change,
+            }
+        })
+        .collect();
+
+    Ok((total, snapshots))
+}
+
+async fn timeline_buckets(
+    state: &AppState,
+    params: &TimelineQuery,
+    bucket: BucketSize,
+    limit: i64,
+    offset: i64,
+) -> anyhow::Result<(i64, Vec<TimelineBucket>)> {
+    let query = format!(
+        r#"
+        {ORDERED_CAPTURES},
+        buckets AS (
+            SELECT date_trunc($4, o.timestamp) AS start,
+                   COUNT(*) AS captures,
+                   COUNT(*) FILTER (
+                       WHERE o.previous_sha256 IS NOT NULL AND o.previous_sha256 <> o.sha256
+                   ) AS changes,
+                   MAX(c.magnitude) AS max_magnitude,
+                   AVG(c.magnitude)::real AS avg_magnitude
+            FROM ordered o
+            LEFT JOIN snapshot_changes c ON c.snapshot_id = o.id
+            WHERE ($2::timestamptz IS NULL OR o.timestamp >= $2)
+              AND ($3::timestamptz IS NULL OR o.timestamp <= $3)
+            GROUP BY 1
+        )
+        "#
+    );
+
+    let total: i64 = sqlx::query_scalar(&format!("{query} SELECT COUNT(*) FROM buckets"))
+        .bind(&params.url)
+        .bind(params.from)
+        .bind(params.to)
+        .bind(bucket.as_str())
+        .fetch_one(&state.pool)
+        .await?;
+
+    let buckets = sqlx::query_as::<_, TimelineBucket>(&format!(
+        "{query} SELECT * FROM buckets ORDER BY start ASC LIMIT $5 OFFSET $6"
+    ))
+    .bind(&params.url)
+    .bind(params.from)
+    .bind(params.to)
+    .bind(bucket.as_str())
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(&state.pool)
+    .await?;
+
+    Ok((total, buckets))
+}
+
+// --- Change Magnitude Worker ---
+
+/// Fills `snapshot_changes` for captures that do not have a row yet
+pub struct ChangeMagnitudeWorker {
+    state: Arc<AppState>,
+    classifier: Classifier,
+    batch_size: i64,
+}
+
+impl ChangeMagnitudeWorker {
+    pub fn new(state: Arc<AppState>) -> Self {
+        Self {
+            state,
+            classifier: Classifier::new(),
+            batch_size: 50,
+        }
+    }
+
+    pub async fn run_loop(&self) {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            match self.process_batch().await {
+                Ok(0) => {}
+                Ok(count) => tracing::info!("Computed change magnitude for {} snapshots", count),
+                Err(e) => tracing::error!("Change magnitude error: {}", e),
+            }
+        }
+    }
+
+    async fn process_batch(&self) -> anyhow::Result<usize> {
+        let rows = sqlx::query(
+            r#"
+            SELECT s.id, s.url, s.timestamp, s.sha256, s.content_type,
+                   s.warc_file, s."offset", s.length,
+                   p.id AS previous_id, p.sha256 AS previous_sha256,
+                   p.warc_file AS previous_warc_file, p.offset AS previous_offset,
+                   p.length AS previous_length
+            FROM snapshots s
+            LEFT JOIN LATERAL (
+                SELECT prev.id, prev.sha256, prev.warc_file, prev."offset", prev.length FROM snapshots prev
+                WHERE prev.url = s.url AND prev.timestamp < s.timestamp
+                ORDER BY prev.timestamp DESC
+                LIMIT 1
+            ) p ON TRUE
+            WHERE NOT EXISTS (SELECT 1 FROM snapshot_changes c WHERE c.snapshot_id = s.id)
+            ORDER BY s.timestamp ASC
+            LIMIT $1
+            "#,
+        )
+        .bind(self.batch_size)
+        .fetch_all(&self.state.pool)
+        .await?;
+
+        let count = rows.len();
+        for row in rows {
+            let id: Uuid = row.get("id");
+            let previous_id: Option<Uuid> = row.get("previous_id");
+            let digest: String = row.get("sha256");
+            let previous_digest: Option<String> = row.get("previous_sha256");
+            let content_type: String = row.get("content_type");
+
+            let change = match previous_digest {
+                None => ChangeMagnitude::unchanged(),
+                Some(previous) if previous == digest => ChangeMagnitude::unchanged(),
+                Some(_) if !is_textual(&content_type) => ChangeMagnitude::combine(None, None, None),
+                // One unreadable capture must not hold back the rest of the batch
+                Some(_) => self.compare(&row).await.unwrap_or_else(|e| {
+                    tracing::warn!("Change magnitude of snapshot {} unknown: {}", id, e);
+                    ChangeMagnitude::unknown()
+                }),
+            };
+
+            // A visual score written first is kept and folded into the magnitude
+            sqlx::query(
+                r#"
+                INSERT INTO snapshot_changes
+                    (snapshot_id, url, timestamp, previous_id, text_diff_ratio, semantic_category, magnitude)
+                VALUES ($1, $2, $3, $4, $5, $6, $7)
+                ON CONFLICT (snapshot_id) DO UPDATE SET
+                    previous_id = EXCLUDED.previous_id,
+                    text_diff_ratio = EXCLUDED.text_diff_ratio,
+                    semantic_category = EXCLUDED.semantic_category,
+                    magnitude = GREATEST(EXCLUDED.magnitude, COALESCE(snapshot_changes.visual_diff_score, 0)),
+                    computed_at = NOW()
+                "#,
+            )
+            .bind(id)
+            .bind(row.get::<String, _>("url"))
+            .bind(row.get::<DateTime<Utc>, _>("timestamp"))
+            .bind(previous_id)
+            .bind(change.text_diff_ratio)
+            .bind(&change.semantic_category)
+            .bind(change.magnitude)
+            .execute(&self.state.pool)
+            .await?;
+        }
+
+        Ok(count)
+    }
+
+    /// Diff the response bodies of a row's capture and its predecessor;
+    /// status lines and headers such as `Date` change on every fetch
+    async fn compare(&self, row: &sqlx::postgres::PgRow) -> anyhow::Result<ChangeMagnitude> {
+        let current = self
+            .state
+            .warc_reader
+            .read_record(row.get("warc_file"), row.get("offset"), row.get("length"))
+            .await?;
+        let previous = self
+            .state
+            .warc_reader
+            .read_record(
+                row.get("previous_warc_file"),
+                row.get("previous_offset"),
+                row.get("previous_length"),
+            )
+            .await?;
+        let body = |record| {
+            HttpResponse::parse(strip_separator(record))
+                .map(|http| String::from_utf8_lossy(http.body))
+                .ok_or_else(|| anyhow::anyhow!("stored record is not an HTTP response"))
+        };
+        Ok(ChangeMagnitude::between(
+            &body(&previous)?,
+            &body(&current)?,
+            &self.classifier,
+        ))
+    }
+}
+
+fn is_textual(content_type: &str) -> bool {
+    let mime = content_type.to_ascii_lowercase();
+    mime.contains("html") || mime.starts_with("text/") || mime.contains("xml")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::test_state;
+    use archive_common::storage::{ArchiveStorage, FsStorage};
+    use archive_common::testing::scratch_pool;
+
+    #[test]
+    fn test_small_edits_are_not_full_rewrites() {
+        let classifier = Classifier::new();
+        let page = |body: &str| format!("<html><body><p>{}</p></body></html>", body);
+        let base =
+            "Our team shipped the quarterly report with charts tables and notes for every region";
+
+        let typo = ChangeMagnitude::between(
+            &page(base),
+            &page(&base.replace("notes", "note")),
+            &classifier,
+        );
+        let rewrite = ChangeMagnitude::between(
+            &page(base),
+            &page("Completely different article about gardening tomatoes in small balconies"),
+            &classifier,
+        );
+
+        assert!(typo.magnitude > 0.0 && typo.magnitude < 0.2);
+        assert!(rewrite.magnitude > 0.8);
+        assert_eq!(
+            ChangeMagnitude::between(&page(base), &page(base), &classifier).magnitude,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_intensity_prefers_precomputed_magnitude() {
+        assert_eq!(intensity(false, Some(0.9), None), MIN_INTENSITY);
+        assert_eq!(intensity(true, Some(0.3), Some((0, u64::MAX))), 0.3);
+        assert_eq!(intensity(true, None, Some((0, 0b1111))), 4.0 / 32.0);
+        assert_eq!(intensity(true, None, None), 1.0);
+
+        let price = ChangeMagnitude::combine(Some(0.05), None, Some(SemanticCategory::PriceChange));
+        assert_eq!(price.magnitude, SIGNIFICANT_CHANGE_FLOOR);
+        assert_eq!(
+            ChangeMagnitude::combine(Some(0.1), Some(0.7), None).magnitude,
+            0.7
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unreadable_capture_gets_unknown_magnitude_without_failing_the_batch() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let storage = Arc::new(FsStorage::new(
+            std::env::temp_dir().join(format!("timeline-{}", Uuid::new_v4())),
+        ));
+        let record = |text: &str| format!("WARC/1.1\r\n\r\nHTTP/1.1 200 OK\r\n\r\n<p>{}</p>", text);
+        let (first, second) = (record("hello world"), record("goodbye world"));
+        storage
+            .put(
+                "good.warc",
+                format!("{}{}", first, second).into_bytes().into(),
+            )
+            .await
+            .unwrap();
+
+        // (url, minutes ago, file, offset, length, digest)
+        let rows = [
+            ("https://a.com/", 40, "good.warc", 0, first.len(), "a1"),
+            ("https://a.com/", 30, "missing.warc", 0, 10, "a2"),
+            ("https://b.com/", 20, "good.warc", 0, first.len(), "b1"),
+            (
+                "https://b.com/",
+                10,
+                "good.warc",
+                first.len(),
+                second.len(),
+                "b2",
+            ),
+        ];
+        for (url, minutes, file, offset, length, digest) in rows {
+            sqlx::query(
+                r#"
+                INSERT INTO snapshots (id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type)
+                VALUES ($1, $2, NOW() - make_interval(mins => $3), $4, $5, $6, $7, 200, 'text/html')
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(url)
+            .bind(minutes)
+            .bind(file)
+            .bind(offset as i64)
+            .bind(length as i64)
+            .bind(digest)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let worker = ChangeMagnitudeWorker::new(test_state(pool.clone(), storage));
+        assert_eq!(worker.process_batch().await.unwrap(), 4);
+
+        let changes: Vec<(String, Option<String>, f32)> = sqlx::query_as(
+            r#"
+            SELECT s.sha256, c.semantic_category, c.magnitude
+            FROM snapshot_changes c JOIN snapshots s ON s.id = c.snapshot_id
+            ORDER BY s.sha256
+            "#,
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            changes[1],
+            ("a2".to_string(), Some("Unknown".to_string()), 1.0)
+        );
+        let (_, category, magnitude) = &changes[3];
+        assert_ne!(category.as_deref(), Some("Unknown"));
+        assert!(*magnitude > 0.0 && *magnitude < 1.0);
+    }
+
+    #[tokio::test]
+    async fn test_recaptures_differing_only_in_headers_are_unchanged() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let storage = Arc::new(FsStorage::new(
+            std::env::temp_dir().join(format!("timeline-{}", Uuid::new_v4())),
+        ));
+        let record = |date: &str, etag: &str| {
+            format!(
+                "WARC/1.1\r\n\r\nHTTP/1.1 200 OK\r\nDate: {}\r\nETag: \"{}\"\r\nContent-Type: text/html\r\n\r\n<p>hello world</p>\r\n\r\n",
+                date, etag
+            )
+        };
+        let first = record("Mon, 05 Jan 2026 10:00:00 GMT", "abc");
+        let second = record("Tue, 06 Jan 2026 10:00:00 GMT", "def");
+        storage
+            .put(
+                "pages.warc",
+                format!("{}{}", first, second).into_bytes().into(),
+            )
+            .await
+            .unwrap();
+
+        // Digests differ so the records are read and diffed
+        for (minutes, offset, length, digest) in [
+            (20, 0, first.len(), "d1"),
+            (10, first.len(), second.len(), "d2"),
+        ] {
+            sqlx::query(
+                r#"
+                INSERT INTO snapshots (id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type)
+                VALUES ($1, 'https://a.com/', NOW() - make_interval(mins => $2), 'pages.warc', $3, $4, $5, 200, 'text/html')
+                "#,
+            )
+            .bind(Uuid::new_v4())
+            .bind(minutes)
+            .bind(offset as i64)
+            .bind(length as i64)
+            .bind(digest)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let worker = ChangeMagnitudeWorker::new(test_state(pool.clone(), storage));
+        assert_eq!(worker.process_batch().await.unwrap(), 2);
+        let magnitude: f32 = sqlx::query_scalar(
+            "SELECT c.magnitude FROM snapshot_changes c JOIN snapshots s ON s.id = c.snapshot_id WHERE s.sha256 = 'd2'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(magnitude, 0.0);
+    }
+}
@@ -0,0 +1,338 @@
+use crate::fixity::{self, InclusionProof};
+use crate::AppState;
+use anyhow::{anyhow, Result};
+use archive_common::merkle::{self, Hash};
+use archive_timestamp::{verify_token, TsaClient};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::IntoResponse,
+    Json,
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::PgPool;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Manifests timestamped per round, so a TSA is not flooded after an outage
+const STAMP_BATCH: i64 = 20;
+
+fn decode_hash(hex_hash: &str) -> Result<Hash> {
+    hex::decode(hex_hash)?
+        .try_into()
+        .map_err(|_| anyhow!("hash is not 32 bytes"))
+}
+
+/// Countersign the roots of manifests that have no RFC 3161 token yet,
+/// oldest first. A manifest that fails is logged and backs off, doubling
+/// from 20 minutes up to a day, so it cannot hold back later ones; returns
+/// the number stamped
+pub async fn stamp_manifests(pool: &PgPool, tsa: &TsaClient) -> Result<usize> {
+    let pending: Vec<(i64, String)> = sqlx::query_as(
+        r#"
+        SELECT id, root FROM merkle_manifests
+        WHERE tsa_token IS NULL
+          AND (tsa_attempted_at IS NULL
+               OR tsa_attempted_at + INTERVAL '10 minutes' * LEAST(POWER(2, tsa_attempts), 144) <= NOW())
+        ORDER BY id
+        LIMIT $1
+        "#,
+    )
+    .bind(STAMP_BATCH)
+    .fetch_all(pool)
+    .await?;
+
+    let mut stamped = 0;
+    for (id, root) in &pending {
+        let result = async {
+            let (token, timestamp) = tsa.timestamp(&decode_hash(root)?).await?;
+            sqlx::query(
+                r#"
+                UPDATE merkle_manifests SET tsa_url = $2, tsa_token = $3, tsa_time = $4
+                WHERE id = $1 AND tsa_token IS NULL
+                "#,
+            )
+            .bind(id)
+            .bind(tsa.url())
+            .bind(&token)
+            .bind(timestamp.gen_time)
+            .execute(pool)
+            .await?;
+            anyhow::Ok(())
+        }
+        .await;
+        match result {
+            Ok(()) => stamped += 1,
+            Err(e) => {
+                tracing::warn!("Timestamping Merkle manifest {} failed: {}", id, e);
+                sqlx::query(
+                    "UPDATE merkle_manifests SET tsa_attempts = tsa_attempts + 1, tsa_attempted_at = NOW() WHERE id = $1",
+                )
+                .bind(id)
+                .execute(pool)
+                .await?;
+            }
+        }
+    }
+    Ok(stamped)
+}
+
+#[derive(Serialize)]
+struct TokenInfo {
+    gen_time: DateTime<Utc>,
+    serial_number: String,
+    policy: String,
+    signer: String,
+    trust_anchor: Option<String>,
+}
+
+#[derive(Serialize)]
+struct TimestampVerification {
+    /// Every check below passed and the token is trusted
+    verified: bool,
+    /// The token's signer chains to a `TSA_CA_FILE` certificate; without
+    /// one, a token signed by any certificate would pass the other checks
+    trusted: bool,
+    /// The snapshot row still hashes to the leaf in the manifest
+    leaf_matches: bool,
+    /// The audit path leads from the leaf to the manifest root
+    included: bool,
+    /// What the token attests, when its signature verifies over the root
+    timestamp: Option<TokenInfo>,
+    error: Option<String>,
+    tsa_url: Option<String>,
+    /// Base64 DER TimeStampToken over the manifest root
+    token: String,
+    proof: InclusionProof,
+}
+
+async fn verify_snapshot(
+    pool: &PgPool,
+    tsa: Option<&TsaClient>,
+    id: Uuid,
+) -> Result<Option<TimestampVerification>> {
+    let Some(proof) = fixity::inclusion_proof(pool, id).await? else {
+        return Ok(None);
+    };
+    let Some((tsa_url, token)) = sqlx::query_as::<_, (Option<String>, Vec<u8>)>(
+        "SELECT tsa_url, tsa_token FROM merkle_manifests WHERE id = $1 AND tsa_token IS NOT NULL",
+    )
+    .bind(proof.manifest.id)
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let (url, timestamp, sha256): (String, DateTime<Utc>, String) =
+        sqlx::query_as("SELECT url, timestamp, sha256 FROM snapshots WHERE id = $1")
+            .bind(id)
+            .fetch_one(pool)
+            .await?;
+    let leaf = merkle::leaf_hash(&merkle::snapshot_leaf(id, &url, timestamp, &sha256));
+    let leaf_matches = hex::encode(leaf) == proof.leaf_hash;
+
+    let root = decode_hash(&proof.manifest.root)?;
+    let audit_path = proof
+        .audit_path
+        .iter()
+        .map(|h| decode_hash(h))
+        .collect::<Result<Vec<_>>>()?;
+    let included = merkle::verify_inclusion(
+        &leaf,
+        proof.leaf_index as u64,
+        proof.manifest.tree_size as u64,
+        &audit_path,
+        &root,
+    );
+
+    // Without trust anchors the token's signature is still checked, but
+    // nothing ties its signer to a TSA we trust
+    let anchors = tsa.map(TsaClient::anchors).unwrap_or_default();
+    let (timestamp, error) = match verify_token(&token, &root, anchors) {
+        Ok(t) => (
+            Some(TokenInfo {
+                gen_time: t.gen_time,
+                serial_number: t.serial_number,
+                policy: t.policy,
+                signer: t.signer,
+                trust_anchor: t.trust_anchor,
+            }),
+            None,
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    };
+    let trusted = timestamp.as_ref().is_some_and(|t| t.trust_anchor.is_some());
+    let error = error
+        .or_else(|| (!leaf_matches).then(|| "snapshot no longer matches its leaf".to_string()))
+        .or_else(|| (!included).then(|| "leaf is not included under the root".to_string()))
+        .or_else(|| {
+            (!trusted)
+                .then(|| "no TSA_CA_FILE trust anchor to check the signer against".to_string())
+        });
+
+    Ok(Some(TimestampVerification {
+        verified: error.is_none(),
+        trusted,
+        leaf_matches,
+        included,
+        timestamp,
+        error,
+        tsa_url,
+        token: BASE64.encode(&token),
+        proof,
+    }))
+}
+
+/// GET /api/v1/snapshot/:id/timestamp — the RFC 3161 token over the root of
+/// the manifest covering a capture, verified together with its inclusion proof
+pub async fn get_snapshot_timestamp(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<Uuid>,
+) -> impl IntoResponse {
+    match verify_snapshot(&state.pool, state.tsa.as_ref(), id).await {
+        Ok(Some(verification)) => Json(verification).into_response(),
+        Ok(None) => (
+            StatusCode::NOT_FOUND,
+            "Snapshot is not covered by a timestamped manifest yet",
+        )
+            .into_response(),
+        Err(e) => {
+            tracing::error!("Timestamp verification error for {}: {}", id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Error verifying timestamp",
+            )
+                .into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixity::{FixityConfig, FixityWorker};
+    use crate::tests::test_state;
+    use archive_common::storage::FsStorage;
+    use archive_common::testing::scratch_pool;
+    use archive_timestamp::testing::TestTsa;
+    use axum::{body::Bytes, routing::post, Router};
+
+    /// Serve `tsa` over HTTP; returns its URL
+    async fn serve(tsa: Arc<TestTsa>) -> String {
+        let app = Router::new().route(
+            "/tsa",
+            post(move |body: Bytes| async move { tsa.reply(&body).unwrap() }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/tsa", addr)
+    }
+
+    /// A snapshot covered by a signed manifest
+    async fn signed_snapshot(pool: &PgPool) -> Uuid {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO snapshots (id, url, warc_file, "offset", length, sha256, status_code, content_type)
+            VALUES ($1, 'https://example.com/', 'a.warc', 0, 10, $2, 200, 'text/html')
+            "#,
+        )
+        .bind(id)
+        .bind("a".repeat(64))
+        .execute(pool)
+        .await
+        .unwrap();
+        let storage = Arc::new(FsStorage::new(std::env::temp_dir()));
+        let worker = FixityWorker::new(test_state(pool.clone(), storage), FixityConfig::from_env());
+        worker.sign_manifest().await.unwrap().unwrap();
+        id
+    }
+
+    #[tokio::test]
+    async fn test_verification_needs_a_trust_anchor_and_an_unchanged_capture() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let tsa = Arc::new(TestTsa::new().unwrap());
+        let url = serve(tsa.clone()).await;
+        let id = signed_snapshot(&pool).await;
+        let client = TsaClient::new(&url).with_anchors(vec![tsa.root()]);
+        assert_eq!(stamp_manifests(&pool, &client).await.unwrap(), 1);
+
+        let trusted = verify_snapshot(&pool, Some(&client), id)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(trusted.verified && trusted.trusted && trusted.leaf_matches && trusted.included);
+        assert_eq!(trusted.error, None);
+        assert_eq!(
+            trusted.timestamp.unwrap().trust_anchor.as_deref(),
+            Some("CN=ArchiveStream Test Root")
+        );
+
+        // Without TSA_CA_FILE the token still checks out, but is not trusted
+        for tsa in [Some(TsaClient::new(&url)), None] {
+            let untrusted = verify_snapshot(&pool, tsa.as_ref(), id)
+                .await
+                .unwrap()
+                .unwrap();
+            assert!(!untrusted.verified && !untrusted.trusted);
+            assert!(untrusted.leaf_matches && untrusted.included);
+            assert!(untrusted.error.unwrap().contains("TSA_CA_FILE"));
+        }
+
+        sqlx::query("UPDATE snapshots SET sha256 = $2 WHERE id = $1")
+            .bind(id)
+            .bind("b".repeat(64))
+            .execute(&pool)
+            .await
+            .unwrap();
+        let changed = verify_snapshot(&pool, Some(&client), id)
+            .await
+            .unwrap()
+            .unwrap();
+        // The token still checks out; the leaf rebuilt from the row does not
+        assert!(changed.trusted);
+        assert!(!changed.verified && !changed.leaf_matches && !changed.included);
+        assert_eq!(
+            changed.error.as_deref(),
+            Some("snapshot no longer matches its leaf")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_a_failing_manifest_backs_off_without_blocking_later_ones() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        // A root that is not a hash can never be timestamped
+        sqlx::query(
+            r#"
+            INSERT INTO merkle_manifests (root, tree_size, node_id, public_key, signature, created_at)
+            VALUES ('not-a-hash', 1, 'node', 'key', 'signature', NOW())
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        signed_snapshot(&pool).await;
+
+        let tsa = Arc::new(TestTsa::new().unwrap());
+        let client = TsaClient::new(&serve(tsa).await);
+        assert_eq!(stamp_manifests(&pool, &client).await.unwrap(), 1);
+        assert_eq!(stamp_manifests(&pool, &client).await.unwrap(), 0);
+
+        let attempts: Vec<(String, i32, bool)> = sqlx::query_as(
+            "SELECT root, tsa_attempts, tsa_token IS NOT NULL FROM merkle_manifests ORDER BY id",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(attempts[0], ("not-a-hash".to_string(), 1, false));
+        assert_eq!((attempts[1].1, attempts[1].2), (0, true));
+    }
+}
@@ -0,0 +1,490 @@
+use crate::export::{
+    capture_record, strip_separator, warcinfo_record, ExportCapture, ExportQuery, Selection,
+};
+use crate::replay::WarcReader;
+use crate::AppState;
+use archive_common::extractor::extract_text;
+use archive_common::surt::surt;
+use axum::{
+    body::Body,
+    extract::{Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use bytes::Bytes;
+use chrono::Utc;
+use ed25519_dalek::{Signer, SigningKey};
+use serde::Serialize;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use std::io::{Seek, Write};
+use std::sync::Arc;
+use tokio::io::AsyncReadExt;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+pub const WACZ_VERSION: &str = "1.1.1";
+
+/// Name of the single WARC inside a package
+const WARC_NAME: &str = "data.warc.gz";
+
+/// Title and description written to `datapackage.json`
+#[derive(Debug, Clone)]
+pub struct PackageInfo {
+    pub title: String,
+    pub description: String,
+}
+
+#[derive(Serialize)]
+struct Resource {
+    name: String,
+    path: String,
+    hash: String,
+    bytes: u64,
+}
+
+/// Writes a WACZ package: the WARC is streamed into the zip as captures are
+/// added; indexes, pages and the data package are written by [`WaczWriter::finish`].
+pub struct WaczWriter<W: Write + Seek> {
+    zip: ZipWriter<W>,
+    warc_hash: Sha256,
+    warc_bytes: u64,
+    /// `(sort key, line)` of each CDXJ entry
+    index: Vec<(String, String)>,
+    pages: Vec<serde_json::Value>,
+}
+
+impl<W: Write + Seek> WaczWriter<W> {
+    pub fn new(writer: W, warcinfo: &[u8]) -> anyhow::Result<Self> {
+        let mut zip = ZipWriter::new(writer);
+        // WARCs are already gzipped and must stay seekable, so they are stored
+        zip.start_file(
+            format!("archive/{}", WARC_NAME),
+            FileOptions::default()
+                .compression_method(CompressionMethod::Stored)
+                .large_file(true),
+        )?;
+        let mut wacz = Self {
+            zip,
+            warc_hash: Sha256::new(),
+            warc_bytes: 0,
+            index: Vec::new(),
+            pages: Vec::new(),
+        };
+        wacz.append_member(warcinfo)?;
+        Ok(wacz)
+    }
+
+    fn append_member(&mut self, member: &[u8]) -> std::io::Result<u64> {
+        let offset = self.warc_bytes;
+        self.zip.write_all(member)?;
+        self.warc_hash.update(member);
+        self.warc_bytes += member.len() as u64;
+        Ok(offset)
+    }
+
+    /// Add one response, or a revisit of `original`, with its stored HTTP message
+    pub fn add_capture(
+        &mut self,
+        capture: &ExportCapture,
+        original: Option<&ExportCapture>,
+        http: &[u8],
+    ) -> anyhow::Result<()> {
+        let member = capture_record(capture, original, http)?;
+        let offset = self.append_member(&member)?;
+
+        let timestamp = capture.timestamp.format("%Y%m%d%H%M%S").to_string();
+        let mime = match original {
+            Some(_) => "warc/revisit",
+            None => capture.content_type.as_str(),
+        };
+        let entry = json!({
+            "url": capture.url,
+            "mime": mime,
+            "status": capture.status_code.to_string(),
+            "digest": format!("sha256:{}", capture.sha256),
+            "length": member.len(),
+            "offset": offset,
+            "filename": WARC_NAME,
+        });
+        let key = format!("{} {}", surt(&capture.url), timestamp);
+        self.index.push((key.clone(), format!("{} {}", key, entry)));
+
+        let is_page = original.is_none()
+            && capture.content_type.contains("html")
+            && (200..300).contains(&capture.status_code);
+        if is_page {
+            let body = http
+                .windows(4)
+                .position(|w| w == b"\r\n\r\n")
+                .map(|end| &http[end + 4..])
+                .unwrap_or(http);
+            let title = extract_text(&String::from_utf8_lossy(body)).title;
+            let mut page = json!({
+                "id": capture.id.simple().to_string(),
+                "url": capture.url,
+                "ts": capture.timestamp.to_rfc3339(),
+            });
+            if !title.is_empty() {
+                page["title"] = json!(title);
+            }
+            self.pages.push(page);
+        }
+        Ok(())
+    }
+
+    pub fn captures(&self) -> usize {
+        self.index.len()
+    }
+
+    fn write_file(
+        &mut self,
+        path: &str,
+        content: &[u8],
+        resources: &mut Vec<Resource>,
+    ) -> anyhow::Result<()> {
+        self.zip.start_file(
+            path,
+            FileOptions::default().compression_method(CompressionMethod::Deflated),
+        )?;
+        self.zip.write_all(content)?;
+        resources.push(Resource {
+            name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            path: path.to_string(),
+            hash: format!("sha256:{:x}", Sha256::digest(content)),
+            bytes: content.len() as u64,
+        });
+        Ok(())
+    }
+
+    /// Write indexes, pages, `datapackage.json` and `datapackage-digest.json`,
+    /// signing the data package digest when a key is given
+    pub fn finish(mut self, info: &PackageInfo, key: Option<&SigningKey>) -> anyhow::Result<W> {
+        let mut resources = vec![Resource {
+            name: WARC_NAME.to_string(),
+            path: format!("archive/{}", WARC_NAME),
+            hash: format!("sha256:{:x}", self.warc_hash.clone().finalize()),
+            bytes: self.warc_bytes,
+        }];
+
+        let mut index = std::mem::take(&mut self.index);
+        index.sort();
+        let cdxj = index
+            .into_iter()
+            .map(|(_, line)| line + "\n")
+            .collect::<String>();
+        self.write_file("indexes/index.cdxj", cdxj.as_bytes(), &mut resources)?;
+
+        let mut pages = json!({"format": "json-pages-1.0", "id": "pages", "title": "All Pages"})
+            .to_string()
+            + "\n";
+        for page in std::mem::take(&mut self.pages) {
+            pages.push_str(&page.to_string());
+            pages.push('\n');
+        }
+        self.write_file("pages/pages.jsonl", pages.as_bytes(), &mut resources)?;
+
+        let created = Utc::now().to_rfc3339();
+        let software = format!("ArchiveStream {}", env!("CARGO_PKG_VERSION"));
+        let datapackage = serde_json::to_vec_pretty(&json!({
+            "profile": "data-package",
+            "wacz_version": WACZ_VERSION,
+            "title": info.title,
+            "description": info.description,
+            "created": created,
+            "software": software,
+            "resources": resources,
+        }))?;
+        let datapackage_hash = format!("sha256:{:x}", Sha256::digest(&datapackage));
+        self.zip.start_file(
+            "datapackage.json",
+            FileOptions::default().compression_method(CompressionMethod::Deflated),
+        )?;
+        self.zip.write_all(&datapackage)?;
+
+        let mut digest = json!({"path": "datapackage.json", "hash": datapackage_hash});
+        if let Some(key) = key {
+            let signature = key.sign(datapackage_hash.as_bytes());
+            digest["signedData"] = json!({
+                "hash": datapackage_hash,
+                "created": created,
+                "software": software,
+                "algorithm": "ed25519",
+                "publicKey": BASE64.encode(key.verifying_key().to_bytes()),
+                "signature": BASE64.encode(signature.to_bytes()),
+            });
+        }
+        self.zip.start_file(
+            "datapackage-digest.json",
+            FileOptions::default().compression_method(CompressionMethod::Deflated),
+        )?;
+        self.zip.write_all(&serde_json::to_vec_pretty(&digest)?)?;
+
+        Ok(self.zip.finish()?)
+    }
+}
+
+/// Ed25519 key from `WACZ_SIGNING_KEY` (hex-encoded 32-byte seed), if set
+pub fn signing_key_from_env() -> anyhow::Result<Option<SigningKey>> {
+    let Ok(seed) = std::env::var("WACZ_SIGNING_KEY") else {
+        return Ok(None);
+    };
+    let seed: [u8; 32] = hex::decode(seed.trim())?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("WACZ_SIGNING_KEY must be 32 bytes of hex"))?;
+    Ok(Some(SigningKey::from_bytes(&seed)))
+}
+
+/// Package every capture of `selection` into `writer`
+pub async fn build_package<W: Write + Seek>(
+    pool: &PgPool,
+    reader: &WarcReader,
+    selection: &Selection,
+    info: &PackageInfo,
+    key: Option<&SigningKey>,
+    writer: W,
+) -> anyhow::Result<(W, usize)> {
+    let plan = selection.plan(pool).await?;
+    let warcinfo = warcinfo_record(WARC_NAME, &info.description)?;
+    let mut wacz = WaczWriter::new(writer, &warcinfo)?;
+    for (capture, original) in &plan {
+        let http = reader
+            .read_record(&capture.warc_file, capture.offset, capture.length)
+            .await?;
+        wacz.add_capture(capture, original.as_ref(), strip_separator(&http))?;
+    }
+    let captures = wacz.captures();
+    Ok((wacz.finish(info, key)?, captures))
+}
+
+/// Title of a collection, falling back to a description of the selection
+async fn package_info(pool: &PgPool, selection: &Selection) -> PackageInfo {
+    let collection = match &selection.collection {
+        Some(id) => sqlx::query_as::<_, (String, Option<String>)>(
+            "SELECT title, description FROM collections WHERE id = $1",
+        )
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+        .ok()
+        .flatten(),
+        None => None,
+    };
+    match collection {
+        Some((title, description)) => PackageInfo {
+            title,
+            description: description.unwrap_or_else(|| selection.describe()),
+        },
+        None => PackageInfo {
+            title: format!("ArchiveStream export ({})", selection.describe()),
+            description: format!("Captures selected by {}", selection.describe()),
+        },
+    }
+}
+
+/// GET /api/v1/export/wacz?job=|collection=|prefix=|host=&from=&to=
+/// Builds a WACZ package, signed when `WACZ_SIGNING_KEY` is set
+pub async fn export_wacz(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<ExportQuery>,
+) -> impl IntoResponse {
+    let selection = match Selection::parse(
+        params.prefix,
+        params.host,
+        params.job,
+        params.collection,
+        params.from.as_deref(),
+        params.to.as_deref(),
+    ) {
+        Ok(selection) => selection,
+        Err(message) => return (StatusCode::BAD_REQUEST, message).into_response(),
+    };
+    let key = match signing_key_from_env() {
+        Ok(key) => key,
+        Err(e) => {
+            tracing::error!("WACZ signing key error: {}", e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "Signing key misconfigured",
+            )
+                .into_response();
+        }
+    };
+
+    // Packages can be large; build on disk and stream the file
+    let path = std::env::temp_dir().join(format!("archivestream-{}.wacz", uuid::Uuid::new_v4()));
+    let info = package_info(&state.pool, &selection).await;
+    let built = match std::fs::File::create(&path) {
+        Ok(file) => {
+            build_package(
+                &state.pool,
+                &state.warc_reader,
+                &selection,
+                &info,
+                key.as_ref(),
+                file,
+            )
+            .await
+        }
+        Err(e) => Err(e.into()),
+    };
+    if let Err(e) = built {
+        tracing::error!("WACZ build error: {}", e);
+        let _ = std::fs::remove_file(&path);
+        return (StatusCode::INTERNAL_SERVER_ERROR, "WACZ build failed").into_response();
+    }
+
+    let file = match tokio::fs::File::open(&path).await {
+        Ok(file) => file,
+        Err(e) => {
+            tracing::error!("WACZ read error: {}", e);
+            return (StatusCode::INTERNAL_SERVER_ERROR, "WACZ build failed").into_response();
+        }
+    };
+    let stream = futures::stream::unfold(Some((file, path)), |state| async move {
+        let (mut file, path) = state?;
+        let mut chunk = vec![0u8; 64 * 1024];
+        match file.read(&mut chunk).await {
+            Ok(0) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                None
+            }
+            Ok(n) => {
+                chunk.truncate(n);
+                Some((Ok(Bytes::from(chunk)), Some((file, path))))
+            }
+            Err(e) => {
+                let _ = tokio::fs::remove_file(&path).await;
+                Some((Err(e), None))
+            }
+        }
+    });
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/wacz")
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!(
+                "attachment; filename=\"archivestream-{}.wacz\"",
+                Utc::now().format("%Y%m%d%H%M%S")
+            ),
+        )
+        .body(Body::from_stream(stream))
+        .unwrap()
+        .into_response()
+}
+
+/// `archive-api wacz (--job <id> | --collection <id> | --prefix <url> | --host <host>)
+///  [--from <ts>] [--to <ts>] [--title <title>] --out <file.wacz>`
+pub async fn cli(pool: PgPool, reader: WarcReader, args: &[String]) -> anyhow::Result<()> {
+    let flag = |name: &str| {
+        args.iter()
+            .position(|a| a == name)
+            .and_then(|i| args.get(i + 1))
+            .cloned()
+    };
+    let out = flag("--out").ok_or_else(|| anyhow::anyhow!("--out <file.wacz> is required"))?;
+    let selection = Selection::parse(
+        flag("--prefix"),
+        flag("--host"),
+        flag("--job"),
+        flag("--collection"),
+        flag("--from").as_deref(),
+        flag("--to").as_deref(),
+    )
+    .map_err(anyhow::Error::msg)?;
+
+    let mut info = package_info(&pool, &selection).await;
+    if let Some(title) = flag("--title") {
+        info.title = title;
+    }
+    let key = signing_key_from_env()?;
+    let file = std::fs::File::create(&out)?;
+    let (_, captures) =
+        build_package(&pool, &reader, &selection, &info, key.as_ref(), file).await?;
+    tracing::info!(
+        "Wrote {} captures to {}{}",
+        captures,
+        out,
+        if key.is_some() { " (signed)" } else { "" }
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+    use ed25519_dalek::{Signature, Verifier};
+    use std::io::{Cursor, Read};
+    use uuid::Uuid;
+
+    #[test]
+    fn test_package_layout_and_signature() {
+        let capture = ExportCapture {
+            id: Uuid::from_u128(7),
+            url: "https://example.com/".to_string(),
+            timestamp: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+            warc_file: "a.warc".to_string(),
+            offset: 0,
+            length: 0,
+            sha256: "ab".repeat(32),
+            status_code: 200,
+            content_type: "text/html".to_string(),
+            revisit_of: None,
+            revisit_profile: None,
+        };
+        let http = b"HTTP/1.1 200 OK\r\nContent-Type: text/html\r\n\r\n<html><head><title>Evidence</title></head><body>hi</body></html>";
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let info = PackageInfo {
+            title: "Test".to_string(),
+            description: "test package".to_string(),
+        };
+
+        let warcinfo = warcinfo_record(WARC_NAME, "test").unwrap();
+        let mut wacz = WaczWriter::new(Cursor::new(Vec::new()), &warcinfo).unwrap();
+        wacz.add_capture(&capture, None, http).unwrap();
+        let bytes = wacz.finish(&info, Some(&key)).unwrap().into_inner();
+
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut read = |name: &str| {
+            let mut content = Vec::new();
+            archive
+                .by_name(name)
+                .unwrap()
+                .read_to_end(&mut content)
+                .unwrap();
+            content
+        };
+
+        let warc = read("archive/data.warc.gz");
+        let cdxj = String::from_utf8(read("indexes/index.cdxj")).unwrap();
+        assert!(cdxj.starts_with("com,example)/ 20231114221320 {"));
+        assert!(cdxj.contains(&format!("\"offset\":{}", warcinfo.len())));
+        let pages = String::from_utf8(read("pages/pages.jsonl")).unwrap();
+        assert!(pages.contains("\"title\":\"Evidence\""));
+
+        let datapackage = read("datapackage.json");
+        let package: serde_json::Value = serde_json::from_slice(&datapackage).unwrap();
+        assert_eq!(
+            package["resources"][0]["hash"],
+            format!("sha256:{:x}", Sha256::digest(&warc))
+        );
+
+        let digest: serde_json::Value =
+            serde_json::from_slice(&read("datapackage-digest.json")).unwrap();
+        let hash = format!("sha256:{:x}", Sha256::digest(&datapackage));
+        assert_eq!(digest["hash"], hash);
+        let signature = BASE64
+            .decode(digest["signedData"]["signature"].as_str().unwrap())
+            .unwrap();
+        let signature = Signature::from_slice(&signature).unwrap();
+        assert!(key
+            .verifying_key()
+            .verify(hash.as_bytes(), &signature)
+            .is_ok());
+    }
+}
@@ -3,6 +3,12 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 pub mod extractor;
+pub mod merkle;
+pub mod simhash;
+pub mod storage;
+pub mod surt;
+#[cfg(feature = "testing")]
+pub mod testing;
 pub mod tracing;
 pub mod zerocopy;
 
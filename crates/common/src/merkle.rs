@@ -0,0 +1,150 @@
+//! RFC 6962 Merkle trees: leaves and interior nodes are hashed with
+//! distinct prefixes, so an inclusion proof cannot pass a node off as a leaf
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+pub type Hash = [u8; 32];
+
+pub fn leaf_hash(data: &[u8]) -> Hash {
+    Sha256::new()
+        .chain_update([0u8])
+        .chain_update(data)
+        .finalize()
+        .into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    Sha256::new()
+        .chain_update([1u8])
+        .chain_update(left)
+        .chain_update(right)
+        .finalize()
+        .into()
+}
+
+/// Bytes a capture's leaf commits to: its id, URL, capture time and payload
+/// digest, one per line
+pub fn snapshot_leaf(id: Uuid, url: &str, timestamp: DateTime<Utc>, sha256: &str) -> Vec<u8> {
+    format!(
+        "{}\n{}\n{}\n{}",
+        id,
+        url,
+        timestamp.to_rfc3339_opts(SecondsFormat::Micros, true),
+        sha256
+    )
+    .into_bytes()
+}
+
+/// Largest power of two strictly below `n`, for `n > 1`
+fn split(n: usize) -> usize {
+    1 << (usize::BITS - 1 - (n - 1).leading_zeros())
+}
+
+/// Root over leaf hashes; the empty tree hashes the empty string
+pub fn root(leaves: &[Hash]) -> Hash {
+    match leaves.len() {
+        0 => Sha256::digest([]).into(),
+        1 => leaves[0],
+        n => {
+            let k = split(n);
+            node_hash(&root(&leaves[..k]), &root(&leaves[k..]))
+        }
+    }
+}
+
+/// Audit path for the leaf at `index`, nearest sibling first
+pub fn inclusion_proof(leaves: &[Hash], index: usize) -> Vec<Hash> {
+    let n = leaves.len();
+    if n <= 1 || index >= n {
+        return Vec::new();
+    }
+    let k = split(n);
+    let (mut path, sibling) = if index < k {
+        (inclusion_proof(&leaves[..k], index), root(&leaves[k..]))
+    } else {
+        (inclusion_proof(&leaves[k..], index - k), root(&leaves[..k]))
+    };
+    path.push(sibling);
+    path
+}
+
+/// Check that `leaf` sits at `index` of a tree of `size` leaves with `root`
+/// (RFC 9162 section 2.1.3.2)
+pub fn verify_inclusion(leaf: &Hash, index: u64, size: u64, proof: &[Hash], root: &Hash) -> bool {
+    if index >= size {
+        return false;
+    }
+    let (mut fn_, mut sn) = (index, size - 1);
+    let mut r = *leaf;
+    for p in proof {
+        if sn == 0 {
+            return false;
+        }
+        if fn_ & 1 == 1 || fn_ == sn {
+            r = node_hash(p, &r);
+            while fn_ & 1 == 0 && fn_ != 0 {
+                fn_ >>= 1;
+                sn >>= 1;
+            }
+        } else {
+            r = node_hash(&r, p);
+        }
+        fn_ >>= 1;
+        sn >>= 1;
+    }
+    sn == 0 && r == *root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_root_matches_rfc6962_vectors_and_proofs_verify() {
+        // The leaf inputs of the Certificate Transparency reference tests
+        let inputs: [&[u8]; 8] = [
+            b"",
+            b"\x00",
+            b"\x10",
+            b"\x20\x21",
+            b"\x30\x31",
+            b"\x40\x41\x42\x43",
+            b"\x50\x51\x52\x53\x54\x55\x56\x57",
+            b"\x60\x61\x62\x63\x64\x65\x66\x67\x68\x69\x6a\x6b\x6c\x6d\x6e\x6f",
+        ];
+        let leaves: Vec<Hash> = inputs.iter().map(|d| leaf_hash(d)).collect();
+        assert_eq!(
+            hex::encode(root(&leaves)),
+            "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328"
+        );
+        assert_eq!(
+            hex::encode(root(&leaves[..1])),
+            "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d"
+        );
+
+        for size in 1..=leaves.len() {
+            let tree = &leaves[..size];
+            let tree_root = root(tree);
+            for (index, leaf) in tree.iter().enumerate() {
+                let proof = inclusion_proof(tree, index);
+                let (index, size) = (index as u64, size as u64);
+                assert!(verify_inclusion(leaf, index, size, &proof, &tree_root));
+                let tampered = leaf_hash(b"tampered");
+                assert!(!verify_inclusion(
+                    &tampered, index, size, &proof, &tree_root
+                ));
+                if size > 1 {
+                    assert!(!verify_inclusion(
+                        leaf,
+                        (index + 1) % size,
+                        size,
+                        &proof,
+                        &tree_root
+                    ));
+                }
+            }
+        }
+    }
+}
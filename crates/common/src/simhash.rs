@@ -0,0 +1,77 @@
+//! 64-bit SimHash over word shingles, for spotting near-duplicate captures.
+
+/// Words per shingle; 3 keeps word order without making one edited word
+/// disturb too many features
+const SHINGLE_WORDS: usize = 3;
+
+/// FNV-1a, stable across platforms and releases
+fn fnv1a(bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf2_9ce4_8422_2325u64;
+    for b in bytes {
+        hash ^= *b as u64;
+        hash = hash.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    hash
+}
+
+/// SimHash of already-extracted text. Similar texts differ in few bits.
+pub fn simhash(text: &str) -> u64 {
+    let words = text.split_whitespace().collect::<Vec<_>>();
+    let mut weights = [0i64; 64];
+
+    let mut add = |feature: &str| {
+        let hash = fnv1a(feature.as_bytes());
+        for (bit, weight) in weights.iter_mut().enumerate() {
+            if hash >> bit & 1 == 1 {
+                *weight += 1;
+            } else {
+                *weight -= 1;
+            }
+        }
+    };
+
+    if words.len() < SHINGLE_WORDS {
+        for word in &words {
+            add(word);
+        }
+    } else {
+        for shingle in words.windows(SHINGLE_WORDS) {
+            add(&shingle.join(" "));
+        }
+    }
+
+    weights
+        .iter()
+        .enumerate()
+        .filter(|(_, w)| **w > 0)
+        .fold(0u64, |acc, (bit, _)| acc | 1 << bit)
+}
+
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// `1.0` for identical fingerprints down to `0.0` when every bit differs
+pub fn similarity(a: u64, b: u64) -> f32 {
+    1.0 - hamming_distance(a, b) as f32 / 64.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_near_duplicates_are_close() {
+        let base = "the city council voted on tuesday to approve the new budget for parks \
+                    libraries and road repairs after a long public hearing with residents";
+        let ad_rotated = format!("{} sponsored by acme", base);
+        let rewritten = "a storm knocked out power across the region overnight and crews \
+                         expect repairs to take several days according to the utility";
+
+        let a = simhash(base);
+        assert_eq!(hamming_distance(a, simhash(base)), 0);
+        assert!(hamming_distance(a, simhash(&ad_rotated)) <= 8);
+        assert!(hamming_distance(a, simhash(rewritten)) > 16);
+        assert_eq!(similarity(a, a), 1.0);
+    }
+}
@@ -0,0 +1,189 @@
+use super::{check_name, ArchiveStorage, ObjectInfo};
+use crate::zerocopy::ZeroCopyWarcReader;
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// WARC files in a local directory, read through memory maps
+pub struct FsStorage {
+    root: PathBuf,
+    /// Open maps by object name, dropped when the object is replaced
+    readers: Mutex<HashMap<String, Arc<ZeroCopyWarcReader>>>,
+}
+
+impl FsStorage {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            readers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Rooted at `ARCHIVE_DATA_DIR` (default `data/archive`)
+    pub fn from_env() -> Self {
+        Self::new(std::env::var("ARCHIVE_DATA_DIR").unwrap_or_else(|_| "data/archive".into()))
+    }
+
+    fn path(&self, name: &str) -> Result<PathBuf> {
+        check_name(name)?;
+        Ok(self.root.join(name))
+    }
+
+    /// Writes land in a hidden temporary file first, so readers never map a
+    /// half-written object
+    fn staging_path(&self, name: &str) -> PathBuf {
+        self.root
+            .join(format!(".{}.{}.tmp", name, Uuid::new_v4().simple()))
+    }
+
+    async fn replace(&self, name: &str, staged: &Path) -> Result<()> {
+        let path = self.path(name)?;
+        if let Err(e) = tokio::fs::rename(staged, &path).await {
+            let _ = tokio::fs::remove_file(staged).await;
+            return Err(e.into());
+        }
+        self.readers.lock().unwrap().remove(name);
+        Ok(())
+    }
+
+    async fn reader(&self, name: &str, reopen: bool) -> Result<Arc<ZeroCopyWarcReader>> {
+        if !reopen {
+            if let Some(reader) = self.readers.lock().unwrap().get(name) {
+                return Ok(reader.clone());
+            }
+        }
+        let path = self.path(name)?;
+        let reader =
+            Arc::new(tokio::task::spawn_blocking(move || ZeroCopyWarcReader::open(path)).await??);
+        self.readers
+            .lock()
+            .unwrap()
+            .insert(name.to_string(), reader.clone());
+        Ok(reader)
+    }
+}
+
+#[async_trait]
+impl ArchiveStorage for FsStorage {
+    async fn put(&self, name: &str, data: Bytes) -> Result<()> {
+        check_name(name)?;
+        tokio::fs::create_dir_all(&self.root).await?;
+        let staged = self.staging_path(name);
+        tokio::fs::write(&staged, &data).await?;
+        self.replace(name, &staged).await
+    }
+
+    async fn put_file(&self, name: &str, path: &Path) -> Result<()> {
+        check_name(name)?;
+        tokio::fs::create_dir_all(&self.root).await?;
+        let staged = self.staging_path(name);
+        tokio::fs::copy(path, &staged).await?;
+        self.replace(name, &staged).await
+    }
+
+    async fn get_range(&self, name: &str, offset: u64, length: u64) -> Result<Bytes> {
+        let end = offset + length;
+        let mut reader = self.reader(name, false).await?;
+        // A map taken before another process replaced the file is stale
+        if end > reader.len() as u64 {
+            reader = self.reader(name, true).await?;
+        }
+        if end > reader.len() as u64 {
+            bail!(
+                "range {}..{} is past the end of {} ({} bytes)",
+                offset,
+                end,
+                name,
+                reader.len()
+            );
+        }
+        Ok(Bytes::copy_from_slice(reader.read_record(offset, length)))
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let mut entries = match tokio::fs::read_dir(&self.root).await {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+        let mut objects = Vec::new();
+        while let Some(entry) = entries.next_entry().await? {
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            let metadata = entry.metadata().await?;
+            if name.starts_with('.') || !name.starts_with(prefix) || !metadata.is_file() {
+                continue;
+            }
+            objects.push(ObjectInfo {
+                name,
+                size: metadata.len(),
+            });
+        }
+        objects.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(objects)
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        let path = self.path(name)?;
+        self.readers.lock().unwrap().remove(name);
+        match tokio::fs::remove_file(path).await {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.into()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_put_ranged_get_list_and_delete() {
+        let root = std::env::temp_dir().join(format!("archive-fs-{}", Uuid::new_v4().simple()));
+        let storage = FsStorage::new(&root);
+
+        storage
+            .put("a.warc", Bytes::from_static(b"WARC/1.1\r\n\r\nfirst"))
+            .await
+            .unwrap();
+        assert_eq!(
+            &storage.get_range("a.warc", 12, 5).await.unwrap()[..],
+            b"first"
+        );
+        assert!(storage.get_range("a.warc", 12, 6).await.is_err());
+
+        // A replaced object is read afresh
+        storage
+            .put("a.warc", Bytes::from_static(b"WARC/1.1\r\n\r\nsecond"))
+            .await
+            .unwrap();
+        assert_eq!(
+            &storage.get_range("a.warc", 12, 6).await.unwrap()[..],
+            b"second"
+        );
+
+        storage
+            .put("b.warc", Bytes::from_static(b"x"))
+            .await
+            .unwrap();
+        let listed = storage.list("a").await.unwrap();
+        assert_eq!(
+            listed,
+            vec![ObjectInfo {
+                name: "a.warc".into(),
+                size: 18
+            }]
+        );
+
+        assert!(storage.put("../escape", Bytes::new()).await.is_err());
+        storage.delete("a.warc").await.unwrap();
+        storage.delete("a.warc").await.unwrap();
+        assert_eq!(storage.list("").await.unwrap().len(), 1);
+        std::fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,96 @@
+use super::{check_length, check_name, ArchiveStorage, ObjectInfo};
+use anyhow::Result;
+use archive_ipfs::{IpfsObject, IpfsStorage};
+use async_trait::async_trait;
+use bytes::Bytes;
+
+/// WARC files in a directory of a Kubo node's MFS, added as CIDv1 with raw
+/// leaves so their CIDs match `archive_ipfs::generate_cid`
+pub struct IpfsArchiveStorage {
+    client: IpfsStorage,
+    root: String,
+}
+
+impl IpfsArchiveStorage {
+    pub fn new(client: IpfsStorage, root: &str) -> Self {
+        Self {
+            client,
+            root: root.trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// `IPFS_API_URL`, optional `IPFS_GATEWAY_URL` for reads, and the MFS
+    /// directory `IPFS_MFS_ROOT` (default `/archivestream`)
+    pub fn from_env() -> Result<Self> {
+        let api_url =
+            std::env::var("IPFS_API_URL").unwrap_or_else(|_| "http://localhost:5001".into());
+        let mut client = IpfsStorage::new(&api_url)?;
+        if let Ok(gateway) = std::env::var("IPFS_GATEWAY_URL") {
+            if !gateway.is_empty() {
+                client = client.with_gateway(&gateway);
+            }
+        }
+        let root = std::env::var("IPFS_MFS_ROOT").unwrap_or_else(|_| "/archivestream".into());
+        Ok(Self::new(client, &root))
+    }
+
+    pub fn client(&self) -> &IpfsStorage {
+        &self.client
+    }
+
+    /// Every stored object with its current CID
+    pub async fn objects(&self) -> Result<Vec<IpfsObject>> {
+        self.client.list_files(&self.root).await
+    }
+
+    fn path(&self, name: &str) -> Result<String> {
+        check_name(name)?;
+        Ok(format!("{}/{}", self.root, name))
+    }
+}
+
+#[async_trait]
+impl ArchiveStorage for IpfsArchiveStorage {
+    async fn put(&self, name: &str, data: Bytes) -> Result<()> {
+        let path = self.path(name)?;
+        let cid = self.client.write_file(&path, data.to_vec()).await?;
+        let expected = archive_ipfs::generate_cid(&data);
+        if cid != expected {
+            tracing::warn!(
+                "IPFS node stored {} as {} where {} was computed locally",
+                name,
+                cid,
+                expected
+            );
+        }
+        Ok(())
+    }
+
+    async fn get_range(&self, name: &str, offset: u64, length: u64) -> Result<Bytes> {
+        let data = self
+            .client
+            .read_file(&self.path(name)?, offset, length)
+            .await?;
+        check_length(name, offset, length, &data)?;
+        Ok(data)
+    }
+
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>> {
+        let mut objects: Vec<ObjectInfo> = self
+            .objects()
+            .await?
+            .into_iter()
+            .filter(|o| o.name.starts_with(prefix))
+            .map(|o| ObjectInfo {
+                name: o.name,
+                size: o.size,
+            })
+            .collect();
+        objects.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(objects)
+    }
+
+    async fn delete(&self, name: &str) -> Result<()> {
+        self.client.remove_file(&self.path(name)?).await
+    }
+}
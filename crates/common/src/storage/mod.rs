@@ -0,0 +1,82 @@
+//! Where WARC files live. Every reader and writer goes through
+//! [`ArchiveStorage`]; `ARCHIVE_STORAGE` picks the backend
+
+mod fs;
+mod ipfs;
+mod s3;
+
+pub use fs::FsStorage;
+pub use ipfs::IpfsArchiveStorage;
+pub use s3::S3Storage;
+
+use anyhow::{bail, Result};
+use async_trait::async_trait;
+use bytes::Bytes;
+use std::path::Path;
+use std::sync::Arc;
+
+/// A stored object and its size in bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectInfo {
+    pub name: String,
+    pub size: u64,
+}
+
+/// Flat namespace of named, immutable-once-written objects
+#[async_trait]
+pub trait ArchiveStorage: Send + Sync {
+    /// Store `data` under `name`, replacing any object already there
+    async fn put(&self, name: &str, data: Bytes) -> Result<()>;
+
+    /// Store the file at `path` under `name`
+    async fn put_file(&self, name: &str, path: &Path) -> Result<()> {
+        self.put(name, tokio::fs::read(path).await?.into()).await
+    }
+
+    /// `length` bytes of `name` starting at `offset`; a short read is an error
+    async fn get_range(&self, name: &str, offset: u64, length: u64) -> Result<Bytes>;
+
+    /// Objects whose names start with `prefix`
+    async fn list(&self, prefix: &str) -> Result<Vec<ObjectInfo>>;
+
+    /// Remove `name`; a missing object is not an error
+    async fn delete(&self, name: &str) -> Result<()>;
+}
+
+/// The backend named by `ARCHIVE_STORAGE`: `fs`, `s3` (default) or `ipfs`
+pub fn from_env() -> Result<Arc<dyn ArchiveStorage>> {
+    let backend = std::env::var("ARCHIVE_STORAGE").unwrap_or_else(|_| "s3".into());
+    Ok(match backend.as_str() {
+        "fs" => Arc::new(FsStorage::from_env()),
+        "s3" => Arc::new(S3Storage::from_env()),
+        "ipfs" => Arc::new(IpfsArchiveStorage::from_env()?),
+        other => bail!("unknown ARCHIVE_STORAGE {}", other),
+    })
+}
+
+/// Object names are plain file names, so no backend can be steered outside
+/// its root
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty()
+        || name.starts_with('.')
+        || name.contains(['/', '\\'])
+        || name.chars().any(|c| c.is_control())
+    {
+        bail!("invalid object name {:?}", name);
+    }
+    Ok(())
+}
+
+/// A range read must return exactly what was asked for
+fn check_length(name: &str, offset: u64, length: u64, data: &Bytes) -> Result<()> {
+    if data.len() as u64 != length {
+        bail!(
+            "expected {} bytes of {} at {}, got {}",
+            length,
+            name,
+            offset,
+            data.len()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,38 @@
+/// Canonical, sort-friendly URL key (SURT), as used by CDXJ: `com,example)/path?query`
+pub fn surt(url: &str) -> String {
+    let Ok(parsed) = url::Url::parse(url) else {
+        return url.to_lowercase();
+    };
+    let host = parsed.host_str().unwrap_or_default();
+    let host = host.strip_prefix("www.").unwrap_or(host);
+    let mut key = host.split('.').rev().collect::<Vec<_>>().join(",");
+    if let Some(port) = parsed.port() {
+        key.push_str(&format!(":{}", port));
+    }
+    key.push(')');
+    key.push_str(parsed.path());
+    if let Some(query) = parsed.query() {
+        let mut params = query.split('&').collect::<Vec<_>>();
+        params.sort_unstable();
+        key.push('?');
+        key.push_str(&params.join("&"));
+    }
+    key.to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_surt_keys() {
+        assert_eq!(
+            surt("https://www.Example.com/Path?b=2&a=1"),
+            "com,example)/path?a=1&b=2"
+        );
+        assert_eq!(
+            surt("http://news.example.org:8080/"),
+            "org,example,news:8080)/"
+        );
+    }
+}
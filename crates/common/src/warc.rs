@@ -1,6 +1,5 @@
+use crate::storage::ArchiveStorage;
 use chrono::Utc;
-use std::fs::OpenOptions;
-use std::io::Write;
 use uuid::Uuid;
 
 pub struct WarcRecord {
@@ -40,26 +39,45 @@ impl WarcRecord {
     }
 }
 
+/// Collects records into one WARC that is stored as a single object
 pub struct WarcWriter {
-    file_path: String,
+    name: String,
+    buffer: Vec<u8>,
 }
 
 impl WarcWriter {
-    pub fn new(file_path: String) -> Self {
-        Self { file_path }
+    pub fn new(name: String) -> Self {
+        Self {
+            name,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.buffer.is_empty()
     }
 
     /// Appends a record to the WARC file and returns (offset, length)
-    pub fn write_record(&mut self, record: &WarcRecord) -> std::io::Result<(u64, u64)> {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(&self.file_path)?;
+    pub fn write_record(&mut self, record: &WarcRecord) -> (u64, u64) {
+        self.write_bytes(&record.to_warc_bytes())
+    }
 
-        let pos = file.metadata()?.len();
-        let bytes = record.to_warc_bytes();
-        file.write_all(&bytes)?;
+    /// Appends an already serialized record and returns (offset, length)
+    pub fn write_bytes(&mut self, record: &[u8]) -> (u64, u64) {
+        let pos = self.buffer.len() as u64;
+        self.buffer.extend_from_slice(record);
+        (pos, record.len() as u64)
+    }
 
-        Ok((pos, bytes.len() as u64))
+    /// Stores the WARC; an empty one is not stored
+    pub async fn finish(self, storage: &dyn ArchiveStorage) -> anyhow::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        storage.put(&self.name, self.buffer.into()).await
     }
 }
@@ -24,6 +24,15 @@ impl ZeroCopyWarcReader {
         Ok(Self { mmap, index })
     }
 
+    /// Size of the mapped file in bytes
+    pub fn len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mmap.is_empty()
+    }
+
     /// Read a WARC record without copying data
     pub fn read_record(&self, offset: u64, length: u64) -> &[u8] {
         let start = offset as usize;
@@ -0,0 +1,163 @@
+use crate::dedup::{DedupBackend, DedupService, NearDupConfig};
+use crate::fetcher::{Fetcher, FetcherConfig};
+use crate::frontier::{ClaimContext, FrontierBackend, FrontierService};
+use crate::lease::{LeaseConfig, LeaseManager};
+use crate::memory::{MemoryDedup, MemoryFrontier, MemoryRateLimiter};
+use crate::rate_limit::{RateLimitBackend, RateLimiter};
+use crate::region::{Region, RegionRouter, SharedRegionRouter};
+use crate::retry::RetryPolicy;
+use crate::robots::RobotsChecker;
+use crate::Crawler;
+use archive_common::storage::ArchiveStorage;
+use archive_intelligence::{PoissonPredictor, PredictiveEngine};
+use sqlx::PgPool;
+use std::sync::atomic::AtomicI32;
+use std::sync::Arc;
+
+/// Assembles a [`Crawler`] from interchangeable backends.
+///
+/// Defaults to in-memory frontier, dedup and rate limiting, WARCs in the
+/// backend named by `ARCHIVE_STORAGE` and configuration from the environment,
+/// so `Crawler::builder().build()` needs no database.
+#[derive(Default)]
+pub struct CrawlerBuilder {
+    fetcher: Option<Fetcher>,
+    storage: Option<Arc<dyn ArchiveStorage>>,
+    frontier: Option<Arc<dyn FrontierBackend>>,
+    dedup: Option<Arc<dyn DedupBackend>>,
+    rate_limiter: Option<Arc<dyn RateLimitBackend>>,
+    robots: Option<RobotsChecker>,
+    retry_policy: Option<RetryPolicy>,
+    predictor: Option<Arc<dyn PredictiveEngine>>,
+    claim_context: Option<ClaimContext>,
+    lease_config: Option<LeaseConfig>,
+    near_dup: Option<NearDupConfig>,
+    pool: Option<PgPool>,
+}
+
+impl CrawlerBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the Postgres frontier, dedup and rate limiter, plus worker
+    /// heartbeats and the lease reaper. Backends set explicitly still win.
+    pub fn postgres(mut self, pool: PgPool) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    pub fn storage(mut self, storage: Arc<dyn ArchiveStorage>) -> Self {
+        self.storage = Some(storage);
+        self
+    }
+
+    pub fn frontier(mut self, frontier: Arc<dyn FrontierBackend>) -> Self {
+        self.frontier = Some(frontier);
+        self
+    }
+
+    pub fn dedup(mut self, dedup: Arc<dyn DedupBackend>) -> Self {
+        self.dedup = Some(dedup);
+        self
+    }
+
+    pub fn rate_limiter(mut self, rate_limiter: Arc<dyn RateLimitBackend>) -> Self {
+        self.rate_limiter = Some(rate_limiter);
+        self
+    }
+
+    pub fn fetcher(mut self, fetcher: Fetcher) -> Self {
+        self.fetcher = Some(fetcher);
+        self
+    }
+
+    pub fn robots(mut self, robots: RobotsChecker) -> Self {
+        self.robots = Some(robots);
+        self
+    }
+
+    pub fn retry_policy(mut self, retry_policy: RetryPolicy) -> Self {
+        self.retry_policy = Some(retry_policy);
+        self
+    }
+
+    pub fn predictor(mut self, predictor: Arc<dyn PredictiveEngine>) -> Self {
+        self.predictor = Some(predictor);
+        self
+    }
+
+    pub fn claim_context(mut self, claim_context: ClaimContext) -> Self {
+        self.claim_context = Some(claim_context);
+        self
+    }
+
+    pub fn lease_config(mut self, lease_config: LeaseConfig) -> Self {
+        self.lease_config = Some(lease_config);
+        self
+    }
+
+    pub fn near_dup_config(mut self, near_dup: NearDupConfig) -> Self {
+        self.near_dup = Some(near_dup);
+        self
+    }
+
+    /// Fails only when the default storage backend is misconfigured
+    pub fn build(self) -> anyhow::Result<Crawler> {
+        let lease_config = self.lease_config.unwrap_or_else(LeaseConfig::from_env);
+        let claim_context = self.claim_context.unwrap_or_else(|| {
+            let mut ctx = ClaimContext::from_env(Region::from_env());
+            ctx.lease_duration = lease_config.lease_duration;
+            ctx
+        });
+        let pool = self.pool;
+
+        // Without `REGIONS`, the default Postgres frontier's ring follows the
+        // regions of live workers
+        let configured = RegionRouter::configured_regions();
+        let region_router = SharedRegionRouter::new(RegionRouter::with_regions(
+            configured.clone().unwrap_or_default(),
+        ));
+        let live_regions = (self.frontier.is_none() && configured.is_none() && pool.is_some())
+            .then(|| region_router.clone());
+        let frontier = self.frontier.unwrap_or_else(|| match &pool {
+            Some(pool) => Arc::new(FrontierService::with_router(pool.clone(), region_router)),
+            None => Arc::new(MemoryFrontier::with_router(region_router)),
+        });
+        let dedup = self.dedup.unwrap_or_else(|| match &pool {
+            Some(pool) => Arc::new(DedupService::new(pool.clone())),
+            None => Arc::new(MemoryDedup::new()),
+        });
+        let rate_limiter = self.rate_limiter.unwrap_or_else(|| match &pool {
+            Some(pool) => Arc::new(RateLimiter::new(pool.clone())),
+            None => Arc::new(MemoryRateLimiter::default()),
+        });
+        let leases = pool.map(|pool| LeaseManager::new(pool, lease_config.clone()));
+
+        let storage = match self.storage {
+            Some(storage) => storage,
+            None => archive_common::storage::from_env()?,
+        };
+
+        Ok(Crawler {
+            fetcher: self
+                .fetcher
+                .unwrap_or_else(|| Fetcher::with_config(FetcherConfig::from_env())),
+            storage,
+            dedup,
+            near_dup: self.near_dup.unwrap_or_else(NearDupConfig::from_env),
+            frontier,
+            robots: self.robots.unwrap_or_default(),
+            retry_policy: self.retry_policy.unwrap_or_else(RetryPolicy::from_env),
+            predictor: self
+                .predictor
+                .unwrap_or_else(|| Arc::new(PoissonPredictor::from_env())),
+            claim_context,
+            lease_config,
+            leases,
+            live_regions,
+            in_flight: Arc::new(AtomicI32::new(0)),
+            rate_limiter,
+        })
+    }
+}
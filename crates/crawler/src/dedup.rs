@@ -1,6 +1,84 @@
 use anyhow::Result;
+use archive_common::simhash;
+use async_trait::async_trait;
 use sqlx::PgPool;
 
+/// When a capture counts as a near-duplicate of the URL's previous one
+#[derive(Debug, Clone)]
+pub struct NearDupConfig {
+    /// Largest SimHash Hamming distance still considered the same content
+    pub max_distance: u32,
+    /// Skip writing near-duplicate payloads that are not exact duplicates
+    pub skip_storage: bool,
+}
+
+impl Default for NearDupConfig {
+    fn default() -> Self {
+        Self {
+            max_distance: 3,
+            skip_storage: false,
+        }
+    }
+}
+
+impl NearDupConfig {
+    /// Reads `NEAR_DUP_MAX_DISTANCE` and `NEAR_DUP_SKIP_STORAGE`
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(distance) = std::env::var("NEAR_DUP_MAX_DISTANCE")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+        {
+            config.max_distance = distance.min(64);
+        }
+        if let Ok(skip) = std::env::var("NEAR_DUP_SKIP_STORAGE") {
+            config.skip_storage = matches!(skip.as_str(), "1" | "true" | "yes");
+        }
+        config
+    }
+
+    pub fn is_near_duplicate(&self, similar: &SimilarCapture) -> bool {
+        similar.distance <= self.max_distance
+    }
+}
+
+/// How a capture's SimHash compares with the URL's previous capture
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SimilarCapture {
+    pub distance: u32,
+    pub similarity: f32,
+}
+
+impl SimilarCapture {
+    pub fn between(previous: u64, current: u64) -> Self {
+        Self {
+            distance: simhash::hamming_distance(previous, current),
+            similarity: simhash::similarity(previous, current),
+        }
+    }
+}
+
+/// Index of payload digests already written to WARC storage
+#[async_trait]
+pub trait DedupBackend: Send + Sync {
+    async fn is_duplicate(&self, hash: &str) -> Result<bool>;
+
+    async fn insert_payload(
+        &self,
+        hash: &str,
+        warc_path: &str,
+        offset: u64,
+        size: u64,
+    ) -> Result<()>;
+
+    /// Compare `simhash` with the latest stored for `url`; `None` on the
+    /// first capture
+    async fn compare_simhash(&self, url: &str, simhash: u64) -> Result<Option<SimilarCapture>>;
+
+    /// Keep `simhash` as the latest for `url`, once its capture is stored
+    async fn set_simhash(&self, url: &str, simhash: u64) -> Result<()>;
+}
+
 pub struct DedupService {
     pool: PgPool,
 }
@@ -9,8 +87,11 @@ impl DedupService {
     pub fn new(pool: PgPool) -> Self {
         Self { pool }
     }
+}
 
-    pub async fn is_duplicate(&self, hash: &str) -> Result<bool> {
+#[async_trait]
+impl DedupBackend for DedupService {
+    async fn is_duplicate(&self, hash: &str) -> Result<bool> {
         let exists = sqlx::query("SELECT 1 AS x FROM payloads WHERE hash = $1")
             .bind(hash)
             .fetch_optional(&self.pool)
@@ -19,7 +100,7 @@ impl DedupService {
         Ok(exists.is_some())
     }
 
-    pub async fn insert_payload(
+    async fn insert_payload(
         &self,
         hash: &str,
         warc_path: &str,
@@ -38,4 +119,30 @@ impl DedupService {
 
         Ok(())
     }
+
+    async fn compare_simhash(&self, url: &str, simhash: u64) -> Result<Option<SimilarCapture>> {
+        let previous: Option<(i64,)> =
+            sqlx::query_as("SELECT simhash FROM url_simhashes WHERE url = $1")
+                .bind(url)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(previous.map(|(h,)| SimilarCapture::between(h as u64, simhash)))
+    }
+
+    async fn set_simhash(&self, url: &str, simhash: u64) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO url_simhashes (url, simhash, updated_at)
+            VALUES ($1, $2, NOW())
+            ON CONFLICT (url) DO UPDATE SET simhash = EXCLUDED.simhash, updated_at = NOW()
+            "#,
+        )
+        .bind(url)
+        .bind(simhash as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
 }
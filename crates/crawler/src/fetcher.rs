@@ -1,55 +1,301 @@
-use crate::warc::WarcRecord;
-use bytes::Bytes;
+use crate::sniff::sniff_mime;
+use crate::warc::{TlsDetails, WarcRecord};
+use anyhow::anyhow;
 use futures::StreamExt;
-use reqwest::Client;
+use reqwest::header::{
+    CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, LOCATION,
+    TRANSFER_ENCODING,
+};
+use reqwest::{redirect, Client, Response, StatusCode, Version};
 use sha2::{Digest, Sha256};
+use std::time::Duration;
+use tokio::time::Instant;
+use url::Url;
+
+/// HTTP cache validators remembered from the previous capture of a URL
+#[derive(Debug, Clone, Default)]
+pub struct CacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl CacheValidators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+/// Raised when a fetch exceeds its connect, read or total time budget
+#[derive(Debug)]
+pub struct FetchTimeout(pub String);
+
+impl std::fmt::Display for FetchTimeout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "timed out fetching {}", self.0)
+    }
+}
+
+impl std::error::Error for FetchTimeout {}
+
+/// Limits applied to every fetch
+#[derive(Debug, Clone)]
+pub struct FetcherConfig {
+    /// Time allowed to establish the TCP/TLS connection
+    pub connect_timeout: Duration,
+    /// Longest gap allowed between two body chunks
+    pub read_timeout: Duration,
+    /// Wall-clock budget for the whole fetch, redirects included
+    pub total_timeout: Duration,
+    /// Payloads are truncated (and marked `WARC-Truncated: length`) past this size
+    pub max_body_size: usize,
+    /// Maximum number of redirect hops followed before giving up
+    pub max_redirects: usize,
+}
+
+impl Default for FetcherConfig {
+    fn default() -> Self {
+        Self {
+            connect_timeout: Duration::from_secs(10),
+            read_timeout: Duration::from_secs(30),
+            total_timeout: Duration::from_secs(120),
+            max_body_size: 50 * 1024 * 1024,
+            max_redirects: 10,
+        }
+    }
+}
+
+impl FetcherConfig {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let secs = |key: &str, default: Duration| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(default)
+        };
+        let num = |key: &str, default: usize| {
+            std::env::var(key)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        };
+
+        Self {
+            connect_timeout: secs("FETCH_CONNECT_TIMEOUT_SECS", defaults.connect_timeout),
+            read_timeout: secs("FETCH_READ_TIMEOUT_SECS", defaults.read_timeout),
+            total_timeout: secs("FETCH_TOTAL_TIMEOUT_SECS", defaults.total_timeout),
+            max_body_size: num("FETCH_MAX_BODY_BYTES", defaults.max_body_size),
+            max_redirects: num("FETCH_MAX_REDIRECTS", defaults.max_redirects),
+        }
+    }
+}
+
+/// Everything captured while fetching one frontier URL
+pub struct FetchResult {
+    /// Intermediate 3xx responses, in the order they were followed
+    pub redirects: Vec<WarcRecord>,
+    /// The final non-redirect response
+    pub record: WarcRecord,
+}
 
 pub struct Fetcher {
     client: Client,
+    config: FetcherConfig,
 }
 
 impl Fetcher {
     pub fn new() -> Self {
+        Self::with_config(FetcherConfig::default())
+    }
+
+    pub fn with_config(config: FetcherConfig) -> Self {
         Self {
             client: Client::builder()
                 .user_agent("ArchiveStream/0.1.0 (+https://github.com/ArchiveStream/ArchiveStream)")
+                .connect_timeout(config.connect_timeout)
+                // Redirects are followed by hand so each hop becomes its own capture
+                .redirect(redirect::Policy::none())
+                .tls_info(true)
                 .build()
                 .unwrap(),
+            config,
+        }
+    }
+
+    pub async fn fetch(&self, url: &str) -> anyhow::Result<FetchResult> {
+        self.fetch_conditional(url, &CacheValidators::default())
+            .await
+    }
+
+    /// Fetch a URL, sending `If-None-Match` / `If-Modified-Since` when validators
+    /// from a previous capture are known. A `304 Not Modified` comes back as a
+    /// record with an empty body; see [`WarcRecord::is_not_modified`].
+    pub async fn fetch_conditional(
+        &self,
+        url: &str,
+        validators: &CacheValidators,
+    ) -> anyhow::Result<FetchResult> {
+        let deadline = Instant::now() + self.config.total_timeout;
+        let mut redirects = Vec::new();
+        let mut current = url.to_string();
+
+        loop {
+            let mut request = self.client.get(&current);
+            // Validators belong to the frontier URL, not to wherever it redirects
+            if redirects.is_empty() {
+                if let Some(etag) = &validators.etag {
+                    request = request.header(IF_NONE_MATCH, etag);
+                }
+                if let Some(last_modified) = &validators.last_modified {
+                    request = request.header(IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+
+            let response = tokio::time::timeout_at(deadline, request.send())
+                .await
+                .map_err(|_| FetchTimeout(current.clone()))??;
+
+            let record = self
+                .capture(&current, response, validators, deadline)
+                .await?;
+
+            match record.location.clone() {
+                Some(location) if record.is_redirect() => {
+                    if redirects.len() >= self.config.max_redirects {
+                        return Err(anyhow!(
+                            "too many redirects ({}) starting at {}",
+                            redirects.len(),
+                            url
+                        ));
+                    }
+                    current = location;
+                    redirects.push(record);
+                }
+                _ => return Ok(FetchResult { redirects, record }),
+            }
         }
     }
 
-    pub async fn fetch(&self, url: &str) -> anyhow::Result<WarcRecord> {
-        let response = self.client.get(url).send().await?;
+    async fn capture(
+        &self,
+        url: &str,
+        response: Response,
+        validators: &CacheValidators,
+        deadline: Instant,
+    ) -> anyhow::Result<WarcRecord> {
         let status = response.status();
-        let content_type = response
+        let http_version = match response.version() {
+            Version::HTTP_09 => "HTTP/0.9",
+            Version::HTTP_10 => "HTTP/1.0",
+            Version::HTTP_2 => "HTTP/2",
+            Version::HTTP_3 => "HTTP/3",
+            _ => "HTTP/1.1",
+        };
+        // The body below is stored de-chunked, so its framing header is not kept
+        let http_headers = response
             .headers()
-            .get("content-type")
-            .and_then(|h| h.to_str().ok())
-            .unwrap_or("text/html")
-            .to_string();
+            .iter()
+            .filter(|(name, _)| *name != TRANSFER_ENCODING)
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect();
+        let remote_ip = response.remote_addr().map(|addr| addr.ip());
+        let tls = response
+            .extensions()
+            .get::<reqwest::tls::TlsInfo>()
+            .and_then(|info| info.peer_certificate())
+            .map(|der| TlsDetails {
+                peer_certificate_sha256: format!("{:x}", Sha256::digest(der)),
+            });
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(|s| s.to_string())
+        };
+        let mut etag = header(ETAG);
+        let mut last_modified = header(LAST_MODIFIED);
+        if status == StatusCode::NOT_MODIFIED {
+            // A 304 need not repeat the validators it confirmed
+            etag = etag.or_else(|| validators.etag.clone());
+            last_modified = last_modified.or_else(|| validators.last_modified.clone());
+        }
+        let declared_type = header(CONTENT_TYPE);
+        let location = header(LOCATION).and_then(|loc| {
+            Url::parse(url)
+                .ok()
+                .and_then(|base| base.join(&loc).ok())
+                .map(|u| u.to_string())
+        });
+
+        let mut record = WarcRecord {
+            url: url.to_string(),
+            timestamp: chrono::Utc::now(),
+            content: Vec::new(),
+            content_type: String::new(),
+            status_code: status.as_u16(),
+            http_version: http_version.to_string(),
+            http_headers,
+            payload_digest: String::new(),
+            etag,
+            last_modified,
+            location,
+            remote_ip,
+            tls,
+            truncated: None,
+        };
+
+        if status == StatusCode::NOT_MODIFIED {
+            record.content_type = declared_type.unwrap_or_default();
+            return Ok(record);
+        }
 
-        // Streaming body to compute hash without buffering everything if possible
-        // Actually, for WARC record we need the full content too.
         let mut hasher = Sha256::new();
-        let mut content = Vec::new();
         let mut stream = response.bytes_stream();
 
-        while let Some(item) = stream.next().await {
-            let chunk: Bytes = item?;
+        loop {
+            let read_deadline = (Instant::now() + self.config.read_timeout).min(deadline);
+            let chunk = match tokio::time::timeout_at(read_deadline, stream.next()).await {
+                Ok(Some(Ok(chunk))) => chunk,
+                Ok(None) => break,
+                Ok(Some(Err(e))) => {
+                    if record.content.is_empty() {
+                        return Err(e.into());
+                    }
+                    record.truncated = Some("disconnect".to_string());
+                    break;
+                }
+                Err(_) => {
+                    if record.content.is_empty() {
+                        return Err(FetchTimeout(url.to_string()).into());
+                    }
+                    record.truncated = Some("time".to_string());
+                    break;
+                }
+            };
+
+            let remaining = self.config.max_body_size - record.content.len();
+            if chunk.len() > remaining {
+                hasher.update(&chunk[..remaining]);
+                record.content.extend_from_slice(&chunk[..remaining]);
+                record.truncated = Some("length".to_string());
+                break;
+            }
             hasher.update(&chunk);
-            content.extend_from_slice(&chunk);
+            record.content.extend_from_slice(&chunk);
         }
 
-        let payload_digest = format!("{:x}", hasher.finalize());
+        record.payload_digest = format!("{:x}", hasher.finalize());
+        record.content_type = resolve_content_type(declared_type.as_deref(), &record.content);
 
-        Ok(WarcRecord {
-            url: url.to_string(),
-            timestamp: chrono::Utc::now(),
-            content,
-            content_type,
-            status_code: status.as_u16(),
-            payload_digest,
-        })
+        Ok(record)
     }
 }
 
@@ -58,3 +304,147 @@ impl Default for Fetcher {
         Self::new()
     }
 }
+
+/// Prefer the declared type, but sniff the payload when the server sent
+/// nothing or a generic `application/octet-stream`
+fn resolve_content_type(declared: Option<&str>, content: &[u8]) -> String {
+    match declared {
+        Some(ct) if !ct.trim().is_empty() && !ct.starts_with("application/octet-stream") => {
+            ct.to_string()
+        }
+        _ => sniff_mime(content)
+            .unwrap_or("application/octet-stream")
+            .to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::matchers::path;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn redirect(server: &MockServer, from: &str, to: &str) {
+        Mock::given(path(from))
+            .respond_with(
+                ResponseTemplate::new(302)
+                    .insert_header("location", to)
+                    .insert_header("etag", format!("\"{}\"", from)),
+            )
+            .mount(server)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_redirect_chain_keeps_each_hop_and_its_own_headers() {
+        let server = MockServer::start().await;
+        redirect(&server, "/a", "/b").await;
+        Mock::given(path("/b"))
+            .respond_with(ResponseTemplate::new(301).insert_header("location", "/c"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/c"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("done", "text/plain"))
+            .mount(&server)
+            .await;
+
+        let validators = CacheValidators {
+            etag: Some("\"old\"".to_string()),
+            last_modified: Some("Mon, 05 Jan 2026 10:00:00 GMT".to_string()),
+        };
+        let result = Fetcher::new()
+            .fetch_conditional(&format!("{}/a", server.uri()), &validators)
+            .await
+            .unwrap();
+
+        let hops: Vec<_> = result
+            .redirects
+            .iter()
+            .map(|r| (r.url.clone(), r.status_code))
+            .collect();
+        assert_eq!(
+            hops,
+            vec![
+                (format!("{}/a", server.uri()), 302),
+                (format!("{}/b", server.uri()), 301),
+            ]
+        );
+        assert_eq!(result.redirects[0].etag.as_deref(), Some("\"/a\""));
+        assert!(result.redirects[0]
+            .http_headers
+            .iter()
+            .any(|(name, value)| name == "location" && value == "/b"));
+        // Neither a hop nor the final response inherits the frontier's validators
+        assert!(result.redirects[1].etag.is_none());
+        assert!(result.redirects[1].last_modified.is_none());
+        assert_eq!(result.record.url, format!("{}/c", server.uri()));
+        assert_eq!(result.record.content, b"done");
+        assert!(result.record.etag.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redirect_limit() {
+        let server = MockServer::start().await;
+        redirect(&server, "/a", "/b").await;
+        redirect(&server, "/b", "/c").await;
+        redirect(&server, "/c", "/a").await;
+
+        let config = FetcherConfig {
+            max_redirects: 2,
+            ..FetcherConfig::default()
+        };
+        let err = Fetcher::with_config(config)
+            .fetch(&format!("{}/a", server.uri()))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("too many redirects (2)"));
+    }
+
+    #[tokio::test]
+    async fn test_body_over_the_size_cap_is_truncated() {
+        let server = MockServer::start().await;
+        Mock::given(path("/big"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(vec![b'x'; 4096], "text/plain"))
+            .mount(&server)
+            .await;
+
+        let config = FetcherConfig {
+            max_body_size: 1000,
+            ..FetcherConfig::default()
+        };
+        let record = Fetcher::with_config(config)
+            .fetch(&format!("{}/big", server.uri()))
+            .await
+            .unwrap()
+            .record;
+        assert_eq!(record.content.len(), 1000);
+        assert_eq!(record.truncated.as_deref(), Some("length"));
+        assert_eq!(
+            record.payload_digest,
+            format!("{:x}", Sha256::digest(vec![b'x'; 1000]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_response_times_out() {
+        let server = MockServer::start().await;
+        Mock::given(path("/slow"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let config = FetcherConfig {
+            total_timeout: Duration::from_millis(200),
+            ..FetcherConfig::default()
+        };
+        let started = Instant::now();
+        let err = Fetcher::with_config(config)
+            .fetch(&format!("{}/slow", server.uri()))
+            .await
+            .err()
+            .unwrap();
+        assert!(err.downcast_ref::<FetchTimeout>().is_some());
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
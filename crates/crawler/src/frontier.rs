@@ -1,10 +1,164 @@
+use crate::fetcher::CacheValidators;
+use crate::lease::LeaseConfig;
+use crate::priority::{PriorityInputs, PriorityScorer, WeightedScorer};
+use crate::region::{RebalanceReport, Region, RegionRouter, SharedRegionRouter};
+use crate::retry::{ErrorClass, RetryPolicy};
 use anyhow::Result;
+use archive_intelligence::{CrawlPrediction, PredictionContext};
+use async_trait::async_trait;
 use chrono::Utc;
 use sqlx::PgPool;
+use std::sync::Arc;
 use url::Url;
+use uuid::Uuid;
+
+/// Priority given to page requisites so they are fetched right after their page
+pub const EMBED_PRIORITY: i32 = 100;
+
+/// Longest chain of embeds followed from a page (e.g. iframe in iframe), since
+/// embeds keep their page's depth and so are not bounded by it
+pub const MAX_EMBED_HOPS: i32 = 3;
+
+/// Storage for the crawl frontier: what to fetch next, leases, failures and
+/// the capture history the scheduler needs. [`FrontierService`] is the
+/// Postgres implementation; [`crate::memory::MemoryFrontier`] needs no database.
+#[async_trait]
+pub trait FrontierBackend: Send + Sync {
+    /// Add a URL with an explicit priority; existing URLs are left untouched
+    async fn add_url(&self, url: &str, priority: i32, depth: i32) -> Result<()>;
+
+    async fn add_seed(&self, url: &str, job: &JobSettings) -> Result<()>;
+
+    /// Add a link found on `source_url`; `embed_hops` counts the embeds between
+    /// it and the nearest page reached by an outlink, 0 for outlinks themselves
+    async fn add_discovered(
+        &self,
+        url: &str,
+        source_url: &str,
+        depth: i32,
+        embed_hops: i32,
+        job: &JobSettings,
+    ) -> Result<()>;
+
+    async fn claim_urls(&self, limit: i32, ctx: &ClaimContext) -> Result<Vec<FrontierUrl>>;
+
+    /// Extend the lease on `url` if `worker_id` still holds it; `false` means it was lost
+    async fn renew_lease(
+        &self,
+        url: &str,
+        worker_id: &str,
+        lease_duration: std::time::Duration,
+    ) -> Result<bool>;
+
+    async fn complete(&self, url: &str) -> Result<()>;
+
+    /// Give up `worker_id`'s lease on `url` without recording an outcome, so
+    /// it can be claimed again straight away
+    async fn release_lease(&self, url: &str, worker_id: &str) -> Result<()>;
+
+    /// Put `worker_id`'s claimed `url` back unfetched, due again at `until`;
+    /// its attempts are untouched
+    async fn defer(&self, url: &str, worker_id: &str, until: chrono::DateTime<Utc>) -> Result<()>;
+
+    async fn reschedule(
+        &self,
+        url: &str,
+        next_fetch_at: chrono::DateTime<Utc>,
+        change_probability: f32,
+    ) -> Result<()>;
+
+    async fn set_validators(&self, url: &str, validators: &CacheValidators) -> Result<()>;
+
+    async fn register_failure(
+        &self,
+        url: &str,
+        class: ErrorClass,
+        error: &str,
+        policy: &RetryPolicy,
+    ) -> Result<FailureOutcome>;
+
+    async fn track_event(
+        &self,
+        url: &str,
+        status: &str,
+        http_status: Option<i32>,
+        duration_ms: i32,
+    ) -> Result<()>;
+
+    /// Record one fetch outcome for change-rate estimation
+    async fn record_observation(&self, observation: &ChangeObservation<'_>) -> Result<()>;
+
+    /// Change observations of `url`, oldest first
+    async fn get_snapshot_history(
+        &self,
+        url: &str,
+    ) -> Result<Vec<archive_intelligence::SnapshotHistory>>;
+
+    /// Pooled change statistics over every URL of `domain`
+    async fn domain_change_stats(&self, domain: &str) -> Result<Option<DomainChangeStats>>;
+
+    /// Keep a prediction for auditing the scheduler
+    async fn record_prediction(
+        &self,
+        prediction: &CrawlPrediction,
+        ctx: &PredictionContext,
+        observations: usize,
+    ) -> Result<()>;
+
+    /// Response record holding the payload of a URL's latest capture; for a
+    /// revisit, the response it refers to
+    async fn latest_capture(&self, url: &str) -> Result<Option<PreviousCapture>>;
+
+    /// Earliest full capture of a payload, which revisits of it point at
+    async fn original_capture(&self, sha256: &str) -> Result<Option<PreviousCapture>>;
+
+    /// Create the snapshot of a response stored in a WARC; returns its id
+    async fn record_capture(&self, capture: &NewCapture<'_>) -> Result<Uuid>;
+
+    /// Create a revisit snapshot under WARC `profile` pointing at `previous`
+    async fn record_revisit(
+        &self,
+        url: &str,
+        timestamp: chrono::DateTime<Utc>,
+        profile: &str,
+        previous: &PreviousCapture,
+    ) -> Result<Uuid>;
+}
 
 pub struct FrontierService {
     pool: PgPool,
+    region_router: SharedRegionRouter,
+    scorer: Arc<dyn PriorityScorer>,
+}
+
+/// Who is claiming work, and how long another region's URLs must be overdue
+/// before this worker steals them
+#[derive(Debug, Clone)]
+pub struct ClaimContext {
+    pub worker_id: String,
+    pub region: Region,
+    pub steal_after: chrono::Duration,
+    /// Lease length for URLs without their own `lease_duration_secs`
+    pub lease_duration: std::time::Duration,
+}
+
+impl ClaimContext {
+    /// Reads `WORKER_ID` (default: random UUID) and `CLAIM_STEAL_AFTER_SECS` (default: 300);
+    /// the lease length comes from [`LeaseConfig::from_env`]
+    pub fn from_env(region: Region) -> Self {
+        let worker_id = std::env::var("WORKER_ID").unwrap_or_else(|_| Uuid::new_v4().to_string());
+        let steal_after_secs = std::env::var("CLAIM_STEAL_AFTER_SECS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(300);
+
+        Self {
+            worker_id,
+            region,
+            steal_after: chrono::Duration::seconds(steal_after_secs),
+            lease_duration: LeaseConfig::from_env().lease_duration,
+        }
+    }
 }
 
 #[derive(Debug, sqlx::FromRow)]
@@ -12,55 +166,409 @@ pub struct FrontierUrl {
     pub url: String,
     pub domain: Option<String>,
     pub depth: i32,
+    /// Embeds followed to reach this URL since the last outlink
+    pub embed_hops: i32,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Job-level boost, inherited by every link discovered from this URL
+    pub priority_boost: i32,
+    /// Job-level freshness target for the recrawl scheduler, inherited likewise
+    pub freshness_target: Option<f32>,
+    /// Job-level lease length, inherited likewise
+    pub lease_duration_secs: Option<i32>,
+}
+
+/// Settings of a crawl job, carried from its seed to everything discovered from it
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobSettings {
+    pub boost: i32,
+    pub freshness_target: Option<f32>,
+    /// Lease length for the job's URLs, for jobs known to fetch slowly;
+    /// `None` uses the claiming worker's default
+    pub lease_duration: Option<std::time::Duration>,
+}
+
+impl JobSettings {
+    fn lease_duration_secs(&self) -> Option<i32> {
+        self.lease_duration.map(|d| d.as_secs() as i32)
+    }
+}
+
+/// One fetch outcome, as recorded for change-rate estimation
+#[derive(Debug, Clone)]
+pub struct ChangeObservation<'a> {
+    pub url: &'a str,
+    pub observed_at: chrono::DateTime<Utc>,
+    pub payload_digest: &'a str,
+    /// Normalized-text hash; `None` (e.g. a 304) repeats the previous observation's
+    pub text_hash: Option<&'a str>,
+    pub simhash: Option<u64>,
+    /// SimHash similarity to the previous capture
+    pub similarity: Option<f32>,
+    /// Within the near-duplicate distance; not counted as a domain change
+    pub near_duplicate: bool,
+}
+
+/// Changes seen across a domain's URLs and the time they were observed over
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DomainChangeStats {
+    pub changes: i64,
+    pub intervals: i64,
+    pub exposure_secs: f64,
+}
+
+impl FrontierUrl {
+    pub fn job(&self) -> JobSettings {
+        JobSettings {
+            boost: self.priority_boost,
+            freshness_target: self.freshness_target,
+            lease_duration: self
+                .lease_duration_secs
+                .map(|secs| std::time::Duration::from_secs(secs.max(0) as u64)),
+        }
+    }
+
+    pub fn validators(&self) -> CacheValidators {
+        CacheValidators {
+            etag: self.etag.clone(),
+            last_modified: self.last_modified.clone(),
+        }
+    }
+}
+
+/// Stored inputs of the priority scorer for one frontier row
+#[derive(Debug, sqlx::FromRow)]
+struct ScoreRow {
+    depth: i32,
+    inlink_count: i32,
+    change_probability: Option<f32>,
+    priority_boost: i32,
+    is_embed: bool,
+    next_fetch_at: chrono::DateTime<Utc>,
+    last_captured_at: Option<chrono::DateTime<Utc>>,
+}
+
+impl ScoreRow {
+    fn inputs(&self) -> PriorityInputs {
+        // Staleness is judged at the moment the URL becomes claimable
+        let due = self.next_fetch_at.max(Utc::now());
+        PriorityInputs {
+            depth: self.depth,
+            inlinks: self.inlink_count,
+            change_probability: self.change_probability,
+            since_last_capture: self.last_captured_at.map(|t| due - t),
+            boost: self.priority_boost,
+            is_embed: self.is_embed,
+        }
+    }
+}
+
+/// The host a URL is filed and routed under: its domain, or the address of
+/// an IP host
+pub(crate) fn url_host(url: &str) -> Option<String> {
+    Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(|h| h.to_string()))
+}
+
+/// What happened to a URL after a failed fetch
+#[derive(Debug, Clone, PartialEq)]
+pub enum FailureOutcome {
+    Retry {
+        next_fetch_at: chrono::DateTime<Utc>,
+    },
+    DeadLettered,
+    /// The URL has no frontier row (completed or removed elsewhere); nothing
+    /// was recorded
+    NotQueued,
+}
+
+/// A stored response record, which revisit records point at
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PreviousCapture {
+    pub id: Uuid,
+    pub url: String,
+    pub timestamp: chrono::DateTime<Utc>,
+    pub warc_file: String,
+    pub offset: i64,
+    pub length: i64,
+    pub sha256: String,
+    pub status_code: i16,
+    pub content_type: String,
+    pub payload_hash: Option<String>,
+}
+
+/// A response just written to a WARC, and where
+#[derive(Debug, Clone)]
+pub struct NewCapture<'a> {
+    pub url: &'a str,
+    pub timestamp: chrono::DateTime<Utc>,
+    pub warc_file: &'a str,
+    pub offset: i64,
+    pub length: i64,
+    pub sha256: &'a str,
+    pub status_code: i16,
+    pub content_type: &'a str,
 }
 
 impl FrontierService {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self::with_router(pool, RegionRouter::new())
     }
 
-    pub async fn add_url(&self, url: &str, priority: i32, depth: i32) -> Result<()> {
-        let domain = Url::parse(url)
-            .ok()
-            .and_then(|u| u.domain().map(|d| d.to_string()));
+    pub fn with_router(pool: PgPool, region_router: impl Into<SharedRegionRouter>) -> Self {
+        Self {
+            pool,
+            region_router: region_router.into(),
+            scorer: Arc::new(WeightedScorer::default()),
+        }
+    }
+
+    /// Replace the default [`WeightedScorer`]
+    pub fn with_scorer(mut self, scorer: Arc<dyn PriorityScorer>) -> Self {
+        self.scorer = scorer;
+        self
+    }
+
+    /// Recompute the stored priority of `url` from its current scoring inputs
+    pub async fn rescore(&self, url: &str) -> Result<Option<i32>> {
+        let row = sqlx::query_as::<_, ScoreRow>(
+            r#"
+            SELECT f.depth, f.inlink_count, f.change_probability, f.priority_boost, f.is_embed,
+                   COALESCE(f.next_fetch_at, NOW()) AS next_fetch_at,
+                   (SELECT MAX(s.timestamp) FROM snapshots s WHERE s.url = f.url) AS last_captured_at
+            FROM url_frontier f
+            WHERE f.url = $1
+            "#,
+        )
+        .bind(url)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some(row) = row else {
+            return Ok(None);
+        };
+        let priority = self.scorer.score(&row.inputs());
+        sqlx::query("UPDATE url_frontier SET priority = $1 WHERE url = $2")
+            .bind(priority)
+            .bind(url)
+            .execute(&self.pool)
+            .await?;
+        Ok(Some(priority))
+    }
+
+    pub fn region_router(&self) -> &SharedRegionRouter {
+        &self.region_router
+    }
+
+    /// Domains currently waiting in the frontier
+    pub async fn frontier_domains(&self) -> Result<Vec<String>> {
+        let domains = sqlx::query_scalar(
+            "SELECT DISTINCT domain FROM url_frontier WHERE domain IS NOT NULL ORDER BY domain",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(domains)
+    }
+
+    /// Point the moved domains of a rebalance at their new region; returns rows updated
+    pub async fn apply_rebalance(&self, report: &RebalanceReport) -> Result<u64> {
+        let mut tx = self.pool.begin().await?;
+        let mut updated = 0;
+        for domain_move in &report.moves {
+            updated +=
+                sqlx::query("UPDATE url_frontier SET preferred_region = $1 WHERE domain = $2")
+                    .bind(domain_move.to.as_str())
+                    .bind(&domain_move.domain)
+                    .execute(&mut *tx)
+                    .await?
+                    .rows_affected();
+        }
+        tx.commit().await?;
+        Ok(updated)
+    }
+}
+
+#[async_trait]
+impl FrontierBackend for FrontierService {
+    async fn add_url(&self, url: &str, priority: i32, depth: i32) -> Result<()> {
+        let domain = url_host(url);
+        let preferred_region = domain
+            .as_deref()
+            .map(|d| self.region_router.route_domain(d).as_str().to_string());
 
         sqlx::query(
-            "INSERT INTO url_frontier (url, domain, priority, depth) VALUES ($1, $2, $3, $4) ON CONFLICT (url) DO NOTHING"
+            "INSERT INTO url_frontier (url, domain, priority, depth, preferred_region) VALUES ($1, $2, $3, $4, COALESCE($5, 'us-east-1')) ON CONFLICT (url) DO NOTHING"
         )
         .bind(url)
         .bind(domain)
         .bind(priority)
         .bind(depth)
+        .bind(preferred_region)
         .execute(&self.pool)
         .await?;
 
         Ok(())
     }
 
-    pub async fn claim_urls(&self, limit: i32) -> Result<Vec<FrontierUrl>> {
+    /// Add a crawl seed scored at depth 0; `job` is inherited by everything
+    /// discovered from it
+    async fn add_seed(&self, url: &str, job: &JobSettings) -> Result<()> {
+        let priority = self.scorer.score(&PriorityInputs {
+            boost: job.boost,
+            ..Default::default()
+        });
+        self.add_url(url, priority, 0).await?;
+        sqlx::query(
+            "UPDATE url_frontier SET priority_boost = $1, freshness_target = $2, lease_duration_secs = $3 WHERE url = $4",
+        )
+        .bind(job.boost)
+        .bind(job.freshness_target)
+        .bind(job.lease_duration_secs())
+        .bind(url)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Add a link found on `source_url`. A new inlink to a URL already in the
+    /// frontier bumps its inlink count and recomputes its priority.
+    async fn add_discovered(
+        &self,
+        url: &str,
+        source_url: &str,
+        depth: i32,
+        embed_hops: i32,
+        job: &JobSettings,
+    ) -> Result<()> {
+        let new_inlink = sqlx::query(
+            "INSERT INTO url_inlinks (url, source_url) VALUES ($1, $2) ON CONFLICT DO NOTHING",
+        )
+        .bind(url)
+        .bind(source_url)
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0;
+
+        let domain = url_host(url);
+        let preferred_region = domain
+            .as_deref()
+            .map(|d| self.region_router.route_domain(d).as_str().to_string());
+        let priority = self.scorer.score(&PriorityInputs {
+            depth,
+            inlinks: 1,
+            boost: job.boost,
+            is_embed: embed_hops > 0,
+            ..Default::default()
+        });
+
+        let inserted = sqlx::query(
+            r#"
+            INSERT INTO url_frontier (url, domain, priority, depth, preferred_region, inlink_count, priority_boost, is_embed, embed_hops, freshness_target, lease_duration_secs)
+            VALUES ($1, $2, $3, $4, COALESCE($5, 'us-east-1'), 1, $6, $7 > 0, $7, $8, $9)
+            ON CONFLICT (url) DO NOTHING
+            "#,
+        )
+        .bind(url)
+        .bind(domain)
+        .bind(priority)
+        .bind(depth)
+        .bind(preferred_region)
+        .bind(job.boost)
+        .bind(embed_hops)
+        .bind(job.freshness_target)
+        .bind(job.lease_duration_secs())
+        .execute(&self.pool)
+        .await?
+        .rows_affected()
+            > 0;
+
+        if !inserted && new_inlink {
+            sqlx::query(
+                r#"
+                UPDATE url_frontier
+                SET inlink_count = inlink_count + 1,
+                    depth = LEAST(depth, $2),
+                    priority_boost = GREATEST(priority_boost, $3),
+                    is_embed = is_embed OR $4 > 0,
+                    embed_hops = LEAST(embed_hops, $4)
+                WHERE url = $1
+                "#,
+            )
+            .bind(url)
+            .bind(depth)
+            .bind(job.boost)
+            .bind(embed_hops)
+            .execute(&self.pool)
+            .await?;
+            self.rescore(url).await?;
+        }
+
+        Ok(())
+    }
+
+    /// Claim due URLs, preferring those routed to the worker's own region. URLs
+    /// routed elsewhere are only taken once they are `steal_after` overdue, so an
+    /// idle or missing region does not strand its backlog.
+    async fn claim_urls(&self, limit: i32, ctx: &ClaimContext) -> Result<Vec<FrontierUrl>> {
         let urls = sqlx::query_as::<_, FrontierUrl>(
             r#"
             UPDATE url_frontier
-            SET leased_until = now() + interval '1 minute'
+            SET leased_until = now() + make_interval(secs => COALESCE(lease_duration_secs, $5)),
+                leased_by_worker = $2,
+                leased_region = $3
             WHERE url IN (
                 SELECT url FROM url_frontier
                 WHERE (leased_until IS NULL OR leased_until < now())
                   AND next_fetch_at <= now()
-                ORDER BY priority DESC, created_at ASC
+                  AND (
+                    preferred_region IS NULL
+                    OR preferred_region = $3
+                    OR next_fetch_at <= now() - make_interval(secs => $4)
+                  )
+                ORDER BY COALESCE(preferred_region = $3, true) DESC, priority DESC, created_at ASC
                 LIMIT $1
                 FOR UPDATE SKIP LOCKED
             )
-            RETURNING url, domain, depth
+            RETURNING url, domain, depth, embed_hops, priority_boost, freshness_target, lease_duration_secs,
+                (SELECT etag FROM url_validators v WHERE v.url = url_frontier.url) AS etag,
+                (SELECT last_modified FROM url_validators v WHERE v.url = url_frontier.url) AS last_modified
             "#,
         )
         .bind(limit as i64)
+        .bind(&ctx.worker_id)
+        .bind(ctx.region.as_str())
+        .bind(ctx.steal_after.num_seconds() as f64)
+        .bind(ctx.lease_duration.as_secs() as i32)
         .fetch_all(&self.pool)
         .await?;
 
         Ok(urls)
     }
 
-    pub async fn complete(&self, url: &str) -> Result<()> {
+    async fn renew_lease(
+        &self,
+        url: &str,
+        worker_id: &str,
+        lease_duration: std::time::Duration,
+    ) -> Result<bool> {
+        let result = sqlx::query(
+            r#"
+            UPDATE url_frontier
+            SET leased_until = NOW() + make_interval(secs => COALESCE(lease_duration_secs, $3))
+            WHERE url = $1 AND leased_by_worker = $2
+            "#,
+        )
+        .bind(url)
+        .bind(worker_id)
+        .bind(lease_duration.as_secs() as i32)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn complete(&self, url: &str) -> Result<()> {
         sqlx::query("DELETE FROM url_frontier WHERE url = $1")
             .bind(url)
             .execute(&self.pool)
@@ -68,46 +576,166 @@ impl FrontierService {
         Ok(())
     }
 
-    pub async fn reschedule(
+    async fn defer(&self, url: &str, worker_id: &str, until: chrono::DateTime<Utc>) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE url_frontier
+            SET next_fetch_at = $3, leased_until = NULL, leased_by_worker = NULL, leased_region = NULL
+            WHERE url = $1 AND leased_by_worker = $2
+            "#,
+        )
+        .bind(url)
+        .bind(worker_id)
+        .bind(until)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn release_lease(&self, url: &str, worker_id: &str) -> Result<()> {
+        sqlx::query(
+            r#"
+            UPDATE url_frontier
+            SET leased_until = NULL, leased_by_worker = NULL, leased_region = NULL
+            WHERE url = $1 AND leased_by_worker = $2
+            "#,
+        )
+        .bind(url)
+        .bind(worker_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Schedule the next visit; the priority is rescored with the predicted
+    /// change probability and the capture age at `next_fetch_at`
+    async fn reschedule(
         &self,
         url: &str,
         next_fetch_at: chrono::DateTime<Utc>,
-        priority: i32,
+        change_probability: f32,
     ) -> Result<()> {
         sqlx::query(
-            "UPDATE url_frontier SET next_fetch_at = $1, priority = $2, leased_until = NULL, fetch_attempts = 0 WHERE url = $3"
+            "UPDATE url_frontier SET next_fetch_at = $1, change_probability = $2, leased_until = NULL, leased_by_worker = NULL, leased_region = NULL, fetch_attempts = 0 WHERE url = $3"
         )
         .bind(next_fetch_at)
-        .bind(priority)
+        .bind(change_probability)
         .bind(url)
         .execute(&self.pool)
         .await?;
+        self.rescore(url).await?;
         Ok(())
     }
 
-    pub async fn fail(&self, url: &str, backoff_seconds: i64) -> Result<()> {
-        let next_fetch = Utc::now() + chrono::Duration::seconds(backoff_seconds);
+    /// Remember the `ETag` / `Last-Modified` of the latest capture for conditional
+    /// revisits. They are kept apart from the frontier row, which completing deletes
+    async fn set_validators(&self, url: &str, validators: &CacheValidators) -> Result<()> {
+        if validators.is_empty() {
+            sqlx::query("DELETE FROM url_validators WHERE url = $1")
+                .bind(url)
+                .execute(&self.pool)
+                .await?;
+            return Ok(());
+        }
         sqlx::query(
-            "UPDATE url_frontier SET fetch_attempts = fetch_attempts + 1, next_fetch_at = $1, leased_until = NULL WHERE url = $2"
+            r#"
+            INSERT INTO url_validators (url, etag, last_modified) VALUES ($1, $2, $3)
+            ON CONFLICT (url) DO UPDATE SET
+                etag = EXCLUDED.etag, last_modified = EXCLUDED.last_modified, updated_at = NOW()
+            "#,
         )
-        .bind(next_fetch)
         .bind(url)
+        .bind(&validators.etag)
+        .bind(&validators.last_modified)
         .execute(&self.pool)
         .await?;
         Ok(())
     }
 
-    pub async fn track_event(
+    /// Record a failed fetch and either schedule a retry according to `policy`
+    /// or move the URL into `dead_letter_urls` once its attempts are exhausted
+    async fn register_failure(
+        &self,
+        url: &str,
+        class: ErrorClass,
+        error: &str,
+        policy: &RetryPolicy,
+    ) -> Result<FailureOutcome> {
+        let mut tx = self.pool.begin().await?;
+
+        let attempts: Option<i32> = sqlx::query_scalar(
+            r#"
+            UPDATE url_frontier
+            SET fetch_attempts = fetch_attempts + 1, last_error = $1, last_error_class = $2,
+                leased_until = NULL, leased_by_worker = NULL, leased_region = NULL
+            WHERE url = $3
+            RETURNING fetch_attempts
+            "#,
+        )
+        .bind(error)
+        .bind(class.as_str())
+        .bind(url)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let attempts = match attempts {
+            Some(a) => a,
+            None => {
+                // Row vanished (completed elsewhere); nothing to retry
+                tx.commit().await?;
+                return Ok(FailureOutcome::NotQueued);
+            }
+        };
+
+        let outcome = match policy.next_backoff(class, attempts) {
+            Some(backoff) => {
+                let next_fetch_at = Utc::now() + backoff;
+                sqlx::query("UPDATE url_frontier SET next_fetch_at = $1 WHERE url = $2")
+                    .bind(next_fetch_at)
+                    .bind(url)
+                    .execute(&mut *tx)
+                    .await?;
+                FailureOutcome::Retry { next_fetch_at }
+            }
+            None => {
+                // Rows queued before IP hosts were filed under their address
+                let host = url_host(url).unwrap_or_default();
+                sqlx::query(
+                    r#"
+                    INSERT INTO dead_letter_urls (url, domain, priority, depth, fetch_attempts, error_class, last_error, first_queued_at)
+                    SELECT url, COALESCE(domain, $2), priority, depth, fetch_attempts, last_error_class, last_error, created_at
+                    FROM url_frontier WHERE url = $1
+                    ON CONFLICT (url) DO UPDATE SET
+                        fetch_attempts = EXCLUDED.fetch_attempts,
+                        error_class = EXCLUDED.error_class,
+                        last_error = EXCLUDED.last_error,
+                        dead_lettered_at = NOW()
+                    "#,
+                )
+                .bind(url)
+                .bind(host)
+                .execute(&mut *tx)
+                .await?;
+                sqlx::query("DELETE FROM url_frontier WHERE url = $1")
+                    .bind(url)
+                    .execute(&mut *tx)
+                    .await?;
+                FailureOutcome::DeadLettered
+            }
+        };
+
+        tx.commit().await?;
+        Ok(outcome)
+    }
+
+    async fn track_event(
         &self,
         url: &str,
         status: &str,
         http_status: Option<i32>,
         duration_ms: i32,
     ) -> Result<()> {
-        let domain = Url::parse(url)
-            .ok()
-            .and_then(|u| u.domain().map(|d| d.to_string()))
-            .unwrap_or_default();
+        let domain = url_host(url).unwrap_or_default();
         sqlx::query(
             "INSERT INTO crawl_events (domain, url, status, http_status, duration_ms) VALUES ($1, $2, $3, $4, $5)"
         )
@@ -121,12 +749,89 @@ impl FrontierService {
         Ok(())
     }
 
-    pub async fn get_snapshot_history(
+    async fn record_observation(&self, observation: &ChangeObservation<'_>) -> Result<()> {
+        let ChangeObservation {
+            url,
+            observed_at,
+            payload_digest,
+            text_hash,
+            ..
+        } = *observation;
+        let domain = url_host(url).unwrap_or_default();
+        let mut tx = self.pool.begin().await?;
+
+        let previous: Option<(chrono::DateTime<Utc>, String)> = sqlx::query_as(
+            "SELECT observed_at, text_hash FROM change_observations WHERE url = $1 ORDER BY observed_at DESC LIMIT 1",
+        )
+        .bind(url)
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        let text_hash = text_hash
+            .map(|h| h.to_string())
+            .or_else(|| previous.as_ref().map(|(_, h)| h.clone()))
+            .unwrap_or_else(|| payload_digest.to_string());
+        let changed = previous.as_ref().map(|(_, h)| *h != text_hash);
+
+        sqlx::query(
+            r#"
+            INSERT INTO change_observations
+                (url, domain, observed_at, payload_digest, text_hash, changed, simhash, similarity, near_duplicate)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(url)
+        .bind(&domain)
+        .bind(observed_at)
+        .bind(payload_digest)
+        .bind(&text_hash)
+        .bind(changed)
+        .bind(observation.simhash.map(|h| h as i64))
+        .bind(observation.similarity)
+        .bind(observation.near_duplicate)
+        .execute(&mut *tx)
+        .await?;
+
+        if let Some((previous_at, _)) = previous {
+            let exposure = (observed_at - previous_at).num_seconds().max(0) as f64;
+            sqlx::query(
+                r#"
+                INSERT INTO domain_change_stats (domain, changes, intervals, exposure_secs)
+                VALUES ($1, $2, 1, $3)
+                ON CONFLICT (domain) DO UPDATE SET
+                    changes = domain_change_stats.changes + EXCLUDED.changes,
+                    intervals = domain_change_stats.intervals + 1,
+                    exposure_secs = domain_change_stats.exposure_secs + EXCLUDED.exposure_secs,
+                    updated_at = NOW()
+                "#,
+            )
+            .bind(&domain)
+            .bind((changed.unwrap_or(false) && !observation.near_duplicate) as i64)
+            .bind(exposure)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await?;
+        Ok(())
+    }
+
+    async fn get_snapshot_history(
         &self,
         url: &str,
     ) -> Result<Vec<archive_intelligence::SnapshotHistory>> {
+        // Captures from before change observations were recorded only have the
+        // raw payload hash; they still give the estimator their timing.
         let history = sqlx::query_as::<_, archive_intelligence::SnapshotHistory>(
-            "SELECT timestamp, sha256 as content_hash FROM snapshots WHERE url = $1 ORDER BY timestamp ASC"
+            r#"
+            SELECT timestamp, sha256 AS content_hash, NULL::real AS similarity FROM snapshots
+            WHERE url = $1
+              AND timestamp < COALESCE((SELECT MIN(observed_at) FROM change_observations WHERE url = $1), 'infinity')
+            UNION ALL
+            SELECT observed_at AS timestamp, text_hash AS content_hash, similarity FROM change_observations
+            WHERE url = $1
+            ORDER BY timestamp ASC
+            "#,
         )
         .bind(url)
         .fetch_all(&self.pool)
@@ -134,4 +839,368 @@ impl FrontierService {
 
         Ok(history)
     }
+
+    async fn domain_change_stats(&self, domain: &str) -> Result<Option<DomainChangeStats>> {
+        let stats = sqlx::query_as::<_, DomainChangeStats>(
+            "SELECT changes, intervals, exposure_secs FROM domain_change_stats WHERE domain = $1",
+        )
+        .bind(domain)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(stats)
+    }
+
+    async fn record_prediction(
+        &self,
+        prediction: &CrawlPrediction,
+        ctx: &PredictionContext,
+        observations: usize,
+    ) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO crawl_predictions
+                (url, next_fetch_at, change_probability, change_rate_per_day, confidence,
+                 freshness_target, prior_alpha, prior_beta_secs, observations)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            "#,
+        )
+        .bind(&prediction.url)
+        .bind(prediction.next_fetch_at)
+        .bind(prediction.change_probability)
+        .bind(prediction.change_rate_per_day)
+        .bind(prediction.confidence)
+        .bind(ctx.freshness_target)
+        .bind(ctx.prior.map(|p| p.alpha))
+        .bind(ctx.prior.map(|p| p.beta_secs))
+        .bind(observations as i32)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn latest_capture(&self, url: &str) -> Result<Option<PreviousCapture>> {
+        let capture = sqlx::query_as::<_, PreviousCapture>(
+            r#"
+            SELECT o.id, o.url, o.timestamp, o.warc_file, o."offset", o.length, o.sha256, o.status_code, o.content_type, o.payload_hash
+            FROM snapshots s
+            JOIN snapshots o ON o.id = COALESCE(s.revisit_of, s.id)
+            WHERE s.url = $1
+            ORDER BY s.timestamp DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(url)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(capture)
+    }
+
+    async fn original_capture(&self, sha256: &str) -> Result<Option<PreviousCapture>> {
+        let capture = sqlx::query_as::<_, PreviousCapture>(
+            r#"
+            SELECT id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type, payload_hash
+            FROM snapshots
+            WHERE sha256 = $1 AND revisit_of IS NULL
+            ORDER BY timestamp
+            LIMIT 1
+            "#,
+        )
+        .bind(sha256)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(capture)
+    }
+
+    async fn record_capture(&self, capture: &NewCapture<'_>) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO snapshots (id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type, payload_hash)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $7)
+            "#,
+        )
+        .bind(id)
+        .bind(capture.url)
+        .bind(capture.timestamp)
+        .bind(capture.warc_file)
+        .bind(capture.offset)
+        .bind(capture.length)
+        .bind(capture.sha256)
+        .bind(capture.status_code)
+        .bind(capture.content_type)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+
+    /// Record a `304 Not Modified` as a revisit snapshot that shares the previous
+    /// capture's payload, so replay resolves it and the change history sees an
+    /// unchanged observation at this point in time.
+    async fn record_revisit(
+        &self,
+        url: &str,
+        timestamp: chrono::DateTime<Utc>,
+        profile: &str,
+        previous: &PreviousCapture,
+    ) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+        sqlx::query(
+            r#"
+            INSERT INTO snapshots (id, url, timestamp, warc_file, "offset", length, sha256, status_code, content_type, payload_hash, revisit_of, revisit_profile)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)
+            "#,
+        )
+        .bind(id)
+        .bind(url)
+        .bind(timestamp)
+        .bind(&previous.warc_file)
+        .bind(previous.offset)
+        .bind(previous.length)
+        .bind(&previous.sha256)
+        .bind(previous.status_code)
+        .bind(&previous.content_type)
+        .bind(previous.payload_hash.as_ref().unwrap_or(&previous.sha256))
+        .bind(previous.id)
+        .bind(profile)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archive_common::testing::scratch_pool;
+
+    fn ctx() -> ClaimContext {
+        ClaimContext {
+            worker_id: "test-worker".to_string(),
+            region: Region::new("us-east-1"),
+            steal_after: chrono::Duration::minutes(5),
+            lease_duration: std::time::Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claim_prefers_own_region_and_steals_overdue_urls() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let frontier = FrontierService::with_router(
+            pool.clone(),
+            RegionRouter::with_regions(vec![Region::new("us-east-1"), Region::new("eu-west-1")]),
+        );
+        for (url, region, overdue_mins) in [
+            ("https://own.example/", "us-east-1", 0),
+            ("https://due.example/", "eu-west-1", 1),
+            ("https://overdue.example/", "eu-west-1", 10),
+        ] {
+            frontier.add_url(url, 0, 0).await.unwrap();
+            sqlx::query(
+                "UPDATE url_frontier SET preferred_region = $2, next_fetch_at = NOW() - make_interval(mins => $3), priority = 0 WHERE url = $1",
+            )
+            .bind(url)
+            .bind(region)
+            .bind(overdue_mins)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        // Own region first, even ahead of an overdue URL of another region
+        let first = frontier.claim_urls(1, &ctx()).await.unwrap();
+        assert_eq!(first[0].url, "https://own.example/");
+
+        // Another region's URL is only taken once it is past `steal_after`
+        let rest = frontier.claim_urls(10, &ctx()).await.unwrap();
+        let urls: Vec<_> = rest.iter().map(|u| u.url.as_str()).collect();
+        assert_eq!(urls, vec!["https://overdue.example/"]);
+        let leased_region: Option<String> = sqlx::query_scalar(
+            "SELECT leased_region FROM url_frontier WHERE url = 'https://overdue.example/'",
+        )
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(leased_region.as_deref(), Some("us-east-1"));
+    }
+
+    #[tokio::test]
+    async fn test_job_lease_duration_reaches_seed_and_discovered_urls() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let frontier = FrontierService::with_router(
+            pool.clone(),
+            RegionRouter::with_regions(vec![Region::new("us-east-1")]),
+        );
+        let job = JobSettings {
+            lease_duration: Some(std::time::Duration::from_secs(900)),
+            ..JobSettings::default()
+        };
+        let seed = "https://slow.example/";
+        frontier.add_seed(seed, &job).await.unwrap();
+
+        let claimed = frontier.claim_urls(10, &ctx()).await.unwrap();
+        assert_eq!(claimed[0].job(), job);
+        let lease_secs: f64 = sqlx::query_scalar(
+            "SELECT EXTRACT(EPOCH FROM leased_until - NOW())::float8 FROM url_frontier WHERE url = $1",
+        )
+        .bind(seed)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert!(lease_secs > 800.0, "lease of {}s", lease_secs);
+
+        let page = "https://slow.example/page";
+        frontier
+            .add_discovered(page, seed, 1, 0, &claimed[0].job())
+            .await
+            .unwrap();
+        let stored: Option<i32> =
+            sqlx::query_scalar("SELECT lease_duration_secs FROM url_frontier WHERE url = $1")
+                .bind(page)
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+        assert_eq!(stored, Some(900));
+    }
+
+    #[tokio::test]
+    async fn test_ip_host_urls_are_dead_lettered_in_postgres() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let frontier = FrontierService::with_router(
+            pool.clone(),
+            RegionRouter::with_regions(vec![Region::new("us-east-1")]),
+        );
+        let url = "http://192.0.2.7:8080/gone";
+        frontier.add_url(url, 0, 0).await.unwrap();
+        let policy = RetryPolicy::default();
+
+        let first = frontier
+            .register_failure(url, ErrorClass::ClientError, "HTTP 404", &policy)
+            .await
+            .unwrap();
+        assert!(matches!(first, FailureOutcome::Retry { .. }));
+        let second = frontier
+            .register_failure(url, ErrorClass::ClientError, "HTTP 404", &policy)
+            .await
+            .unwrap();
+        assert_eq!(second, FailureOutcome::DeadLettered);
+
+        let (domain, attempts, class): (String, i32, String) = sqlx::query_as(
+            "SELECT domain, fetch_attempts, error_class FROM dead_letter_urls WHERE url = $1",
+        )
+        .bind(url)
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+        assert_eq!(domain, "192.0.2.7");
+        assert_eq!(attempts, 2);
+        assert_eq!(class, "http_4xx");
+        let queued: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM url_frontier")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(queued, 0);
+
+        // A failure for a URL no longer queued records nothing
+        let gone = frontier
+            .register_failure(url, ErrorClass::ClientError, "HTTP 404", &policy)
+            .await
+            .unwrap();
+        assert_eq!(gone, FailureOutcome::NotQueued);
+    }
+
+    #[tokio::test]
+    async fn test_captures_validators_and_revisits_in_postgres() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let frontier = FrontierService::with_router(
+            pool.clone(),
+            RegionRouter::with_regions(vec![Region::new("us-east-1")]),
+        );
+        let url = "https://example.com/page";
+
+        // Validators survive the frontier row of a completed first visit
+        frontier.add_url(url, 0, 0).await.unwrap();
+        let validators = CacheValidators {
+            etag: Some("\"v1\"".to_string()),
+            last_modified: Some("Mon, 05 Jan 2026 10:00:00 GMT".to_string()),
+        };
+        frontier.set_validators(url, &validators).await.unwrap();
+        frontier.complete(url).await.unwrap();
+        frontier.add_url(url, 0, 0).await.unwrap();
+        let claimed = frontier.claim_urls(10, &ctx()).await.unwrap();
+        assert_eq!(claimed.len(), 1);
+        assert_eq!(claimed[0].etag, validators.etag);
+        assert_eq!(claimed[0].last_modified, validators.last_modified);
+
+        assert!(frontier.latest_capture(url).await.unwrap().is_none());
+        let first = frontier
+            .record_capture(&NewCapture {
+                url,
+                timestamp: Utc::now() - chrono::Duration::days(1),
+                warc_file: "crawl.warc",
+                offset: 42,
+                length: 100,
+                sha256: "abc",
+                status_code: 200,
+                content_type: "text/html",
+            })
+            .await
+            .unwrap();
+
+        let previous = frontier.latest_capture(url).await.unwrap().unwrap();
+        assert_eq!((previous.offset, previous.length), (42, 100));
+        // Two 304s in a row both revisit the response, not each other
+        for hours_ago in [2, 1] {
+            let previous = frontier.latest_capture(url).await.unwrap().unwrap();
+            let revisit = frontier
+                .record_revisit(
+                    url,
+                    Utc::now() - chrono::Duration::hours(hours_ago),
+                    crate::warc::PROFILE_SERVER_NOT_MODIFIED,
+                    &previous,
+                )
+                .await
+                .unwrap();
+            let (revisit_of, profile): (Option<Uuid>, Option<String>) =
+                sqlx::query_as("SELECT revisit_of, revisit_profile FROM snapshots WHERE id = $1")
+                    .bind(revisit)
+                    .fetch_one(&pool)
+                    .await
+                    .unwrap();
+            assert_eq!(revisit_of, Some(first));
+            assert_eq!(
+                profile.as_deref(),
+                Some(crate::warc::PROFILE_SERVER_NOT_MODIFIED)
+            );
+        }
+        let latest = frontier.latest_capture(url).await.unwrap().unwrap();
+        assert_eq!(latest.id, first);
+        assert_eq!(
+            (latest.warc_file.as_str(), latest.offset),
+            ("crawl.warc", 42)
+        );
+        let original = frontier.original_capture("abc").await.unwrap().unwrap();
+        assert_eq!(original.id, first);
+
+        // A 304 without a capture to point at clears them again
+        frontier
+            .set_validators(url, &CacheValidators::default())
+            .await
+            .unwrap();
+        frontier.complete(url).await.unwrap();
+        frontier.add_url(url, 0, 0).await.unwrap();
+        let claimed = frontier.claim_urls(10, &ctx()).await.unwrap();
+        assert!(claimed[0].validators().is_empty());
+    }
 }
@@ -0,0 +1,308 @@
+use crate::frontier::ClaimContext;
+use crate::region::{Region, RegionRouter};
+use anyhow::Result;
+use serde::Serialize;
+use sqlx::PgPool;
+use std::time::Duration;
+
+/// How long claimed URLs stay leased and how often the lease is renewed
+#[derive(Debug, Clone)]
+pub struct LeaseConfig {
+    /// Lease length for URLs without their own `lease_duration_secs`
+    pub lease_duration: Duration,
+    /// Interval between lease renewals and worker heartbeats
+    pub heartbeat_interval: Duration,
+    /// A worker whose heartbeat is older than this is considered dead
+    pub worker_timeout: Duration,
+}
+
+impl Default for LeaseConfig {
+    fn default() -> Self {
+        Self {
+            lease_duration: Duration::from_secs(60),
+            heartbeat_interval: Duration::from_secs(20),
+            worker_timeout: Duration::from_secs(120),
+        }
+    }
+}
+
+impl LeaseConfig {
+    /// Reads `LEASE_DURATION_SECS`, `LEASE_HEARTBEAT_SECS` and `WORKER_TIMEOUT_SECS`
+    pub fn from_env() -> Self {
+        let default = Self::default();
+        let secs = |name: &str, fallback: Duration| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<u64>().ok())
+                .map(Duration::from_secs)
+                .unwrap_or(fallback)
+        };
+
+        Self {
+            lease_duration: secs("LEASE_DURATION_SECS", default.lease_duration),
+            heartbeat_interval: secs("LEASE_HEARTBEAT_SECS", default.heartbeat_interval),
+            worker_timeout: secs("WORKER_TIMEOUT_SECS", default.worker_timeout),
+        }
+        .normalized()
+    }
+
+    /// Keep renewals well inside the lease so one missed beat does not lose it
+    pub fn normalized(mut self) -> Self {
+        self.lease_duration = self.lease_duration.max(Duration::from_secs(3));
+        let ceiling = self.lease_duration / 3;
+        if self.heartbeat_interval.is_zero() || self.heartbeat_interval > ceiling {
+            self.heartbeat_interval = ceiling;
+        }
+        self.worker_timeout = self.worker_timeout.max(self.heartbeat_interval * 2);
+        self
+    }
+}
+
+/// A lease released because its worker stopped heartbeating
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct RecoveredLease {
+    pub url: String,
+    pub worker_id: String,
+    pub region: Option<String>,
+}
+
+/// Worker heartbeats and the stuck-lease reaper (Postgres only)
+#[derive(Clone)]
+pub struct LeaseManager {
+    pool: PgPool,
+    config: LeaseConfig,
+}
+
+impl LeaseManager {
+    pub fn new(pool: PgPool, config: LeaseConfig) -> Self {
+        Self { pool, config }
+    }
+
+    pub fn config(&self) -> &LeaseConfig {
+        &self.config
+    }
+
+    /// Upsert this worker's row in `workers` with a fresh heartbeat
+    pub async fn heartbeat(&self, ctx: &ClaimContext, active_tasks: i32) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO workers (worker_id, region, last_heartbeat, active_tasks)
+            VALUES ($1, $2, NOW(), $3)
+            ON CONFLICT (worker_id) DO UPDATE SET
+                region = EXCLUDED.region,
+                last_heartbeat = NOW(),
+                active_tasks = EXCLUDED.active_tasks
+            "#,
+        )
+        .bind(&ctx.worker_id)
+        .bind(ctx.region.as_str())
+        .bind(active_tasks)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Regions with at least one worker whose heartbeat has not expired
+    pub async fn live_regions(&self) -> Result<Vec<Region>> {
+        let regions: Vec<String> = sqlx::query_scalar(
+            "SELECT DISTINCT region FROM workers WHERE last_heartbeat >= NOW() - make_interval(secs => $1) ORDER BY region",
+        )
+        .bind(self.config.worker_timeout.as_secs() as f64)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(regions
+            .iter()
+            .filter_map(|r| r.parse::<Region>().ok())
+            .collect())
+    }
+
+    /// The ring crawlers route with: `REGIONS`, or else the live regions
+    pub async fn region_router(&self) -> Result<RegionRouter> {
+        let regions = match RegionRouter::configured_regions() {
+            Some(regions) => regions,
+            None => self.live_regions().await?,
+        };
+        Ok(RegionRouter::with_regions(regions))
+    }
+
+    pub async fn record_processed(&self, worker_id: &str) -> Result<()> {
+        sqlx::query(
+            "UPDATE workers SET total_processed = total_processed + 1 WHERE worker_id = $1",
+        )
+        .bind(worker_id)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Release every lease held by workers whose heartbeat expired, drop those
+    /// workers and return the recovered URLs
+    pub async fn reap_expired(&self, reaper_id: &str) -> Result<Vec<RecoveredLease>> {
+        let mut tx = self.pool.begin().await?;
+        let timeout = self.config.worker_timeout.as_secs() as f64;
+
+        let recovered = sqlx::query_as::<_, RecoveredLease>(
+            r#"
+            UPDATE url_frontier f
+            SET leased_until = NULL, leased_by_worker = NULL, leased_region = NULL
+            FROM url_frontier old
+            JOIN workers w ON w.worker_id = old.leased_by_worker
+            WHERE f.url = old.url
+              AND w.last_heartbeat < NOW() - make_interval(secs => $1)
+            RETURNING f.url, old.leased_by_worker AS worker_id, old.leased_region AS region
+            "#,
+        )
+        .bind(timeout)
+        .fetch_all(&mut *tx)
+        .await?;
+
+        for lease in &recovered {
+            sqlx::query(
+                "INSERT INTO recovered_leases (url, worker_id, region, recovered_by) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(&lease.url)
+            .bind(&lease.worker_id)
+            .bind(&lease.region)
+            .bind(reaper_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        sqlx::query("DELETE FROM workers WHERE last_heartbeat < NOW() - make_interval(secs => $1)")
+            .bind(timeout)
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(recovered)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archive_common::testing::scratch_pool;
+
+    #[test]
+    fn test_heartbeat_stays_inside_lease() {
+        let config = LeaseConfig {
+            lease_duration: Duration::from_secs(600),
+            heartbeat_interval: Duration::from_secs(900),
+            worker_timeout: Duration::from_secs(10),
+        }
+        .normalized();
+
+        assert_eq!(config.heartbeat_interval, Duration::from_secs(200));
+        assert_eq!(config.worker_timeout, Duration::from_secs(400));
+        assert_eq!(
+            LeaseConfig::default().normalized().heartbeat_interval,
+            Duration::from_secs(20)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ring_follows_regions_of_live_workers() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO workers (worker_id, region, last_heartbeat) VALUES
+                ('a', 'us-east-1', NOW()),
+                ('b', 'us-east-1', NOW()),
+                ('c', 'sa-east-1', NOW()),
+                ('dead', 'eu-west-1', NOW() - INTERVAL '1 hour')
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+
+        let leases = LeaseManager::new(pool, LeaseConfig::default());
+        let live = leases.live_regions().await.unwrap();
+        assert_eq!(
+            live,
+            vec![Region::new("sa-east-1"), Region::new("us-east-1")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reaper_frees_leases_of_dead_workers() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        sqlx::query(
+            r#"
+            INSERT INTO workers (worker_id, region, last_heartbeat) VALUES
+                ('alive', 'us-east-1', NOW()),
+                ('dead', 'eu-west-1', NOW() - INTERVAL '1 hour')
+            "#,
+        )
+        .execute(&pool)
+        .await
+        .unwrap();
+        for (url, worker) in [
+            ("https://a.example/held", "alive"),
+            ("https://b.example/stuck1", "dead"),
+            ("https://b.example/stuck2", "dead"),
+        ] {
+            sqlx::query(
+                r#"
+                INSERT INTO url_frontier (url, domain, leased_until, leased_by_worker, leased_region)
+                VALUES ($1, 'example', NOW() + INTERVAL '10 minutes', $2, 'eu-west-1')
+                "#,
+            )
+            .bind(url)
+            .bind(worker)
+            .execute(&pool)
+            .await
+            .unwrap();
+        }
+
+        let leases = LeaseManager::new(pool.clone(), LeaseConfig::default());
+        let mut recovered = leases.reap_expired("reaper").await.unwrap();
+        recovered.sort_by(|a, b| a.url.cmp(&b.url));
+        let urls: Vec<_> = recovered.iter().map(|l| l.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            vec!["https://b.example/stuck1", "https://b.example/stuck2"]
+        );
+        assert!(recovered
+            .iter()
+            .all(|l| l.worker_id == "dead" && l.region.as_deref() == Some("eu-west-1")));
+
+        // The live worker keeps its lease; the dead one's are claimable again
+        let leased: Vec<(String, Option<String>)> = sqlx::query_as(
+            "SELECT url, leased_by_worker FROM url_frontier WHERE leased_until IS NOT NULL ORDER BY url",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(
+            leased,
+            vec![(
+                "https://a.example/held".to_string(),
+                Some("alive".to_string())
+            )]
+        );
+
+        let logged: Vec<(String, String, String)> = sqlx::query_as(
+            "SELECT url, worker_id, recovered_by FROM recovered_leases ORDER BY url",
+        )
+        .fetch_all(&pool)
+        .await
+        .unwrap();
+        assert_eq!(logged.len(), 2);
+        assert!(logged
+            .iter()
+            .all(|(_, worker, by)| worker == "dead" && by == "reaper"));
+        let workers: Vec<String> = sqlx::query_scalar("SELECT worker_id FROM workers")
+            .fetch_all(&pool)
+            .await
+            .unwrap();
+        assert_eq!(workers, vec!["alive".to_string()]);
+
+        // A second pass finds nothing left to recover
+        assert!(leases.reap_expired("reaper").await.unwrap().is_empty());
+    }
+}
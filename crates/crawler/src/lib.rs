@@ -1,172 +1,1218 @@
+pub mod builder;
 pub mod dedup;
 pub mod fetcher;
 pub mod frontier;
+pub mod lease;
+pub mod memory;
+pub mod normalize;
 pub mod parser;
+pub mod priority;
 pub mod rate_limit;
 pub mod region;
+pub mod retry;
 pub mod robots;
+pub mod sniff;
 pub mod warc;
 
-use crate::dedup::DedupService;
-use crate::fetcher::Fetcher;
-use crate::frontier::FrontierService;
-use crate::rate_limit::RateLimiter;
-use crate::region::{Region, RegionRouter};
-use archive_intelligence::{PredictiveEngine, StandardPredictor};
+pub use crate::builder::CrawlerBuilder;
+
+use crate::dedup::{DedupBackend, NearDupConfig};
+use crate::fetcher::{CacheValidators, FetchResult, Fetcher};
+use crate::frontier::{
+    ChangeObservation, ClaimContext, FailureOutcome, FrontierBackend, FrontierUrl, JobSettings,
+    NewCapture, PreviousCapture, MAX_EMBED_HOPS,
+};
+use crate::lease::{LeaseConfig, LeaseManager};
+use crate::parser::LinkKind;
+use crate::rate_limit::RateLimitBackend;
+use crate::region::SharedRegionRouter;
+use crate::retry::{ErrorClass, RetryPolicy};
+use crate::robots::RobotsChecker;
+use crate::warc::{WarcRecord, PROFILE_IDENTICAL_PAYLOAD, PROFILE_SERVER_NOT_MODIFIED};
+use anyhow::Context;
+use archive_common::storage::ArchiveStorage;
+use archive_common::warc::WarcWriter;
+use archive_intelligence::{
+    ChangePrior, CrawlPrediction, PredictionContext, PredictiveEngine, SnapshotHistory,
+};
+use chrono::{DateTime, Utc};
 use sqlx::PgPool;
-use std::sync::Arc;
-use tracing::{error, info};
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicI32, Ordering};
+use std::sync::{Arc, Mutex};
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+/// How much observation time a domain's pooled change rate is worth as a prior
+const DOMAIN_PRIOR_WEIGHT_DAYS: i64 = 7;
+
+/// A snapshot row waiting for its batch's WARC to be stored
+enum PendingRow {
+    /// A response record in the batch's WARC; `payload_size` is set for a
+    /// payload new to the dedup index
+    Capture {
+        url: String,
+        timestamp: DateTime<Utc>,
+        offset: u64,
+        length: u64,
+        sha256: String,
+        status_code: u16,
+        content_type: String,
+        payload_size: Option<u64>,
+    },
+    /// A capture whose payload is already archived in `original`
+    Revisit {
+        url: String,
+        timestamp: DateTime<Utc>,
+        profile: &'static str,
+        original: PreviousCapture,
+    },
+}
+
+/// The frontier writes for a fetched URL. They wait for the batch's WARC too:
+/// validators or a completed row without a stored capture would lose the
+/// capture for good, as the next fetch would be a 304 with nothing to revisit
+struct PendingVisit {
+    url: String,
+    /// Empty unless a snapshot row of the URL itself is written, which clears
+    /// validators that no stored capture answers to
+    validators: CacheValidators,
+    observed_at: DateTime<Utc>,
+    payload_digest: String,
+    text_hash: Option<String>,
+    simhash: Option<u64>,
+    /// Whether `simhash` becomes the one later captures are compared with;
+    /// only when a snapshot row is written
+    keep_simhash: bool,
+    similarity: Option<f32>,
+    near_duplicate: bool,
+    /// The scheduled next visit and the observation count it was made from;
+    /// `None` completes the URL
+    prediction: Option<(CrawlPrediction, PredictionContext, usize)>,
+}
+
+/// The records of one claimed batch, stored as a single WARC before any
+/// snapshot row points into it
+struct CaptureBatch {
+    warc: WarcWriter,
+    rows: Vec<PendingRow>,
+    visits: Vec<PendingVisit>,
+}
+
+impl CaptureBatch {
+    fn new() -> Self {
+        Self {
+            warc: WarcWriter::new(format!(
+                "crawl-{}-{}.warc",
+                Utc::now().format("%Y%m%d%H%M%S"),
+                Uuid::new_v4().simple()
+            )),
+            rows: Vec::new(),
+            visits: Vec::new(),
+        }
+    }
+
+    fn response(&mut self, record: &WarcRecord, new_payload: bool) {
+        let (offset, length) = self.warc.write_bytes(&record.to_warc_bytes());
+        self.rows.push(PendingRow::Capture {
+            url: record.url.clone(),
+            timestamp: record.timestamp,
+            offset,
+            length,
+            sha256: record.payload_digest.clone(),
+            status_code: record.status_code,
+            content_type: record.content_type.clone(),
+            payload_size: new_payload.then_some(record.content.len() as u64),
+        });
+    }
+
+    /// A revisit shares the location of the response holding its payload,
+    /// like imported and synced revisits; exports write its revisit record
+    fn revisit(&mut self, record: &WarcRecord, profile: &'static str, original: PreviousCapture) {
+        self.rows.push(PendingRow::Revisit {
+            url: record.url.clone(),
+            timestamp: record.timestamp,
+            profile,
+            original,
+        });
+    }
+}
 
 pub struct Crawler {
     fetcher: Fetcher,
-    dedup: DedupService,
-    frontier: FrontierService,
+    storage: Arc<dyn ArchiveStorage>,
+    dedup: Arc<dyn DedupBackend>,
+    near_dup: NearDupConfig,
+    frontier: Arc<dyn FrontierBackend>,
+    robots: RobotsChecker,
+    retry_policy: RetryPolicy,
     predictor: Arc<dyn PredictiveEngine>,
-    #[allow(dead_code)]
-    region: Region,
-    #[allow(dead_code)]
-    region_router: RegionRouter,
-    #[allow(dead_code)]
-    rate_limiter: RateLimiter,
+    claim_context: ClaimContext,
+    lease_config: LeaseConfig,
+    /// Worker heartbeats and the lease reaper; only with the Postgres backend
+    leases: Option<LeaseManager>,
+    /// The default frontier's ring when it follows live regions in `workers`
+    live_regions: Option<SharedRegionRouter>,
+    in_flight: Arc<AtomicI32>,
+    rate_limiter: Arc<dyn RateLimitBackend>,
 }
 
 impl Crawler {
-    pub fn new(pool: PgPool) -> Self {
-        let region = Region::from_env();
-        info!("Initializing crawler in region: {}", region.as_str());
+    /// A crawler on the Postgres backends, storing WARCs in the backend named by
+    /// `ARCHIVE_STORAGE`, configured from the environment
+    pub fn new(pool: PgPool) -> anyhow::Result<Self> {
+        let crawler = Self::builder().postgres(pool).build()?;
+        info!(
+            "Initializing crawler {} in region: {}",
+            crawler.claim_context.worker_id,
+            crawler.claim_context.region.as_str()
+        );
+        Ok(crawler)
+    }
 
-        Self {
-            fetcher: Fetcher::new(),
-            dedup: DedupService::new(pool.clone()),
-            frontier: FrontierService::new(pool.clone()),
-            predictor: Arc::new(StandardPredictor),
-            region,
-            region_router: RegionRouter::new(),
-            rate_limiter: RateLimiter::new(pool),
-        }
+    /// Start from in-memory backends and environment configuration
+    pub fn builder() -> CrawlerBuilder {
+        CrawlerBuilder::new()
     }
 
     pub async fn add_url(&self, url: &str) -> anyhow::Result<()> {
-        self.frontier.add_url(url, 0, 0).await
+        self.frontier.add_seed(url, &JobSettings::default()).await
+    }
+
+    /// Seed a crawl job; its boost and freshness target apply to every page
+    /// discovered from the seed
+    pub async fn add_seed(&self, url: &str, job: &JobSettings) -> anyhow::Result<()> {
+        self.frontier.add_seed(url, job).await
     }
 
     pub async fn run(&self) -> anyhow::Result<()> {
         info!("Crawler loop started in stateless mode");
+        self.spawn_heartbeat();
         loop {
-            // Claim a batch of URLs from the frontier
-            match self.frontier.claim_urls(10).await {
-                Ok(urls) if !urls.is_empty() => {
-                    for f_url in urls {
-                        let url = f_url.url;
-                        let depth = f_url.depth;
-                        info!("Crawling: {} (Depth: {})", url, depth);
-
-                        let start_time = std::time::Instant::now();
-                        match self.fetcher.fetch(&url).await {
-                            Ok(record) => {
-                                let duration = start_time.elapsed().as_millis() as i32;
-                                info!("Fetched {} (Digest: {})", url, record.payload_digest);
-
-                                let _ = self
-                                    .frontier
-                                    .track_event(
-                                        &url,
-                                        "success",
-                                        Some(record.status_code as i32),
-                                        duration,
-                                    )
-                                    .await;
-
-                                let is_duplicate = self
-                                    .dedup
-                                    .is_duplicate(&record.payload_digest)
-                                    .await
-                                    .unwrap_or(false);
-
-                                if is_duplicate {
-                                    info!("Deduplicated: Payload exists for {}", url);
-                                    let _warc = record.to_warc_string(true);
-                                    // TODO: Save to S3
-                                } else {
-                                    info!("New payload for {}", url);
-                                    let _warc = record.to_warc_string(false);
-                                    // TODO: Save to S3
-                                    let _ = self
-                                        .dedup
-                                        .insert_payload(
-                                            &record.payload_digest,
-                                            "shared.warc.gz",
-                                            0,
-                                            record.content.len() as u64,
-                                        )
-                                        .await;
-                                }
-
-                                // Mark as complete in frontier
-                                // Mark as complete in frontier OR reschedule if it's a known URL (Phase 7.3)
-                                let history = self
-                                    .frontier
-                                    .get_snapshot_history(&url)
+            match self.run_once(10).await {
+                Ok(0) => {
+                    // No URLs to claim, wait
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    error!("Frontier claim error: {}", e);
+                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                }
+            }
+        }
+    }
+
+    /// Claim one batch of up to `limit` URLs, crawl it and store its WARC;
+    /// returns how many were crawled
+    pub async fn run_once(&self, limit: i32) -> anyhow::Result<usize> {
+        let urls = self.frontier.claim_urls(limit, &self.claim_context).await?;
+        let count = urls.len();
+        if count == 0 {
+            return Ok(0);
+        }
+
+        // A URL is only done once the batch's WARC and its frontier updates
+        // are stored, so its lease is kept until then
+        let held = Arc::new(Mutex::new(
+            urls.iter().map(|u| u.url.clone()).collect::<HashSet<_>>(),
+        ));
+        let keeper = self.keep_leases(held.clone());
+        let mut batch = CaptureBatch::new();
+        for f_url in urls {
+            let url = f_url.url.clone();
+            self.crawl_leased(f_url, &mut batch).await;
+            // A failure is recorded straight away and gives up the lease
+            if !batch.visits.iter().any(|v| v.url == url) {
+                held.lock().unwrap_or_else(|e| e.into_inner()).remove(&url);
+            }
+        }
+        let stored = self.store_batch(batch).await;
+        keeper.abort();
+        stored?;
+        Ok(count)
+    }
+
+    /// Renew the leases on `held` every heartbeat until aborted, so no other
+    /// worker claims a URL while it is fetched or waits for its batch's WARC
+    fn keep_leases(&self, held: Arc<Mutex<HashSet<String>>>) -> tokio::task::JoinHandle<()> {
+        let worker_id = self.claim_context.worker_id.clone();
+        let frontier = self.frontier.clone();
+        let lease_duration = self.claim_context.lease_duration;
+        let heartbeat_interval = self.lease_config.heartbeat_interval;
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(heartbeat_interval);
+            // The first tick fires immediately and the leases are fresh
+            ticker.tick().await;
+            loop {
+                ticker.tick().await;
+                let urls = held
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner())
+                    .iter()
+                    .cloned()
+                    .collect::<Vec<_>>();
+                for url in urls {
+                    match frontier.renew_lease(&url, &worker_id, lease_duration).await {
+                        Ok(true) => {}
+                        Ok(false) => {
+                            warn!("Lost lease on {}", url);
+                            held.lock().unwrap_or_else(|e| e.into_inner()).remove(&url);
+                        }
+                        Err(e) => warn!("Lease renewal failed for {}: {}", url, e),
+                    }
+                }
+            }
+        })
+    }
+
+    /// Store the batch's WARC, then create the snapshot and payload rows that
+    /// point into it and apply its URLs' frontier updates. If storing fails,
+    /// nothing is written and the URLs' leases are released for a retry
+    async fn store_batch(&self, batch: CaptureBatch) -> anyhow::Result<()> {
+        let CaptureBatch { warc, rows, visits } = batch;
+        let warc_file = warc.name().to_string();
+        if let Err(e) = warc.finish(self.storage.as_ref()).await {
+            for visit in &visits {
+                if let Err(e) = self
+                    .frontier
+                    .release_lease(&visit.url, &self.claim_context.worker_id)
+                    .await
+                {
+                    warn!("Failed to release lease on {}: {}", visit.url, e);
+                }
+            }
+            return Err(e)
+                .with_context(|| format!("storing {} ({} captures)", warc_file, rows.len()));
+        }
+
+        for row in rows {
+            let (url, result) = match &row {
+                PendingRow::Capture {
+                    url,
+                    timestamp,
+                    offset,
+                    length,
+                    sha256,
+                    status_code,
+                    content_type,
+                    payload_size,
+                } => {
+                    let capture = NewCapture {
+                        url,
+                        timestamp: *timestamp,
+                        warc_file: &warc_file,
+                        offset: *offset as i64,
+                        length: *length as i64,
+                        sha256,
+                        status_code: *status_code as i16,
+                        content_type,
+                    };
+                    let result = match self.frontier.record_capture(&capture).await {
+                        Ok(_) => match payload_size {
+                            Some(size) => {
+                                self.dedup
+                                    .insert_payload(sha256, &warc_file, *offset, *size)
                                     .await
-                                    .unwrap_or_default();
-                                if !history.is_empty() {
-                                    match self.predictor.predict_next_crawl(&history).await {
-                                        Ok(prediction) => {
-                                            info!(
-                                                "Rescheduling {}: Next crawl at {}, Priority: {}",
-                                                url,
-                                                prediction.next_fetch_at,
-                                                prediction.recommended_priority
-                                            );
-                                            let _ = self
-                                                .frontier
-                                                .reschedule(
-                                                    &url,
-                                                    prediction.next_fetch_at,
-                                                    prediction.recommended_priority,
-                                                )
-                                                .await;
-                                        }
-                                        Err(_) => {
-                                            let _ = self.frontier.complete(&url).await;
-                                        }
-                                    }
-                                } else {
-                                    let _ = self.frontier.complete(&url).await;
-                                }
-
-                                // Extract links and feed back to frontier
-                                if record.content_type.contains("html") {
-                                    let html = String::from_utf8_lossy(&record.content);
-                                    let links = parser::extract_links(&url, &html);
-                                    info!("Discovered {} links", links.len());
-
-                                    for link in links {
-                                        let _ = self.frontier.add_url(&link, 0, depth + 1).await;
-                                    }
-                                }
                             }
-                            Err(e) => {
-                                let duration = start_time.elapsed().as_millis() as i32;
-                                error!("Failed {}: {}. Exponential backoff applied.", url, e);
-                                let _ = self
-                                    .frontier
-                                    .track_event(&url, "error", None, duration)
-                                    .await;
-                                let _ = self.frontier.fail(&url, 3600).await; // 1 hour backoff
+                            None => Ok(()),
+                        },
+                        Err(e) => Err(e),
+                    };
+                    (url, result)
+                }
+                PendingRow::Revisit {
+                    url,
+                    timestamp,
+                    profile,
+                    original,
+                } => (
+                    url,
+                    self.frontier
+                        .record_revisit(url, *timestamp, profile, original)
+                        .await
+                        .map(|_| ()),
+                ),
+            };
+            if let Err(e) = result {
+                error!(
+                    "Failed to record capture of {} in {}: {}",
+                    url, warc_file, e
+                );
+            }
+        }
+
+        for visit in visits {
+            self.apply_visit(visit).await;
+        }
+        Ok(())
+    }
+
+    /// Save a fetched URL's validators and change observation, then reschedule
+    /// it or complete a first visit (Phase 7.3)
+    async fn apply_visit(&self, visit: PendingVisit) {
+        let url = &visit.url;
+        let _ = self.frontier.set_validators(url, &visit.validators).await;
+        if let Some(simhash) = visit.simhash.filter(|_| visit.keep_simhash) {
+            let _ = self.dedup.set_simhash(url, simhash).await;
+        }
+        let _ = self
+            .frontier
+            .record_observation(&ChangeObservation {
+                url,
+                observed_at: visit.observed_at,
+                payload_digest: &visit.payload_digest,
+                text_hash: visit.text_hash.as_deref(),
+                simhash: visit.simhash,
+                similarity: visit.similarity,
+                near_duplicate: visit.near_duplicate,
+            })
+            .await;
+
+        match visit.prediction {
+            Some((prediction, ctx, observations)) => {
+                let _ = self
+                    .frontier
+                    .record_prediction(&prediction, &ctx, observations)
+                    .await;
+                let _ = self
+                    .frontier
+                    .reschedule(url, prediction.next_fetch_at, prediction.change_probability)
+                    .await;
+            }
+            None => {
+                let _ = self.frontier.complete(url).await;
+            }
+        }
+    }
+
+    /// Heartbeat this worker, follow region membership and reap leases of
+    /// dead workers in the background
+    fn spawn_heartbeat(&self) {
+        let Some(leases) = self.leases.clone() else {
+            return;
+        };
+        let ctx = self.claim_context.clone();
+        let in_flight = self.in_flight.clone();
+        let live_regions = self.live_regions.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(leases.config().heartbeat_interval);
+            loop {
+                ticker.tick().await;
+                if let Err(e) = leases
+                    .heartbeat(&ctx, in_flight.load(Ordering::Relaxed))
+                    .await
+                {
+                    warn!("Worker heartbeat failed: {}", e);
+                }
+                if let Some(router) = &live_regions {
+                    match leases.live_regions().await {
+                        Ok(regions) => {
+                            if router.set_regions(regions) {
+                                info!("Region ring rebuilt over {:?}", router.current().regions());
                             }
                         }
+                        Err(e) => warn!("Region membership lookup failed: {}", e),
                     }
                 }
-                Ok(_) => {
-                    // No URLs to claim, wait
-                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+                match leases.reap_expired(&ctx.worker_id).await {
+                    Ok(recovered) if !recovered.is_empty() => {
+                        for lease in &recovered {
+                            warn!(
+                                "Recovered lease on {} from dead worker {}",
+                                lease.url, lease.worker_id
+                            );
+                        }
+                    }
+                    Ok(_) => {}
+                    Err(e) => warn!("Lease reaper failed: {}", e),
                 }
-                Err(e) => {
-                    error!("Frontier claim error: {}", e);
-                    tokio::time::sleep(tokio::time::Duration::from_secs(5)).await;
+            }
+        });
+    }
+
+    /// Crawl a claimed URL, counted in this worker's heartbeat while in flight
+    async fn crawl_leased(&self, f_url: FrontierUrl, batch: &mut CaptureBatch) {
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.crawl_url(f_url, batch).await;
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+
+        if let Some(leases) = &self.leases {
+            let _ = leases.record_processed(&self.claim_context.worker_id).await;
+        }
+    }
+
+    async fn crawl_url(&self, f_url: FrontierUrl, batch: &mut CaptureBatch) {
+        let url = f_url.url.clone();
+        let depth = f_url.depth;
+        info!("Crawling: {} (Depth: {})", url, depth);
+
+        let start_time = std::time::Instant::now();
+
+        if !self.robots.can_crawl(&url).await {
+            self.handle_failure(
+                &url,
+                ErrorClass::Robots,
+                "disallowed by robots.txt",
+                None,
+                0,
+            )
+            .await;
+            return;
+        }
+
+        if !self.within_rate_limit(&f_url).await {
+            return;
+        }
+
+        let FetchResult {
+            redirects,
+            mut record,
+        } = match self
+            .fetcher
+            .fetch_conditional(&url, &f_url.validators())
+            .await
+        {
+            Ok(result) => result,
+            Err(e) => {
+                let duration = start_time.elapsed().as_millis() as i32;
+                let class = ErrorClass::classify(&e);
+                self.handle_failure(&url, class, &e.to_string(), None, duration)
+                    .await;
+                return;
+            }
+        };
+
+        let duration = start_time.elapsed().as_millis() as i32;
+        for hop in &redirects {
+            info!(
+                "Redirect {} {} -> {}",
+                hop.status_code,
+                hop.url,
+                hop.location.as_deref().unwrap_or_default()
+            );
+            batch.response(hop, false);
+        }
+
+        if let Some(class) = ErrorClass::from_status(record.status_code) {
+            let message = format!("HTTP {}", record.status_code);
+            self.handle_failure(
+                &url,
+                class,
+                &message,
+                Some(record.status_code as i32),
+                duration,
+            )
+            .await;
+            return;
+        }
+
+        info!("Fetched {} (Digest: {})", record.url, record.payload_digest);
+        if let Some(reason) = &record.truncated {
+            warn!("Truncated {} ({})", record.url, reason);
+        }
+
+        let _ = self
+            .frontier
+            .track_event(&url, "success", Some(record.status_code as i32), duration)
+            .await;
+
+        // Compare the visible text with the previous capture (304s carry no body)
+        let normalized = if record.is_not_modified() {
+            None
+        } else {
+            normalize::normalized_text(&record.content_type, &record.content)
+        };
+        let simhash = normalized.as_deref().map(archive_common::simhash::simhash);
+        let similar = match simhash {
+            Some(hash) => self.dedup.compare_simhash(&url, hash).await.unwrap_or(None),
+            None => None,
+        };
+        let near_duplicate = similar.is_some_and(|s| self.near_dup.is_near_duplicate(&s));
+
+        let stored = if record.is_not_modified() {
+            match self.frontier.latest_capture(&url).await {
+                Ok(Some(previous)) => {
+                    info!("Not modified: {} (revisit of {})", url, previous.id);
+                    record.payload_digest = previous.sha256.clone();
+                    batch.revisit(&record, PROFILE_SERVER_NOT_MODIFIED, previous);
+                    true
+                }
+                _ => {
+                    // Nothing to point the revisit at; the validators are
+                    // dropped so the next fetch downloads the full body.
+                    warn!("304 for {} without a previous capture", url);
+                    false
+                }
+            }
+        } else if let Some(original) = self.archived_original(&record.payload_digest).await {
+            info!("Deduplicated: Payload exists for {}", url);
+            batch.revisit(&record, PROFILE_IDENTICAL_PAYLOAD, original);
+            true
+        } else if near_duplicate && self.near_dup.skip_storage {
+            info!("Near-duplicate of previous capture, not stored: {}", url);
+            false
+        } else {
+            if near_duplicate {
+                info!("Near-duplicate of previous capture: {}", url);
+            }
+            info!("New payload for {}", url);
+            batch.response(&record, true);
+            true
+        };
+
+        // Validators belong to the URL that answered with them; after a
+        // redirect that is the target, so the origin gets none to send.
+        let validators = if stored && redirects.is_empty() {
+            CacheValidators {
+                etag: record.etag.clone(),
+                last_modified: record.last_modified.clone(),
+            }
+        } else {
+            CacheValidators::default()
+        };
+
+        // Predict the next visit from the history plus this observation; the
+        // frontier writes wait for the batch's WARC. 304s repeat the previous
+        // text hash.
+        let text_hash = (!record.is_not_modified())
+            .then(|| normalize::hash_normalized(normalized.as_deref(), &record.content));
+        let mut history = self
+            .frontier
+            .get_snapshot_history(&url)
+            .await
+            .unwrap_or_default();
+        history.push(SnapshotHistory {
+            timestamp: record.timestamp,
+            content_hash: text_hash
+                .clone()
+                .or_else(|| history.last().map(|h| h.content_hash.clone()))
+                .unwrap_or_else(|| record.payload_digest.clone()),
+            similarity: similar.map(|s| s.similarity),
+        });
+        let prediction = if history.len() > 1 {
+            let ctx = self.prediction_context(&f_url).await;
+            match self.predictor.predict_with_context(&history, &ctx).await {
+                Ok(prediction) => {
+                    info!(
+                        "Rescheduling {}: Next crawl at {}, Change probability: {:.2}",
+                        url, prediction.next_fetch_at, prediction.change_probability
+                    );
+                    Some((prediction, ctx, history.len()))
                 }
+                Err(_) => None,
+            }
+        } else {
+            None
+        };
+        batch.visits.push(PendingVisit {
+            url: url.clone(),
+            validators,
+            observed_at: record.timestamp,
+            payload_digest: record.payload_digest.clone(),
+            text_hash,
+            simhash,
+            keep_simhash: stored,
+            similarity: similar.map(|s| s.similarity),
+            near_duplicate,
+            prediction,
+        });
+
+        // Extract links and feed back to frontier.
+        // Embeds (page requisites) keep the parent's depth and jump the
+        // queue so replay has the assets captured close to the page; chains
+        // of embeds are cut off after MAX_EMBED_HOPS instead.
+        let links =
+            parser::extract_from_document(&record.url, &record.content_type, &record.content);
+        if !links.is_empty() {
+            info!("Discovered {} links", links.len());
+        }
+
+        let job = f_url.job();
+        for link in links {
+            let (link_depth, embed_hops) = match link.kind {
+                LinkKind::Outlink => (depth + 1, 0),
+                LinkKind::Embed => (depth, f_url.embed_hops + 1),
+            };
+            if embed_hops > MAX_EMBED_HOPS {
+                continue;
+            }
+            let _ = self
+                .frontier
+                .add_discovered(&link.url, &url, link_depth, embed_hops, &job)
+                .await;
+        }
+    }
+
+    /// Count a request to the URL's host; over the limit, the URL is put back
+    /// until the next window without counting as a failed attempt
+    async fn within_rate_limit(&self, f_url: &FrontierUrl) -> bool {
+        let Some(domain) = &f_url.domain else {
+            return true;
+        };
+        let region = self.claim_context.region.as_str();
+        match self.rate_limiter.check_and_increment(domain, region).await {
+            Ok(true) => true,
+            Ok(false) => {
+                let until = self.rate_limiter.retry_at(Utc::now());
+                info!("Rate limited: {} deferred until {}", f_url.url, until);
+                if let Err(e) = self
+                    .frontier
+                    .defer(&f_url.url, &self.claim_context.worker_id, until)
+                    .await
+                {
+                    warn!("Failed to defer {}: {}", f_url.url, e);
+                }
+                false
+            }
+            Err(e) => {
+                warn!("Rate limit check failed for {}: {}", domain, e);
+                true
+            }
+        }
+    }
+
+    /// The capture holding an already archived payload, if any
+    async fn archived_original(&self, digest: &str) -> Option<PreviousCapture> {
+        if !self.dedup.is_duplicate(digest).await.unwrap_or(false) {
+            return None;
+        }
+        self.frontier
+            .original_capture(digest)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("Original capture lookup failed for {}: {}", digest, e);
+                None
+            })
+    }
+
+    /// Domain prior and job freshness target for the recrawl scheduler
+    async fn prediction_context(&self, f_url: &FrontierUrl) -> PredictionContext {
+        let stats = match &f_url.domain {
+            Some(domain) => self
+                .frontier
+                .domain_change_stats(domain)
+                .await
+                .ok()
+                .flatten(),
+            None => None,
+        };
+
+        PredictionContext {
+            url: f_url.url.clone(),
+            prior: stats.filter(|s| s.intervals > 0).map(|s| {
+                ChangePrior::from_domain_stats(
+                    s.changes as f64,
+                    s.exposure_secs,
+                    chrono::Duration::days(DOMAIN_PRIOR_WEIGHT_DAYS),
+                )
+            }),
+            freshness_target: f_url.freshness_target.map(f64::from),
+        }
+    }
+
+    async fn handle_failure(
+        &self,
+        url: &str,
+        class: ErrorClass,
+        message: &str,
+        http_status: Option<i32>,
+        duration: i32,
+    ) {
+        let _ = self
+            .frontier
+            .track_event(url, "error", http_status, duration)
+            .await;
+
+        match self
+            .frontier
+            .register_failure(url, class, message, &self.retry_policy)
+            .await
+        {
+            Ok(FailureOutcome::Retry { next_fetch_at }) => {
+                warn!(
+                    "Failed {} ({}): {}. Retrying at {}",
+                    url,
+                    class.as_str(),
+                    message,
+                    next_fetch_at
+                );
+            }
+            Ok(FailureOutcome::DeadLettered) => {
+                error!(
+                    "Failed {} ({}): {}. Moved to dead-letter queue",
+                    url,
+                    class.as_str(),
+                    message
+                );
+            }
+            Ok(FailureOutcome::NotQueued) => {
+                warn!(
+                    "Failed {} ({}): {}. No longer queued",
+                    url,
+                    class.as_str(),
+                    message
+                );
+            }
+            Err(e) => error!("Failed to record failure for {}: {}", url, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::{MemoryDedup, MemoryFrontier, MemoryRateLimiter};
+    use crate::rate_limit::RateLimits;
+    use crate::region::{Region, RegionRouter};
+    use archive_common::storage::FsStorage;
+    use wiremock::matchers::{header, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn test_crawler(
+        frontier: &MemoryFrontier,
+        dedup: &MemoryDedup,
+        storage: &Arc<FsStorage>,
+    ) -> Crawler {
+        Crawler::builder()
+            .frontier(Arc::new(frontier.clone()))
+            .dedup(Arc::new(dedup.clone()))
+            .storage(storage.clone())
+            .claim_context(ClaimContext {
+                worker_id: "test".to_string(),
+                region: Region::new("us-east-1"),
+                steal_after: chrono::Duration::minutes(5),
+                lease_duration: std::time::Duration::from_secs(60),
+            })
+            .retry_policy(RetryPolicy::default())
+            .build()
+            .unwrap()
+    }
+
+    fn test_frontier() -> MemoryFrontier {
+        MemoryFrontier::with_router(RegionRouter::with_regions(vec![Region::new("us-east-1")]))
+    }
+
+    fn test_storage() -> Arc<FsStorage> {
+        Arc::new(FsStorage::new(
+            std::env::temp_dir().join(format!("crawler-{}", Uuid::new_v4())),
+        ))
+    }
+
+    /// The stored bytes of a capture's record
+    async fn stored_record(storage: &FsStorage, capture: &PreviousCapture) -> String {
+        let bytes = storage
+            .get_range(
+                &capture.warc_file,
+                capture.offset as u64,
+                capture.length as u64,
+            )
+            .await
+            .unwrap();
+        String::from_utf8_lossy(&bytes).into_owned()
+    }
+
+    #[tokio::test]
+    async fn test_crawl_against_mock_server_without_database() {
+        let server = MockServer::start().await;
+        Mock::given(path("/"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw(
+                r#"<html><body><a href="/missing">x</a><img src="/logo.png"></body></html>"#,
+                "text/html",
+            ))
+            .mount(&server)
+            .await;
+        Mock::given(path("/logo.png"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_raw(&b"\x89PNG\r\n\x1a\n"[..], "image/png"),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(path("/missing"))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+
+        let frontier = test_frontier();
+        let dedup = MemoryDedup::new();
+        let storage = test_storage();
+        let crawler = test_crawler(&frontier, &dedup, &storage);
+
+        let seed = format!("{}/", server.uri());
+        crawler.add_url(&seed).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+
+        // The page is done; its image and link were queued with the image first
+        assert!(frontier.get(&seed).is_none());
+        let logo = frontier.get(&format!("{}/logo.png", server.uri())).unwrap();
+        let missing = frontier.get(&format!("{}/missing", server.uri())).unwrap();
+        assert!(logo.is_embed && logo.depth == 0);
+        assert_eq!(missing.depth, 1);
+        assert!(logo.priority > missing.priority);
+
+        assert_eq!(crawler.run_once(10).await.unwrap(), 2);
+        assert_eq!(dedup.len(), 2);
+        let page = frontier.latest_capture(&seed).await.unwrap().unwrap();
+        let record = stored_record(&storage, &page).await;
+        assert!(record.starts_with("WARC/1.1\r\nWARC-Type: response\r\n"));
+        assert!(record.contains("HTTP/1.1 200 OK\r\n"));
+        assert!(record.contains("<img src=\"/logo.png\">"));
+        assert_eq!(frontier.len(), 1);
+        let missing = frontier.get(&format!("{}/missing", server.uri())).unwrap();
+        assert_eq!(missing.fetch_attempts, 1);
+        assert_eq!(missing.last_error_class.as_deref(), Some("http_4xx"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_batch_keeps_its_leases_until_stored() {
+        let server = MockServer::start().await;
+        for page in ["/slow1", "/slow2", "/slow3"] {
+            Mock::given(path(page))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_raw("<p>slow</p>", "text/html")
+                        .set_delay(std::time::Duration::from_millis(400)),
+                )
+                .mount(&server)
+                .await;
+        }
+
+        // Each fetch outlasts the lease, so only renewals keep the batch claimed
+        let frontier = test_frontier();
+        let worker = |id: &str| {
+            Crawler::builder()
+                .frontier(Arc::new(frontier.clone()))
+                .dedup(Arc::new(MemoryDedup::new()))
+                .storage(test_storage())
+                .claim_context(ClaimContext {
+                    worker_id: id.to_string(),
+                    region: Region::new("us-east-1"),
+                    steal_after: chrono::Duration::minutes(5),
+                    lease_duration: std::time::Duration::from_millis(300),
+                })
+                .lease_config(LeaseConfig {
+                    lease_duration: std::time::Duration::from_millis(300),
+                    heartbeat_interval: std::time::Duration::from_millis(50),
+                    worker_timeout: std::time::Duration::from_secs(60),
+                })
+                .retry_policy(RetryPolicy::default())
+                .build()
+                .unwrap()
+        };
+        let (slow, other) = (worker("slow"), worker("other"));
+        for page in ["/slow1", "/slow2", "/slow3"] {
+            slow.add_url(&format!("{}{}", server.uri(), page))
+                .await
+                .unwrap();
+        }
+
+        let batch = async {
+            let crawled = slow.run_once(10).await.unwrap();
+            assert_eq!(crawled, 3);
+        };
+        let poll = async {
+            let mut stolen = 0;
+            for _ in 0..30 {
+                stolen += other.run_once(10).await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
             }
+            stolen
+        };
+        let ((), stolen) = tokio::join!(batch, poll);
+
+        assert_eq!(stolen, 0);
+        let requests = server.received_requests().await.unwrap();
+        let fetched = requests
+            .iter()
+            .filter(|r| r.url.path().starts_with("/slow"))
+            .count();
+        assert_eq!(fetched, 3);
+        assert!(frontier.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_urls_are_deferred_without_fetching() {
+        let server = MockServer::start().await;
+        Mock::given(path("/a"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("<p>a</p>", "text/html"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/b"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("<p>b</p>", "text/html"))
+            .mount(&server)
+            .await;
+
+        let frontier = test_frontier();
+        let limits = RateLimits {
+            global_per_domain: 1,
+            regional_per_domain: 1,
+            window_seconds: 3600,
+        };
+        let crawler = Crawler::builder()
+            .frontier(Arc::new(frontier.clone()))
+            .dedup(Arc::new(MemoryDedup::new()))
+            .storage(test_storage())
+            .rate_limiter(Arc::new(MemoryRateLimiter::new(limits.clone())))
+            .claim_context(ClaimContext {
+                worker_id: "test".to_string(),
+                region: Region::new("us-east-1"),
+                steal_after: chrono::Duration::minutes(5),
+                lease_duration: std::time::Duration::from_secs(60),
+            })
+            .retry_policy(RetryPolicy::default())
+            .build()
+            .unwrap();
+        for page in ["/a", "/b"] {
+            crawler
+                .add_url(&format!("{}{}", server.uri(), page))
+                .await
+                .unwrap();
+        }
+        assert_eq!(crawler.run_once(10).await.unwrap(), 2);
+
+        // One page used the host's budget; the other waits for the next window
+        let fetched = server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|r| ["/a", "/b"].contains(&r.url.path()))
+            .count();
+        assert_eq!(fetched, 1);
+        assert_eq!(frontier.len(), 1);
+        let deferred = ["/a", "/b"]
+            .iter()
+            .find_map(|page| frontier.get(&format!("{}{}", server.uri(), page)))
+            .unwrap();
+        assert_eq!(deferred.fetch_attempts, 0);
+        assert!(deferred.leased_by_worker.is_none());
+        assert_eq!(deferred.next_fetch_at, limits.next_window(Utc::now()));
+        assert_eq!(crawler.run_once(10).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_embed_chains_stop_after_max_hops() {
+        let server = MockServer::start().await;
+        for hop in 0..=MAX_EMBED_HOPS + 1 {
+            Mock::given(path(format!("/frame{}", hop)))
+                .respond_with(ResponseTemplate::new(200).set_body_raw(
+                    format!(r#"<iframe src="/frame{}"></iframe>"#, hop + 1),
+                    "text/html",
+                ))
+                .mount(&server)
+                .await;
+        }
+
+        let frontier = test_frontier();
+        let dedup = MemoryDedup::new();
+        let storage = test_storage();
+        let crawler = test_crawler(&frontier, &dedup, &storage);
+
+        crawler
+            .add_url(&format!("{}/frame0", server.uri()))
+            .await
+            .unwrap();
+        let mut fetched = 0;
+        for _ in 0..=MAX_EMBED_HOPS + 1 {
+            fetched += crawler.run_once(10).await.unwrap();
         }
+
+        // The seed plus one frame per allowed hop; the next frame is never queued
+        assert_eq!(fetched, 1 + MAX_EMBED_HOPS as usize);
+        assert_eq!(frontier.len(), 0);
+        let last = format!("{}/frame{}", server.uri(), MAX_EMBED_HOPS);
+        assert!(frontier.latest_capture(&last).await.unwrap().is_some());
+        let cut = format!("{}/frame{}", server.uri(), MAX_EMBED_HOPS + 1);
+        assert!(frontier.latest_capture(&cut).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_redirect_hops_are_stored_as_their_own_captures() {
+        let server = MockServer::start().await;
+        Mock::given(path("/old"))
+            .respond_with(ResponseTemplate::new(301).insert_header("location", "/new"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/new"))
+            .respond_with(ResponseTemplate::new(200).set_body_raw("<p>moved</p>", "text/html"))
+            .mount(&server)
+            .await;
+
+        let frontier = test_frontier();
+        let storage = test_storage();
+        let crawler = test_crawler(&frontier, &MemoryDedup::new(), &storage);
+        let old = format!("{}/old", server.uri());
+        crawler.add_url(&old).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+
+        let hop = frontier.latest_capture(&old).await.unwrap().unwrap();
+        assert_eq!(hop.status_code, 301);
+        let record = stored_record(&storage, &hop).await;
+        assert!(record.contains("HTTP/1.1 301 Moved Permanently\r\n"));
+        assert!(record.contains("location: /new\r\n"));
+
+        let new = format!("{}/new", server.uri());
+        let target = frontier.latest_capture(&new).await.unwrap().unwrap();
+        assert_eq!(target.warc_file, hop.warc_file);
+        assert!(stored_record(&storage, &target)
+            .await
+            .contains("<p>moved</p>"));
+    }
+
+    #[tokio::test]
+    async fn test_recrawl_sends_validators_and_records_304_as_revisit() {
+        let server = MockServer::start().await;
+        Mock::given(path("/page"))
+            .and(header("if-none-match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"v1\"")
+                    .set_body_raw("<p>hello</p>", "text/html"),
+            )
+            .mount(&server)
+            .await;
+
+        let frontier = test_frontier();
+        let storage = test_storage();
+        let crawler = test_crawler(&frontier, &MemoryDedup::new(), &storage);
+        let url = format!("{}/page", server.uri());
+        crawler.add_url(&url).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+
+        // The first visit completes the URL, but its validators are kept
+        assert!(frontier.get(&url).is_none());
+        assert_eq!(
+            frontier.validators(&url).unwrap().etag.as_deref(),
+            Some("\"v1\"")
+        );
+
+        let previous = frontier.latest_capture(&url).await.unwrap().unwrap();
+        assert!(stored_record(&storage, &previous)
+            .await
+            .contains("<p>hello</p>"));
+
+        // Two 304s in a row: both revisit the first capture, not each other
+        crawler.add_url(&url).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+        // The second visit rescheduled the URL; make it due again
+        frontier.reschedule(&url, Utc::now(), 1.0).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+        let requests = server.received_requests().await.unwrap();
+        let requests: Vec<_> = requests
+            .iter()
+            .filter(|r| r.url.path() == "/page")
+            .collect();
+        assert!(requests[0].headers.get("if-none-match").is_none());
+        assert_eq!(requests[1].headers.get("if-none-match").unwrap(), "\"v1\"");
+        assert_eq!(requests[2].headers.get("if-none-match").unwrap(), "\"v1\"");
+
+        assert_eq!(
+            frontier.revisit_targets(&url),
+            vec![previous.id, previous.id]
+        );
+        let latest = frontier.latest_capture(&url).await.unwrap().unwrap();
+        assert_eq!(latest.id, previous.id);
+        assert!(frontier.validators(&url).is_some());
+
+        // Both revisits share the first capture's record; no WARC is written
+        // that no snapshot points into
+        let objects = storage.list("").await.unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[0].name, previous.warc_file);
+    }
+
+    #[tokio::test]
+    async fn test_redirect_target_validators_are_not_sent_to_the_origin() {
+        let server = MockServer::start().await;
+        Mock::given(path("/old"))
+            .respond_with(ResponseTemplate::new(301).insert_header("location", "/new"))
+            .mount(&server)
+            .await;
+        Mock::given(path("/new"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"new\"")
+                    .set_body_raw("<p>moved</p>", "text/html"),
+            )
+            .mount(&server)
+            .await;
+
+        let frontier = test_frontier();
+        let crawler = test_crawler(&frontier, &MemoryDedup::new(), &test_storage());
+        let old = format!("{}/old", server.uri());
+        crawler.add_url(&old).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+        assert!(frontier.validators(&old).is_none());
+
+        crawler.add_url(&old).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+        let requests = server.received_requests().await.unwrap();
+        assert!(requests
+            .iter()
+            .filter(|r| r.url.path() == "/old")
+            .all(|r| r.headers.get("if-none-match").is_none()));
+    }
+
+    #[tokio::test]
+    async fn test_skipped_near_duplicates_keep_no_validators_or_simhash() {
+        let words = (0..200).map(|n| format!("word{} ", n)).collect::<String>();
+        let server = MockServer::start().await;
+        Mock::given(path("/page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"v1\"")
+                    .set_body_raw(format!("<p>{}</p>", words), "text/html"),
+            )
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(path("/page"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("etag", "\"v2\"")
+                    .set_body_raw(format!("<p>{} word200</p>", words), "text/html"),
+            )
+            .mount(&server)
+            .await;
+
+        let frontier = test_frontier();
+        let dedup = MemoryDedup::new();
+        let crawler = Crawler::builder()
+            .frontier(Arc::new(frontier.clone()))
+            .dedup(Arc::new(dedup.clone()))
+            .storage(test_storage())
+            .claim_context(ClaimContext {
+                worker_id: "test".to_string(),
+                region: Region::new("us-east-1"),
+                steal_after: chrono::Duration::minutes(5),
+                lease_duration: std::time::Duration::from_secs(60),
+            })
+            .near_dup_config(NearDupConfig {
+                max_distance: 64,
+                skip_storage: true,
+            })
+            .build()
+            .unwrap();
+        let url = format!("{}/page", server.uri());
+        crawler.add_url(&url).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+        let first = frontier.latest_capture(&url).await.unwrap().unwrap();
+        let first_simhash = dedup.simhash(&url).unwrap();
+
+        crawler.add_url(&url).await.unwrap();
+        assert_eq!(crawler.run_once(10).await.unwrap(), 1);
+
+        // Nothing was stored, so nothing may answer for the skipped capture
+        assert_eq!(
+            frontier.latest_capture(&url).await.unwrap().unwrap().id,
+            first.id
+        );
+        assert!(frontier.validators(&url).is_none());
+        assert_eq!(dedup.simhash(&url), Some(first_simhash));
     }
 }
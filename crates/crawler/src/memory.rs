@@ -0,0 +1,855 @@
+use crate::dedup::{DedupBackend, SimilarCapture};
+use crate::fetcher::CacheValidators;
+use crate::frontier::{
+    url_host, ChangeObservation, ClaimContext, DomainChangeStats, FailureOutcome, FrontierBackend,
+    FrontierUrl, JobSettings, NewCapture, PreviousCapture,
+};
+use crate::priority::{PriorityInputs, PriorityScorer, WeightedScorer};
+use crate::rate_limit::{RateLimitBackend, RateLimits};
+use crate::region::{RegionRouter, SharedRegionRouter};
+use crate::retry::{ErrorClass, RetryPolicy};
+use anyhow::Result;
+use archive_intelligence::{CrawlPrediction, PredictionContext, SnapshotHistory};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// One `url_frontier` row
+#[derive(Debug, Clone)]
+pub struct MemoryFrontierEntry {
+    pub url: String,
+    pub domain: Option<String>,
+    pub priority: i32,
+    pub depth: i32,
+    pub preferred_region: String,
+    pub next_fetch_at: DateTime<Utc>,
+    pub fetch_attempts: i32,
+    pub leased_until: Option<DateTime<Utc>>,
+    pub leased_by_worker: Option<String>,
+    pub last_error: Option<String>,
+    pub last_error_class: Option<String>,
+    pub inlinks: HashSet<String>,
+    pub change_probability: Option<f32>,
+    pub priority_boost: i32,
+    pub freshness_target: Option<f32>,
+    pub lease_duration: Option<std::time::Duration>,
+    pub is_embed: bool,
+    pub embed_hops: i32,
+    seq: u64,
+}
+
+/// One `crawl_events` row
+#[derive(Debug, Clone)]
+pub struct MemoryCrawlEvent {
+    pub url: String,
+    pub status: String,
+    pub http_status: Option<i32>,
+    pub duration_ms: i32,
+}
+
+#[derive(Default)]
+struct FrontierState {
+    entries: HashMap<String, MemoryFrontierEntry>,
+    captures: HashMap<String, Vec<PreviousCapture>>,
+    /// The response each revisit snapshot points at, by revisit id
+    revisit_of: HashMap<Uuid, PreviousCapture>,
+    /// `url_validators` rows, which outlive completed frontier entries
+    validators: HashMap<String, CacheValidators>,
+    dead_letters: HashMap<String, MemoryFrontierEntry>,
+    events: Vec<MemoryCrawlEvent>,
+    observations: HashMap<String, Vec<SnapshotHistory>>,
+    domain_stats: HashMap<String, DomainChangeStats>,
+    predictions: Vec<CrawlPrediction>,
+    next_seq: u64,
+}
+
+/// [`FrontierBackend`] kept in process memory, for tests and single-process runs.
+/// Mirrors the Postgres semantics: leases, region preference, retries and dead letters.
+#[derive(Clone)]
+pub struct MemoryFrontier {
+    state: Arc<Mutex<FrontierState>>,
+    region_router: SharedRegionRouter,
+    scorer: Arc<dyn PriorityScorer>,
+}
+
+impl MemoryFrontier {
+    pub fn new() -> Self {
+        Self::with_router(RegionRouter::new())
+    }
+
+    pub fn with_router(region_router: impl Into<SharedRegionRouter>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(FrontierState::default())),
+            region_router: region_router.into(),
+            scorer: Arc::new(WeightedScorer::default()),
+        }
+    }
+
+    pub fn with_scorer(mut self, scorer: Arc<dyn PriorityScorer>) -> Self {
+        self.scorer = scorer;
+        self
+    }
+
+    pub fn get(&self, url: &str) -> Option<MemoryFrontierEntry> {
+        self.lock().entries.get(url).cloned()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn dead_letters(&self) -> Vec<MemoryFrontierEntry> {
+        self.lock().dead_letters.values().cloned().collect()
+    }
+
+    pub fn events(&self) -> Vec<MemoryCrawlEvent> {
+        self.lock().events.clone()
+    }
+
+    pub fn predictions(&self) -> Vec<CrawlPrediction> {
+        self.lock().predictions.clone()
+    }
+
+    /// Validators remembered for the next conditional fetch of `url`
+    pub fn validators(&self, url: &str) -> Option<CacheValidators> {
+        self.lock().validators.get(url).cloned()
+    }
+
+    /// Store a capture so later fetches can be recorded as revisits of it
+    /// Ids of the responses a URL's revisits point at, oldest revisit first
+    pub fn revisit_targets(&self, url: &str) -> Vec<Uuid> {
+        let state = self.lock();
+        let mut revisits = state
+            .captures
+            .get(url)
+            .into_iter()
+            .flatten()
+            .filter_map(|c| state.revisit_of.get(&c.id).map(|o| (c.timestamp, o.id)))
+            .collect::<Vec<_>>();
+        revisits.sort();
+        revisits.into_iter().map(|(_, id)| id).collect()
+    }
+
+    pub fn insert_capture(&self, url: &str, capture: PreviousCapture) {
+        self.lock()
+            .captures
+            .entry(url.to_string())
+            .or_default()
+            .push(capture);
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, FrontierState> {
+        // A panic while holding the lock leaves plain data behind; keep going
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn rescore(&self, state: &mut FrontierState, url: &str) {
+        let last_captured_at = state
+            .captures
+            .get(url)
+            .and_then(|c| c.iter().map(|c| c.timestamp).max());
+        if let Some(entry) = state.entries.get_mut(url) {
+            let due = entry.next_fetch_at.max(Utc::now());
+            entry.priority = self.scorer.score(&PriorityInputs {
+                depth: entry.depth,
+                inlinks: entry.inlinks.len() as i32,
+                change_probability: entry.change_probability,
+                since_last_capture: last_captured_at.map(|t| due - t),
+                boost: entry.priority_boost,
+                is_embed: entry.is_embed,
+            });
+        }
+    }
+
+    fn insert<'a>(
+        &self,
+        state: &'a mut FrontierState,
+        url: &str,
+        priority: i32,
+        depth: i32,
+    ) -> Option<&'a mut MemoryFrontierEntry> {
+        if state.entries.contains_key(url) {
+            return None;
+        }
+
+        let domain = url_host(url);
+        let preferred_region = domain
+            .as_deref()
+            .map(|d| self.region_router.route_domain(d).as_str().to_string())
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let seq = state.next_seq;
+        state.next_seq += 1;
+
+        let entry = MemoryFrontierEntry {
+            url: url.to_string(),
+            domain,
+            priority,
+            depth,
+            preferred_region,
+            next_fetch_at: Utc::now(),
+            fetch_attempts: 0,
+            leased_until: None,
+            leased_by_worker: None,
+            last_error: None,
+            last_error_class: None,
+            inlinks: HashSet::new(),
+            change_probability: None,
+            priority_boost: 0,
+            freshness_target: None,
+            lease_duration: None,
+            is_embed: false,
+            embed_hops: 0,
+            seq,
+        };
+        Some(state.entries.entry(url.to_string()).or_insert(entry))
+    }
+}
+
+impl Default for MemoryFrontier {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl FrontierBackend for MemoryFrontier {
+    async fn add_url(&self, url: &str, priority: i32, depth: i32) -> Result<()> {
+        let mut state = self.lock();
+        self.insert(&mut state, url, priority, depth);
+        Ok(())
+    }
+
+    async fn add_seed(&self, url: &str, job: &JobSettings) -> Result<()> {
+        let priority = self.scorer.score(&PriorityInputs {
+            boost: job.boost,
+            ..Default::default()
+        });
+        let mut state = self.lock();
+        self.insert(&mut state, url, priority, 0);
+        if let Some(entry) = state.entries.get_mut(url) {
+            entry.priority_boost = job.boost;
+            entry.freshness_target = job.freshness_target;
+            entry.lease_duration = job.lease_duration;
+        }
+        Ok(())
+    }
+
+    async fn add_discovered(
+        &self,
+        url: &str,
+        source_url: &str,
+        depth: i32,
+        embed_hops: i32,
+        job: &JobSettings,
+    ) -> Result<()> {
+        let priority = self.scorer.score(&PriorityInputs {
+            depth,
+            inlinks: 1,
+            boost: job.boost,
+            is_embed: embed_hops > 0,
+            ..Default::default()
+        });
+
+        let mut state = self.lock();
+        if let Some(entry) = self.insert(&mut state, url, priority, depth) {
+            entry.inlinks.insert(source_url.to_string());
+            entry.priority_boost = job.boost;
+            entry.freshness_target = job.freshness_target;
+            entry.lease_duration = job.lease_duration;
+            entry.is_embed = embed_hops > 0;
+            entry.embed_hops = embed_hops;
+            return Ok(());
+        }
+
+        let new_inlink = match state.entries.get_mut(url) {
+            Some(entry) => {
+                let new_inlink = entry.inlinks.insert(source_url.to_string());
+                if new_inlink {
+                    entry.depth = entry.depth.min(depth);
+                    entry.priority_boost = entry.priority_boost.max(job.boost);
+                    entry.is_embed |= embed_hops > 0;
+                    entry.embed_hops = entry.embed_hops.min(embed_hops);
+                }
+                new_inlink
+            }
+            None => false,
+        };
+        if new_inlink {
+            self.rescore(&mut state, url);
+        }
+        Ok(())
+    }
+
+    async fn claim_urls(&self, limit: i32, ctx: &ClaimContext) -> Result<Vec<FrontierUrl>> {
+        let now = Utc::now();
+        let steal_before = now - ctx.steal_after;
+        let region = ctx.region.as_str();
+        let mut state = self.lock();
+
+        let mut candidates = state
+            .entries
+            .values()
+            .filter(|e| !matches!(e.leased_until, Some(t) if t >= now))
+            .filter(|e| e.next_fetch_at <= now)
+            .filter(|e| e.preferred_region == region || e.next_fetch_at <= steal_before)
+            .map(|e| {
+                (
+                    e.preferred_region == region,
+                    e.priority,
+                    e.seq,
+                    e.url.clone(),
+                )
+            })
+            .collect::<Vec<_>>();
+        candidates.sort_by(|a, b| b.0.cmp(&a.0).then(b.1.cmp(&a.1)).then(a.2.cmp(&b.2)));
+
+        let mut claimed = Vec::new();
+        for (_, _, _, url) in candidates.into_iter().take(limit.max(0) as usize) {
+            let validators = state.validators.get(&url).cloned().unwrap_or_default();
+            if let Some(entry) = state.entries.get_mut(&url) {
+                let lease = entry.lease_duration.unwrap_or(ctx.lease_duration);
+                entry.leased_until = Some(now + chrono::Duration::from_std(lease)?);
+                entry.leased_by_worker = Some(ctx.worker_id.clone());
+                claimed.push(FrontierUrl {
+                    url: entry.url.clone(),
+                    domain: entry.domain.clone(),
+                    depth: entry.depth,
+                    embed_hops: entry.embed_hops,
+                    etag: validators.etag,
+                    last_modified: validators.last_modified,
+                    priority_boost: entry.priority_boost,
+                    freshness_target: entry.freshness_target,
+                    lease_duration_secs: entry.lease_duration.map(|d| d.as_secs() as i32),
+                });
+            }
+        }
+        Ok(claimed)
+    }
+
+    async fn renew_lease(
+        &self,
+        url: &str,
+        worker_id: &str,
+        lease_duration: std::time::Duration,
+    ) -> Result<bool> {
+        let mut state = self.lock();
+        match state.entries.get_mut(url) {
+            Some(entry) if entry.leased_by_worker.as_deref() == Some(worker_id) => {
+                let lease = entry.lease_duration.unwrap_or(lease_duration);
+                entry.leased_until = Some(Utc::now() + chrono::Duration::from_std(lease)?);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    async fn complete(&self, url: &str) -> Result<()> {
+        self.lock().entries.remove(url);
+        Ok(())
+    }
+
+    async fn defer(&self, url: &str, worker_id: &str, until: DateTime<Utc>) -> Result<()> {
+        let mut state = self.lock();
+        if let Some(entry) = state.entries.get_mut(url) {
+            if entry.leased_by_worker.as_deref() == Some(worker_id) {
+                entry.leased_until = None;
+                entry.leased_by_worker = None;
+                entry.next_fetch_at = until;
+            }
+        }
+        Ok(())
+    }
+
+    async fn release_lease(&self, url: &str, worker_id: &str) -> Result<()> {
+        let mut state = self.lock();
+        if let Some(entry) = state.entries.get_mut(url) {
+            if entry.leased_by_worker.as_deref() == Some(worker_id) {
+                entry.leased_until = None;
+                entry.leased_by_worker = None;
+            }
+        }
+        Ok(())
+    }
+
+    async fn reschedule(
+        &self,
+        url: &str,
+        next_fetch_at: DateTime<Utc>,
+        change_probability: f32,
+    ) -> Result<()> {
+        let mut state = self.lock();
+        if let Some(entry) = state.entries.get_mut(url) {
+            entry.next_fetch_at = next_fetch_at;
+            entry.change_probability = Some(change_probability);
+            entry.leased_until = None;
+            entry.leased_by_worker = None;
+            entry.fetch_attempts = 0;
+        }
+        self.rescore(&mut state, url);
+        Ok(())
+    }
+
+    async fn set_validators(&self, url: &str, validators: &CacheValidators) -> Result<()> {
+        let mut state = self.lock();
+        if validators.is_empty() {
+            state.validators.remove(url);
+        } else {
+            state.validators.insert(url.to_string(), validators.clone());
+        }
+        Ok(())
+    }
+
+    async fn register_failure(
+        &self,
+        url: &str,
+        class: ErrorClass,
+        error: &str,
+        policy: &RetryPolicy,
+    ) -> Result<FailureOutcome> {
+        let mut state = self.lock();
+        let Some(entry) = state.entries.get_mut(url) else {
+            return Ok(FailureOutcome::NotQueued);
+        };
+
+        entry.fetch_attempts += 1;
+        entry.last_error = Some(error.to_string());
+        entry.last_error_class = Some(class.as_str().to_string());
+        entry.leased_until = None;
+        entry.leased_by_worker = None;
+
+        match policy.next_backoff(class, entry.fetch_attempts) {
+            Some(backoff) => {
+                let next_fetch_at = Utc::now() + backoff;
+                entry.next_fetch_at = next_fetch_at;
+                Ok(FailureOutcome::Retry { next_fetch_at })
+            }
+            None => {
+                if let Some(entry) = state.entries.remove(url) {
+                    state.dead_letters.insert(url.to_string(), entry);
+                }
+                Ok(FailureOutcome::DeadLettered)
+            }
+        }
+    }
+
+    async fn track_event(
+        &self,
+        url: &str,
+        status: &str,
+        http_status: Option<i32>,
+        duration_ms: i32,
+    ) -> Result<()> {
+        self.lock().events.push(MemoryCrawlEvent {
+            url: url.to_string(),
+            status: status.to_string(),
+            http_status,
+            duration_ms,
+        });
+        Ok(())
+    }
+
+    async fn record_observation(&self, observation: &ChangeObservation<'_>) -> Result<()> {
+        let ChangeObservation {
+            url,
+            observed_at,
+            payload_digest,
+            text_hash,
+            ..
+        } = *observation;
+        let domain = url_host(url).unwrap_or_default();
+        let mut state = self.lock();
+        let observations = state.observations.entry(url.to_string()).or_default();
+        let previous = observations.last().cloned();
+
+        let content_hash = text_hash
+            .map(|h| h.to_string())
+            .or_else(|| previous.as_ref().map(|p| p.content_hash.clone()))
+            .unwrap_or_else(|| payload_digest.to_string());
+        observations.push(SnapshotHistory {
+            timestamp: observed_at,
+            content_hash: content_hash.clone(),
+            similarity: observation.similarity,
+        });
+
+        if let Some(previous) = previous {
+            let stats = state
+                .domain_stats
+                .entry(domain)
+                .or_insert(DomainChangeStats {
+                    changes: 0,
+                    intervals: 0,
+                    exposure_secs: 0.0,
+                });
+            stats.intervals += 1;
+            stats.exposure_secs += (observed_at - previous.timestamp).num_seconds().max(0) as f64;
+            if previous.content_hash != content_hash && !observation.near_duplicate {
+                stats.changes += 1;
+            }
+        }
+        Ok(())
+    }
+
+    async fn get_snapshot_history(&self, url: &str) -> Result<Vec<SnapshotHistory>> {
+        let state = self.lock();
+        if let Some(observations) = state.observations.get(url) {
+            return Ok(observations.clone());
+        }
+
+        let mut history = state
+            .captures
+            .get(url)
+            .map(|captures| {
+                captures
+                    .iter()
+                    .map(|c| SnapshotHistory {
+                        timestamp: c.timestamp,
+                        content_hash: c.sha256.clone(),
+                        similarity: None,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+        history.sort_by_key(|h| h.timestamp);
+        Ok(history)
+    }
+
+    async fn domain_change_stats(&self, domain: &str) -> Result<Option<DomainChangeStats>> {
+        Ok(self.lock().domain_stats.get(domain).cloned())
+    }
+
+    async fn record_prediction(
+        &self,
+        prediction: &CrawlPrediction,
+        _ctx: &PredictionContext,
+        _observations: usize,
+    ) -> Result<()> {
+        self.lock().predictions.push(prediction.clone());
+        Ok(())
+    }
+
+    async fn latest_capture(&self, url: &str) -> Result<Option<PreviousCapture>> {
+        let state = self.lock();
+        let latest = state
+            .captures
+            .get(url)
+            .and_then(|c| c.iter().max_by_key(|c| c.timestamp));
+        Ok(latest.map(|c| state.revisit_of.get(&c.id).unwrap_or(c).clone()))
+    }
+
+    async fn original_capture(&self, sha256: &str) -> Result<Option<PreviousCapture>> {
+        // Revisits share their original's digest and are never earlier than it
+        Ok(self
+            .lock()
+            .captures
+            .values()
+            .flatten()
+            .filter(|c| c.sha256 == sha256)
+            .min_by_key(|c| c.timestamp)
+            .cloned())
+    }
+
+    async fn record_capture(&self, capture: &NewCapture<'_>) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+        self.insert_capture(
+            capture.url,
+            PreviousCapture {
+                id,
+                url: capture.url.to_string(),
+                timestamp: capture.timestamp,
+                warc_file: capture.warc_file.to_string(),
+                offset: capture.offset,
+                length: capture.length,
+                sha256: capture.sha256.to_string(),
+                status_code: capture.status_code,
+                content_type: capture.content_type.to_string(),
+                payload_hash: Some(capture.sha256.to_string()),
+            },
+        );
+        Ok(id)
+    }
+
+    async fn record_revisit(
+        &self,
+        url: &str,
+        timestamp: DateTime<Utc>,
+        _profile: &str,
+        previous: &PreviousCapture,
+    ) -> Result<Uuid> {
+        let id = Uuid::new_v4();
+        self.insert_capture(
+            url,
+            PreviousCapture {
+                id,
+                url: url.to_string(),
+                timestamp,
+                ..previous.clone()
+            },
+        );
+        let mut state = self.lock();
+        let original = state
+            .revisit_of
+            .get(&previous.id)
+            .unwrap_or(previous)
+            .clone();
+        state.revisit_of.insert(id, original);
+        Ok(id)
+    }
+}
+
+/// WARC path, offset and size of a stored payload
+type PayloadLocation = (String, u64, u64);
+
+/// Domain, region and window start of a rate limit counter
+type WindowKey = (String, String, DateTime<Utc>);
+
+/// [`DedupBackend`] over an in-process set of payload digests
+#[derive(Clone, Default)]
+pub struct MemoryDedup {
+    payloads: Arc<Mutex<HashMap<String, PayloadLocation>>>,
+    simhashes: Arc<Mutex<HashMap<String, u64>>>,
+}
+
+impl MemoryDedup {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.payloads
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The SimHash later captures of `url` are compared with
+    pub fn simhash(&self, url: &str) -> Option<u64> {
+        self.simhashes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(url)
+            .copied()
+    }
+}
+
+#[async_trait]
+impl DedupBackend for MemoryDedup {
+    async fn is_duplicate(&self, hash: &str) -> Result<bool> {
+        Ok(self
+            .payloads
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains_key(hash))
+    }
+
+    async fn insert_payload(
+        &self,
+        hash: &str,
+        warc_path: &str,
+        offset: u64,
+        size: u64,
+    ) -> Result<()> {
+        self.payloads
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .entry(hash.to_string())
+            .or_insert_with(|| (warc_path.to_string(), offset, size));
+        Ok(())
+    }
+
+    async fn compare_simhash(&self, url: &str, simhash: u64) -> Result<Option<SimilarCapture>> {
+        let previous = self
+            .simhashes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(url)
+            .copied();
+        Ok(previous.map(|h| SimilarCapture::between(h, simhash)))
+    }
+
+    async fn set_simhash(&self, url: &str, simhash: u64) -> Result<()> {
+        self.simhashes
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(url.to_string(), simhash);
+        Ok(())
+    }
+}
+
+/// [`RateLimitBackend`] counting requests per window in process memory
+#[derive(Clone, Default)]
+pub struct MemoryRateLimiter {
+    limits: RateLimits,
+    counts: Arc<Mutex<HashMap<WindowKey, i32>>>,
+}
+
+impl MemoryRateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits,
+            counts: Arc::default(),
+        }
+    }
+}
+
+#[async_trait]
+impl RateLimitBackend for MemoryRateLimiter {
+    async fn check_and_increment(&self, domain: &str, region: &str) -> Result<bool> {
+        let window_start = self.limits.window_start(Utc::now());
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        counts.retain(|(_, _, start), _| *start == window_start);
+
+        let regional = counts
+            .get(&(domain.to_string(), region.to_string(), window_start))
+            .copied()
+            .unwrap_or(0);
+        if regional >= self.limits.regional_per_domain {
+            return Ok(false);
+        }
+        let global: i32 = counts
+            .iter()
+            .filter(|((d, _, _), _)| d == domain)
+            .map(|(_, c)| *c)
+            .sum();
+        if global >= self.limits.global_per_domain {
+            return Ok(false);
+        }
+
+        *counts
+            .entry((domain.to_string(), region.to_string(), window_start))
+            .or_insert(0) += 1;
+        Ok(true)
+    }
+
+    fn retry_at(&self, now: DateTime<Utc>) -> DateTime<Utc> {
+        self.limits.next_window(now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::region::Region;
+
+    fn ctx(region: &str) -> ClaimContext {
+        ClaimContext {
+            worker_id: "test-worker".to_string(),
+            region: Region::new(region),
+            steal_after: chrono::Duration::minutes(5),
+            lease_duration: std::time::Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_claim_leases_and_orders_by_priority() {
+        let router = RegionRouter::with_regions(vec![Region::new("us-east-1")]);
+        let frontier = MemoryFrontier::with_router(router);
+        frontier
+            .add_url("https://a.example/low", 1, 0)
+            .await
+            .unwrap();
+        frontier
+            .add_url("https://a.example/high", 50, 0)
+            .await
+            .unwrap();
+
+        let claimed = frontier.claim_urls(10, &ctx("us-east-1")).await.unwrap();
+        let urls = claimed.iter().map(|u| u.url.as_str()).collect::<Vec<_>>();
+        assert_eq!(
+            urls,
+            vec!["https://a.example/high", "https://a.example/low"]
+        );
+
+        // Leased URLs are not handed out twice
+        assert!(frontier
+            .claim_urls(10, &ctx("us-east-1"))
+            .await
+            .unwrap()
+            .is_empty());
+        assert!(frontier
+            .renew_lease(
+                "https://a.example/low",
+                "test-worker",
+                ctx("x").lease_duration
+            )
+            .await
+            .unwrap());
+        assert!(!frontier
+            .renew_lease("https://a.example/low", "other", ctx("x").lease_duration)
+            .await
+            .unwrap());
+
+        // Only the holder can release a lease, which makes the URL claimable
+        frontier
+            .release_lease("https://a.example/low", "other")
+            .await
+            .unwrap();
+        assert!(frontier
+            .claim_urls(10, &ctx("us-east-1"))
+            .await
+            .unwrap()
+            .is_empty());
+        frontier
+            .release_lease("https://a.example/low", "test-worker")
+            .await
+            .unwrap();
+        let reclaimed = frontier.claim_urls(10, &ctx("us-east-1")).await.unwrap();
+        assert_eq!(reclaimed.len(), 1);
+        assert_eq!(reclaimed[0].url, "https://a.example/low");
+    }
+
+    #[tokio::test]
+    async fn test_job_lease_duration_overrides_the_worker_default() {
+        let frontier =
+            MemoryFrontier::with_router(RegionRouter::with_regions(vec![Region::new("us-east-1")]));
+        let job = JobSettings {
+            lease_duration: Some(std::time::Duration::from_secs(900)),
+            ..JobSettings::default()
+        };
+        frontier
+            .add_seed("https://slow.example/", &job)
+            .await
+            .unwrap();
+        let claimed = frontier.claim_urls(10, &ctx("us-east-1")).await.unwrap();
+        assert_eq!(claimed[0].job(), job);
+
+        frontier
+            .add_discovered("https://slow.example/page", &claimed[0].url, 1, 0, &job)
+            .await
+            .unwrap();
+        let entry = frontier.get("https://slow.example/page").unwrap();
+        assert_eq!(entry.lease_duration, job.lease_duration);
+        let seed = frontier.get("https://slow.example/").unwrap();
+        assert!(seed.leased_until.unwrap() > Utc::now() + chrono::Duration::minutes(10));
+    }
+
+    #[tokio::test]
+    async fn test_failures_dead_letter_after_policy_limit() {
+        let frontier = MemoryFrontier::new();
+        let url = "https://gone.example/";
+        frontier.add_url(url, 0, 0).await.unwrap();
+        let policy = RetryPolicy::default();
+
+        let first = frontier
+            .register_failure(url, ErrorClass::ClientError, "HTTP 404", &policy)
+            .await
+            .unwrap();
+        assert!(matches!(first, FailureOutcome::Retry { .. }));
+        let second = frontier
+            .register_failure(url, ErrorClass::ClientError, "HTTP 404", &policy)
+            .await
+            .unwrap();
+        assert_eq!(second, FailureOutcome::DeadLettered);
+        assert!(frontier.is_empty());
+        assert_eq!(frontier.dead_letters().len(), 1);
+    }
+}
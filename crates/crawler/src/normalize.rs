@@ -0,0 +1,98 @@
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::sync::OnceLock;
+
+/// Dates, clock times, unix timestamps and relative times ("5 minutes ago")
+fn volatile_patterns() -> &'static [Regex] {
+    static PATTERNS: OnceLock<Vec<Regex>> = OnceLock::new();
+    PATTERNS.get_or_init(|| {
+        [
+            r"\b\d{1,4}[-/.]\d{1,2}[-/.]\d{1,4}(?:[t ]\d{1,2}:\d{2}(?::\d{2})?(?:\.\d+)?(?:z|[+-]\d{2}:?\d{2})?)?\b",
+            r"\b\d{1,2}:\d{2}(?::\d{2})?\s*(?:am|pm)?\b",
+            r"\b1\d{9}(?:\d{3})?\b",
+            r"\b\d+\s+(?:second|minute|hour|day|week|month|year)s?\s+ago\b",
+            r"\b(?:jan|feb|mar|apr|may|jun|jul|aug|sep|sept|oct|nov|dec)[a-z]*\.?\s+\d{1,2},?\s+\d{4}\b",
+            r"\b\d{1,2}\s+(?:jan|feb|mar|apr|may|jun|jul|aug|sep|sept|oct|nov|dec)[a-z]*\.?\s+\d{4}\b",
+        ]
+        .iter()
+        .map(|p| Regex::new(p).expect("valid volatile pattern"))
+        .collect()
+    })
+}
+
+/// Long letter/digit mixes such as CSRF tokens, nonces, session and cache-busting ids
+fn is_opaque_token(token: &str) -> bool {
+    let token = token.trim_matches(|c: char| !c.is_ascii_alphanumeric());
+    token.len() >= 20
+        && token
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '+' | '/' | '='))
+        && token.chars().any(|c| c.is_ascii_digit())
+        && token.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+/// Visible text with volatile fragments masked, or `None` for non-text payloads
+pub fn normalized_text(content_type: &str, body: &[u8]) -> Option<String> {
+    let mime = content_type.to_ascii_lowercase();
+    let raw = String::from_utf8_lossy(body);
+    let text = if mime.contains("html") {
+        let extracted = archive_common::extractor::extract_text(&raw);
+        format!("{} {}", extracted.title, extracted.text_content)
+    } else if mime.starts_with("text/") || mime.contains("json") || mime.contains("xml") {
+        raw.into_owned()
+    } else {
+        return None;
+    };
+
+    let mut text = text.to_lowercase();
+    for pattern in volatile_patterns() {
+        text = pattern.replace_all(&text, " ").into_owned();
+    }
+
+    let words = text
+        .split_whitespace()
+        .filter(|w| !is_opaque_token(w))
+        .collect::<Vec<_>>();
+    Some(words.join(" "))
+}
+
+/// Hash used for change detection: SHA-256 of the normalized text, falling back
+/// to the raw payload for binary content
+pub fn text_hash(content_type: &str, body: &[u8]) -> String {
+    hash_normalized(normalized_text(content_type, body).as_deref(), body)
+}
+
+/// [`text_hash`] for a caller that already has the normalized text
+pub fn hash_normalized(text: Option<&str>, body: &[u8]) -> String {
+    match text {
+        Some(text) => format!("{:x}", Sha256::digest(text.as_bytes())),
+        None => format!("{:x}", Sha256::digest(body)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trivial_changes_hash_the_same() {
+        let a = br#"<html><body><p>Breaking news today</p>
+            <span>Updated 2026-01-10 14:03:22</span><input type="hidden" value="x">
+            <p>token: a8f3e9c2b1d04f5e6a7b8c9d0e1f2a3b</p><p>Rendered 3 minutes ago</p></body></html>"#;
+        let b = br#"<html><body><p>Breaking   news today</p>
+            <span>Updated 2026-01-11 09:41:07</span>
+            <p>token: 9c1d2e3f4a5b6c7d8e9f0a1b2c3d4e5f</p><p>Rendered 12 minutes ago</p></body></html>"#;
+        assert_eq!(text_hash("text/html", a), text_hash("text/html", b));
+    }
+
+    #[test]
+    fn test_real_changes_hash_differently() {
+        let a = b"<html><body><p>Price: 10 apples</p></body></html>";
+        let b = b"<html><body><p>Price: 12 apples</p></body></html>";
+        assert_ne!(text_hash("text/html", a), text_hash("text/html", b));
+        assert_ne!(
+            text_hash("image/png", b"\x89PNG1"),
+            text_hash("image/png", b"\x89PNG2")
+        );
+    }
+}
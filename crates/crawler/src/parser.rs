@@ -1,20 +1,592 @@
+use regex::Regex;
 use scraper::{Html, Selector};
+use std::collections::HashSet;
+use std::sync::OnceLock;
 use url::Url;
 
-pub fn extract_links(base_url: &str, html_content: &str) -> Vec<String> {
+/// How a discovered URL relates to the page it was found on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LinkKind {
+    /// A navigational link to another page (consumes crawl depth)
+    Outlink,
+    /// A page requisite needed to replay the page (image, script, stylesheet, font, frame)
+    Embed,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ExtractedLink {
+    pub url: String,
+    pub kind: LinkKind,
+}
+
+impl ExtractedLink {
+    fn outlink(url: String) -> Self {
+        Self {
+            url,
+            kind: LinkKind::Outlink,
+        }
+    }
+
+    fn embed(url: String) -> Self {
+        Self {
+            url,
+            kind: LinkKind::Embed,
+        }
+    }
+}
+
+/// Element/attribute pairs that reference a single URL
+const ATTR_SOURCES: &[(&str, &str, LinkKind)] = &[
+    ("a[href]", "href", LinkKind::Outlink),
+    ("area[href]", "href", LinkKind::Outlink),
+    ("img[src]", "src", LinkKind::Embed),
+    ("script[src]", "src", LinkKind::Embed),
+    ("iframe[src]", "src", LinkKind::Embed),
+    ("frame[src]", "src", LinkKind::Embed),
+    ("embed[src]", "src", LinkKind::Embed),
+    ("source[src]", "src", LinkKind::Embed),
+    ("audio[src]", "src", LinkKind::Embed),
+    ("video[src]", "src", LinkKind::Embed),
+    ("video[poster]", "poster", LinkKind::Embed),
+    ("track[src]", "src", LinkKind::Embed),
+    ("input[type=image][src]", "src", LinkKind::Embed),
+    ("object[data]", "data", LinkKind::Embed),
+    ("body[background]", "background", LinkKind::Embed),
+];
+
+/// `<link rel=...>` values that point at resources needed to render the page
+const EMBED_RELS: &[&str] = &[
+    "stylesheet",
+    "icon",
+    "shortcut",
+    "apple-touch-icon",
+    "preload",
+    "prefetch",
+    "modulepreload",
+    "manifest",
+];
+
+/// `<link rel=...>` values that point at other documents worth crawling
+const OUTLINK_RELS: &[&str] = &["alternate", "next", "prev", "previous"];
+
+/// Extract every URL referenced by an HTML document, classified as outlink or embed
+pub fn extract_links(base_url: &str, html_content: &str) -> Vec<ExtractedLink> {
     let document = Html::parse_document(html_content);
-    let selector = Selector::parse("a[href]").unwrap();
+    let mut base = Url::parse(base_url).ok();
+
+    // Honour <base href> for relative resolution
+    let base_selector = Selector::parse("base[href]").unwrap();
+    if let Some(href) = document
+        .select(&base_selector)
+        .next()
+        .and_then(|el| el.value().attr("href"))
+    {
+        if let Some(resolved) = resolve(base.as_ref(), href) {
+            base = Url::parse(&resolved).ok();
+        }
+    }
+
+    let mut links = LinkSet::default();
+
+    for (css, attr, kind) in ATTR_SOURCES {
+        let selector = Selector::parse(css).unwrap();
+        for element in document.select(&selector) {
+            if let Some(url) = element
+                .value()
+                .attr(attr)
+                .and_then(|v| resolve(base.as_ref(), v))
+            {
+                links.push(url, *kind);
+            }
+        }
+    }
+
+    // srcset on <img> and <source>
+    let srcset_selector = Selector::parse("img[srcset], source[srcset]").unwrap();
+    for element in document.select(&srcset_selector) {
+        if let Some(srcset) = element.value().attr("srcset") {
+            for candidate in parse_srcset(srcset) {
+                if let Some(url) = resolve(base.as_ref(), candidate) {
+                    links.push(url, LinkKind::Embed);
+                }
+            }
+        }
+    }
+
+    // <link rel=... href=...>
+    let link_selector = Selector::parse("link[href]").unwrap();
+    for element in document.select(&link_selector) {
+        let rel = element
+            .value()
+            .attr("rel")
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let kind = if rel
+            .split_ascii_whitespace()
+            .any(|r| EMBED_RELS.contains(&r))
+        {
+            LinkKind::Embed
+        } else if rel
+            .split_ascii_whitespace()
+            .any(|r| OUTLINK_RELS.contains(&r))
+        {
+            LinkKind::Outlink
+        } else {
+            continue;
+        };
+        if let Some(url) = element
+            .value()
+            .attr("href")
+            .and_then(|v| resolve(base.as_ref(), v))
+        {
+            links.push(url, kind);
+        }
+    }
+
+    // <meta http-equiv="refresh" content="0; url=...">
+    let meta_selector = Selector::parse("meta[http-equiv][content]").unwrap();
+    for element in document.select(&meta_selector) {
+        let is_refresh = element
+            .value()
+            .attr("http-equiv")
+            .map(|v| v.eq_ignore_ascii_case("refresh"))
+            .unwrap_or(false);
+        if !is_refresh {
+            continue;
+        }
+        if let Some(url) = element
+            .value()
+            .attr("content")
+            .and_then(parse_meta_refresh)
+            .and_then(|v| resolve(base.as_ref(), v))
+        {
+            links.push(url, LinkKind::Outlink);
+        }
+    }
+
+    // <object><param name="movie" value="..."></object>
+    let param_selector = Selector::parse("object param[name][value]").unwrap();
+    for element in document.select(&param_selector) {
+        let name = element.value().attr("name").unwrap_or("");
+        if name.eq_ignore_ascii_case("movie") || name.eq_ignore_ascii_case("src") {
+            if let Some(url) = element
+                .value()
+                .attr("value")
+                .and_then(|v| resolve(base.as_ref(), v))
+            {
+                links.push(url, LinkKind::Embed);
+            }
+        }
+    }
+
+    // Inline <style> blocks and style="" attributes
+    let style_selector = Selector::parse("style").unwrap();
+    for element in document.select(&style_selector) {
+        let css: String = element.text().collect();
+        for url in css_urls(&css) {
+            if let Some(url) = resolve(base.as_ref(), url) {
+                links.push(url, LinkKind::Embed);
+            }
+        }
+    }
+    let inline_style_selector = Selector::parse("[style]").unwrap();
+    for element in document.select(&inline_style_selector) {
+        if let Some(css) = element.value().attr("style") {
+            for url in css_urls(css) {
+                if let Some(url) = resolve(base.as_ref(), url) {
+                    links.push(url, LinkKind::Embed);
+                }
+            }
+        }
+    }
+
+    links.into_vec()
+}
+
+/// Extract `url(...)` and `@import` references from a stylesheet
+pub fn extract_css_links(base_url: &str, css: &str) -> Vec<ExtractedLink> {
     let base = Url::parse(base_url).ok();
+    let mut links = LinkSet::default();
+    for url in css_urls(css) {
+        if let Some(url) = resolve(base.as_ref(), url) {
+            links.push(url, LinkKind::Embed);
+        }
+    }
+    links.into_vec()
+}
 
-    document
-        .select(&selector)
-        .filter_map(|element| element.value().attr("href"))
-        .filter_map(|href| {
-            if let Some(ref base_url) = base {
-                base_url.join(href).ok().map(|u| u.to_string())
-            } else {
-                Some(href.to_string())
+/// Extract page URLs from a `sitemap.xml` `<urlset>` or a `<sitemapindex>`
+pub fn extract_sitemap_links(xml: &str) -> Vec<ExtractedLink> {
+    let doc = match roxmltree::Document::parse(xml) {
+        Ok(d) => d,
+        Err(_) => return vec![],
+    };
+
+    let mut links = LinkSet::default();
+    for node in doc.descendants().filter(|n| n.has_tag_name("loc")) {
+        if let Some(text) = node.text() {
+            let text = text.trim();
+            if Url::parse(text).is_ok() {
+                links.push(text.to_string(), LinkKind::Outlink);
+            }
+        }
+    }
+    links.into_vec()
+}
+
+/// Extract item URLs from an RSS 2.0 or Atom feed
+pub fn extract_feed_links(base_url: &str, xml: &str) -> Vec<ExtractedLink> {
+    let doc = match roxmltree::Document::parse(xml) {
+        Ok(d) => d,
+        Err(_) => return vec![],
+    };
+    let base = Url::parse(base_url).ok();
+
+    let mut links = LinkSet::default();
+    for node in doc.descendants() {
+        match node.tag_name().name() {
+            // RSS: <link>https://...</link>, Atom: <link href="..." rel="alternate"/>
+            "link" => {
+                let target = node.attribute("href").or_else(|| node.text());
+                let rel = node.attribute("rel").unwrap_or("alternate");
+                if rel == "self" {
+                    continue;
+                }
+                if let Some(url) = target.and_then(|t| resolve(base.as_ref(), t.trim())) {
+                    links.push(url, LinkKind::Outlink);
+                }
             }
-        })
+            // Podcast and media enclosures are resources of the item
+            "enclosure" | "content" => {
+                if let Some(url) = node
+                    .attribute("url")
+                    .or_else(|| node.attribute("src"))
+                    .and_then(|t| resolve(base.as_ref(), t))
+                {
+                    links.push(url, LinkKind::Embed);
+                }
+            }
+            _ => {}
+        }
+    }
+    links.into_vec()
+}
+
+/// Dispatch extraction based on the fetched content type and body
+pub fn extract_from_document(
+    base_url: &str,
+    content_type: &str,
+    body: &[u8],
+) -> Vec<ExtractedLink> {
+    let content_type = content_type.to_ascii_lowercase();
+    let text = String::from_utf8_lossy(body);
+
+    if content_type.contains("html") {
+        extract_links(base_url, &text)
+    } else if content_type.contains("css") {
+        extract_css_links(base_url, &text)
+    } else if content_type.contains("xml") || content_type.contains("rss") {
+        let head: String = text.chars().take(1024).collect();
+        if head.contains("<urlset") || head.contains("<sitemapindex") {
+            extract_sitemap_links(&text)
+        } else if head.contains("<rss") || head.contains("<feed") || head.contains("<rdf") {
+            extract_feed_links(base_url, &text)
+        } else {
+            vec![]
+        }
+    } else {
+        vec![]
+    }
+}
+
+/// Preserves discovery order while dropping duplicate URLs. An embed wins
+/// over an outlink so that page requisites are never depth-limited.
+#[derive(Default)]
+struct LinkSet {
+    seen: HashSet<String>,
+    links: Vec<ExtractedLink>,
+}
+
+impl LinkSet {
+    fn push(&mut self, url: String, kind: LinkKind) {
+        if self.seen.insert(url.clone()) {
+            self.links.push(match kind {
+                LinkKind::Outlink => ExtractedLink::outlink(url),
+                LinkKind::Embed => ExtractedLink::embed(url),
+            });
+        } else if kind == LinkKind::Embed {
+            if let Some(existing) = self.links.iter_mut().find(|l| l.url == url) {
+                existing.kind = LinkKind::Embed;
+            }
+        }
+    }
+
+    fn into_vec(self) -> Vec<ExtractedLink> {
+        self.links
+    }
+}
+
+fn resolve(base: Option<&Url>, href: &str) -> Option<String> {
+    let href = href.trim();
+    if href.is_empty()
+        || href.starts_with('#')
+        || href.starts_with("data:")
+        || href.starts_with("javascript:")
+        || href.starts_with("mailto:")
+        || href.starts_with("tel:")
+        || href.starts_with("about:")
+    {
+        return None;
+    }
+
+    let mut url = match base {
+        Some(base) => base.join(href).ok()?,
+        None => Url::parse(href).ok()?,
+    };
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+    url.set_fragment(None);
+    Some(url.to_string())
+}
+
+/// Split a `srcset` attribute into its candidate URLs. As in the HTML spec,
+/// a URL runs to the next whitespace, so commas inside it (`data:` URIs, CDN
+/// transforms) do not split it; a comma ends a candidate only after its URL
+fn parse_srcset(srcset: &str) -> Vec<&str> {
+    let mut candidates = Vec::new();
+    let mut rest = srcset;
+    loop {
+        rest = rest.trim_start_matches(|c: char| c.is_ascii_whitespace() || c == ',');
+        if rest.is_empty() {
+            return candidates;
+        }
+        let end = rest
+            .find(|c: char| c.is_ascii_whitespace())
+            .unwrap_or(rest.len());
+        let (url, after) = rest.split_at(end);
+        let trimmed = url.trim_end_matches(',');
+        candidates.push(trimmed);
+        if trimmed.len() < url.len() {
+            // Trailing commas end a candidate without descriptors
+            rest = after;
+            continue;
+        }
+        // Descriptors run to the next comma outside parentheses
+        let mut depth = 0usize;
+        let end = after
+            .char_indices()
+            .find(|&(_, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth = depth.saturating_sub(1),
+                    ',' => return depth == 0,
+                    _ => {}
+                }
+                false
+            })
+            .map_or(after.len(), |(i, _)| i);
+        rest = &after[end..];
+    }
+}
+
+/// Parse the target out of a meta refresh `content` value (`5; url=/next`,
+/// `0;URL = '/next'` or a bare `0; /next`)
+fn parse_meta_refresh(content: &str) -> Option<&str> {
+    let (_, rest) = content.split_once(';')?;
+    let rest = rest.trim();
+    let target = match rest.get(..3) {
+        Some(prefix) if prefix.eq_ignore_ascii_case("url") => {
+            match rest[3..].trim_start().strip_prefix('=') {
+                Some(target) => target,
+                None => rest,
+            }
+        }
+        _ => rest,
+    };
+    let target = target.trim().trim_matches(|c| c == '\'' || c == '"');
+    (!target.is_empty()).then_some(target)
+}
+
+fn css_urls(css: &str) -> Vec<&str> {
+    static CSS_URL: OnceLock<Regex> = OnceLock::new();
+    let re = CSS_URL.get_or_init(|| {
+        Regex::new(r#"(?i)url\(\s*['"]?([^'")\s]+)['"]?\s*\)|@import\s+['"]([^'"]+)['"]"#).unwrap()
+    });
+
+    re.captures_iter(css)
+        .filter_map(|c| c.get(1).or_else(|| c.get(2)))
+        .map(|m| m.as_str())
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls_of(links: &[ExtractedLink], kind: LinkKind) -> Vec<&str> {
+        links
+            .iter()
+            .filter(|l| l.kind == kind)
+            .map(|l| l.url.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_classifies_outlinks_and_embeds() {
+        let html = r#"
+            <html><head>
+              <link rel="stylesheet" href="/style.css">
+              <link rel="alternate" type="application/rss+xml" href="/feed.xml">
+              <link rel="notalternate" href="/not-a-feed.xml">
+              <meta http-equiv="refresh" content="5; url=/next">
+              <style>body { background: url('/bg.png'); } @import "/print.css";</style>
+            </head><body>
+              <a href="/about#team">About</a>
+              <img src="/logo.png" srcset="/logo-2x.png 2x, /logo-3x.png 3x">
+              <iframe src="https://player.example.net/embed/1"></iframe>
+              <object data="/movie.swf"><param name="movie" value="/movie.swf"></object>
+              <div style="background-image: url(/hero.jpg)"></div>
+              <a href="javascript:void(0)">noop</a>
+            </body></html>
+        "#;
+
+        let links = extract_links("https://example.com/index.html", html);
+        let outlinks = urls_of(&links, LinkKind::Outlink);
+        let embeds = urls_of(&links, LinkKind::Embed);
+
+        assert!(outlinks.contains(&"https://example.com/about"));
+        assert!(outlinks.contains(&"https://example.com/feed.xml"));
+        assert!(outlinks.contains(&"https://example.com/next"));
+        assert_eq!(outlinks.len(), 3);
+
+        for expected in [
+            "https://example.com/style.css",
+            "https://example.com/bg.png",
+            "https://example.com/print.css",
+            "https://example.com/logo.png",
+            "https://example.com/logo-2x.png",
+            "https://example.com/logo-3x.png",
+            "https://player.example.net/embed/1",
+            "https://example.com/movie.swf",
+            "https://example.com/hero.jpg",
+        ] {
+            assert!(embeds.contains(&expected), "missing embed {}", expected);
+        }
+    }
+
+    #[test]
+    fn test_meta_refresh_forms() {
+        assert_eq!(parse_meta_refresh("5; url=/next"), Some("/next"));
+        assert_eq!(parse_meta_refresh("0;URL = '/next'"), Some("/next"));
+        assert_eq!(parse_meta_refresh("0; /next"), Some("/next"));
+        assert_eq!(parse_meta_refresh("0; urlish"), Some("urlish"));
+        // Multi-byte characters must not be sliced mid-character
+        assert_eq!(parse_meta_refresh("0; aéé"), Some("aéé"));
+        assert_eq!(parse_meta_refresh("0; é"), Some("é"));
+        assert_eq!(parse_meta_refresh("5"), None);
+    }
+
+    #[test]
+    fn test_srcset_urls_may_contain_commas() {
+        assert_eq!(
+            parse_srcset("/a.png 1x, /b.png 2x"),
+            vec!["/a.png", "/b.png"]
+        );
+        assert_eq!(
+            parse_srcset("data:image/png;base64,iVBORw0KGgo= 1x, /b.png 2x"),
+            vec!["data:image/png;base64,iVBORw0KGgo=", "/b.png"]
+        );
+        assert_eq!(
+            parse_srcset("https://cdn.example.com/w_100,h_50/a.jpg 100w,https://cdn.example.com/w_200,h_100/a.jpg 200w"),
+            vec![
+                "https://cdn.example.com/w_100,h_50/a.jpg",
+                "https://cdn.example.com/w_200,h_100/a.jpg"
+            ]
+        );
+        assert_eq!(parse_srcset("/a.png,/b.png"), vec!["/a.png,/b.png"]);
+        assert_eq!(parse_srcset("/a.png, /b.png"), vec!["/a.png", "/b.png"]);
+        assert_eq!(
+            parse_srcset(" /a.png 1x (x, y), /b.png"),
+            vec!["/a.png", "/b.png"]
+        );
+    }
+
+    #[test]
+    fn test_respects_base_href() {
+        let html = r#"<html><head><base href="https://cdn.example.com/assets/"></head>
+            <body><img src="a.png"></body></html>"#;
+        let links = extract_links("https://example.com/", html);
+        assert_eq!(links[0].url, "https://cdn.example.com/assets/a.png");
+    }
+
+    #[test]
+    fn test_sitemap_and_index() {
+        let urlset = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <urlset xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <url><loc>https://example.com/a</loc></url>
+              <url><loc> https://example.com/b </loc></url>
+            </urlset>"#;
+        let links = extract_from_document(
+            "https://example.com/sitemap.xml",
+            "application/xml",
+            urlset.as_bytes(),
+        );
+        assert_eq!(
+            urls_of(&links, LinkKind::Outlink),
+            vec!["https://example.com/a", "https://example.com/b"]
+        );
+
+        let index = r#"<sitemapindex xmlns="http://www.sitemaps.org/schemas/sitemap/0.9">
+              <sitemap><loc>https://example.com/sitemap-1.xml</loc></sitemap>
+            </sitemapindex>"#;
+        let links = extract_sitemap_links(index);
+        assert_eq!(links[0].url, "https://example.com/sitemap-1.xml");
+    }
+
+    #[test]
+    fn test_rss_and_atom_feeds() {
+        let rss = r#"<rss version="2.0"><channel>
+              <link>https://example.com/</link>
+              <item><link>https://example.com/post/1</link>
+                <enclosure url="https://example.com/ep1.mp3" type="audio/mpeg"/></item>
+            </channel></rss>"#;
+        let links = extract_feed_links("https://example.com/feed", rss);
+        assert!(urls_of(&links, LinkKind::Outlink).contains(&"https://example.com/post/1"));
+        assert_eq!(
+            urls_of(&links, LinkKind::Embed),
+            vec!["https://example.com/ep1.mp3"]
+        );
+
+        let atom = r#"<feed xmlns="http://www.w3.org/2005/Atom">
+              <link rel="self" href="https://example.com/atom.xml"/>
+              <entry><link href="/entry/1"/></entry>
+            </feed>"#;
+        let links = extract_from_document(
+            "https://example.com/atom.xml",
+            "application/atom+xml",
+            atom.as_bytes(),
+        );
+        assert_eq!(
+            urls_of(&links, LinkKind::Outlink),
+            vec!["https://example.com/entry/1"]
+        );
+    }
+
+    #[test]
+    fn test_css_document() {
+        let css = "@import url('fonts.css'); .a { src: url(\"../f/font.woff2\") format('woff2'); }";
+        let links = extract_from_document(
+            "https://example.com/css/main.css",
+            "text/css",
+            css.as_bytes(),
+        );
+        assert_eq!(
+            urls_of(&links, LinkKind::Embed),
+            vec![
+                "https://example.com/css/fonts.css",
+                "https://example.com/f/font.woff2"
+            ]
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use chrono::Duration;
+
+/// Everything known about a URL when its frontier priority is (re)computed
+#[derive(Debug, Clone, Default)]
+pub struct PriorityInputs {
+    pub depth: i32,
+    /// Distinct pages seen linking to the URL
+    pub inlinks: i32,
+    /// From the latest `CrawlPrediction`; `None` before the first capture
+    pub change_probability: Option<f32>,
+    /// Age of the latest capture at the time the URL becomes due; `None` if never captured
+    pub since_last_capture: Option<Duration>,
+    /// Boost inherited from the seed that started the crawl job
+    pub boost: i32,
+    pub is_embed: bool,
+}
+
+/// Turns [`PriorityInputs`] into the `priority` stored on `url_frontier`;
+/// higher values are claimed first
+pub trait PriorityScorer: Send + Sync {
+    fn score(&self, inputs: &PriorityInputs) -> i32;
+}
+
+/// Weighted sum of normalised signals, each in `0.0..=1.0`
+#[derive(Debug, Clone)]
+pub struct WeightedScorer {
+    /// Weight of `1 / (1 + depth)`
+    pub depth: f64,
+    /// Weight of `ln(1 + inlinks) / ln(1 + inlink_saturation)`
+    pub inlinks: f64,
+    pub inlink_saturation: i32,
+    /// Weight of the change probability (0.5 when unknown)
+    pub change: f64,
+    /// Weight of capture age relative to `stale_after` (never captured counts as fully stale)
+    pub staleness: f64,
+    pub stale_after: Duration,
+    /// Flat bonus so page requisites are fetched right after their page
+    pub embed_bonus: i32,
+}
+
+impl Default for WeightedScorer {
+    fn default() -> Self {
+        Self {
+            depth: 100.0,
+            inlinks: 60.0,
+            inlink_saturation: 1000,
+            change: 50.0,
+            staleness: 40.0,
+            stale_after: Duration::days(30),
+            embed_bonus: crate::frontier::EMBED_PRIORITY,
+        }
+    }
+}
+
+impl PriorityScorer for WeightedScorer {
+    fn score(&self, inputs: &PriorityInputs) -> i32 {
+        let depth = 1.0 / (1.0 + inputs.depth.max(0) as f64);
+
+        let inlinks = ((1.0 + inputs.inlinks.max(0) as f64).ln()
+            / (1.0 + self.inlink_saturation.max(1) as f64).ln())
+        .min(1.0);
+
+        let change = inputs.change_probability.unwrap_or(0.5).clamp(0.0, 1.0) as f64;
+
+        let staleness = match inputs.since_last_capture {
+            None => 1.0,
+            Some(age) => {
+                let limit = self.stale_after.num_seconds().max(1) as f64;
+                (age.num_seconds().max(0) as f64 / limit).min(1.0)
+            }
+        };
+
+        let score = self.depth * depth
+            + self.inlinks * inlinks
+            + self.change * change
+            + self.staleness * staleness;
+        let bonus = if inputs.is_embed { self.embed_bonus } else { 0 };
+
+        (score.round() as i32)
+            .saturating_add(bonus)
+            .saturating_add(inputs.boost)
+            .max(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shallow_popular_pages_rank_higher() {
+        let scorer = WeightedScorer::default();
+        let homepage = PriorityInputs {
+            depth: 0,
+            inlinks: 200,
+            ..Default::default()
+        };
+        let deep = PriorityInputs {
+            depth: 6,
+            inlinks: 1,
+            ..Default::default()
+        };
+        assert!(scorer.score(&homepage) > scorer.score(&deep));
+
+        let more_links = PriorityInputs {
+            inlinks: 50,
+            ..deep.clone()
+        };
+        assert!(scorer.score(&more_links) > scorer.score(&deep));
+    }
+
+    #[test]
+    fn test_change_rate_staleness_and_boost() {
+        let scorer = WeightedScorer::default();
+        let base = PriorityInputs {
+            depth: 2,
+            change_probability: Some(0.1),
+            since_last_capture: Some(Duration::days(1)),
+            ..Default::default()
+        };
+        let volatile = PriorityInputs {
+            change_probability: Some(0.9),
+            ..base.clone()
+        };
+        let stale = PriorityInputs {
+            since_last_capture: Some(Duration::days(60)),
+            ..base.clone()
+        };
+        let boosted = PriorityInputs {
+            boost: 500,
+            ..base.clone()
+        };
+
+        assert!(scorer.score(&volatile) > scorer.score(&base));
+        assert!(scorer.score(&stale) > scorer.score(&base));
+        assert_eq!(scorer.score(&boosted), scorer.score(&base) + 500);
+        assert_eq!(
+            scorer.score(&PriorityInputs {
+                boost: -10_000,
+                ..base
+            }),
+            0
+        );
+    }
+}
@@ -1,46 +1,62 @@
 use anyhow::Result;
+use async_trait::async_trait;
 use chrono::{Duration, Utc};
 use sqlx::PgPool;
 
-/// Token bucket rate limiter with per-domain and per-region limits
-pub struct RateLimiter {
-    pool: PgPool,
-    global_limit_per_domain: i32,
-    regional_limit_per_domain: i32,
-    window_seconds: i64,
+/// Per-domain request budgets shared by every rate limiter backend
+#[derive(Debug, Clone)]
+pub struct RateLimits {
+    pub global_per_domain: i32,
+    pub regional_per_domain: i32,
+    pub window_seconds: i64,
 }
 
-impl RateLimiter {
-    pub fn new(pool: PgPool) -> Self {
+impl Default for RateLimits {
+    fn default() -> Self {
         Self {
-            pool,
-            global_limit_per_domain: 10, // 10 requests per minute globally
-            regional_limit_per_domain: 5, // 5 requests per minute per region
+            global_per_domain: 10,  // 10 requests per minute globally
+            regional_per_domain: 5, // 5 requests per minute per region
             window_seconds: 60,
         }
     }
+}
 
-    /// Check if a request is allowed for a domain in a specific region
-    pub async fn check_and_increment(&self, domain: &str, region: &str) -> Result<bool> {
-        let window_start = self.current_window_start();
+impl RateLimits {
+    /// Start of the fixed window containing `now`
+    pub fn window_start(&self, now: chrono::DateTime<Utc>) -> chrono::DateTime<Utc> {
+        let seconds_since_epoch = now.timestamp();
+        let window_aligned = (seconds_since_epoch / self.window_seconds) * self.window_seconds;
+        chrono::DateTime::from_timestamp(window_aligned, 0).unwrap_or(now)
+    }
 
-        // 1. Check regional limit
-        let regional_count = self.get_count(domain, region, window_start).await?;
-        if regional_count >= self.regional_limit_per_domain {
-            tracing::warn!("Regional rate limit exceeded for {} in {}", domain, region);
-            return Ok(false);
-        }
+    /// Start of the window after the one containing `now`
+    pub fn next_window(&self, now: chrono::DateTime<Utc>) -> chrono::DateTime<Utc> {
+        self.window_start(now) + Duration::seconds(self.window_seconds)
+    }
+}
 
-        // 2. Check global limit (sum across all regions)
-        let global_count = self.get_global_count(domain, window_start).await?;
-        if global_count >= self.global_limit_per_domain {
-            tracing::warn!("Global rate limit exceeded for {}", domain);
-            return Ok(false);
-        }
+/// Decides whether a request to a domain may go out now
+#[async_trait]
+pub trait RateLimitBackend: Send + Sync {
+    /// Check if a request is allowed for a domain in a specific region, counting it if so
+    async fn check_and_increment(&self, domain: &str, region: &str) -> Result<bool>;
 
-        // 3. Increment counter
-        self.increment(domain, region, window_start).await?;
-        Ok(true)
+    /// When a request denied at `now` may be tried again
+    fn retry_at(&self, now: chrono::DateTime<Utc>) -> chrono::DateTime<Utc>;
+}
+
+/// Token bucket rate limiter with per-domain and per-region limits
+pub struct RateLimiter {
+    pool: PgPool,
+    limits: RateLimits,
+}
+
+impl RateLimiter {
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            pool,
+            limits: RateLimits::default(),
+        }
     }
 
     async fn get_count(
@@ -104,13 +120,6 @@ impl RateLimiter {
         Ok(())
     }
 
-    fn current_window_start(&self) -> chrono::DateTime<Utc> {
-        let now = Utc::now();
-        let seconds_since_epoch = now.timestamp();
-        let window_aligned = (seconds_since_epoch / self.window_seconds) * self.window_seconds;
-        chrono::DateTime::from_timestamp(window_aligned, 0).unwrap_or(now)
-    }
-
     /// Cleanup old rate limit windows (run periodically)
     pub async fn cleanup_old_windows(&self) -> Result<()> {
         let cutoff = Utc::now() - Duration::hours(1);
@@ -122,3 +131,32 @@ impl RateLimiter {
         Ok(())
     }
 }
+
+#[async_trait]
+impl RateLimitBackend for RateLimiter {
+    async fn check_and_increment(&self, domain: &str, region: &str) -> Result<bool> {
+        let window_start = self.limits.window_start(Utc::now());
+
+        // 1. Check regional limit
+        let regional_count = self.get_count(domain, region, window_start).await?;
+        if regional_count >= self.limits.regional_per_domain {
+            tracing::warn!("Regional rate limit exceeded for {} in {}", domain, region);
+            return Ok(false);
+        }
+
+        // 2. Check global limit (sum across all regions)
+        let global_count = self.get_global_count(domain, window_start).await?;
+        if global_count >= self.limits.global_per_domain {
+            tracing::warn!("Global rate limit exceeded for {}", domain);
+            return Ok(false);
+        }
+
+        // 3. Increment counter
+        self.increment(domain, region, window_start).await?;
+        Ok(true)
+    }
+
+    fn retry_at(&self, now: chrono::DateTime<Utc>) -> chrono::DateTime<Utc> {
+        self.limits.next_window(now)
+    }
+}
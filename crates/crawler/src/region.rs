@@ -1,71 +1,235 @@
-use std::collections::hash_map::DefaultHasher;
-use std::hash::{Hash, Hasher};
-use std::str::FromStr; // Add this line
-
-/// Regions supported by ArchiveStream
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum Region {
-    UsEast1,
-    EuWest1,
-    ApSouth1,
-}
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+/// Regions used when neither `REGIONS` nor the `workers` table provide any
+pub const DEFAULT_REGIONS: &[&str] = &["us-east-1", "eu-west-1", "ap-south-1"];
+
+/// Virtual nodes placed on the ring per region; more nodes smooth the distribution
+pub const DEFAULT_VIRTUAL_NODES: usize = 160;
+
+/// A deployment region identifier such as `us-east-1`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+pub struct Region(String);
 
 impl Region {
-    pub fn as_str(&self) -> &'static str {
-        match self {
-            Region::UsEast1 => "us-east-1",
-            Region::EuWest1 => "eu-west-1",
-            Region::ApSouth1 => "ap-south-1",
-        }
+    pub fn new(name: impl Into<String>) -> Self {
+        Self(name.into())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
     }
 
     pub fn from_env() -> Self {
         std::env::var("REGION")
             .ok()
-            .and_then(|r| Region::from_str(&r).ok()) // Use the FromStr implementation
-            .unwrap_or(Region::UsEast1)
+            .and_then(|r| Region::from_str(&r).ok())
+            .unwrap_or_else(|| Region::new(DEFAULT_REGIONS[0]))
     }
 }
 
-// Implement FromStr trait for Region
 impl FromStr for Region {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "us-east-1" => Ok(Region::UsEast1),
-            "eu-west-1" => Ok(Region::EuWest1),
-            "ap-south-1" => Ok(Region::ApSouth1),
-            _ => Err(anyhow::anyhow!("Invalid region: {}", s)),
+        let s = s.trim();
+        let valid = !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+        if valid {
+            Ok(Region::new(s))
+        } else {
+            Err(anyhow::anyhow!("Invalid region: {}", s))
         }
     }
 }
 
-/// Consistent hashing for domain -> region affinity
+impl std::fmt::Display for Region {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Consistent hashing for domain -> region affinity.
+///
+/// Each region owns `virtual_nodes` points on a 64-bit ring keyed by a SHA-256
+/// derived hash, which (unlike `DefaultHasher`) is stable across Rust releases
+/// and processes. Adding or removing a region only moves the domains that fall
+/// between its points and their predecessors, roughly `1/n` of the keyspace.
+#[derive(Debug, Clone)]
 pub struct RegionRouter {
     regions: Vec<Region>,
+    ring: BTreeMap<u64, usize>,
+    virtual_nodes: usize,
+}
+
+/// A domain whose preferred region changes between two ring layouts
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DomainMove {
+    pub domain: String,
+    pub from: Region,
+    pub to: Region,
+}
+
+/// Which domains would move if the ring changed from one region set to another
+#[derive(Debug, Clone, Serialize)]
+pub struct RebalanceReport {
+    pub current_regions: Vec<Region>,
+    pub proposed_regions: Vec<Region>,
+    pub total_domains: usize,
+    pub moves: Vec<DomainMove>,
+}
+
+impl RebalanceReport {
+    pub fn moved_fraction(&self) -> f64 {
+        if self.total_domains == 0 {
+            return 0.0;
+        }
+        self.moves.len() as f64 / self.total_domains as f64
+    }
 }
 
 impl RegionRouter {
     pub fn new() -> Self {
-        Self {
-            regions: vec![Region::UsEast1, Region::EuWest1, Region::ApSouth1],
+        Self::with_regions(DEFAULT_REGIONS.iter().map(|r| Region::new(*r)).collect())
+    }
+
+    /// Build a ring over `regions`; an empty list falls back to [`DEFAULT_REGIONS`]
+    pub fn with_regions(regions: Vec<Region>) -> Self {
+        Self::with_virtual_nodes(regions, DEFAULT_VIRTUAL_NODES)
+    }
+
+    pub fn with_virtual_nodes(mut regions: Vec<Region>, virtual_nodes: usize) -> Self {
+        regions.sort();
+        regions.dedup();
+        if regions.is_empty() {
+            regions = DEFAULT_REGIONS.iter().map(|r| Region::new(*r)).collect();
         }
+
+        let mut router = Self {
+            regions,
+            ring: BTreeMap::new(),
+            virtual_nodes: virtual_nodes.max(1),
+        };
+        router.rebuild();
+        router
     }
 
-    /// Assign a domain to a preferred region using consistent hashing
-    pub fn route_domain(&self, domain: &str) -> Region {
-        let mut hasher = DefaultHasher::new();
-        domain.hash(&mut hasher);
-        let hash = hasher.finish();
+    /// Regions from the comma-separated `REGIONS` variable, or the defaults
+    pub fn from_config() -> Self {
+        Self::with_regions(Self::configured_regions().unwrap_or_default())
+    }
+
+    /// The valid regions in `REGIONS`; `None` when it names none, and the
+    /// ring follows the live regions in `workers` instead
+    pub fn configured_regions() -> Option<Vec<Region>> {
+        let regions = std::env::var("REGIONS")
+            .ok()?
+            .split(',')
+            .filter_map(|r| Region::from_str(r).ok())
+            .collect::<Vec<_>>();
+        (!regions.is_empty()).then_some(regions)
+    }
+
+    pub fn regions(&self) -> &[Region] {
+        &self.regions
+    }
+
+    pub fn add_region(&mut self, region: Region) {
+        if !self.regions.contains(&region) {
+            self.regions.push(region);
+            self.regions.sort();
+            self.rebuild();
+        }
+    }
+
+    /// Remove a region; the last remaining region cannot be removed
+    pub fn remove_region(&mut self, region: &Region) -> bool {
+        if self.regions.len() <= 1 || !self.regions.contains(region) {
+            return false;
+        }
+        self.regions.retain(|r| r != region);
+        self.rebuild();
+        true
+    }
 
-        let index = (hash as usize) % self.regions.len();
-        self.regions[index].clone()
+    fn rebuild(&mut self) {
+        self.ring.clear();
+        for (idx, region) in self.regions.iter().enumerate() {
+            for vnode in 0..self.virtual_nodes {
+                let point = stable_hash(format!("{}#{}", region.as_str(), vnode).as_bytes());
+                self.ring.insert(point, idx);
+            }
+        }
+    }
+
+    /// Assign a domain to a preferred region using consistent hashing
+    pub fn route_domain(&self, domain: &str) -> &Region {
+        let hash = stable_hash(domain.to_ascii_lowercase().as_bytes());
+        let idx = self
+            .ring
+            .range(hash..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, idx)| *idx)
+            .unwrap_or(0);
+        &self.regions[idx]
     }
 
     /// Check if this worker should prioritize a domain based on region affinity
     pub fn should_prioritize(&self, domain: &str, worker_region: &Region) -> bool {
-        &self.route_domain(domain) == worker_region
+        self.route_domain(domain) == worker_region
+    }
+
+    /// Compare this ring against `proposed` for the given domains
+    pub fn rebalance_report<'a, I>(&self, proposed: &RegionRouter, domains: I) -> RebalanceReport
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut total_domains = 0;
+        let mut moves = Vec::new();
+        for domain in domains {
+            total_domains += 1;
+            let from = self.route_domain(domain);
+            let to = proposed.route_domain(domain);
+            if from != to {
+                moves.push(DomainMove {
+                    domain: domain.to_string(),
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            }
+        }
+
+        RebalanceReport {
+            current_regions: self.regions.clone(),
+            proposed_regions: proposed.regions.clone(),
+            total_domains,
+            moves,
+        }
+    }
+
+    /// Preview the effect of adding `region` before doing it
+    pub fn preview_add<'a, I>(&self, region: Region, domains: I) -> RebalanceReport
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut proposed = self.clone();
+        proposed.add_region(region);
+        self.rebalance_report(&proposed, domains)
+    }
+
+    /// Preview the effect of removing `region` before doing it
+    pub fn preview_remove<'a, I>(&self, region: &Region, domains: I) -> RebalanceReport
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let mut proposed = self.clone();
+        proposed.remove_region(region);
+        self.rebalance_report(&proposed, domains)
     }
 }
 
@@ -75,10 +239,65 @@ impl Default for RegionRouter {
     }
 }
 
+/// A [`RegionRouter`] shared by the frontier and the task following region
+/// membership in `workers`; replacing the ring reroutes new URLs at once
+#[derive(Debug, Clone, Default)]
+pub struct SharedRegionRouter(Arc<RwLock<RegionRouter>>);
+
+impl SharedRegionRouter {
+    pub fn new(router: RegionRouter) -> Self {
+        Self(Arc::new(RwLock::new(router)))
+    }
+
+    pub fn route_domain(&self, domain: &str) -> Region {
+        self.read().route_domain(domain).clone()
+    }
+
+    /// A copy of the current ring
+    pub fn current(&self) -> RegionRouter {
+        self.read().clone()
+    }
+
+    /// Rebuild the ring over `regions` unless it already holds exactly them;
+    /// returns whether it changed. An empty list leaves the ring as it is
+    pub fn set_regions(&self, mut regions: Vec<Region>) -> bool {
+        regions.sort();
+        regions.dedup();
+        if regions.is_empty() || self.read().regions == regions {
+            return false;
+        }
+        let mut router = self.0.write().unwrap_or_else(|e| e.into_inner());
+        *router = RegionRouter::with_virtual_nodes(regions, router.virtual_nodes);
+        true
+    }
+
+    fn read(&self) -> std::sync::RwLockReadGuard<'_, RegionRouter> {
+        self.0.read().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl From<RegionRouter> for SharedRegionRouter {
+    fn from(router: RegionRouter) -> Self {
+        Self::new(router)
+    }
+}
+
+/// First 8 bytes of SHA-256, big-endian
+fn stable_hash(bytes: &[u8]) -> u64 {
+    let digest = Sha256::digest(bytes);
+    let mut buf = [0u8; 8];
+    buf.copy_from_slice(&digest[..8]);
+    u64::from_be_bytes(buf)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn domains(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("domain{}.com", i)).collect()
+    }
+
     #[test]
     fn test_consistent_routing() {
         let router = RegionRouter::new();
@@ -93,7 +312,7 @@ mod tests {
     #[test]
     fn test_distribution() {
         let router = RegionRouter::new();
-        let domains: Vec<String> = (0..1000).map(|i| format!("domain{}.com", i)).collect();
+        let domains = domains(1000);
 
         let mut counts = std::collections::HashMap::new();
         for domain in domains {
@@ -110,4 +329,74 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_routing_is_stable_across_instances_and_order() {
+        let a = RegionRouter::with_regions(vec![
+            Region::new("eu-west-1"),
+            Region::new("us-east-1"),
+            Region::new("ap-south-1"),
+        ]);
+        let b = RegionRouter::new();
+        for domain in domains(200) {
+            assert_eq!(a.route_domain(&domain), b.route_domain(&domain));
+        }
+    }
+
+    #[test]
+    fn test_adding_region_moves_only_its_share() {
+        let router = RegionRouter::new();
+        let domains = domains(2000);
+        let new_region = Region::new("sa-east-1");
+        let report = router.preview_add(new_region.clone(), domains.iter().map(|d| d.as_str()));
+
+        // Every move goes to the new region, and only about a quarter of domains move
+        assert!(report.moves.iter().all(|m| m.to == new_region));
+        let fraction = report.moved_fraction();
+        assert!(fraction > 0.15 && fraction < 0.35, "moved {}", fraction);
+    }
+
+    #[test]
+    fn test_removing_region_moves_only_its_domains() {
+        let router = RegionRouter::new();
+        let domains = domains(1000);
+        let removed = Region::new("eu-west-1");
+        let report = router.preview_remove(&removed, domains.iter().map(|d| d.as_str()));
+
+        assert!(report.moves.iter().all(|m| m.from == removed));
+        let owned = domains
+            .iter()
+            .filter(|d| router.route_domain(d) == &removed)
+            .count();
+        assert_eq!(report.moves.len(), owned);
+    }
+
+    #[test]
+    fn test_shared_router_follows_region_membership() {
+        let shared =
+            SharedRegionRouter::new(RegionRouter::with_regions(vec![Region::new("us-east-1")]));
+        assert_eq!(shared.route_domain("example.com").as_str(), "us-east-1");
+
+        let live = vec![Region::new("eu-west-1"), Region::new("us-east-1")];
+        assert!(shared.set_regions(live.clone()));
+        let expected = RegionRouter::with_regions(live.clone());
+        for domain in domains(100) {
+            assert_eq!(
+                &shared.route_domain(&domain),
+                expected.route_domain(&domain)
+            );
+        }
+
+        // The same set in another order, or no live regions, keeps the ring
+        assert!(!shared.set_regions(live.into_iter().rev().collect()));
+        assert!(!shared.set_regions(Vec::new()));
+        assert_eq!(shared.current().regions().len(), 2);
+    }
+
+    #[test]
+    fn test_cannot_remove_last_region() {
+        let mut router = RegionRouter::with_regions(vec![Region::new("us-east-1")]);
+        assert!(!router.remove_region(&Region::new("us-east-1")));
+        assert_eq!(router.route_domain("example.com").as_str(), "us-east-1");
+    }
 }
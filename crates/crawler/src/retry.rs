@@ -0,0 +1,260 @@
+use crate::fetcher::FetchTimeout;
+use chrono::Duration;
+use rand::Rng;
+
+/// Coarse failure categories that get their own retry behaviour
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorClass {
+    Dns,
+    Connect,
+    Timeout,
+    ClientError,
+    ServerError,
+    Robots,
+    Other,
+}
+
+impl ErrorClass {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorClass::Dns => "dns",
+            ErrorClass::Connect => "connect",
+            ErrorClass::Timeout => "timeout",
+            ErrorClass::ClientError => "http_4xx",
+            ErrorClass::ServerError => "http_5xx",
+            ErrorClass::Robots => "robots",
+            ErrorClass::Other => "other",
+        }
+    }
+
+    /// Classify a fetch error by walking its source chain
+    pub fn classify(err: &anyhow::Error) -> Self {
+        if err.downcast_ref::<FetchTimeout>().is_some() {
+            return ErrorClass::Timeout;
+        }
+
+        if let Some(req_err) = err.downcast_ref::<reqwest::Error>() {
+            if req_err.is_timeout() {
+                return ErrorClass::Timeout;
+            }
+            if req_err.is_connect() {
+                let chain = err
+                    .chain()
+                    .map(|e| e.to_string().to_ascii_lowercase())
+                    .collect::<Vec<_>>()
+                    .join(": ");
+                if chain.contains("dns")
+                    || chain.contains("failed to lookup address")
+                    || chain.contains("name or service not known")
+                    || chain.contains("no such host")
+                {
+                    return ErrorClass::Dns;
+                }
+                return ErrorClass::Connect;
+            }
+            if let Some(status) = req_err.status() {
+                return Self::from_status(status.as_u16()).unwrap_or(ErrorClass::Other);
+            }
+        }
+
+        ErrorClass::Other
+    }
+
+    /// Map an HTTP status to a failure class; `None` means the response is a usable capture
+    pub fn from_status(status: u16) -> Option<Self> {
+        match status {
+            // Rate limiting is transient even though it is a 4xx
+            429 => Some(ErrorClass::ServerError),
+            400..=499 => Some(ErrorClass::ClientError),
+            500..=599 => Some(ErrorClass::ServerError),
+            _ => None,
+        }
+    }
+}
+
+/// Backoff parameters for one error class
+#[derive(Debug, Clone)]
+pub struct BackoffRule {
+    /// Delay before the first retry
+    pub base: Duration,
+    /// Upper bound on the delay
+    pub max: Duration,
+    /// Attempts (including the first) after which the URL is dead-lettered
+    pub max_attempts: i32,
+    /// Fraction of the delay randomised in either direction (0.0 - 1.0)
+    pub jitter: f64,
+}
+
+impl BackoffRule {
+    fn new(base: Duration, max: Duration, max_attempts: i32) -> Self {
+        Self {
+            base,
+            max,
+            max_attempts,
+            jitter: 0.2,
+        }
+    }
+}
+
+/// Decides when a failed URL is retried and when it is given up on
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub dns: BackoffRule,
+    pub connect: BackoffRule,
+    pub timeout: BackoffRule,
+    pub client_error: BackoffRule,
+    pub server_error: BackoffRule,
+    pub robots: BackoffRule,
+    pub other: BackoffRule,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            dns: BackoffRule::new(Duration::hours(1), Duration::days(1), 3),
+            connect: BackoffRule::new(Duration::minutes(5), Duration::hours(6), 5),
+            timeout: BackoffRule::new(Duration::minutes(10), Duration::hours(6), 5),
+            // A 404/410 rarely comes back; check once more before giving up
+            client_error: BackoffRule::new(Duration::hours(6), Duration::days(1), 2),
+            server_error: BackoffRule::new(Duration::minutes(2), Duration::hours(12), 6),
+            robots: BackoffRule::new(Duration::days(1), Duration::days(7), 3),
+            other: BackoffRule::new(Duration::minutes(15), Duration::hours(12), 4),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Default rules with the attempt cap overridden by `RETRY_MAX_ATTEMPTS` when set
+    pub fn from_env() -> Self {
+        let policy = Self::default();
+        match std::env::var("RETRY_MAX_ATTEMPTS")
+            .ok()
+            .and_then(|v| v.parse::<i32>().ok())
+        {
+            Some(max) => policy.with_max_attempts(max),
+            None => policy,
+        }
+    }
+
+    /// Every class gives up after `max` attempts, raising or lowering its default
+    pub fn with_max_attempts(mut self, max: i32) -> Self {
+        for rule in self.rules_mut() {
+            rule.max_attempts = max.max(1);
+        }
+        self
+    }
+
+    pub fn rule(&self, class: ErrorClass) -> &BackoffRule {
+        match class {
+            ErrorClass::Dns => &self.dns,
+            ErrorClass::Connect => &self.connect,
+            ErrorClass::Timeout => &self.timeout,
+            ErrorClass::ClientError => &self.client_error,
+            ErrorClass::ServerError => &self.server_error,
+            ErrorClass::Robots => &self.robots,
+            ErrorClass::Other => &self.other,
+        }
+    }
+
+    fn rules_mut(&mut self) -> [&mut BackoffRule; 7] {
+        [
+            &mut self.dns,
+            &mut self.connect,
+            &mut self.timeout,
+            &mut self.client_error,
+            &mut self.server_error,
+            &mut self.robots,
+            &mut self.other,
+        ]
+    }
+
+    /// Delay before the next attempt, or `None` once `attempts` (failures so far,
+    /// including this one) has reached the class limit and the URL should be dead-lettered
+    pub fn next_backoff(&self, class: ErrorClass, attempts: i32) -> Option<Duration> {
+        let delay = self.base_delay(class, attempts)?;
+        let rule = self.rule(class);
+        if rule.jitter <= 0.0 {
+            return Some(delay);
+        }
+
+        let spread = rand::thread_rng().gen_range(-rule.jitter..=rule.jitter);
+        let millis = delay.num_milliseconds() as f64 * (1.0 + spread);
+        Some(Duration::milliseconds(millis.max(0.0) as i64))
+    }
+
+    /// Un-jittered exponential delay: `base * 2^(attempts - 1)`, capped at `max`
+    pub fn base_delay(&self, class: ErrorClass, attempts: i32) -> Option<Duration> {
+        let rule = self.rule(class);
+        if attempts >= rule.max_attempts {
+            return None;
+        }
+
+        let exponent = (attempts.max(1) - 1).min(30) as u32;
+        let delay = rule
+            .base
+            .checked_mul(2i32.saturating_pow(exponent))
+            .unwrap_or(rule.max);
+        Some(delay.min(rule.max))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_classification() {
+        assert_eq!(ErrorClass::from_status(200), None);
+        assert_eq!(ErrorClass::from_status(304), None);
+        assert_eq!(ErrorClass::from_status(404), Some(ErrorClass::ClientError));
+        assert_eq!(ErrorClass::from_status(429), Some(ErrorClass::ServerError));
+        assert_eq!(ErrorClass::from_status(503), Some(ErrorClass::ServerError));
+    }
+
+    #[test]
+    fn test_exponential_backoff_is_capped() {
+        let policy = RetryPolicy::default();
+        let class = ErrorClass::ServerError;
+        assert_eq!(policy.base_delay(class, 1), Some(Duration::minutes(2)));
+        assert_eq!(policy.base_delay(class, 2), Some(Duration::minutes(4)));
+        assert_eq!(policy.base_delay(class, 3), Some(Duration::minutes(8)));
+        assert_eq!(policy.base_delay(class, 6), None);
+
+        let policy = RetryPolicy {
+            server_error: BackoffRule::new(Duration::hours(1), Duration::hours(3), 50),
+            ..RetryPolicy::default()
+        };
+        assert_eq!(policy.base_delay(class, 40), Some(Duration::hours(3)));
+    }
+
+    #[test]
+    fn test_jitter_stays_in_bounds() {
+        let policy = RetryPolicy::default();
+        for _ in 0..100 {
+            let delay = policy.next_backoff(ErrorClass::Connect, 1).unwrap();
+            assert!(delay >= Duration::minutes(4) && delay <= Duration::minutes(6));
+        }
+    }
+
+    #[test]
+    fn test_dead_letter_after_max_attempts() {
+        let policy = RetryPolicy::default();
+        assert!(policy.next_backoff(ErrorClass::ClientError, 1).is_some());
+        assert!(policy.next_backoff(ErrorClass::ClientError, 2).is_none());
+
+        // The override raises a class's cap as well as lowering it
+        let policy = RetryPolicy::default().with_max_attempts(4);
+        assert!(policy.next_backoff(ErrorClass::ClientError, 3).is_some());
+        assert!(policy.next_backoff(ErrorClass::ServerError, 4).is_none());
+    }
+
+    #[test]
+    fn test_classifies_typed_timeout() {
+        let err = anyhow::Error::new(FetchTimeout("https://example.com".into()));
+        assert_eq!(ErrorClass::classify(&err), ErrorClass::Timeout);
+        assert_eq!(
+            ErrorClass::classify(&anyhow::anyhow!("boom")),
+            ErrorClass::Other
+        );
+    }
+}
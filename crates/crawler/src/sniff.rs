@@ -0,0 +1,128 @@
+/// Magic-byte signatures checked in order; the first match wins
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"wOFF", "font/woff"),
+    (b"wOF2", "font/woff2"),
+    (b"\x00\x01\x00\x00", "font/ttf"),
+    (b"OTTO", "font/otf"),
+    (b"\x00\x00\x01\x00", "image/x-icon"),
+    (b"ID3", "audio/mpeg"),
+    (b"OggS", "audio/ogg"),
+    (b"\x1a\x45\xdf\xa3", "video/webm"),
+    (b"%!PS", "application/postscript"),
+    (b"\x00asm", "application/wasm"),
+];
+
+/// Guess a MIME type from the leading bytes of a payload, following the
+/// spirit of the WHATWG MIME sniffing rules. Returns `None` when nothing
+/// matches so callers can fall back to a neutral type.
+pub fn sniff_mime(content: &[u8]) -> Option<&'static str> {
+    for (magic, mime) in SIGNATURES {
+        if content.starts_with(magic) {
+            return Some(mime);
+        }
+    }
+
+    // RIFF containers carry their format at offset 8
+    if content.len() >= 12 && content.starts_with(b"RIFF") {
+        return match &content[8..12] {
+            b"WEBP" => Some("image/webp"),
+            b"WAVE" => Some("audio/wav"),
+            b"AVI " => Some("video/x-msvideo"),
+            _ => None,
+        };
+    }
+
+    // ISO base media (mp4, mov) has "ftyp" at offset 4
+    if content.len() >= 12 && &content[4..8] == b"ftyp" {
+        return match &content[8..12] {
+            b"avif" => Some("image/avif"),
+            b"qt  " => Some("video/quicktime"),
+            _ => Some("video/mp4"),
+        };
+    }
+
+    sniff_text(content)
+}
+
+fn sniff_text(content: &[u8]) -> Option<&'static str> {
+    let head = &content[..content.len().min(512)];
+    let text = String::from_utf8_lossy(head);
+    let trimmed = text.trim_start_matches('\u{feff}').trim_start();
+    let lower = trimmed.to_ascii_lowercase();
+
+    if lower.starts_with("<!doctype html")
+        || lower.starts_with("<html")
+        || lower.starts_with("<head")
+        || lower.starts_with("<body")
+    {
+        return Some("text/html");
+    }
+    if lower.starts_with("<?xml") || lower.starts_with("<rss") || lower.starts_with("<feed") {
+        if lower.contains("<svg") {
+            return Some("image/svg+xml");
+        }
+        if lower.contains("<rss") {
+            return Some("application/rss+xml");
+        }
+        if lower.contains("<feed") {
+            return Some("application/atom+xml");
+        }
+        return Some("application/xml");
+    }
+    if lower.starts_with("<svg") {
+        return Some("image/svg+xml");
+    }
+    if lower.starts_with('{') || lower.starts_with('[') {
+        return Some("application/json");
+    }
+
+    // Binary if there are control bytes that never occur in text
+    if head
+        .iter()
+        .any(|&b| b < 0x09 || (b > 0x0d && b < 0x20 && b != 0x1b))
+    {
+        return None;
+    }
+    if head.is_empty() {
+        return None;
+    }
+    Some("text/plain")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_binary_signatures() {
+        assert_eq!(sniff_mime(b"\x89PNG\r\n\x1a\n...."), Some("image/png"));
+        assert_eq!(sniff_mime(b"\xff\xd8\xff\xe0JFIF"), Some("image/jpeg"));
+        assert_eq!(
+            sniff_mime(b"RIFF\x00\x00\x00\x00WEBPVP8 "),
+            Some("image/webp")
+        );
+        assert_eq!(sniff_mime(b"\x00\x00\x00\x18ftypmp42"), Some("video/mp4"));
+        assert_eq!(sniff_mime(b"wOF2\x00\x01"), Some("font/woff2"));
+        assert_eq!(sniff_mime(b"\x00\x01\x02\x03\x04"), None);
+    }
+
+    #[test]
+    fn test_text_signatures() {
+        assert_eq!(sniff_mime(b"  <!DOCTYPE html><html>"), Some("text/html"));
+        assert_eq!(
+            sniff_mime(b"<?xml version=\"1.0\"?><rss version=\"2.0\">"),
+            Some("application/rss+xml")
+        );
+        assert_eq!(sniff_mime(b"<svg xmlns=\"\">"), Some("image/svg+xml"));
+        assert_eq!(sniff_mime(b"{\"a\": 1}"), Some("application/json"));
+        assert_eq!(sniff_mime(b"hello world"), Some("text/plain"));
+        assert_eq!(sniff_mime(b""), None);
+    }
+}
@@ -1,37 +1,184 @@
 use chrono::Utc;
+use std::net::IpAddr;
 use uuid::Uuid;
 
+/// Version line of every record; the revisit profiles below are the ones it defines
+const WARC_VERSION: &str = "WARC/1.1";
+/// WARC/1.1 revisit profile for identical payloads found by digest
+pub const PROFILE_IDENTICAL_PAYLOAD: &str =
+    "http://netpreserve.org/warc/1.1/revisit/identical-payload-digest";
+/// WARC/1.1 revisit profile for `304 Not Modified` responses
+pub const PROFILE_SERVER_NOT_MODIFIED: &str =
+    "http://netpreserve.org/warc/1.1/revisit/server-not-modified";
+
 pub struct WarcRecord {
     pub url: String,
     pub timestamp: chrono::DateTime<Utc>,
     pub content: Vec<u8>,
     pub content_type: String,
     pub status_code: u16,
+    /// Protocol of the status line, e.g. `HTTP/1.1`
+    pub http_version: String,
+    /// Response headers in the order received
+    pub http_headers: Vec<(String, String)>,
     pub payload_digest: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    /// Absolute `Location` target of a 3xx response
+    pub location: Option<String>,
+    pub remote_ip: Option<IpAddr>,
+    pub tls: Option<TlsDetails>,
+    /// `WARC-Truncated` reason (`length`, `time`, `disconnect`) when the payload is incomplete
+    pub truncated: Option<String>,
+}
+
+/// Transport security details observed for a capture
+#[derive(Debug, Clone)]
+pub struct TlsDetails {
+    pub peer_certificate_sha256: String,
 }
 
 impl WarcRecord {
-    pub fn to_warc_string(&self, is_revisit: bool) -> String {
-        let record_id = Uuid::new_v4();
-        let timestamp = self.timestamp.format("%Y-%m-%dT%H:%M:%SZ").to_string();
-        let warc_type = if is_revisit { "revisit" } else { "response" };
+    /// True when the server answered a conditional request with `304 Not Modified`
+    pub fn is_not_modified(&self) -> bool {
+        self.status_code == 304
+    }
+
+    pub fn is_redirect(&self) -> bool {
+        matches!(self.status_code, 301 | 302 | 303 | 307 | 308)
+    }
+
+    /// `response` record holding the HTTP status line, headers and payload
+    pub fn to_warc_bytes(&self) -> Vec<u8> {
+        let mut block = self.http_head();
+        block.extend_from_slice(&self.content);
+        self.record("response", "", &block)
+    }
+
+    /// `revisit` record under `profile`; its block is the HTTP head only. With
+    /// `refers_to`, it names the URL and date of the capture holding the payload
+    pub fn to_revisit_bytes(
+        &self,
+        profile: &str,
+        refers_to: Option<(&str, chrono::DateTime<Utc>)>,
+    ) -> Vec<u8> {
+        let mut fields = format!("WARC-Profile: {}\r\n", profile);
+        if let Some((url, date)) = refers_to {
+            fields.push_str(&format!(
+                "WARC-Refers-To-Target-URI: {}\r\nWARC-Refers-To-Date: {}\r\n",
+                url,
+                date.format("%Y-%m-%dT%H:%M:%SZ")
+            ));
+        }
+        self.record("revisit", &fields, &self.http_head())
+    }
+
+    /// Status line and headers as an HTTP message head
+    fn http_head(&self) -> Vec<u8> {
+        let reason = reqwest::StatusCode::from_u16(self.status_code)
+            .ok()
+            .and_then(|s| s.canonical_reason())
+            .unwrap_or("");
+        let mut head = format!("{} {} {}\r\n", self.http_version, self.status_code, reason);
+        for (name, value) in &self.http_headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str("\r\n");
+        head.into_bytes()
+    }
 
-        format!(
-            "WARC/1.0\r\n\
+    fn record(&self, warc_type: &str, fields: &str, block: &[u8]) -> Vec<u8> {
+        let mut bytes = format!(
+            "{}\r\n\
             WARC-Type: {}\r\n\
             WARC-Record-ID: <urn:uuid:{}>\r\n\
             WARC-Date: {}\r\n\
             WARC-Target-URI: {}\r\n\
+            {}\
+            {}\
             WARC-Payload-Digest: sha256:{}\r\n\
             Content-Type: application/http; msgtype=response\r\n\
             Content-Length: {}\r\n\
             \r\n",
+            WARC_VERSION,
             warc_type,
-            record_id,
-            timestamp,
+            Uuid::new_v4(),
+            self.timestamp.format("%Y-%m-%dT%H:%M:%SZ"),
             self.url,
+            fields,
+            self.capture_headers(),
             self.payload_digest,
-            self.content.len()
+            block.len()
         )
+        .into_bytes();
+        bytes.extend_from_slice(block);
+        bytes.extend_from_slice(b"\r\n\r\n");
+        bytes
+    }
+
+    /// Optional WARC fields describing how the capture was made
+    fn capture_headers(&self) -> String {
+        let mut headers = String::new();
+        if let Some(ip) = self.remote_ip {
+            headers.push_str(&format!("WARC-IP-Address: {}\r\n", ip));
+        }
+        if let Some(tls) = &self.tls {
+            headers.push_str(&format!(
+                "WARC-Peer-Certificate-Digest: sha256:{}\r\n",
+                tls.peer_certificate_sha256
+            ));
+        }
+        if let Some(reason) = &self.truncated {
+            headers.push_str(&format!("WARC-Truncated: {}\r\n", reason));
+        }
+        headers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(status_code: u16, content: &[u8]) -> WarcRecord {
+        WarcRecord {
+            url: "https://example.com/".to_string(),
+            timestamp: Utc::now(),
+            content: content.to_vec(),
+            content_type: "text/html".to_string(),
+            status_code,
+            http_version: "HTTP/1.1".to_string(),
+            http_headers: vec![("content-type".to_string(), "text/html".to_string())],
+            payload_digest: "ab".repeat(32),
+            etag: None,
+            last_modified: None,
+            location: None,
+            remote_ip: None,
+            tls: None,
+            truncated: None,
+        }
+    }
+
+    #[test]
+    fn test_records_use_the_version_of_their_revisit_profiles() {
+        let record = record(304, b"");
+        for bytes in [
+            record.to_warc_bytes(),
+            record.to_revisit_bytes(PROFILE_IDENTICAL_PAYLOAD, None),
+            record.to_revisit_bytes(PROFILE_SERVER_NOT_MODIFIED, Some((&record.url, Utc::now()))),
+        ] {
+            assert!(bytes.starts_with(b"WARC/1.1\r\n"));
+        }
+        assert!(PROFILE_IDENTICAL_PAYLOAD.contains("/warc/1.1/"));
+        assert!(PROFILE_SERVER_NOT_MODIFIED.contains("/warc/1.1/"));
+    }
+
+    #[test]
+    fn test_response_block_is_the_http_message() {
+        let bytes = record(200, b"<p>hi</p>").to_warc_bytes();
+        let text = String::from_utf8(bytes).unwrap();
+        let (header, rest) = text.split_once("\r\n\r\n").unwrap();
+        let block = "HTTP/1.1 200 OK\r\ncontent-type: text/html\r\n\r\n<p>hi</p>";
+        assert!(header.contains(&format!("Content-Length: {}", block.len())));
+        assert_eq!(rest, format!("{}\r\n\r\n", block));
     }
 }
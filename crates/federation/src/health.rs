@@ -0,0 +1,138 @@
+use crate::{Peer, PeerStatus};
+use chrono::{DateTime, Duration, Utc};
+
+/// How peers are probed and when they are given up on
+#[derive(Debug, Clone)]
+pub struct HealthConfig {
+    /// Probe interval for healthy peers, and the base of the failure backoff
+    pub interval_secs: i64,
+    /// Consecutive failed probes before a peer is marked unreachable
+    pub unreachable_after: u32,
+    /// Longest wait between probes of a failing peer
+    pub max_backoff_secs: i64,
+    /// Unreachable peers not seen for this long are forgotten
+    pub remove_after_secs: i64,
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        Self {
+            interval_secs: 60,
+            unreachable_after: 3,
+            max_backoff_secs: 6 * 3600,
+            remove_after_secs: 7 * 86400,
+        }
+    }
+}
+
+impl HealthConfig {
+    pub fn from_env() -> Self {
+        fn var<T: std::str::FromStr>(name: &str, default: T) -> T {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse().ok())
+                .unwrap_or(default)
+        }
+        let defaults = Self::default();
+        Self {
+            interval_secs: var("FEDERATION_PROBE_INTERVAL_SECS", defaults.interval_secs),
+            unreachable_after: var("FEDERATION_UNREACHABLE_AFTER", defaults.unreachable_after),
+            max_backoff_secs: var("FEDERATION_MAX_BACKOFF_SECS", defaults.max_backoff_secs),
+            remove_after_secs: var("FEDERATION_REMOVE_AFTER_SECS", defaults.remove_after_secs),
+        }
+    }
+
+    /// Wait before the next probe after `failures` consecutive failures
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let secs = self
+            .interval_secs
+            .saturating_mul(1i64 << failures.min(30))
+            .min(self.max_backoff_secs.max(self.interval_secs));
+        Duration::seconds(secs)
+    }
+}
+
+/// What to do with a peer after a probe
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProbeOutcome {
+    Keep,
+    Remove,
+}
+
+/// Apply a probe result to `peer`: success revives it; failures back off
+/// exponentially, mark it unreachable and finally drop it
+pub fn record_probe(
+    config: &HealthConfig,
+    peer: &mut Peer,
+    reachable: bool,
+    now: DateTime<Utc>,
+) -> ProbeOutcome {
+    if reachable {
+        peer.failures = 0;
+        peer.last_seen = now;
+        peer.status = PeerStatus::Active;
+        peer.next_probe_at = now + Duration::seconds(config.interval_secs);
+        return ProbeOutcome::Keep;
+    }
+
+    peer.failures = peer.failures.saturating_add(1);
+    if peer.failures >= config.unreachable_after {
+        peer.status = PeerStatus::Unreachable;
+    }
+    if peer.status == PeerStatus::Unreachable
+        && now - peer.last_seen > Duration::seconds(config.remove_after_secs)
+    {
+        return ProbeOutcome::Remove;
+    }
+    peer.next_probe_at = now + config.backoff(peer.failures);
+    ProbeOutcome::Keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failures_back_off_then_mark_unreachable_and_remove() {
+        let config = HealthConfig::default();
+        let start = Utc::now();
+        let mut peer = Peer {
+            id: "a".to_string(),
+            endpoint: "http://a".to_string(),
+            last_seen: start,
+            status: PeerStatus::Active,
+            capabilities: Vec::new(),
+            public_key: String::new(),
+            failures: 0,
+            next_probe_at: start,
+        };
+
+        assert_eq!(
+            record_probe(&config, &mut peer, false, start),
+            ProbeOutcome::Keep
+        );
+        assert_eq!(peer.status, PeerStatus::Active);
+        assert_eq!(peer.next_probe_at - start, Duration::seconds(120));
+        record_probe(&config, &mut peer, false, start);
+        record_probe(&config, &mut peer, false, start);
+        assert_eq!(peer.status, PeerStatus::Unreachable);
+        assert_eq!(peer.next_probe_at - start, Duration::seconds(480));
+
+        for _ in 0..20 {
+            record_probe(&config, &mut peer, false, start);
+        }
+        assert_eq!(
+            peer.next_probe_at - start,
+            Duration::seconds(config.max_backoff_secs)
+        );
+
+        let later = start + Duration::days(8);
+        assert_eq!(
+            record_probe(&config, &mut peer, false, later),
+            ProbeOutcome::Remove
+        );
+
+        record_probe(&config, &mut peer, true, later);
+        assert_eq!((peer.status, peer.failures), (PeerStatus::Active, 0));
+    }
+}
@@ -0,0 +1,277 @@
+use anyhow::{anyhow, bail, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chrono::Utc;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use std::io::Write;
+use std::path::Path;
+
+/// Header carrying the signing node's id
+pub const NODE_ID_HEADER: &str = "x-archivestream-node";
+/// Header carrying the Unix time the request was signed at
+pub const TIMESTAMP_HEADER: &str = "x-archivestream-timestamp";
+/// Header carrying the base64 Ed25519 signature of the request
+pub const SIGNATURE_HEADER: &str = "x-archivestream-signature";
+
+/// Signed requests older or newer than this are rejected
+pub const MAX_CLOCK_SKEW_SECS: i64 = 300;
+
+/// Node id for a public key: hex SHA-256 of the raw key bytes
+pub fn node_id_for(public_key: &VerifyingKey) -> String {
+    format!("{:x}", Sha256::digest(public_key.as_bytes()))
+}
+
+pub fn decode_public_key(public_key: &str) -> Result<VerifyingKey> {
+    let bytes: [u8; 32] = BASE64
+        .decode(public_key)?
+        .try_into()
+        .map_err(|_| anyhow!("public key must be 32 bytes"))?;
+    Ok(VerifyingKey::from_bytes(&bytes)?)
+}
+
+/// Verify a base64 `signature` of `message` by a base64 `public_key`
+pub fn verify(public_key: &str, message: &[u8], signature: &str) -> Result<()> {
+    let key = decode_public_key(public_key)?;
+    let signature = Signature::from_slice(&BASE64.decode(signature)?)?;
+    key.verify(message, &signature)
+        .map_err(|_| anyhow!("invalid signature"))
+}
+
+/// Bytes a handshake proof signs: the challenge, bound to both nodes so a
+/// proof cannot be replayed against another peer
+pub fn handshake_message(challenge: &str, signer: &str, verifier: &str) -> Vec<u8> {
+    format!(
+        "archivestream-handshake\n{}\n{}\n{}",
+        challenge, signer, verifier
+    )
+    .into_bytes()
+}
+
+/// Bytes a request signature covers
+pub fn request_message(method: &str, path: &str, timestamp: i64, body: &[u8]) -> Vec<u8> {
+    format!(
+        "{}\n{}\n{}\n{:x}",
+        method.to_ascii_uppercase(),
+        path,
+        timestamp,
+        Sha256::digest(body)
+    )
+    .into_bytes()
+}
+
+/// Bytes a Merkle manifest signature covers; each manifest names the root
+/// before it, so the signed roots form a chain nothing can drop out of
+pub fn manifest_message(
+    signer: &str,
+    created_at: &str,
+    tree_size: i64,
+    root: &str,
+    previous_root: &str,
+) -> Vec<u8> {
+    format!(
+        "archivestream-merkle-manifest\n{}\n{}\n{}\n{}\n{}",
+        signer, created_at, tree_size, root, previous_root
+    )
+    .into_bytes()
+}
+
+/// Random base64 challenge for a handshake
+pub fn new_challenge() -> String {
+    let mut nonce = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut nonce);
+    BASE64.encode(nonce)
+}
+
+/// Check a signed request from the holder of `public_key`
+pub fn verify_request(
+    public_key: &str,
+    method: &str,
+    path: &str,
+    timestamp: i64,
+    body: &[u8],
+    signature: &str,
+) -> Result<()> {
+    if (Utc::now().timestamp() - timestamp).abs() > MAX_CLOCK_SKEW_SECS {
+        bail!("request timestamp outside the allowed clock skew");
+    }
+    verify(
+        public_key,
+        &request_message(method, path, timestamp, body),
+        signature,
+    )
+}
+
+#[cfg(unix)]
+fn check_key_permissions(path: &Path) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let mode = std::fs::metadata(path)?.permissions().mode();
+    if mode & 0o077 != 0 {
+        bail!(
+            "identity key {} is readable by group or others (mode {:o}); run chmod 600 on it",
+            path.display(),
+            mode & 0o777
+        );
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_key_permissions(_path: &Path) -> Result<()> {
+    Ok(())
+}
+
+/// The Ed25519 keypair a node is known by
+pub struct NodeIdentity {
+    key: SigningKey,
+}
+
+impl NodeIdentity {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from_bytes(&seed),
+        }
+    }
+
+    pub fn generate() -> Self {
+        let mut seed = [0u8; 32];
+        rand::rngs::OsRng.fill_bytes(&mut seed);
+        Self::from_seed(seed)
+    }
+
+    /// Load the hex seed at `path`, creating a new identity there if missing.
+    /// The file is created readable by its owner only, and a key file that
+    /// group or others can read is refused
+    pub fn load_or_generate(path: &Path) -> Result<Self> {
+        if path.exists() {
+            check_key_permissions(path)?;
+            let seed: [u8; 32] = hex::decode(std::fs::read_to_string(path)?.trim())?
+                .try_into()
+                .map_err(|_| anyhow!("identity key must be 32 bytes of hex"))?;
+            return Ok(Self::from_seed(seed));
+        }
+        let identity = Self::generate();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let mut options = std::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options
+            .open(path)?
+            .write_all(hex::encode(identity.key.to_bytes()).as_bytes())?;
+        Ok(identity)
+    }
+
+    /// Identity from `FEDERATION_IDENTITY_KEY` (hex seed) or the file at
+    /// `FEDERATION_IDENTITY_FILE`
+    pub fn from_env() -> Result<Self> {
+        if let Ok(seed) = std::env::var("FEDERATION_IDENTITY_KEY") {
+            let seed: [u8; 32] = hex::decode(seed.trim())?
+                .try_into()
+                .map_err(|_| anyhow!("FEDERATION_IDENTITY_KEY must be 32 bytes of hex"))?;
+            return Ok(Self::from_seed(seed));
+        }
+        let path = std::env::var("FEDERATION_IDENTITY_FILE")
+            .unwrap_or_else(|_| "data/federation/identity.key".into());
+        Self::load_or_generate(Path::new(&path))
+    }
+
+    pub fn node_id(&self) -> String {
+        node_id_for(&self.key.verifying_key())
+    }
+
+    pub fn public_key(&self) -> String {
+        BASE64.encode(self.key.verifying_key().as_bytes())
+    }
+
+    /// Base64 signature of `message`
+    pub fn sign(&self, message: &[u8]) -> String {
+        BASE64.encode(self.key.sign(message).to_bytes())
+    }
+
+    /// Headers authenticating a request to a peer
+    pub fn sign_request(
+        &self,
+        method: &str,
+        path: &str,
+        body: &[u8],
+    ) -> [(&'static str, String); 3] {
+        let timestamp = Utc::now().timestamp();
+        [
+            (NODE_ID_HEADER, self.node_id()),
+            (TIMESTAMP_HEADER, timestamp.to_string()),
+            (
+                SIGNATURE_HEADER,
+                self.sign(&request_message(method, path, timestamp, body)),
+            ),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_id_binds_key_and_signatures_verify() {
+        let node = NodeIdentity::from_seed([1u8; 32]);
+        let other = NodeIdentity::from_seed([2u8; 32]);
+        assert_eq!(
+            node.node_id(),
+            node_id_for(&decode_public_key(&node.public_key()).unwrap())
+        );
+        assert_ne!(node.node_id(), other.node_id());
+
+        let message = handshake_message("nonce", &node.node_id(), &other.node_id());
+        let proof = node.sign(&message);
+        assert!(verify(&node.public_key(), &message, &proof).is_ok());
+        assert!(verify(&other.public_key(), &message, &proof).is_err());
+        let replayed = handshake_message("nonce", &node.node_id(), "someone-else");
+        assert!(verify(&node.public_key(), &replayed, &proof).is_err());
+    }
+
+    #[test]
+    fn test_signed_requests_cover_path_body_and_time() {
+        let node = NodeIdentity::from_seed([3u8; 32]);
+        let headers = node.sign_request("get", "/api/v1/federation/manifest?limit=50", b"");
+        let timestamp: i64 = headers[1].1.parse().unwrap();
+        let signature = &headers[2].1;
+
+        let check = |path: &str, timestamp: i64, body: &[u8]| {
+            verify_request(&node.public_key(), "GET", path, timestamp, body, signature)
+        };
+        assert!(check("/api/v1/federation/manifest?limit=50", timestamp, b"").is_ok());
+        assert!(check("/api/v1/federation/manifest?limit=5000", timestamp, b"").is_err());
+        assert!(check("/api/v1/federation/manifest?limit=50", timestamp, b"x").is_err());
+
+        let stale = Utc::now().timestamp() - MAX_CLOCK_SKEW_SECS - 1;
+        let stale_headers = request_message("GET", "/", stale, b"");
+        let stale_signature = node.sign(&stale_headers);
+        assert!(
+            verify_request(&node.public_key(), "GET", "/", stale, b"", &stale_signature).is_err()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_key_file_is_private_and_shared_keys_are_refused() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir =
+            std::env::temp_dir().join(format!("archivestream-identity-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("identity.key");
+
+        let node = NodeIdentity::load_or_generate(&path).unwrap();
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(
+            NodeIdentity::load_or_generate(&path).unwrap().node_id(),
+            node.node_id()
+        );
+
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(NodeIdentity::load_or_generate(&path).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,208 @@
+use archive_common::surt::surt;
+use archive_common::Snapshot;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Peer id of the policy applied to peers without one of their own
+pub const DEFAULT_PEER: &str = "*";
+
+/// Whether a policy governs what we pull from a peer or what it may pull from us
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Direction {
+    Pull,
+    Push,
+}
+
+impl Direction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pull => "pull",
+            Self::Push => "push",
+        }
+    }
+
+    pub fn parse(direction: &str) -> Option<Self> {
+        match direction {
+            "pull" => Some(Self::Pull),
+            "push" => Some(Self::Push),
+            _ => None,
+        }
+    }
+}
+
+/// Which captures are replicated; empty lists and unset bounds admit everything
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReplicationFilter {
+    /// Hosts, including their subdomains
+    #[serde(default)]
+    pub hosts: Vec<String>,
+    /// SURT prefixes such as `com,example)/news`; a URL in scope of either
+    /// `hosts` or `surt_prefixes` is admitted
+    #[serde(default)]
+    pub surt_prefixes: Vec<String>,
+    /// Collection ids on the serving node
+    #[serde(default)]
+    pub collections: Vec<String>,
+    /// MIME types such as `text/html`, or `image/*`
+    #[serde(default)]
+    pub mime_types: Vec<String>,
+    #[serde(default)]
+    pub from: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub to: Option<DateTime<Utc>>,
+    /// Largest WARC record to replicate
+    #[serde(default)]
+    pub max_record_bytes: Option<i64>,
+}
+
+impl ReplicationFilter {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Whether `snapshot` is in scope; `collections` is `None` when
+    /// membership is unknown, which leaves that criterion to the serving node
+    pub fn admits(&self, snapshot: &Snapshot, collections: Option<&[String]>) -> bool {
+        self.admits_url(&snapshot.url)
+            && self.admits_mime(&snapshot.content_type)
+            && self.from.is_none_or(|from| snapshot.timestamp >= from)
+            && self.to.is_none_or(|to| snapshot.timestamp <= to)
+            && self
+                .max_record_bytes
+                .is_none_or(|max| snapshot.length <= max)
+            && match collections {
+                Some(member_of) if !self.collections.is_empty() => {
+                    self.collections.iter().any(|c| member_of.contains(c))
+                }
+                _ => true,
+            }
+    }
+
+    fn admits_url(&self, url: &str) -> bool {
+        if self.hosts.is_empty() && self.surt_prefixes.is_empty() {
+            return true;
+        }
+        let host = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(|h| h.to_ascii_lowercase()))
+            .unwrap_or_default();
+        let in_hosts = self.hosts.iter().any(|h| {
+            let h = h.to_ascii_lowercase();
+            host == h || host.ends_with(&format!(".{}", h))
+        });
+        let key = surt(url);
+        in_hosts
+            || self
+                .surt_prefixes
+                .iter()
+                .any(|prefix| key.starts_with(&prefix.to_ascii_lowercase()))
+    }
+
+    fn admits_mime(&self, content_type: &str) -> bool {
+        if self.mime_types.is_empty() {
+            return true;
+        }
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        self.mime_types.iter().any(|m| {
+            let m = m.to_ascii_lowercase();
+            match m.strip_suffix("/*") {
+                Some(kind) => mime.split('/').next() == Some(kind),
+                None => mime == m,
+            }
+        })
+    }
+}
+
+/// What is replicated with a peer in one direction, and how much of it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReplicationPolicy {
+    /// A node id, or [`DEFAULT_PEER`]
+    pub peer_id: String,
+    pub direction: Direction,
+    #[serde(default)]
+    pub filter: ReplicationFilter,
+    /// Record bytes transferred per hour; unlimited when unset
+    #[serde(default)]
+    pub bandwidth_bytes_per_hour: Option<i64>,
+    /// Bytes of payload records stored from the peer; pull only
+    #[serde(default)]
+    pub storage_quota_bytes: Option<i64>,
+}
+
+/// Whether `bytes` more fit a budget of which `used` is spent; the first
+/// transfer of a window always does, so one oversized record cannot stall
+/// replication for good
+pub fn within_budget(budget: Option<i64>, used: i64, bytes: i64) -> bool {
+    budget.is_none_or(|budget| used == 0 || used + bytes <= budget)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn snapshot(url: &str, content_type: &str, length: i64) -> Snapshot {
+        Snapshot {
+            id: Uuid::nil(),
+            url: url.to_string(),
+            timestamp: DateTime::parse_from_rfc3339("2026-01-10T00:00:00Z")
+                .unwrap()
+                .with_timezone(&Utc),
+            warc_file: "a.warc".to_string(),
+            offset: 0,
+            length,
+            sha256: String::new(),
+            status_code: 200,
+            content_type: content_type.to_string(),
+            payload_hash: None,
+        }
+    }
+
+    #[test]
+    fn test_filter_scopes_urls_types_dates_sizes_and_collections() {
+        let filter = ReplicationFilter {
+            hosts: vec!["news.example".into()],
+            surt_prefixes: vec!["org,example)/press".into()],
+            collections: vec!["elections".into()],
+            mime_types: vec!["text/html".into(), "image/*".into()],
+            from: Some(
+                DateTime::parse_from_rfc3339("2026-01-01T00:00:00Z")
+                    .unwrap()
+                    .with_timezone(&Utc),
+            ),
+            to: None,
+            max_record_bytes: Some(1000),
+        };
+        let html = "text/html; charset=utf-8";
+
+        assert!(filter.admits(&snapshot("https://news.example/a", html, 10), None));
+        assert!(filter.admits(
+            &snapshot("https://www.news.example/", "image/png", 10),
+            None
+        ));
+        assert!(filter.admits(&snapshot("https://www.example.org/press/1", html, 10), None));
+        assert!(!filter.admits(&snapshot("https://example.org/blog", html, 10), None));
+        assert!(!filter.admits(
+            &snapshot("https://news.example/a", "application/pdf", 10),
+            None
+        ));
+        assert!(!filter.admits(&snapshot("https://news.example/a", html, 5000), None));
+
+        let member = ["elections".to_string()];
+        let other = ["sports".to_string()];
+        let capture = snapshot("https://news.example/a", html, 10);
+        assert!(filter.admits(&capture, Some(&member)));
+        assert!(!filter.admits(&capture, Some(&other)));
+
+        assert!(ReplicationFilter::default().admits(&snapshot("x", "", 1 << 40), None));
+        assert!(within_budget(Some(100), 0, 500));
+        assert!(within_budget(Some(100), 60, 40));
+        assert!(!within_budget(Some(100), 60, 41));
+    }
+}
@@ -0,0 +1,154 @@
+use archive_common::surt::surt;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+/// How a peer answered a federated query
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum PeerSearchStatus {
+    Ok,
+    Timeout,
+    Error,
+}
+
+/// A node holding a merged capture, with its own snapshot id
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Holder {
+    pub node_id: String,
+    pub snapshot_id: Value,
+}
+
+/// One capture in a merged result list
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MergedHit {
+    pub url: String,
+    pub timestamp: Value,
+    pub title: Value,
+    pub snippet: Value,
+    /// Best normalized score across the nodes, in `0..=1`
+    pub score: f64,
+    /// Nodes holding the capture, best scoring first
+    pub nodes: Vec<Holder>,
+}
+
+/// Scores of one node's hits scaled to `0..=1` by its best hit, so engines
+/// with different scales interleave; hits without a score are ranked by
+/// position instead
+pub fn normalize_scores(hits: &[Value]) -> Vec<f64> {
+    let scores: Vec<Option<f64>> = hits.iter().map(|h| h["score"].as_f64()).collect();
+    let max = scores.iter().flatten().cloned().fold(0.0, f64::max);
+    if max > 0.0 && scores.iter().all(Option::is_some) {
+        return scores.into_iter().flatten().map(|s| s / max).collect();
+    }
+    let n = hits.len() as f64;
+    (0..hits.len()).map(|rank| (n - rank as f64) / n).collect()
+}
+
+/// Capture identity across nodes: canonical URL and the second it was taken
+fn capture_key(hit: &Value) -> Option<(String, String)> {
+    let url = hit["url"].as_str()?;
+    let timestamp = match &hit["timestamp"] {
+        Value::String(ts) => DateTime::parse_from_rfc3339(ts)
+            .map(|t| t.with_timezone(&Utc).format("%Y%m%d%H%M%S").to_string())
+            .unwrap_or_else(|_| ts.clone()),
+        other => other.to_string(),
+    };
+    Some((surt(url), timestamp))
+}
+
+/// Interleave the hits of several nodes by normalized score, merging the
+/// same capture held by more than one node
+pub fn merge(sources: Vec<(String, Vec<Value>)>, limit: usize) -> Vec<MergedHit> {
+    let mut merged: Vec<MergedHit> = Vec::new();
+    let mut by_key: HashMap<(String, String), usize> = HashMap::new();
+
+    for (node_id, hits) in sources {
+        let scores = normalize_scores(&hits);
+        for (hit, score) in hits.into_iter().zip(scores) {
+            let Some(key) = capture_key(&hit) else {
+                continue;
+            };
+            let holder = Holder {
+                node_id: node_id.clone(),
+                snapshot_id: hit["snapshot_id"].clone(),
+            };
+            match by_key.get(&key) {
+                Some(&i) => {
+                    let entry = &mut merged[i];
+                    if entry.nodes.iter().any(|h| h.node_id == node_id) {
+                        continue;
+                    }
+                    if score > entry.score {
+                        entry.score = score;
+                        entry.title = hit["title"].clone();
+                        entry.snippet = hit["snippet"].clone();
+                        entry.nodes.insert(0, holder);
+                    } else {
+                        entry.nodes.push(holder);
+                    }
+                }
+                None => {
+                    by_key.insert(key, merged.len());
+                    merged.push(MergedHit {
+                        url: hit["url"].as_str().unwrap_or_default().to_string(),
+                        timestamp: hit["timestamp"].clone(),
+                        title: hit["title"].clone(),
+                        snippet: hit["snippet"].clone(),
+                        score,
+                        nodes: vec![holder],
+                    });
+                }
+            }
+        }
+    }
+
+    // Stable, so ties keep source order with local hits first
+    merged.sort_by(|a, b| b.score.total_cmp(&a.score));
+    merged.truncate(limit);
+    merged
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_merge_interleaves_and_dedupes_across_nodes() {
+        let local = vec![
+            json!({"snapshot_id": "l1", "url": "https://example.com/a", "timestamp": "2026-01-01T00:00:00Z", "score": 10.0}),
+            json!({"snapshot_id": "l2", "url": "https://example.com/b", "timestamp": "2026-01-01T00:00:00Z", "score": 5.0}),
+        ];
+        let remote = vec![
+            json!({"snapshot_id": "r1", "url": "http://www.example.com/b", "timestamp": "2026-01-01T00:00:00.000Z", "score": 0.9}),
+            json!({"snapshot_id": "r2", "url": "https://example.com/c", "timestamp": "2026-01-02T00:00:00Z", "score": 0.3}),
+        ];
+
+        let hits = merge(vec![("local".into(), local), ("peer".into(), remote)], 10);
+        let urls: Vec<_> = hits.iter().map(|h| h.url.as_str()).collect();
+        assert_eq!(
+            urls,
+            [
+                "https://example.com/a",
+                "https://example.com/b",
+                "https://example.com/c"
+            ]
+        );
+        assert_eq!(hits[1].score, 1.0);
+        let nodes: Vec<_> = hits[1].nodes.iter().map(|h| h.node_id.as_str()).collect();
+        assert_eq!(nodes, ["peer", "local"]);
+        assert!((hits[2].score - 1.0 / 3.0).abs() < 1e-9);
+
+        assert_eq!(merge(vec![("x".into(), hits_without_scores())], 1).len(), 1);
+        assert_eq!(normalize_scores(&hits_without_scores()), [1.0, 0.5]);
+    }
+
+    fn hits_without_scores() -> Vec<Value> {
+        vec![
+            json!({"url": "https://example.com/1", "timestamp": "2026-01-01T00:00:00Z"}),
+            json!({"url": "https://example.com/2", "timestamp": "2026-01-01T00:00:00Z"}),
+        ]
+    }
+}
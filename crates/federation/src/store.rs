@@ -0,0 +1,194 @@
+use crate::{Peer, PeerStatus};
+use anyhow::Result;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use sqlx::PgPool;
+
+/// Durable record of known peers, so the federation survives restarts
+#[async_trait]
+pub trait PeerStore: Send + Sync {
+    async fn load(&self) -> Result<Vec<Peer>>;
+
+    /// Insert or replace a peer
+    async fn save(&self, peer: &Peer) -> Result<()>;
+
+    async fn remove(&self, id: &str) -> Result<()>;
+}
+
+impl PeerStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Active => "active",
+            Self::Unreachable => "unreachable",
+            Self::Banned => "banned",
+        }
+    }
+
+    pub fn parse(status: &str) -> Self {
+        match status {
+            "unreachable" => Self::Unreachable,
+            "banned" => Self::Banned,
+            _ => Self::Active,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct PeerRow {
+    id: String,
+    endpoint: String,
+    public_key: String,
+    status: String,
+    capabilities: Vec<String>,
+    last_seen: DateTime<Utc>,
+    failures: i32,
+    next_probe_at: DateTime<Utc>,
+}
+
+pub struct PgPeerStore {
+    pool: PgPool,
+}
+
+impl PgPeerStore {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl PeerStore for PgPeerStore {
+    async fn load(&self) -> Result<Vec<Peer>> {
+        let rows = sqlx::query_as::<_, PeerRow>(
+            r#"
+            SELECT id, endpoint, public_key, status, capabilities, last_seen, failures, next_probe_at
+            FROM federation_peers
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(rows
+            .into_iter()
+            .map(|row| Peer {
+                id: row.id,
+                endpoint: row.endpoint,
+                last_seen: row.last_seen,
+                status: PeerStatus::parse(&row.status),
+                capabilities: row.capabilities,
+                public_key: row.public_key,
+                failures: row.failures.max(0) as u32,
+                next_probe_at: row.next_probe_at,
+            })
+            .collect())
+    }
+
+    async fn save(&self, peer: &Peer) -> Result<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO federation_peers (id, endpoint, public_key, status, capabilities, last_seen, failures, next_probe_at, updated_at)
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, NOW())
+            ON CONFLICT (id) DO UPDATE SET
+                endpoint = EXCLUDED.endpoint,
+                public_key = EXCLUDED.public_key,
+                status = EXCLUDED.status,
+                capabilities = EXCLUDED.capabilities,
+                last_seen = EXCLUDED.last_seen,
+                failures = EXCLUDED.failures,
+                next_probe_at = EXCLUDED.next_probe_at,
+                updated_at = NOW()
+            "#,
+        )
+        .bind(&peer.id)
+        .bind(&peer.endpoint)
+        .bind(&peer.public_key)
+        .bind(peer.status.as_str())
+        .bind(&peer.capabilities)
+        .bind(peer.last_seen)
+        .bind(peer.failures as i32)
+        .bind(peer.next_probe_at)
+        .execute(&self.pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<()> {
+        sqlx::query("DELETE FROM federation_peers WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// In-process store for tests and single-run tools
+#[derive(Default)]
+pub struct MemoryPeerStore {
+    peers: DashMap<String, Peer>,
+}
+
+#[async_trait]
+impl PeerStore for MemoryPeerStore {
+    async fn load(&self) -> Result<Vec<Peer>> {
+        Ok(self.peers.iter().map(|p| p.value().clone()).collect())
+    }
+
+    async fn save(&self, peer: &Peer) -> Result<()> {
+        self.peers.insert(peer.id.clone(), peer.clone());
+        Ok(())
+    }
+
+    async fn remove(&self, id: &str) -> Result<()> {
+        self.peers.remove(id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use archive_common::testing::scratch_pool;
+
+    #[tokio::test]
+    async fn test_pg_store_saves_updates_and_removes_peers() {
+        let Some(pool) = scratch_pool().await else {
+            return;
+        };
+        let store = PgPeerStore::new(pool);
+        let seen = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let mut peer = Peer {
+            id: "node-a".to_string(),
+            endpoint: "https://a.example".to_string(),
+            last_seen: seen,
+            status: PeerStatus::Active,
+            capabilities: vec!["search".to_string(), "sync".to_string()],
+            public_key: "key-a".to_string(),
+            failures: 0,
+            next_probe_at: seen,
+        };
+        store.save(&peer).await.unwrap();
+
+        // Saving again replaces the peer's state
+        peer.status = PeerStatus::Unreachable;
+        peer.failures = 3;
+        peer.next_probe_at = seen + chrono::Duration::hours(1);
+        store.save(&peer).await.unwrap();
+        let loaded = store.load().await.unwrap();
+        assert_eq!(loaded.len(), 1);
+        let loaded = &loaded[0];
+        assert_eq!(
+            (&loaded.id, &loaded.endpoint, &loaded.public_key),
+            (&peer.id, &peer.endpoint, &peer.public_key)
+        );
+        assert_eq!(loaded.status, PeerStatus::Unreachable);
+        assert_eq!(loaded.capabilities, peer.capabilities);
+        assert_eq!(loaded.failures, 3);
+        assert_eq!(
+            (loaded.last_seen, loaded.next_probe_at),
+            (seen, peer.next_probe_at)
+        );
+
+        store.remove("node-a").await.unwrap();
+        assert!(store.load().await.unwrap().is_empty());
+    }
+}
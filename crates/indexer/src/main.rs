@@ -2,6 +2,7 @@ mod opensearch_client;
 
 use archive_common::extractor;
 
+use archive_common::storage::ArchiveStorage;
 use archive_common::Snapshot;
 use opensearch_client::SearchClient;
 use serde_json::json;
@@ -21,12 +22,13 @@ async fn main() -> anyhow::Result<()> {
 
     let pool = PgPool::connect(&database_url).await?;
     let search_client = SearchClient::new(&opensearch_url)?;
+    let storage = archive_common::storage::from_env()?;
 
     search_client.ensure_index().await?;
     info!("Indexer started, connected to DB and OpenSearch");
 
     loop {
-        match process_pending_snapshots(&pool, &search_client).await {
+        match process_pending_snapshots(&pool, &search_client, storage.as_ref()).await {
             Ok(count) if count > 0 => info!("Indexed {} snapshots", count),
             Err(e) => error!("Indexer error: {}", e),
             _ => (),
@@ -38,6 +40,7 @@ async fn main() -> anyhow::Result<()> {
 async fn process_pending_snapshots(
     pool: &PgPool,
     search_client: &SearchClient,
+    storage: &dyn ArchiveStorage,
 ) -> anyhow::Result<usize> {
     // 1. Get unindexed HTML snapshots
     // Note: In real setup, we'd need to add 'indexed_at' column to DB.
@@ -51,7 +54,10 @@ async fn process_pending_snapshots(
                s.payload_hash
         FROM snapshots s
         LEFT JOIN payloads p ON s.payload_hash = p.hash
+        LEFT JOIN index_queue q ON q.snapshot_id = s.id
         WHERE s.content_type LIKE '%html%'
+        -- Explicitly queued snapshots (e.g. imports) go first
+        ORDER BY q.enqueued_at ASC NULLS LAST
         LIMIT 10
         "#,
     )
@@ -60,19 +66,22 @@ async fn process_pending_snapshots(
 
     let mut docs = Vec::new();
     let count = snapshots.len();
+    let ids: Vec<_> = snapshots.iter().map(|s| s.id).collect();
 
-    // In a real implementation, we would load the WARC bytes here.
-    // Since we are simulating, we'll index with mock labels if indexing fails.
     for snapshot in snapshots {
         let domain = Url::parse(&snapshot.url)
             .ok()
             .and_then(|u| u.domain().map(|d| d.to_string()))
             .unwrap_or_default();
 
-        // Simplified for MVP: In real life, fetch bytes from S3 here
-        let extracted = extractor::extract_text(
-            "<html><title>Sample</title><body>Sample content for indexing</body></html>",
-        );
+        let html = match read_html(storage, &snapshot).await {
+            Ok(html) => html,
+            Err(e) => {
+                error!("Failed to read {} from storage: {}", snapshot.id, e);
+                String::new()
+            }
+        };
+        let extracted = extractor::extract_text(&html);
 
         docs.push(json!({
             "snapshot_id": snapshot.id,
@@ -87,8 +96,34 @@ async fn process_pending_snapshots(
 
     search_client.index_snapshots(docs).await?;
 
+    sqlx::query("DELETE FROM index_queue WHERE snapshot_id = ANY($1)")
+        .bind(&ids)
+        .execute(pool)
+        .await?;
+
     // 2. Mark as indexed
     // sqlx::query!("UPDATE snapshots SET indexed_at = NOW() WHERE id = ANY($1)", ids).execute(pool).await?;
 
     Ok(count)
 }
+
+/// The HTTP body of a snapshot's WARC record
+async fn read_html(storage: &dyn ArchiveStorage, snapshot: &Snapshot) -> anyhow::Result<String> {
+    let record = storage
+        .get_range(
+            &snapshot.warc_file,
+            snapshot.offset as u64,
+            snapshot.length as u64,
+        )
+        .await?;
+    // Skip the WARC headers, then the HTTP headers
+    let mut body = &record[..];
+    for _ in 0..2 {
+        let end = body
+            .windows(4)
+            .position(|w| w == b"\r\n\r\n")
+            .ok_or_else(|| anyhow::anyhow!("truncated WARC record"))?;
+        body = &body[end + 4..];
+    }
+    Ok(String::from_utf8_lossy(body).into_owned())
+}
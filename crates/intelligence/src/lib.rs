@@ -3,6 +3,10 @@ use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use sqlx::FromRow;
 
+pub mod scheduler;
+
+pub use scheduler::{ChangePrior, PoissonPredictor, PredictionContext, SchedulerConfig};
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AnalysisResult {
     pub summary: Option<String>,
@@ -34,7 +38,10 @@ pub struct CrawlPrediction {
     pub next_fetch_at: DateTime<Utc>,
     pub recommended_priority: i32,
     pub confidence: f32,
+    /// Probability the page changes before `next_fetch_at`
     pub change_probability: f32,
+    /// Estimated changes per day
+    pub change_rate_per_day: f32,
 }
 
 #[async_trait]
@@ -44,12 +51,28 @@ pub trait PredictiveEngine: Send + Sync {
         &self,
         history: &[SnapshotHistory],
     ) -> anyhow::Result<CrawlPrediction>;
+
+    /// Predict with per-URL context such as a domain prior or a job's freshness
+    /// target. Engines that cannot use it fall back to `predict_next_crawl`.
+    async fn predict_with_context(
+        &self,
+        history: &[SnapshotHistory],
+        ctx: &PredictionContext,
+    ) -> anyhow::Result<CrawlPrediction> {
+        let mut prediction = self.predict_next_crawl(history).await?;
+        prediction.url = ctx.url.clone();
+        Ok(prediction)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, FromRow)]
 pub struct SnapshotHistory {
     pub timestamp: DateTime<Utc>,
     pub content_hash: String,
+    /// SimHash similarity to the previous capture, when both were text
+    #[sqlx(default)]
+    #[serde(default)]
+    pub similarity: Option<f32>,
 }
 
 /// A pass-through engine that uses our existing regex-based logic
@@ -268,6 +291,7 @@ impl PredictiveEngine for StandardPredictor {
                 recommended_priority: 5,
                 confidence: 0.2,
                 change_probability: 0.5,
+                change_rate_per_day: 1.0 / 7.0,
             });
         }
 
@@ -298,6 +322,7 @@ impl PredictiveEngine for StandardPredictor {
                 recommended_priority: 1,
                 confidence: 0.6,
                 change_probability: 0.1,
+                change_rate_per_day: 0.0,
             });
         }
 
@@ -313,6 +338,7 @@ impl PredictiveEngine for StandardPredictor {
             recommended_priority: (changes * 2).min(10),
             confidence: (changes as f32 / history.len() as f32).min(0.9),
             change_probability: 0.8,
+            change_rate_per_day: 86_400.0 / avg_change_interval.num_seconds().max(1) as f32,
         })
     }
 }
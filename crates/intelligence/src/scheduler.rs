@@ -0,0 +1,336 @@
+use crate::{CrawlPrediction, PredictiveEngine, SnapshotHistory};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+const SECS_PER_DAY: f64 = 86_400.0;
+
+/// Gamma prior over a page's change rate: `alpha` pseudo-changes observed over
+/// `beta_secs` pseudo-seconds of exposure
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChangePrior {
+    pub alpha: f64,
+    pub beta_secs: f64,
+}
+
+impl Default for ChangePrior {
+    /// About one change per week, weighted like a week of observation
+    fn default() -> Self {
+        Self {
+            alpha: 1.0,
+            beta_secs: 7.0 * SECS_PER_DAY,
+        }
+    }
+}
+
+impl ChangePrior {
+    /// Prior centred on a domain's pooled change rate, smoothed towards the
+    /// default, carrying `weight` worth of exposure
+    pub fn from_domain_stats(changes: f64, exposure_secs: f64, weight: Duration) -> Self {
+        let base = Self::default();
+        let rate = (changes.max(0.0) + base.alpha) / (exposure_secs.max(0.0) + base.beta_secs);
+        let weight_secs = weight.num_seconds().max(1) as f64;
+        Self {
+            alpha: rate * weight_secs,
+            beta_secs: weight_secs,
+        }
+    }
+
+    pub fn rate_per_sec(&self) -> f64 {
+        self.alpha / self.beta_secs
+    }
+}
+
+/// Per-URL inputs beyond the observation history
+#[derive(Debug, Clone, Default)]
+pub struct PredictionContext {
+    pub url: String,
+    /// Prior for the URL's domain; the scheduler default applies when `None`
+    pub prior: Option<ChangePrior>,
+    /// Job-level freshness target; the scheduler default applies when `None`
+    pub freshness_target: Option<f64>,
+}
+
+/// Bounds and defaults for [`PoissonPredictor`]
+#[derive(Debug, Clone)]
+pub struct SchedulerConfig {
+    pub min_revisit: Duration,
+    pub max_revisit: Duration,
+    /// Desired average probability that the archived copy matches the live page
+    pub freshness_target: f64,
+    pub default_prior: ChangePrior,
+    /// Hash changes at least this similar to the previous capture are not counted
+    pub near_duplicate_similarity: f32,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            min_revisit: Duration::hours(1),
+            max_revisit: Duration::days(30),
+            freshness_target: 0.8,
+            default_prior: ChangePrior::default(),
+            near_duplicate_similarity: 1.0 - 3.0 / 64.0,
+        }
+    }
+}
+
+impl SchedulerConfig {
+    /// Reads `RECRAWL_MIN_SECS`, `RECRAWL_MAX_SECS`, `RECRAWL_FRESHNESS_TARGET`
+    /// and the crawler's `NEAR_DUP_MAX_DISTANCE`
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        let secs = |name: &str| {
+            std::env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .map(Duration::seconds)
+        };
+        if let Some(min) = secs("RECRAWL_MIN_SECS") {
+            config.min_revisit = min;
+        }
+        if let Some(max) = secs("RECRAWL_MAX_SECS") {
+            config.max_revisit = max;
+        }
+        if let Some(target) = std::env::var("RECRAWL_FRESHNESS_TARGET")
+            .ok()
+            .and_then(|v| v.parse::<f64>().ok())
+        {
+            config.freshness_target = target;
+        }
+        if let Some(distance) = std::env::var("NEAR_DUP_MAX_DISTANCE")
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+        {
+            config.near_duplicate_similarity = 1.0 - distance.min(64) as f32 / 64.0;
+        }
+        config.max_revisit = config.max_revisit.max(config.min_revisit);
+        config
+    }
+}
+
+/// Estimates each page's change rate as a Poisson process and picks the
+/// revisit interval that meets a freshness target.
+///
+/// Consecutive observations give `n` intervals of which `x` saw a change. A
+/// single detected change may hide several, so `x` is bias-corrected with the
+/// Cho & Garcia-Molina estimator before the Gamma-Poisson update against the
+/// domain prior. Hash changes the crawler flagged as near-duplicates by SimHash
+/// similarity do not count.
+pub struct PoissonPredictor {
+    config: SchedulerConfig,
+}
+
+impl PoissonPredictor {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Self { config }
+    }
+
+    pub fn from_env() -> Self {
+        Self::new(SchedulerConfig::from_env())
+    }
+
+    /// Posterior change rate (changes per second) and the number of intervals observed
+    pub fn change_rate(&self, history: &[SnapshotHistory], prior: &ChangePrior) -> (f64, usize) {
+        let mut sorted = history.iter().collect::<Vec<_>>();
+        sorted.sort_by_key(|h| h.timestamp);
+
+        let mut intervals = 0usize;
+        let mut changes = 0usize;
+        let mut exposure = 0.0;
+        for pair in sorted.windows(2) {
+            let dt = (pair[1].timestamp - pair[0].timestamp).num_seconds();
+            if dt <= 0 {
+                continue;
+            }
+            intervals += 1;
+            exposure += dt as f64;
+            let near_duplicate = pair[1]
+                .similarity
+                .is_some_and(|s| s >= self.config.near_duplicate_similarity);
+            if pair[1].content_hash != pair[0].content_hash && !near_duplicate {
+                changes += 1;
+            }
+        }
+
+        let n = intervals as f64;
+        let corrected = if intervals == 0 {
+            0.0
+        } else {
+            n * -((n - changes as f64 + 0.5) / (n + 0.5)).ln()
+        };
+        let rate = (prior.alpha + corrected) / (prior.beta_secs + exposure);
+        (rate, intervals)
+    }
+
+    /// Longest interval whose average freshness `(1 - e^(-rt)) / rt` still meets `target`
+    pub fn revisit_interval(&self, rate_per_sec: f64, target: f64) -> Duration {
+        let min = self.config.min_revisit.num_seconds().max(1) as f64;
+        let max = (self.config.max_revisit.num_seconds() as f64).max(min);
+        let target = target.clamp(0.01, 0.999);
+        let freshness = |t: f64| {
+            let x = rate_per_sec * t;
+            if x < 1e-9 {
+                1.0
+            } else {
+                (1.0 - (-x).exp()) / x
+            }
+        };
+
+        if freshness(max) >= target {
+            return Duration::seconds(max as i64);
+        }
+        if freshness(min) < target {
+            return Duration::seconds(min as i64);
+        }
+
+        let (mut lo, mut hi) = (min, max);
+        for _ in 0..64 {
+            let mid = (lo + hi) / 2.0;
+            if freshness(mid) >= target {
+                lo = mid;
+            } else {
+                hi = mid;
+            }
+        }
+        Duration::seconds(lo as i64)
+    }
+
+    pub fn estimate_at(
+        &self,
+        history: &[SnapshotHistory],
+        ctx: &PredictionContext,
+        now: DateTime<Utc>,
+    ) -> CrawlPrediction {
+        let prior = ctx.prior.unwrap_or(self.config.default_prior);
+        let target = ctx.freshness_target.unwrap_or(self.config.freshness_target);
+
+        let (rate, intervals) = self.change_rate(history, &prior);
+        let interval = self.revisit_interval(rate, target);
+        let change_probability = 1.0 - (-rate * interval.num_seconds() as f64).exp();
+
+        CrawlPrediction {
+            url: ctx.url.clone(),
+            next_fetch_at: now + interval,
+            recommended_priority: (change_probability * 10.0).round() as i32,
+            confidence: (intervals as f32 / (intervals as f32 + 3.0)).min(0.95),
+            change_probability: change_probability as f32,
+            change_rate_per_day: (rate * SECS_PER_DAY) as f32,
+        }
+    }
+}
+
+#[async_trait]
+impl PredictiveEngine for PoissonPredictor {
+    async fn predict_next_crawl(
+        &self,
+        history: &[SnapshotHistory],
+    ) -> anyhow::Result<CrawlPrediction> {
+        self.predict_with_context(history, &PredictionContext::default())
+            .await
+    }
+
+    async fn predict_with_context(
+        &self,
+        history: &[SnapshotHistory],
+        ctx: &PredictionContext,
+    ) -> anyhow::Result<CrawlPrediction> {
+        Ok(self.estimate_at(history, ctx, Utc::now()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history(hashes: &[&str], every: Duration) -> Vec<SnapshotHistory> {
+        let start = Utc::now() - every * hashes.len() as i32;
+        hashes
+            .iter()
+            .enumerate()
+            .map(|(i, h)| SnapshotHistory {
+                timestamp: start + every * i as i32,
+                content_hash: h.to_string(),
+                similarity: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_volatile_pages_are_revisited_sooner() {
+        let predictor = PoissonPredictor::new(SchedulerConfig::default());
+        let ctx = PredictionContext::default();
+        let now = Utc::now();
+
+        let volatile = history(&["a", "b", "c", "d", "e", "f"], Duration::hours(6));
+        let stable = history(&["a", "a", "a", "a", "a", "a"], Duration::days(2));
+        let fast = predictor.estimate_at(&volatile, &ctx, now);
+        let slow = predictor.estimate_at(&stable, &ctx, now);
+
+        assert!(fast.next_fetch_at < slow.next_fetch_at);
+        assert!(fast.change_rate_per_day > slow.change_rate_per_day);
+        assert!(fast.confidence > 0.5);
+    }
+
+    #[test]
+    fn test_near_duplicate_changes_are_ignored() {
+        let predictor = PoissonPredictor::new(SchedulerConfig::default());
+        let prior = ChangePrior::default();
+        let changed = history(&["a", "b", "c", "d"], Duration::days(1));
+        let mut rotated_ads = changed.clone();
+        for h in rotated_ads.iter_mut().skip(1) {
+            h.similarity = Some(0.98);
+        }
+
+        let (real, _) = predictor.change_rate(&changed, &prior);
+        let (noise, _) = predictor.change_rate(&rotated_ads, &prior);
+        assert!(noise < real / 2.0);
+    }
+
+    #[test]
+    fn test_bounds_and_freshness_target() {
+        let config = SchedulerConfig {
+            min_revisit: Duration::hours(2),
+            max_revisit: Duration::days(10),
+            ..SchedulerConfig::default()
+        };
+        let predictor = PoissonPredictor::new(config);
+
+        // Changes every few minutes still respect the floor; no changes respect the ceiling
+        assert_eq!(
+            predictor.revisit_interval(1.0 / 60.0, 0.8),
+            Duration::hours(2)
+        );
+        assert_eq!(predictor.revisit_interval(0.0, 0.8), Duration::days(10));
+
+        // A stricter freshness target means a shorter interval
+        let rate = 1.0 / SECS_PER_DAY;
+        assert!(predictor.revisit_interval(rate, 0.95) < predictor.revisit_interval(rate, 0.6));
+    }
+
+    #[test]
+    fn test_domain_prior_drives_cold_start() {
+        let predictor = PoissonPredictor::new(SchedulerConfig::default());
+        let now = Utc::now();
+        let busy_domain = PredictionContext {
+            prior: Some(ChangePrior::from_domain_stats(
+                200.0,
+                10.0 * SECS_PER_DAY,
+                Duration::days(7),
+            )),
+            ..Default::default()
+        };
+        let quiet_domain = PredictionContext {
+            prior: Some(ChangePrior::from_domain_stats(
+                1.0,
+                100.0 * SECS_PER_DAY,
+                Duration::days(7),
+            )),
+            ..Default::default()
+        };
+
+        let busy = predictor.estimate_at(&[], &busy_domain, now);
+        let quiet = predictor.estimate_at(&[], &quiet_domain, now);
+        assert!(busy.next_fetch_at < quiet.next_fetch_at);
+    }
+}
@@ -1,24 +1,29 @@
 pub mod storage;
+pub mod unixfs;
 
-pub use storage::{IpfsSnapshot, IpfsStats, IpfsStorage, SnapshotManifest};
+pub use storage::{
+    IpfsObject, IpfsSnapshot, IpfsStats, IpfsStorage, Publication, SnapshotManifest,
+};
 
+use cid::Cid;
 
-use sha2::{Digest, Sha256};
-
-/// Generate content identifier (CID) for data
+/// Generate content identifier (CID) for data: the CIDv1 `ipfs add
+/// --cid-version=1` gives a file with these bytes
 pub fn generate_cid(data: &[u8]) -> String {
-    let mut hasher = Sha256::new();
-    hasher.update(data);
-    let hash = hasher.finalize();
-
-    // Simplified CID - in production use multihash + multibase
-    format!("Qm{}", bs58::encode(&hash).into_string())
+    unixfs::file_cid(data).to_string()
 }
 
-/// Verify data matches CID
+/// Verify data matches CID. Raw block CIDs are checked by digest; DAG roots
+/// must match the layout `generate_cid` builds
 pub fn verify_cid(data: &[u8], cid: &str) -> bool {
-    let computed = generate_cid(data);
-    computed == cid
+    let Ok(cid) = Cid::try_from(cid) else {
+        return false;
+    };
+    match (cid.codec(), cid.hash().code()) {
+        (unixfs::RAW, unixfs::SHA2_256) => unixfs::block_cid(unixfs::RAW, data) == cid,
+        (unixfs::DAG_PB, unixfs::SHA2_256) => unixfs::file_cid(data) == cid,
+        _ => false,
+    }
 }
 
 #[cfg(test)]
@@ -29,7 +34,7 @@ mod tests {
     fn test_cid_generation() {
         let data = b"test data";
         let cid = generate_cid(data);
-        assert!(cid.starts_with("Qm"));
+        assert!(cid.starts_with("bafkrei"));
         assert!(verify_cid(data, &cid));
     }
 
@@ -40,5 +45,6 @@ mod tests {
 
         assert!(verify_cid(data, &cid));
         assert!(!verify_cid(b"different data", &cid));
+        assert!(!verify_cid(data, "not-a-cid"));
     }
 }
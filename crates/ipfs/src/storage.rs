@@ -1,9 +1,11 @@
-use anyhow::Result;
-use futures_util::{stream::StreamExt, TryStreamExt};
-use ipfs_api_backend_hyper::{IpfsApi, IpfsClient, TryFromUri};
+use crate::{generate_cid, verify_cid};
+use anyhow::{anyhow, bail, Result};
+use bytes::Bytes;
+use reqwest::header::RANGE;
+use reqwest::multipart::{Form, Part};
+use reqwest::{Client, Response};
 use serde::{Deserialize, Serialize};
-use std::io::Cursor;
-use tracing::info;
+use tracing::{info, warn};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct IpfsSnapshot {
@@ -13,116 +15,590 @@ pub struct IpfsSnapshot {
     pub size: u64,
 }
 
+/// A WARC file stored on IPFS
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IpfsObject {
+    pub name: String,
+    pub cid: String,
+    pub size: u64,
+}
+
+/// Client of a Kubo node's RPC API, optionally reading through an HTTP gateway
 pub struct IpfsStorage {
-    client: IpfsClient,
+    client: Client,
+    api_url: String,
+    gateway_url: Option<String>,
 }
 
 impl IpfsStorage {
     /// Connect to IPFS daemon
     pub fn new(api_url: &str) -> Result<Self> {
-        let client = IpfsClient::from_str(api_url)?;
-        Ok(Self { client })
+        reqwest::Url::parse(api_url)?;
+        Ok(Self {
+            client: Client::new(),
+            api_url: api_url.trim_end_matches('/').to_string(),
+            gateway_url: None,
+        })
+    }
+
+    /// Serve ranged reads from a gateway instead of the RPC API
+    pub fn with_gateway(mut self, gateway_url: &str) -> Self {
+        self.gateway_url = Some(gateway_url.trim_end_matches('/').to_string());
+        self
     }
 
-    /// Store WARC content on IPFS
+    /// POST an RPC command; Kubo only accepts POST
+    async fn rpc(&self, command: &str, args: &[(&str, &str)]) -> Result<Response> {
+        let response = self
+            .client
+            .post(format!("{}/api/v0/{}", self.api_url, command))
+            .query(args)
+            .send()
+            .await?;
+        check(response).await
+    }
+
+    /// Store WARC content on IPFS, pinned, and return its CIDv1
     pub async fn store_warc(&self, content: &[u8]) -> Result<String> {
         info!("Storing {} bytes to IPFS", content.len());
 
-        let cursor = Cursor::new(content.to_vec());
-        let response = self.client.add(cursor).await?;
-
-        let cid = response.hash;
-        info!("Stored to IPFS with CID: {}", cid);
+        // The import settings `generate_cid` mirrors
+        let form = Form::new().part("file", Part::bytes(content.to_vec()).file_name("warc"));
+        let response = self
+            .client
+            .post(format!("{}/api/v0/add", self.api_url))
+            .query(&[
+                ("cid-version", "1"),
+                ("raw-leaves", "true"),
+                ("chunker", "size-262144"),
+                ("pin", "true"),
+            ])
+            .multipart(form)
+            .send()
+            .await?;
+        let added: AddResponse = check(response).await?.json().await?;
 
-        Ok(cid)
+        let expected = generate_cid(content);
+        if added.hash != expected {
+            warn!(
+                "IPFS node returned CID {} where {} was computed locally",
+                added.hash, expected
+            );
+        }
+        info!("Stored to IPFS with CID: {}", added.hash);
+        Ok(added.hash)
     }
 
-    /// Retrieve WARC content from IPFS
+    /// Retrieve WARC content from IPFS, checked against its CID
     pub async fn retrieve_warc(&self, cid: &str) -> Result<Vec<u8>> {
         info!("Retrieving CID: {}", cid);
 
-        let data = self
-            .client
-            .cat(cid)
-            .map(|chunk_res| chunk_res.map(|chunk| chunk.to_vec()))
-            .try_concat()
-            .await?;
+        let data = self.rpc("cat", &[("arg", cid)]).await?.bytes().await?;
+        if !verify_cid(&data, cid) {
+            bail!("content retrieved for {} does not match its CID", cid);
+        }
 
         info!("Retrieved {} bytes from IPFS", data.len());
+        Ok(data.to_vec())
+    }
+
+    /// `length` bytes at `offset` of the file at `cid`
+    pub async fn retrieve_range(&self, cid: &str, offset: u64, length: u64) -> Result<Bytes> {
+        if length == 0 {
+            return Ok(Bytes::new());
+        }
+        let data = match &self.gateway_url {
+            Some(gateway) => {
+                let response = self
+                    .client
+                    .get(format!("{}/ipfs/{}", gateway, cid))
+                    .header(RANGE, format!("bytes={}-{}", offset, offset + length - 1))
+                    .send()
+                    .await?;
+                check(response).await?.bytes().await?
+            }
+            None => {
+                let (offset, length) = (offset.to_string(), length.to_string());
+                self.rpc(
+                    "cat",
+                    &[("arg", cid), ("offset", &offset), ("length", &length)],
+                )
+                .await?
+                .bytes()
+                .await?
+            }
+        };
+        if data.len() as u64 != length {
+            bail!(
+                "expected {} bytes of {} at {}, got {}",
+                length,
+                cid,
+                offset,
+                data.len()
+            );
+        }
         Ok(data)
     }
 
     /// Pin content to ensure persistence
     pub async fn pin(&self, cid: &str) -> Result<()> {
         info!("Pinning CID: {}", cid);
-        self.client.pin_add(cid, true).await?;
+        self.rpc("pin/add", &[("arg", cid), ("recursive", "true")])
+            .await?;
         Ok(())
     }
 
     /// Unpin content to allow garbage collection
     pub async fn unpin(&self, cid: &str) -> Result<()> {
         info!("Unpinning CID: {}", cid);
-        self.client.pin_rm(cid, true).await?;
+        self.rpc("pin/rm", &[("arg", cid), ("recursive", "true")])
+            .await?;
         Ok(())
     }
 
-    /// Publish snapshot manifest to IPNS
-    pub async fn publish_manifest(&self, manifest: &SnapshotManifest) -> Result<String> {
+    /// Publish snapshot manifest to IPNS under `key` (the node's own key
+    /// when `None`)
+    pub async fn publish_manifest(
+        &self,
+        manifest: &SnapshotManifest,
+        key: Option<&str>,
+    ) -> Result<Publication> {
         let json = serde_json::to_vec(manifest)?;
         let cid = self.store_warc(&json).await?;
 
-        // Publish to IPNS (requires IPFS key)
-        let response = self
-            .client
-            .name_publish(&cid, true, None, None, None)
-            .await?;
+        let path = format!("/ipfs/{}", cid);
+        let mut args = vec![("arg", path.as_str())];
+        if let Some(key) = key {
+            args.push(("key", key));
+        }
+        let published: NameResponse = self.rpc("name/publish", &args).await?.json().await?;
 
-        Ok(response.name)
+        Ok(Publication {
+            name: published.name,
+            cid,
+        })
     }
 
     /// Resolve IPNS name to latest CID
     pub async fn resolve_ipns(&self, name: &str) -> Result<String> {
-        let response = self.client.name_resolve(Some(name), true, false).await?;
-        Ok(response.path)
+        let resolved: ResolveResponse = self
+            .rpc("name/resolve", &[("arg", name), ("recursive", "true")])
+            .await?
+            .json()
+            .await?;
+        Ok(resolved.path)
     }
 
     /// Get IPFS node stats
     pub async fn stats(&self) -> Result<IpfsStats> {
-        let repo_stats = self.client.stats_repo().await?;
+        Ok(self.rpc("repo/stat", &[]).await?.json().await?)
+    }
+
+    /// Write `content` to the MFS file at `path`, replacing it, and return
+    /// its CID; MFS content is kept by garbage collection without a pin
+    pub async fn write_file(&self, path: &str, content: Vec<u8>) -> Result<String> {
+        let form = Form::new().part("file", Part::bytes(content).file_name("warc"));
+        let response = self
+            .client
+            .post(format!("{}/api/v0/files/write", self.api_url))
+            .query(&[
+                ("arg", path),
+                ("create", "true"),
+                ("truncate", "true"),
+                ("parents", "true"),
+                ("cid-version", "1"),
+                ("raw-leaves", "true"),
+            ])
+            .multipart(form)
+            .send()
+            .await?;
+        check(response).await?;
+        Ok(self.stat_file(path).await?.cid)
+    }
+
+    /// `length` bytes at `offset` of the MFS file at `path`, through the
+    /// gateway when one is set
+    pub async fn read_file(&self, path: &str, offset: u64, length: u64) -> Result<Bytes> {
+        if self.gateway_url.is_some() {
+            let cid = self.stat_file(path).await?.cid;
+            return self.retrieve_range(&cid, offset, length).await;
+        }
+        let (offset_arg, count) = (offset.to_string(), length.to_string());
+        let data = self
+            .rpc(
+                "files/read",
+                &[("arg", path), ("offset", &offset_arg), ("count", &count)],
+            )
+            .await?
+            .bytes()
+            .await?;
+        if data.len() as u64 != length {
+            bail!(
+                "expected {} bytes of {} at {}, got {}",
+                length,
+                path,
+                offset,
+                data.len()
+            );
+        }
+        Ok(data)
+    }
 
-        Ok(IpfsStats {
-            num_objects: repo_stats.num_objects,
-            repo_size: repo_stats.repo_size,
+    pub async fn stat_file(&self, path: &str) -> Result<IpfsObject> {
+        let stat: StatResponse = self
+            .rpc("files/stat", &[("arg", path)])
+            .await?
+            .json()
+            .await?;
+        Ok(IpfsObject {
+            name: path.rsplit('/').next().unwrap_or(path).to_string(),
+            cid: stat.hash,
+            size: stat.size,
         })
     }
+
+    /// Files directly under the MFS directory `dir`; none if it does not exist
+    pub async fn list_files(&self, dir: &str) -> Result<Vec<IpfsObject>> {
+        let listing: LsResponse = match self
+            .rpc("files/ls", &[("arg", dir), ("long", "true")])
+            .await
+        {
+            Ok(response) => response.json().await?,
+            Err(e) if e.to_string().contains("does not exist") => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        Ok(listing
+            .entries
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|e| e.kind == 0)
+            .map(|e| IpfsObject {
+                name: e.name,
+                cid: e.hash,
+                size: e.size,
+            })
+            .collect())
+    }
+
+    /// Remove the MFS file at `path`; a missing file is not an error
+    pub async fn remove_file(&self, path: &str) -> Result<()> {
+        match self
+            .rpc("files/rm", &[("arg", path), ("force", "true")])
+            .await
+        {
+            Err(e) if !e.to_string().contains("does not exist") => Err(e),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Turn an RPC error into its message
+async fn check(response: Response) -> Result<Response> {
+    if response.status().is_success() {
+        return Ok(response);
+    }
+    let status = response.status();
+    let body = response.text().await.unwrap_or_default();
+    let message = serde_json::from_str::<serde_json::Value>(&body)
+        .ok()
+        .and_then(|v| v["Message"].as_str().map(str::to_string))
+        .unwrap_or(body);
+    Err(anyhow!("IPFS request failed with {}: {}", status, message))
+}
+
+#[derive(Deserialize)]
+struct AddResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+#[derive(Deserialize)]
+struct NameResponse {
+    #[serde(rename = "Name")]
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ResolveResponse {
+    #[serde(rename = "Path")]
+    path: String,
+}
+
+#[derive(Deserialize)]
+struct StatResponse {
+    #[serde(rename = "Hash")]
+    hash: String,
+    #[serde(rename = "Size")]
+    size: u64,
+}
+
+#[derive(Deserialize)]
+struct LsResponse {
+    #[serde(rename = "Entries")]
+    entries: Option<Vec<LsEntry>>,
+}
+
+#[derive(Deserialize)]
+struct LsEntry {
+    #[serde(rename = "Name")]
+    name: String,
+    /// 0 for files, 1 for directories
+    #[serde(rename = "Type")]
+    kind: u8,
+    #[serde(rename = "Size")]
+    size: u64,
+    #[serde(rename = "Hash")]
+    hash: String,
+}
+
+/// An IPNS name and the manifest it now points at
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Publication {
+    pub name: String,
+    pub cid: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct SnapshotManifest {
     pub version: String,
+    #[serde(default)]
     pub snapshots: Vec<IpfsSnapshot>,
+    /// WARC files holding the snapshots
+    #[serde(default)]
+    pub files: Vec<IpfsObject>,
     pub created_at: String,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IpfsStats {
+    #[serde(rename = "NumObjects")]
     pub num_objects: u64,
+    #[serde(rename = "RepoSize")]
     pub repo_size: u64,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::{
+        body::Bytes as Body,
+        extract::{Query, State},
+        http::HeaderMap,
+        routing::post,
+        Json, Router,
+    };
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    /// In-process stand-in for the parts of the Kubo RPC API the client uses
+    #[derive(Default)]
+    struct FakeKubo {
+        blocks: Mutex<HashMap<String, Vec<u8>>>,
+        pins: Mutex<Vec<String>>,
+        names: Mutex<HashMap<String, String>>,
+        files: Mutex<HashMap<String, Vec<u8>>>,
+    }
+
+    type Args = Query<HashMap<String, String>>;
+
+    /// The single file of a multipart body
+    fn file_part(headers: &HeaderMap, body: &[u8]) -> Vec<u8> {
+        let content_type = headers["content-type"].to_str().unwrap();
+        let boundary = content_type.split("boundary=").nth(1).unwrap();
+        let start = body.windows(4).position(|w| w == b"\r\n\r\n").unwrap() + 4;
+        let end = body.len() - format!("\r\n--{}--\r\n", boundary).len();
+        body[start..end].to_vec()
+    }
+
+    async fn add(
+        State(kubo): State<Arc<FakeKubo>>,
+        headers: HeaderMap,
+        body: Body,
+    ) -> Json<serde_json::Value> {
+        let content = file_part(&headers, &body);
+        let cid = generate_cid(&content);
+        kubo.blocks.lock().unwrap().insert(cid.clone(), content);
+        kubo.pins.lock().unwrap().push(cid.clone());
+        Json(serde_json::json!({"Name": "warc", "Hash": cid, "Size": "0"}))
+    }
+
+    async fn cat(State(kubo): State<Arc<FakeKubo>>, Query(args): Args) -> Vec<u8> {
+        let data = kubo.blocks.lock().unwrap()[&args["arg"]].clone();
+        let offset: usize = args.get("offset").map_or(0, |o| o.parse().unwrap());
+        let length: usize = args
+            .get("length")
+            .map_or(data.len(), |l| l.parse().unwrap());
+        data[offset..offset + length].to_vec()
+    }
+
+    async fn publish(
+        State(kubo): State<Arc<FakeKubo>>,
+        Query(args): Args,
+    ) -> Json<serde_json::Value> {
+        let name = "k51qzi5uqu5dlvj2baxnqndepeb86cbk3ng7n3i46uzyxzyqj2xjonzllnv0v8";
+        kubo.names
+            .lock()
+            .unwrap()
+            .insert(name.into(), args["arg"].clone());
+        Json(serde_json::json!({"Name": name, "Value": args["arg"]}))
+    }
+
+    async fn resolve(
+        State(kubo): State<Arc<FakeKubo>>,
+        Query(args): Args,
+    ) -> Json<serde_json::Value> {
+        let path = kubo.names.lock().unwrap()[&args["arg"]].clone();
+        Json(serde_json::json!({"Path": path}))
+    }
+
+    async fn files_write(
+        State(kubo): State<Arc<FakeKubo>>,
+        Query(args): Args,
+        headers: HeaderMap,
+        body: Body,
+    ) {
+        let content = file_part(&headers, &body);
+        kubo.files
+            .lock()
+            .unwrap()
+            .insert(args["arg"].clone(), content);
+    }
+
+    async fn files_stat(
+        State(kubo): State<Arc<FakeKubo>>,
+        Query(args): Args,
+    ) -> Json<serde_json::Value> {
+        let data = kubo.files.lock().unwrap()[&args["arg"]].clone();
+        Json(serde_json::json!({"Hash": generate_cid(&data), "Size": data.len(), "Type": "file"}))
+    }
+
+    async fn files_read(State(kubo): State<Arc<FakeKubo>>, Query(args): Args) -> Vec<u8> {
+        let data = kubo.files.lock().unwrap()[&args["arg"]].clone();
+        let offset: usize = args["offset"].parse().unwrap();
+        let count: usize = args["count"].parse().unwrap();
+        data[offset..(offset + count).min(data.len())].to_vec()
+    }
+
+    async fn files_ls(
+        State(kubo): State<Arc<FakeKubo>>,
+        Query(args): Args,
+    ) -> Json<serde_json::Value> {
+        let prefix = format!("{}/", args["arg"]);
+        let entries: Vec<_> = kubo
+            .files
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(path, data)| {
+                let name = path.strip_prefix(&prefix)?;
+                Some(serde_json::json!({
+                    "Name": name, "Type": 0, "Size": data.len(), "Hash": generate_cid(data)
+                }))
+            })
+            .collect();
+        Json(serde_json::json!({"Entries": entries}))
+    }
+
+    async fn files_rm(State(kubo): State<Arc<FakeKubo>>, Query(args): Args) {
+        kubo.files.lock().unwrap().remove(&args["arg"]);
+    }
+
+    async fn serve() -> (String, Arc<FakeKubo>) {
+        let kubo = Arc::new(FakeKubo::default());
+        let app = Router::new()
+            .route("/api/v0/add", post(add))
+            .route("/api/v0/cat", post(cat))
+            .route("/api/v0/name/publish", post(publish))
+            .route("/api/v0/name/resolve", post(resolve))
+            .route("/api/v0/files/write", post(files_write))
+            .route("/api/v0/files/stat", post(files_stat))
+            .route("/api/v0/files/read", post(files_read))
+            .route("/api/v0/files/ls", post(files_ls))
+            .route("/api/v0/files/rm", post(files_rm))
+            .with_state(kubo.clone());
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), kubo)
+    }
+
+    #[tokio::test]
+    async fn test_store_read_ranges_and_publish_against_stand_in() {
+        let (url, kubo) = serve().await;
+        let storage = IpfsStorage::new(&url).unwrap();
+
+        let content = b"WARC/1.1\r\nWARC-Type: response\r\n\r\nHello, IPFS!";
+        let cid = storage.store_warc(content).await.unwrap();
+        assert_eq!(cid, generate_cid(content));
+        assert!(cid.starts_with("bafkrei"));
+        assert_eq!(
+            kubo.pins.lock().unwrap().as_slice(),
+            std::slice::from_ref(&cid)
+        );
+
+        assert_eq!(storage.retrieve_warc(&cid).await.unwrap(), content);
+        let range = storage.retrieve_range(&cid, 33, 5).await.unwrap();
+        assert_eq!(&range[..], b"Hello");
+
+        // Tampered content fails verification
+        kubo.blocks
+            .lock()
+            .unwrap()
+            .insert(cid.clone(), b"forged".to_vec());
+        assert!(storage.retrieve_warc(&cid).await.is_err());
+
+        let manifest = SnapshotManifest {
+            version: "1".into(),
+            snapshots: Vec::new(),
+            files: vec![IpfsObject {
+                name: "a.warc".into(),
+                cid: cid.clone(),
+                size: content.len() as u64,
+            }],
+            created_at: "2026-01-01T00:00:00Z".into(),
+        };
+        let publication = storage.publish_manifest(&manifest, None).await.unwrap();
+        assert_eq!(
+            storage.resolve_ipns(&publication.name).await.unwrap(),
+            format!("/ipfs/{}", publication.cid)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mfs_files_against_stand_in() {
+        let (url, _kubo) = serve().await;
+        let storage = IpfsStorage::new(&url).unwrap();
+
+        let content = b"WARC/1.1\r\n\r\nrecord".to_vec();
+        let cid = storage
+            .write_file("/archive/a.warc", content.clone())
+            .await
+            .unwrap();
+        assert_eq!(cid, generate_cid(&content));
+
+        let range = storage.read_file("/archive/a.warc", 12, 6).await.unwrap();
+        assert_eq!(&range[..], b"record");
+        assert!(storage.read_file("/archive/a.warc", 12, 60).await.is_err());
+
+        let files = storage.list_files("/archive").await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert_eq!((files[0].name.as_str(), files[0].size), ("a.warc", 18));
+        assert_eq!(files[0].cid, cid);
+
+        storage.remove_file("/archive/a.warc").await.unwrap();
+        assert!(storage.list_files("/archive").await.unwrap().is_empty());
+    }
 
     #[tokio::test]
     #[ignore] // Requires running IPFS daemon
     async fn test_ipfs_storage() {
         let storage = IpfsStorage::new("http://localhost:5001").unwrap();
 
-        let content = b"Hello, IPFS!";
-        let cid = storage.store_warc(content).await.unwrap();
+        let content = vec![42u8; 600 * 1024];
+        let cid = storage.store_warc(&content).await.unwrap();
+        assert_eq!(cid, generate_cid(&content));
 
         let retrieved = storage.retrieve_warc(&cid).await.unwrap();
-        assert_eq!(content, retrieved.as_slice());
+        assert_eq!(content, retrieved);
     }
 }
@@ -0,0 +1,138 @@
+//! The UnixFS file DAG `ipfs add --cid-version=1` builds: 256 KiB raw
+//! leaves under a balanced tree of dag-pb nodes with up to 174 links each
+
+use cid::multihash::Multihash;
+use cid::Cid;
+use sha2::{Digest, Sha256};
+
+/// Multicodec of raw binary blocks
+pub const RAW: u64 = 0x55;
+/// Multicodec of dag-pb (protobuf) nodes
+pub const DAG_PB: u64 = 0x70;
+/// Multihash code of SHA2-256
+pub const SHA2_256: u64 = 0x12;
+
+/// Kubo's default chunk size
+pub const CHUNK_SIZE: usize = 256 * 1024;
+/// Kubo's default link count per node of the balanced layout
+pub const MAX_LINKS: usize = 174;
+
+/// CIDv1 of `block` under `codec`, hashed with SHA2-256
+pub fn block_cid(codec: u64, block: &[u8]) -> Cid {
+    let digest = Sha256::digest(block);
+    let hash = Multihash::wrap(SHA2_256, &digest).expect("a SHA-256 digest fits a multihash");
+    Cid::new_v1(codec, hash)
+}
+
+/// A node of the DAG as its parent links to it
+struct Child {
+    cid: Cid,
+    /// Bytes of file content below the node
+    filesize: u64,
+    /// Serialized size of the node and everything below it
+    tsize: u64,
+}
+
+/// Root CID of the DAG for `data`; a single chunk is its own root
+pub fn file_cid(data: &[u8]) -> Cid {
+    let mut level: Vec<Child> = if data.is_empty() {
+        vec![leaf(data)]
+    } else {
+        data.chunks(CHUNK_SIZE).map(leaf).collect()
+    };
+    while level.len() > 1 {
+        level = level.chunks(MAX_LINKS).map(branch).collect();
+    }
+    level.remove(0).cid
+}
+
+fn leaf(chunk: &[u8]) -> Child {
+    Child {
+        cid: block_cid(RAW, chunk),
+        filesize: chunk.len() as u64,
+        tsize: chunk.len() as u64,
+    }
+}
+
+fn branch(children: &[Child]) -> Child {
+    let block = encode_node(children);
+    Child {
+        cid: block_cid(DAG_PB, &block),
+        filesize: children.iter().map(|c| c.filesize).sum(),
+        tsize: block.len() as u64 + children.iter().map(|c| c.tsize).sum::<u64>(),
+    }
+}
+
+/// dag-pb `PBNode` with the links first, then UnixFS `Data` of type File
+fn encode_node(children: &[Child]) -> Vec<u8> {
+    let mut node = Vec::new();
+    for child in children {
+        let mut link = Vec::new();
+        field_bytes(&mut link, 1, &child.cid.to_bytes());
+        field_bytes(&mut link, 2, b"");
+        field_varint(&mut link, 3, child.tsize);
+        field_bytes(&mut node, 2, &link);
+    }
+
+    let mut unixfs = Vec::new();
+    field_varint(&mut unixfs, 1, 2);
+    field_varint(&mut unixfs, 3, children.iter().map(|c| c.filesize).sum());
+    for child in children {
+        field_varint(&mut unixfs, 4, child.filesize);
+    }
+    field_bytes(&mut node, 1, &unixfs);
+    node
+}
+
+fn varint(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn field_varint(out: &mut Vec<u8>, field: u64, value: u64) {
+    varint(out, field << 3);
+    varint(out, value);
+}
+
+fn field_bytes(out: &mut Vec<u8>, field: u64, bytes: &[u8]) {
+    varint(out, (field << 3) | 2);
+    varint(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_small_files_are_single_raw_blocks() {
+        assert_eq!(
+            file_cid(b"hello world").to_string(),
+            "bafkreifzjut3te2nhyekklss27nh3k72ysco7y32koao5eei66wof36n5e"
+        );
+        assert_eq!(
+            file_cid(b"").to_string(),
+            "bafkreihdwdcefgh4dqkjv67uzcmw7ojee6xedzdetojuzjevtenxquvyku"
+        );
+    }
+
+    #[test]
+    fn test_large_files_get_a_balanced_dag_pb_root() {
+        let data = vec![7u8; CHUNK_SIZE * 2 + 10];
+        let root = file_cid(&data);
+        assert_eq!(root.codec(), DAG_PB);
+
+        let leaves: Vec<Child> = data.chunks(CHUNK_SIZE).map(leaf).collect();
+        let block = encode_node(&leaves);
+        assert_eq!(root, block_cid(DAG_PB, &block));
+        // Two identical leaves share a CID, the short tail does not
+        assert_eq!(leaves[0].cid, leaves[1].cid);
+        assert_ne!(leaves[1].cid, leaves[2].cid);
+
+        let wide = vec![1u8; CHUNK_SIZE * (MAX_LINKS + 1)];
+        assert_ne!(file_cid(&wide), file_cid(&wide[..CHUNK_SIZE * MAX_LINKS]));
+    }
+}
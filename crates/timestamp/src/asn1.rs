@@ -0,0 +1,128 @@
+//! The RFC 3161 structures CMS and X.509 crates do not already cover
+
+use der::asn1::{Any, BitString, Int, ObjectIdentifier, OctetString};
+use der::Sequence;
+use spki::AlgorithmIdentifierOwned;
+use x509_cert::ext::pkix::name::GeneralName;
+use x509_cert::ext::Extensions;
+
+pub const ID_SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+pub const ID_CT_TST_INFO: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.16.1.4");
+pub const ID_CONTENT_TYPE: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.3");
+pub const ID_MESSAGE_DIGEST: ObjectIdentifier =
+    ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+pub const ID_KP_TIME_STAMPING: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.6.1.5.5.7.3.8");
+pub const ID_SHA_256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+pub const ID_SHA_384: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+pub const ID_SHA_512: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.3");
+
+/// ```text
+/// MessageImprint ::= SEQUENCE {
+///     hashAlgorithm AlgorithmIdentifier,
+///     hashedMessage OCTET STRING }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct MessageImprint {
+    pub hash_algorithm: AlgorithmIdentifierOwned,
+    pub hashed_message: OctetString,
+}
+
+/// ```text
+/// TimeStampReq ::= SEQUENCE {
+///     version INTEGER { v1(1) },
+///     messageImprint MessageImprint,
+///     reqPolicy TSAPolicyId OPTIONAL,
+///     nonce INTEGER OPTIONAL,
+///     certReq BOOLEAN DEFAULT FALSE,
+///     extensions [0] IMPLICIT Extensions OPTIONAL }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct TimeStampReq {
+    pub version: u8,
+    pub message_imprint: MessageImprint,
+    #[asn1(optional = "true")]
+    pub req_policy: Option<ObjectIdentifier>,
+    #[asn1(optional = "true")]
+    pub nonce: Option<u64>,
+    #[asn1(default = "Default::default")]
+    pub cert_req: bool,
+}
+
+/// ```text
+/// PKIStatusInfo ::= SEQUENCE {
+///     status PKIStatus,
+///     statusString PKIFreeText OPTIONAL,
+///     failInfo PKIFailureInfo OPTIONAL }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct PkiStatusInfo {
+    pub status: u8,
+    #[asn1(optional = "true")]
+    pub status_string: Option<Vec<String>>,
+    #[asn1(optional = "true")]
+    pub fail_info: Option<BitString>,
+}
+
+/// ```text
+/// TimeStampResp ::= SEQUENCE {
+///     status PKIStatusInfo,
+///     timeStampToken TimeStampToken OPTIONAL }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct TimeStampResp {
+    pub status: PkiStatusInfo,
+    #[asn1(optional = "true")]
+    pub time_stamp_token: Option<Any>,
+}
+
+/// ```text
+/// Accuracy ::= SEQUENCE {
+///     seconds INTEGER OPTIONAL,
+///     millis [0] INTEGER (1..999) OPTIONAL,
+///     micros [1] INTEGER (1..999) OPTIONAL }
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct Accuracy {
+    #[asn1(optional = "true")]
+    pub seconds: Option<u32>,
+    #[asn1(context_specific = "0", tag_mode = "IMPLICIT", optional = "true")]
+    pub millis: Option<u16>,
+    #[asn1(context_specific = "1", tag_mode = "IMPLICIT", optional = "true")]
+    pub micros: Option<u16>,
+}
+
+/// ```text
+/// TSTInfo ::= SEQUENCE {
+///     version INTEGER { v1(1) },
+///     policy TSAPolicyId,
+///     messageImprint MessageImprint,
+///     serialNumber INTEGER,
+///     genTime GeneralizedTime,
+///     accuracy Accuracy OPTIONAL,
+///     ordering BOOLEAN DEFAULT FALSE,
+///     nonce INTEGER OPTIONAL,
+///     tsa [0] GeneralName OPTIONAL,
+///     extensions [1] IMPLICIT Extensions OPTIONAL }
+/// ```
+///
+/// `genTime` is kept raw: TSAs may add fractional seconds, which the DER
+/// `GeneralizedTime` type rejects
+#[derive(Clone, Debug, Eq, PartialEq, Sequence)]
+pub struct TstInfo {
+    pub version: u8,
+    pub policy: ObjectIdentifier,
+    pub message_imprint: MessageImprint,
+    pub serial_number: Int,
+    pub gen_time: Any,
+    #[asn1(optional = "true")]
+    pub accuracy: Option<Accuracy>,
+    #[asn1(default = "Default::default")]
+    pub ordering: bool,
+    #[asn1(optional = "true")]
+    pub nonce: Option<Int>,
+    #[asn1(context_specific = "0", tag_mode = "EXPLICIT", optional = "true")]
+    pub tsa: Option<GeneralName>,
+    #[asn1(context_specific = "1", tag_mode = "IMPLICIT", optional = "true")]
+    pub extensions: Option<Extensions>,
+}
@@ -0,0 +1,263 @@
+//! RFC 3161 trusted timestamps: asking a Time Stamping Authority to sign a
+//! digest, and verifying the tokens it returns
+
+mod asn1;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod verify;
+
+pub use verify::{verify_token, Timestamp};
+pub use x509_cert::Certificate;
+
+use anyhow::{anyhow, bail, Context, Result};
+use asn1::{MessageImprint, TimeStampReq, TimeStampResp, TstInfo, ID_SHA_256};
+use der::asn1::{Any, OctetString};
+use der::{Decode, Encode};
+use spki::AlgorithmIdentifierOwned;
+
+/// DER `TimeStampReq` for a SHA-256 `digest`, asking for the TSA's
+/// certificate to be included in the token
+fn request(digest: &[u8; 32], nonce: u64) -> Result<Vec<u8>> {
+    Ok(TimeStampReq {
+        version: 1,
+        message_imprint: MessageImprint {
+            hash_algorithm: AlgorithmIdentifierOwned {
+                oid: ID_SHA_256,
+                parameters: Some(Any::null()),
+            },
+            hashed_message: OctetString::new(digest.as_slice())?,
+        },
+        req_policy: None,
+        nonce: Some(nonce),
+        cert_req: true,
+    }
+    .to_der()?)
+}
+
+/// The token in a DER `TimeStampResp`, which must echo `nonce`
+fn token_from_response(response: &[u8], nonce: u64) -> Result<Vec<u8>> {
+    let response = TimeStampResp::from_der(response).context("invalid TimeStampResp")?;
+    // 0 is granted, 1 granted with modifications
+    if response.status.status > 1 {
+        bail!(
+            "TSA refused the request (status {}): {}",
+            response.status.status,
+            response.status.status_string.unwrap_or_default().join("; ")
+        );
+    }
+    let token = response
+        .time_stamp_token
+        .ok_or_else(|| anyhow!("TSA granted the request without a token"))?
+        .to_der()?;
+
+    let content_info = cms::content_info::ContentInfo::from_der(&token)?;
+    let signed_data: cms::signed_data::SignedData = content_info.content.decode_as()?;
+    let tst_der = signed_data
+        .encap_content_info
+        .econtent
+        .ok_or_else(|| anyhow!("token has no content"))?
+        .decode_as::<OctetString>()?;
+    let echoed = TstInfo::from_der(tst_der.as_bytes())?
+        .nonce
+        .map(|n| n.to_der())
+        .transpose()?;
+    if echoed != Some(nonce.to_der()?) {
+        bail!("TSA response does not echo the request nonce");
+    }
+    Ok(token)
+}
+
+/// Client for one RFC 3161 Time Stamping Authority
+pub struct TsaClient {
+    client: reqwest::Client,
+    url: String,
+    anchors: Vec<Certificate>,
+}
+
+impl TsaClient {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            anchors: Vec::new(),
+        }
+    }
+
+    /// Only accept tokens whose signer chains to one of `anchors`
+    pub fn with_anchors(mut self, anchors: Vec<Certificate>) -> Self {
+        self.anchors = anchors;
+        self
+    }
+
+    /// `TSA_URL`, with trust anchors from the PEM bundle at `TSA_CA_FILE`;
+    /// `None` when no TSA is configured
+    pub fn from_env() -> Result<Option<Self>> {
+        let url = match std::env::var("TSA_URL") {
+            Ok(url) if !url.is_empty() => url,
+            _ => return Ok(None),
+        };
+        let mut client = Self::new(&url);
+        if let Ok(path) = std::env::var("TSA_CA_FILE") {
+            if !path.is_empty() {
+                let pem = std::fs::read(&path).with_context(|| format!("reading {}", path))?;
+                client = client.with_anchors(Certificate::load_pem_chain(&pem)?);
+            }
+        }
+        Ok(Some(client))
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn anchors(&self) -> &[Certificate] {
+        &self.anchors
+    }
+
+    /// Timestamp a SHA-256 `digest`, returning the verified DER token
+    pub async fn timestamp(&self, digest: &[u8; 32]) -> Result<(Vec<u8>, Timestamp)> {
+        let nonce = rand::random::<u64>();
+        let response = self
+            .client
+            .post(&self.url)
+            .header("Content-Type", "application/timestamp-query")
+            .body(request(digest, nonce)?)
+            .send()
+            .await?
+            .error_for_status()?
+            .bytes()
+            .await?;
+        let token = token_from_response(&response, nonce)?;
+        let timestamp = verify_token(&token, digest, &self.anchors)?;
+        Ok((token, timestamp))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    // testdata holds an OpenSSL test TSA's replies for SHA-256("archivestream"),
+    // from an RSA and an ECDSA P-256 certificate issued by root.pem
+    const RSA_QUERY: &[u8] = include_bytes!("../testdata/rsa.tsq");
+    const RSA_REPLY: &[u8] = include_bytes!("../testdata/rsa.tsr");
+    const RSA_NONCE: u64 = 0xE726AABB64858633;
+    const ECDSA_REPLY: &[u8] = include_bytes!("../testdata/ec.tsr");
+    const ECDSA_NONCE: u64 = 0x0E11368BEFE03701;
+    const ROOT: &[u8] = include_bytes!("../testdata/root.pem");
+
+    fn digest() -> [u8; 32] {
+        use sha2::{Digest, Sha256};
+        Sha256::digest(b"archivestream").into()
+    }
+
+    #[test]
+    fn test_request_matches_openssl_query() {
+        assert_eq!(request(&digest(), RSA_NONCE).unwrap(), RSA_QUERY);
+    }
+
+    #[test]
+    fn test_tokens_verify_and_chain_to_the_root() {
+        let root = Certificate::load_pem_chain(ROOT).unwrap();
+        for (reply, nonce, signer) in [
+            (RSA_REPLY, RSA_NONCE, "CN=ArchiveStream Test TSA (RSA)"),
+            (
+                ECDSA_REPLY,
+                ECDSA_NONCE,
+                "CN=ArchiveStream Test TSA (ECDSA)",
+            ),
+        ] {
+            assert!(token_from_response(reply, nonce ^ 1).is_err());
+            let token = token_from_response(reply, nonce).unwrap();
+
+            let timestamp = verify_token(&token, &digest(), &root).unwrap();
+            assert_eq!(timestamp.signer, signer);
+            assert_eq!(
+                timestamp.trust_anchor.as_deref(),
+                Some("CN=ArchiveStream Test Root")
+            );
+            assert_eq!(timestamp.policy, "1.3.6.1.4.1.99999.1");
+            assert!(timestamp.gen_time > Utc.with_ymd_and_hms(2026, 1, 1, 0, 0, 0).unwrap());
+            assert!(verify_token(&token, &digest(), &[])
+                .unwrap()
+                .trust_anchor
+                .is_none());
+
+            let mut other = digest();
+            other[0] ^= 1;
+            assert!(verify_token(&token, &other, &root).is_err());
+
+            // Moving genTime breaks the signed digest of the TSTInfo
+            let mut tampered = token.clone();
+            let at = tampered.windows(4).position(|w| w == b"2026").unwrap();
+            tampered[at + 3] ^= 1;
+            assert!(verify_token(&tampered, &digest(), &root).is_err());
+        }
+
+        // A certificate the TSA does not chain to is no anchor
+        let token = token_from_response(RSA_REPLY, RSA_NONCE).unwrap();
+        let stranger = token_from_response(ECDSA_REPLY, ECDSA_NONCE).unwrap();
+        let stranger_signer = cms::content_info::ContentInfo::from_der(&stranger)
+            .unwrap()
+            .content
+            .decode_as::<cms::signed_data::SignedData>()
+            .unwrap()
+            .certificates
+            .unwrap()
+            .0
+            .into_vec()
+            .into_iter()
+            .find_map(|choice| match choice {
+                cms::cert::CertificateChoices::Certificate(cert)
+                    if cert.tbs_certificate.subject.to_string().contains("ECDSA") =>
+                {
+                    Some(cert)
+                }
+                _ => None,
+            })
+            .unwrap();
+        assert!(verify_token(&token, &digest(), &[stranger_signer]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_client_posts_a_query_and_verifies_the_reply() {
+        use axum::{body::Bytes, routing::post, Router};
+
+        // Stands in for a TSA by replying with the fixture whatever the nonce,
+        // so the client must reject it
+        let app = Router::new().route(
+            "/tsa",
+            post(|body: Bytes| async move {
+                assert_eq!(TimeStampReq::from_der(&body).unwrap().version, 1);
+                RSA_REPLY
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let client = TsaClient::new(&format!("http://{}/tsa", addr));
+        let err = client.timestamp(&digest()).await.unwrap_err();
+        assert!(err.to_string().contains("nonce"), "{}", err);
+    }
+
+    #[test]
+    fn test_test_tsa_tokens_chain_to_its_root() {
+        let tsa = testing::TestTsa::new().unwrap();
+        let token = tsa.token(&digest()).unwrap();
+        let timestamp = verify_token(&token, &digest(), &[tsa.root()]).unwrap();
+        assert_eq!(timestamp.signer, "CN=ArchiveStream Test TSA");
+        assert_eq!(
+            timestamp.trust_anchor.as_deref(),
+            Some("CN=ArchiveStream Test Root")
+        );
+        assert_eq!(timestamp.policy, testing::TEST_POLICY);
+
+        let other = testing::TestTsa::new().unwrap();
+        assert!(verify_token(&token, &digest(), &[other.root()]).is_err());
+        let reply = tsa.reply(&request(&digest(), 7).unwrap()).unwrap();
+        assert!(token_from_response(&reply, 7).is_ok());
+        assert!(token_from_response(&reply, 8).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! A Time Stamping Authority for tests: a P-256 root and a time-stamping
+//! certificate it issued, signing tokens over any digest
+
+use crate::asn1::*;
+use anyhow::Result;
+use chrono::{Duration, Utc};
+use cms::cert::{CertificateChoices, IssuerAndSerialNumber};
+use cms::content_info::{CmsVersion, ContentInfo};
+use cms::signed_data::{
+    CertificateSet, EncapsulatedContentInfo, SignedData, SignerIdentifier, SignerInfo, SignerInfos,
+};
+use der::asn1::{Any, BitString, Int, ObjectIdentifier, OctetString, SetOfVec};
+use der::{Decode, Encode, Tag};
+use p256::ecdsa::{signature::Signer, DerSignature, SigningKey};
+use p256::pkcs8::EncodePublicKey;
+use sha2::{Digest, Sha256};
+use spki::{AlgorithmIdentifierOwned, SubjectPublicKeyInfoOwned};
+use x509_cert::attr::Attribute;
+use x509_cert::ext::pkix::ExtendedKeyUsage;
+use x509_cert::ext::Extension;
+use x509_cert::name::Name;
+use x509_cert::serial_number::SerialNumber;
+use x509_cert::time::{Time, Validity};
+use x509_cert::{Certificate, TbsCertificate, Version};
+
+const ECDSA_WITH_SHA_256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+/// Policy the test TSA issues tokens under
+pub const TEST_POLICY: &str = "1.3.6.1.4.1.99999.1";
+
+fn algorithm(oid: ObjectIdentifier) -> AlgorithmIdentifierOwned {
+    AlgorithmIdentifierOwned {
+        oid,
+        parameters: None,
+    }
+}
+
+fn sign(key: &SigningKey, message: &[u8]) -> Vec<u8> {
+    let signature: DerSignature = key.sign(message);
+    signature.to_bytes().to_vec()
+}
+
+/// A certificate for `subject`'s key, issued by `issuer` with `issuer_key`
+fn issue(
+    subject: &str,
+    key: &SigningKey,
+    issuer: &str,
+    issuer_key: &SigningKey,
+    serial: u8,
+    extensions: Option<Vec<Extension>>,
+) -> Result<Certificate> {
+    let now = Utc::now();
+    let time = |at: chrono::DateTime<Utc>| -> Result<Time> {
+        Ok(Time::GeneralTime(
+            der::asn1::GeneralizedTime::from_unix_duration(std::time::Duration::from_secs(
+                at.timestamp() as u64,
+            ))?,
+        ))
+    };
+    let tbs_certificate = TbsCertificate {
+        version: Version::V3,
+        serial_number: SerialNumber::new(&[serial])?,
+        signature: algorithm(ECDSA_WITH_SHA_256),
+        issuer: issuer.parse::<Name>()?,
+        validity: Validity {
+            not_before: time(now - Duration::days(1))?,
+            not_after: time(now + Duration::days(1))?,
+        },
+        subject: subject.parse::<Name>()?,
+        subject_public_key_info: SubjectPublicKeyInfoOwned::from_der(
+            key.verifying_key().to_public_key_der()?.as_bytes(),
+        )?,
+        issuer_unique_id: None,
+        subject_unique_id: None,
+        extensions,
+    };
+    let signature = sign(issuer_key, &tbs_certificate.to_der()?);
+    Ok(Certificate {
+        tbs_certificate,
+        signature_algorithm: algorithm(ECDSA_WITH_SHA_256),
+        signature: BitString::from_bytes(&signature)?,
+    })
+}
+
+pub struct TestTsa {
+    root: Certificate,
+    cert: Certificate,
+    key: SigningKey,
+}
+
+impl TestTsa {
+    pub fn new() -> Result<Self> {
+        let root_key = SigningKey::random(&mut rand::rngs::OsRng);
+        let key = SigningKey::random(&mut rand::rngs::OsRng);
+        let (root_name, name) = ("CN=ArchiveStream Test Root", "CN=ArchiveStream Test TSA");
+        let time_stamping = Extension {
+            extn_id: ObjectIdentifier::new_unwrap("2.5.29.37"),
+            critical: true,
+            extn_value: OctetString::new(ExtendedKeyUsage(vec![ID_KP_TIME_STAMPING]).to_der()?)?,
+        };
+        Ok(Self {
+            root: issue(root_name, &root_key, root_name, &root_key, 1, None)?,
+            cert: issue(
+                name,
+                &key,
+                root_name,
+                &root_key,
+                2,
+                Some(vec![time_stamping]),
+            )?,
+            key,
+        })
+    }
+
+    /// The certificate tokens chain to
+    pub fn root(&self) -> Certificate {
+        self.root.clone()
+    }
+
+    /// DER TimeStampToken over the SHA-256 `digest`
+    pub fn token(&self, digest: &[u8]) -> Result<Vec<u8>> {
+        self.sign_token(digest, None)
+    }
+
+    /// DER TimeStampResp answering the DER TimeStampReq `request`
+    pub fn reply(&self, request: &[u8]) -> Result<Vec<u8>> {
+        let request = TimeStampReq::from_der(request)?;
+        let token = self.sign_token(
+            request.message_imprint.hashed_message.as_bytes(),
+            request.nonce,
+        )?;
+        Ok(TimeStampResp {
+            status: PkiStatusInfo {
+                status: 0,
+                status_string: None,
+                fail_info: None,
+            },
+            time_stamp_token: Some(Any::from_der(&token)?),
+        }
+        .to_der()?)
+    }
+
+    fn sign_token(&self, digest: &[u8], nonce: Option<u64>) -> Result<Vec<u8>> {
+        let gen_time = Utc::now().format("%Y%m%d%H%M%SZ").to_string();
+        let tst_info = TstInfo {
+            version: 1,
+            policy: ObjectIdentifier::new_unwrap(TEST_POLICY),
+            message_imprint: MessageImprint {
+                hash_algorithm: AlgorithmIdentifierOwned {
+                    oid: ID_SHA_256,
+                    parameters: Some(Any::null()),
+                },
+                hashed_message: OctetString::new(digest)?,
+            },
+            serial_number: Int::new(&rand::random::<u32>().to_be_bytes())?,
+            gen_time: Any::new(Tag::GeneralizedTime, gen_time.as_bytes())?,
+            accuracy: None,
+            ordering: false,
+            nonce: nonce.map(|n| Int::from_der(&n.to_der()?)).transpose()?,
+            tsa: None,
+            extensions: None,
+        }
+        .to_der()?;
+
+        let attribute = |oid, value: Any| -> Result<Attribute> {
+            Ok(Attribute {
+                oid,
+                values: SetOfVec::try_from(vec![value])?,
+            })
+        };
+        let signed_attrs = SetOfVec::try_from(vec![
+            attribute(ID_CONTENT_TYPE, Any::encode_from(&ID_CT_TST_INFO)?)?,
+            attribute(
+                ID_MESSAGE_DIGEST,
+                Any::encode_from(&OctetString::new(Sha256::digest(&tst_info).to_vec())?)?,
+            )?,
+        ])?;
+        let signature = sign(&self.key, &signed_attrs.to_der()?);
+
+        let signer = SignerInfo {
+            version: CmsVersion::V1,
+            sid: SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+                issuer: self.cert.tbs_certificate.issuer.clone(),
+                serial_number: self.cert.tbs_certificate.serial_number.clone(),
+            }),
+            digest_alg: algorithm(ID_SHA_256),
+            signed_attrs: Some(signed_attrs),
+            signature_algorithm: algorithm(ECDSA_WITH_SHA_256),
+            signature: OctetString::new(signature)?,
+            unsigned_attrs: None,
+        };
+        let signed_data = SignedData {
+            version: CmsVersion::V3,
+            digest_algorithms: SetOfVec::try_from(vec![algorithm(ID_SHA_256)])?,
+            encap_content_info: EncapsulatedContentInfo {
+                econtent_type: ID_CT_TST_INFO,
+                econtent: Some(Any::encode_from(&OctetString::new(tst_info)?)?),
+            },
+            certificates: Some(CertificateSet(SetOfVec::try_from(vec![
+                CertificateChoices::Certificate(self.cert.clone()),
+            ])?)),
+            crls: None,
+            signer_infos: SignerInfos(SetOfVec::try_from(vec![signer])?),
+        };
+        Ok(ContentInfo {
+            content_type: ID_SIGNED_DATA,
+            content: Any::encode_from(&signed_data)?,
+        }
+        .to_der()?)
+    }
+}
@@ -0,0 +1,291 @@
+use crate::asn1::*;
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use cms::cert::CertificateChoices;
+use cms::content_info::ContentInfo;
+use cms::signed_data::{SignedData, SignerIdentifier, SignerInfo};
+use der::asn1::{ObjectIdentifier, OctetString};
+use der::{Decode, Encode, Tag, Tagged};
+use sha2::{Digest, Sha256, Sha384, Sha512};
+use spki::{AlgorithmIdentifierOwned, DecodePublicKey, SubjectPublicKeyInfoOwned};
+use x509_cert::ext::pkix::{ExtendedKeyUsage, SubjectKeyIdentifier};
+use x509_cert::Certificate;
+
+/// Chains longer than this between a TSA certificate and a trust anchor are
+/// rejected
+const MAX_CHAIN: usize = 5;
+
+/// What a verified token attests
+#[derive(Debug, Clone, PartialEq)]
+pub struct Timestamp {
+    pub gen_time: DateTime<Utc>,
+    /// Hex serial number, unique per TSA
+    pub serial_number: String,
+    pub policy: String,
+    /// Subject of the certificate that signed the token
+    pub signer: String,
+    /// Subject of the trust anchor the signer chains to, when anchors were given
+    pub trust_anchor: Option<String>,
+}
+
+#[derive(Clone, Copy)]
+enum Hash {
+    Sha256,
+    Sha384,
+    Sha512,
+}
+
+impl Hash {
+    fn from_oid(oid: &ObjectIdentifier) -> Result<Self> {
+        Ok(match *oid {
+            ID_SHA_256 => Hash::Sha256,
+            ID_SHA_384 => Hash::Sha384,
+            ID_SHA_512 => Hash::Sha512,
+            _ => bail!("unsupported digest algorithm {}", oid),
+        })
+    }
+
+    /// The digest a signature algorithm names, if it names one
+    fn from_signature_oid(oid: &ObjectIdentifier) -> Option<Self> {
+        match oid.to_string().as_str() {
+            "1.2.840.113549.1.1.11" | "1.2.840.10045.4.3.2" => Some(Hash::Sha256),
+            "1.2.840.113549.1.1.12" | "1.2.840.10045.4.3.3" => Some(Hash::Sha384),
+            "1.2.840.113549.1.1.13" | "1.2.840.10045.4.3.4" => Some(Hash::Sha512),
+            _ => None,
+        }
+    }
+
+    fn digest(self, data: &[u8]) -> Vec<u8> {
+        match self {
+            Hash::Sha256 => Sha256::digest(data).to_vec(),
+            Hash::Sha384 => Sha384::digest(data).to_vec(),
+            Hash::Sha512 => Sha512::digest(data).to_vec(),
+        }
+    }
+}
+
+/// Check an RSA (PKCS #1 v1.5) or ECDSA (P-256, P-384) signature over `message`
+fn verify_signature(
+    key: &SubjectPublicKeyInfoOwned,
+    algorithm: &AlgorithmIdentifierOwned,
+    digest_algorithm: Option<&AlgorithmIdentifierOwned>,
+    message: &[u8],
+    signature: &[u8],
+) -> Result<()> {
+    // CMS signers may name only the key type and leave the digest to `digestAlgorithm`
+    let hash = match Hash::from_signature_oid(&algorithm.oid) {
+        Some(hash) => hash,
+        None => Hash::from_oid(
+            &digest_algorithm
+                .ok_or_else(|| anyhow!("unsupported signature algorithm {}", algorithm.oid))?
+                .oid,
+        )?,
+    };
+    let hashed = hash.digest(message);
+    let der = key.to_der()?;
+
+    match key.algorithm.oid.to_string().as_str() {
+        "1.2.840.113549.1.1.1" => {
+            let key = rsa::RsaPublicKey::from_public_key_der(&der)?;
+            let scheme = match hash {
+                Hash::Sha256 => rsa::Pkcs1v15Sign::new::<Sha256>(),
+                Hash::Sha384 => rsa::Pkcs1v15Sign::new::<Sha384>(),
+                Hash::Sha512 => rsa::Pkcs1v15Sign::new::<Sha512>(),
+            };
+            key.verify(scheme, &hashed, signature)
+                .map_err(|_| anyhow!("RSA signature does not verify"))
+        }
+        "1.2.840.10045.2.1" => {
+            use p256::ecdsa::signature::hazmat::PrehashVerifier;
+            if let Ok(key) = p256::ecdsa::VerifyingKey::from_public_key_der(&der) {
+                let signature = p256::ecdsa::Signature::from_der(signature)?;
+                key.verify_prehash(&hashed, &signature)
+            } else {
+                let key = p384::ecdsa::VerifyingKey::from_public_key_der(&der)?;
+                let signature = p384::ecdsa::Signature::from_der(signature)?;
+                key.verify_prehash(&hashed, &signature)
+            }
+            .map_err(|_| anyhow!("ECDSA signature does not verify"))
+        }
+        other => bail!("unsupported public key algorithm {}", other),
+    }
+}
+
+/// Whether `issuer` signed `cert`
+fn issued_by(cert: &Certificate, issuer: &Certificate) -> bool {
+    cert.tbs_certificate.issuer == issuer.tbs_certificate.subject
+        && cert.tbs_certificate.to_der().is_ok_and(|tbs| {
+            verify_signature(
+                &issuer.tbs_certificate.subject_public_key_info,
+                &cert.signature_algorithm,
+                None,
+                &tbs,
+                cert.signature.raw_bytes(),
+            )
+            .is_ok()
+        })
+}
+
+fn matches_sid(cert: &Certificate, sid: &SignerIdentifier) -> bool {
+    match sid {
+        SignerIdentifier::IssuerAndSerialNumber(id) => {
+            cert.tbs_certificate.issuer == id.issuer
+                && cert.tbs_certificate.serial_number == id.serial_number
+        }
+        SignerIdentifier::SubjectKeyIdentifier(ski) => cert
+            .tbs_certificate
+            .get::<SubjectKeyIdentifier>()
+            .ok()
+            .flatten()
+            .is_some_and(|(_, own)| own == *ski),
+    }
+}
+
+fn parse_gen_time(gen_time: &der::asn1::Any) -> Result<DateTime<Utc>> {
+    if gen_time.tag() != Tag::GeneralizedTime {
+        bail!("genTime is not a GeneralizedTime");
+    }
+    let text = std::str::from_utf8(gen_time.value())?;
+    let time = NaiveDateTime::parse_from_str(text, "%Y%m%d%H%M%S%.fZ")
+        .with_context(|| format!("invalid genTime {}", text))?;
+    Ok(time.and_utc())
+}
+
+/// The single value of a signed attribute
+fn attribute<'a>(signer: &'a SignerInfo, oid: &ObjectIdentifier) -> Result<&'a der::asn1::Any> {
+    let attrs = signer
+        .signed_attrs
+        .as_ref()
+        .ok_or_else(|| anyhow!("token has no signed attributes"))?;
+    let attr = attrs
+        .iter()
+        .find(|a| a.oid == *oid)
+        .ok_or_else(|| anyhow!("signed attribute {} is missing", oid))?;
+    match attr.values.as_slice() {
+        [value] => Ok(value),
+        _ => bail!("signed attribute {} must have one value", oid),
+    }
+}
+
+/// Verify that `token`, a DER `TimeStampToken`, timestamps the SHA-256
+/// `digest`: the imprint matches, the signed attributes bind the TSTInfo, and
+/// the signature is by a time-stamping certificate valid at `genTime`. With
+/// `anchors`, that certificate must also chain to one of them through the
+/// certificates in the token
+pub fn verify_token(token: &[u8], digest: &[u8], anchors: &[Certificate]) -> Result<Timestamp> {
+    let content_info = ContentInfo::from_der(token).context("token is not a CMS ContentInfo")?;
+    if content_info.content_type != ID_SIGNED_DATA {
+        bail!("token is not CMS SignedData");
+    }
+    let signed_data: SignedData = content_info.content.decode_as()?;
+    if signed_data.encap_content_info.econtent_type != ID_CT_TST_INFO {
+        bail!("token does not carry a TSTInfo");
+    }
+    let tst_der = signed_data
+        .encap_content_info
+        .econtent
+        .as_ref()
+        .ok_or_else(|| anyhow!("token has no content"))?
+        .decode_as::<OctetString>()?
+        .into_bytes();
+    let tst_info = TstInfo::from_der(&tst_der)?;
+
+    let imprint = &tst_info.message_imprint;
+    if imprint.hash_algorithm.oid != ID_SHA_256 || imprint.hashed_message.as_bytes() != digest {
+        bail!("token timestamps a different digest");
+    }
+    let gen_time = parse_gen_time(&tst_info.gen_time)?;
+
+    let signer = match signed_data.signer_infos.0.as_slice() {
+        [signer] => signer,
+        _ => bail!("token must have exactly one signer"),
+    };
+    let certificates: Vec<&Certificate> = signed_data
+        .certificates
+        .iter()
+        .flat_map(|set| set.0.iter())
+        .filter_map(|choice| match choice {
+            CertificateChoices::Certificate(cert) => Some(cert),
+            _ => None,
+        })
+        .chain(anchors)
+        .collect();
+    let cert = *certificates
+        .iter()
+        .find(|cert| matches_sid(cert, &signer.sid))
+        .ok_or_else(|| anyhow!("signing certificate is not in the token"))?;
+
+    // The signature covers the signed attributes, which in turn carry the
+    // content type and a digest of the TSTInfo
+    let content_type: ObjectIdentifier = attribute(signer, &ID_CONTENT_TYPE)?.decode_as()?;
+    if content_type != ID_CT_TST_INFO {
+        bail!("signed content type is not TSTInfo");
+    }
+    let message_digest: OctetString = attribute(signer, &ID_MESSAGE_DIGEST)?.decode_as()?;
+    if message_digest.as_bytes() != Hash::from_oid(&signer.digest_alg.oid)?.digest(&tst_der) {
+        bail!("signed message digest does not match the TSTInfo");
+    }
+    let signed_attrs = signer
+        .signed_attrs
+        .as_ref()
+        .ok_or_else(|| anyhow!("token has no signed attributes"))?
+        .to_der()?;
+    verify_signature(
+        &cert.tbs_certificate.subject_public_key_info,
+        &signer.signature_algorithm,
+        Some(&signer.digest_alg),
+        &signed_attrs,
+        signer.signature.as_bytes(),
+    )?;
+
+    // RFC 3161 section 2.3: the TSA certificate is for time-stamping only
+    let time_stamping = cert
+        .tbs_certificate
+        .get::<ExtendedKeyUsage>()
+        .ok()
+        .flatten()
+        .is_some_and(|(critical, eku)| critical && eku.0 == [ID_KP_TIME_STAMPING]);
+    if !time_stamping {
+        bail!("signing certificate is not a time-stamping certificate");
+    }
+    let validity = &cert.tbs_certificate.validity;
+    let not_before: DateTime<Utc> = validity.not_before.to_system_time().into();
+    let not_after: DateTime<Utc> = validity.not_after.to_system_time().into();
+    if gen_time < not_before || gen_time > not_after {
+        bail!("token was signed outside its certificate's validity");
+    }
+
+    let trust_anchor = if anchors.is_empty() {
+        None
+    } else {
+        let mut current = cert;
+        let mut anchor = None;
+        for _ in 0..MAX_CHAIN {
+            if let Some(found) = anchors
+                .iter()
+                .find(|a| *a == current || issued_by(current, a))
+            {
+                anchor = Some(found);
+                break;
+            }
+            match certificates
+                .iter()
+                .find(|issuer| **issuer != current && issued_by(current, issuer))
+            {
+                Some(issuer) => current = issuer,
+                None => break,
+            }
+        }
+        let anchor = anchor
+            .ok_or_else(|| anyhow!("signing certificate does not chain to a trust anchor"))?;
+        Some(anchor.tbs_certificate.subject.to_string())
+    };
+
+    Ok(Timestamp {
+        gen_time,
+        serial_number: hex::encode(tst_info.serial_number.as_bytes()),
+        policy: tst_info.policy.to_string(),
+        signer: cert.tbs_certificate.subject.to_string(),
+        trust_anchor,
+    })
+}
@@ -0,0 +1,48 @@
+-- WARC Snapshots: snapshot rows point into WARC files in archive storage
+
+-- 1. Claim index without NOW(); an index predicate must be immutable, so the
+--    initial schema could not create it
+DROP INDEX IF EXISTS idx_frontier_claim;
+CREATE INDEX idx_frontier_claim
+ON url_frontier(priority DESC, created_at ASC)
+WHERE leased_until IS NULL;
+
+-- 2. The initial snapshots table held bodies inline; keep any such rows
+--    aside under their own name
+DO $$
+BEGIN
+    IF EXISTS (
+        SELECT 1 FROM information_schema.columns
+        WHERE table_schema = current_schema() AND table_name = 'snapshots' AND column_name = 'data'
+    ) THEN
+        ALTER TABLE snapshots RENAME TO snapshots_inline;
+        ALTER INDEX IF EXISTS idx_snapshots_url RENAME TO idx_snapshots_inline_url;
+        ALTER INDEX IF EXISTS idx_snapshots_sha256 RENAME TO idx_snapshots_inline_sha256;
+    END IF;
+END $$;
+
+-- 3. Snapshots as described in docs/STORAGE.md
+CREATE TABLE IF NOT EXISTS snapshots (
+    id UUID PRIMARY KEY,
+    url TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    warc_file TEXT NOT NULL,
+    "offset" BIGINT NOT NULL,
+    length BIGINT NOT NULL,
+    sha256 TEXT NOT NULL,
+    status_code SMALLINT NOT NULL,
+    content_type TEXT NOT NULL,
+    payload_hash TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_snapshots_url ON snapshots(url);
+CREATE INDEX IF NOT EXISTS idx_snapshots_sha256 ON snapshots(sha256);
+
+-- 4. First stored copy of each payload, which duplicates become revisits of
+CREATE TABLE IF NOT EXISTS payloads (
+    hash TEXT PRIMARY KEY,
+    warc_path TEXT NOT NULL,
+    warc_offset BIGINT NOT NULL,
+    size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ DEFAULT NOW()
+);
@@ -0,0 +1,17 @@
+-- Conditional Revisits: HTTP validators and revisit snapshots
+
+-- 1. ETag / Last-Modified of the latest capture per URL; completing a URL
+--    deletes its frontier row, so they cannot live there
+CREATE TABLE IF NOT EXISTS url_validators (
+    url TEXT PRIMARY KEY,
+    etag TEXT,
+    last_modified TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 2. Revisit snapshots point at the capture whose payload they share, with
+--    the WARC-Profile saying why they hold no payload of their own
+ALTER TABLE snapshots ADD COLUMN IF NOT EXISTS revisit_of UUID;
+ALTER TABLE snapshots ADD COLUMN IF NOT EXISTS revisit_profile TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_snapshots_url_timestamp ON snapshots(url, timestamp DESC);
@@ -0,0 +1,21 @@
+-- Retry Policy: error tracking and dead-letter queue
+
+-- 1. Keep the most recent failure on the frontier row
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS last_error TEXT;
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS last_error_class TEXT;
+
+-- 2. URLs that exhausted their retry budget
+CREATE TABLE IF NOT EXISTS dead_letter_urls (
+    url TEXT PRIMARY KEY,
+    domain TEXT NOT NULL,
+    priority INT DEFAULT 0,
+    depth INT DEFAULT 0,
+    fetch_attempts INT NOT NULL,
+    error_class TEXT NOT NULL,
+    last_error TEXT,
+    first_queued_at TIMESTAMPTZ,
+    dead_lettered_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_dead_letter_domain ON dead_letter_urls(domain);
+CREATE INDEX IF NOT EXISTS idx_dead_letter_class ON dead_letter_urls(error_class, dead_lettered_at);
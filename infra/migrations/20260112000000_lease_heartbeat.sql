@@ -0,0 +1,19 @@
+-- Lease Heartbeat: per-URL lease durations and stuck-lease recovery
+
+-- 1. Optional override of the worker's default lease (e.g. slow browser captures)
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS lease_duration_secs INT;
+
+-- 2. Find a dead worker's leases quickly
+CREATE INDEX IF NOT EXISTS idx_frontier_leased_by ON url_frontier(leased_by_worker) WHERE leased_by_worker IS NOT NULL;
+
+-- 3. Audit trail of leases released by the reaper
+CREATE TABLE IF NOT EXISTS recovered_leases (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    worker_id TEXT NOT NULL,
+    region TEXT,
+    recovered_by TEXT NOT NULL,
+    recovered_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_recovered_leases_time ON recovered_leases(recovered_at);
@@ -0,0 +1,16 @@
+-- Priority Model: inputs for the frontier priority scorer
+
+-- 1. Scoring inputs kept on the frontier row
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS inlink_count INT NOT NULL DEFAULT 0;
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS change_probability REAL;
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS priority_boost INT NOT NULL DEFAULT 0;
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS is_embed BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS embed_hops INT NOT NULL DEFAULT 0;
+
+-- 2. Distinct pages linking to each URL, so re-parsing a page does not inflate counts
+CREATE TABLE IF NOT EXISTS url_inlinks (
+    url TEXT NOT NULL,
+    source_url TEXT NOT NULL,
+    discovered_at TIMESTAMPTZ DEFAULT NOW(),
+    PRIMARY KEY (url, source_url)
+);
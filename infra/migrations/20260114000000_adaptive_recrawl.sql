@@ -0,0 +1,43 @@
+-- Adaptive Recrawl: change observations, domain priors and prediction audit
+
+-- 1. Job-level freshness target, inherited from the seed like priority_boost
+ALTER TABLE url_frontier ADD COLUMN IF NOT EXISTS freshness_target REAL;
+
+-- 2. One row per fetch; text_hash ignores timestamps, tokens and markup
+CREATE TABLE IF NOT EXISTS change_observations (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    domain TEXT NOT NULL,
+    observed_at TIMESTAMPTZ NOT NULL,
+    payload_digest TEXT NOT NULL,
+    text_hash TEXT NOT NULL,
+    changed BOOLEAN
+);
+
+CREATE INDEX IF NOT EXISTS idx_change_observations_url ON change_observations(url, observed_at);
+
+-- 3. Pooled change statistics used as the per-domain prior
+CREATE TABLE IF NOT EXISTS domain_change_stats (
+    domain TEXT PRIMARY KEY,
+    changes BIGINT NOT NULL DEFAULT 0,
+    intervals BIGINT NOT NULL DEFAULT 0,
+    exposure_secs DOUBLE PRECISION NOT NULL DEFAULT 0,
+    updated_at TIMESTAMPTZ DEFAULT NOW()
+);
+
+-- 4. Every scheduling decision, for auditing the estimator
+CREATE TABLE IF NOT EXISTS crawl_predictions (
+    id BIGSERIAL PRIMARY KEY,
+    url TEXT NOT NULL,
+    predicted_at TIMESTAMPTZ DEFAULT NOW(),
+    next_fetch_at TIMESTAMPTZ NOT NULL,
+    change_probability REAL NOT NULL,
+    change_rate_per_day REAL NOT NULL,
+    confidence REAL NOT NULL,
+    freshness_target REAL,
+    prior_alpha DOUBLE PRECISION,
+    prior_beta_secs DOUBLE PRECISION,
+    observations INT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_crawl_predictions_url ON crawl_predictions(url, predicted_at DESC);
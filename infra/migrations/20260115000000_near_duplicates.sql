@@ -0,0 +1,17 @@
+-- Near-Duplicate Detection: SimHash of the normalized text per capture
+
+-- 1. Latest SimHash per URL, compared against on every fetch
+CREATE TABLE IF NOT EXISTS url_simhashes (
+    url TEXT PRIMARY KEY,
+    simhash BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 2. Similarity to the previous capture; near-duplicates are not counted as changes
+ALTER TABLE change_observations
+    ADD COLUMN IF NOT EXISTS simhash BIGINT,
+    ADD COLUMN IF NOT EXISTS similarity REAL,
+    ADD COLUMN IF NOT EXISTS near_duplicate BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- 3. Lets the timeline grade changes by how much the text moved
+ALTER TABLE snapshots ADD COLUMN IF NOT EXISTS simhash BIGINT;
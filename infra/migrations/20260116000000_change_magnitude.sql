@@ -0,0 +1,19 @@
+-- Change Magnitude: precomputed per-capture change scores for the timeline heatmap
+
+-- 1. One row per snapshot, compared with the capture before it
+CREATE TABLE IF NOT EXISTS snapshot_changes (
+    snapshot_id UUID PRIMARY KEY,
+    url TEXT NOT NULL,
+    timestamp TIMESTAMPTZ NOT NULL,
+    previous_id UUID,
+    text_diff_ratio REAL,
+    visual_diff_score REAL,
+    semantic_category TEXT,
+    magnitude REAL NOT NULL,
+    computed_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_snapshot_changes_url ON snapshot_changes(url, timestamp);
+
+-- 2. Range and bucket queries over long histories
+CREATE INDEX IF NOT EXISTS idx_snapshots_url_timestamp_asc ON snapshots(url, timestamp ASC);
@@ -0,0 +1,9 @@
+-- WARC Export: select captures by crawl job and host
+
+-- 1. Crawl job that produced the capture, when known
+ALTER TABLE snapshots ADD COLUMN IF NOT EXISTS job_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_snapshots_job ON snapshots(job_id, timestamp) WHERE job_id IS NOT NULL;
+
+-- 2. Revisit records are exported together with the response they refer to
+CREATE INDEX IF NOT EXISTS idx_snapshots_revisit_of ON snapshots(revisit_of) WHERE revisit_of IS NOT NULL;
@@ -0,0 +1,19 @@
+-- WACZ Packaging: named collections of captures
+
+-- 1. Collections title the packages built from them
+CREATE TABLE IF NOT EXISTS collections (
+    id TEXT PRIMARY KEY,
+    title TEXT NOT NULL,
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 2. Membership
+CREATE TABLE IF NOT EXISTS collection_snapshots (
+    collection_id TEXT NOT NULL REFERENCES collections(id) ON DELETE CASCADE,
+    snapshot_id UUID NOT NULL REFERENCES snapshots(id) ON DELETE CASCADE,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (collection_id, snapshot_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_collection_snapshots_snapshot ON collection_snapshots(snapshot_id);
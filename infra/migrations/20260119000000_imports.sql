@@ -0,0 +1,22 @@
+-- Imports: WARC, ARC and WACZ files ingested from outside the crawler
+
+-- 1. One row per import run; the report holds record counts and errors
+CREATE TABLE IF NOT EXISTS imports (
+    id UUID PRIMARY KEY,
+    source TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'running',
+    report JSONB,
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ
+);
+
+-- 2. Snapshots waiting for the indexer, oldest first
+CREATE TABLE IF NOT EXISTS index_queue (
+    snapshot_id UUID PRIMARY KEY REFERENCES snapshots(id) ON DELETE CASCADE,
+    enqueued_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_index_queue_enqueued ON index_queue(enqueued_at);
+
+-- 3. Revisit lookups by payload
+CREATE INDEX IF NOT EXISTS idx_snapshots_payload_originals ON snapshots(sha256, timestamp) WHERE revisit_of IS NULL;
@@ -0,0 +1,16 @@
+-- Federation Peer Registry: peers survive restarts
+
+CREATE TABLE IF NOT EXISTS federation_peers (
+    id TEXT PRIMARY KEY,                 -- hex SHA-256 of the public key
+    endpoint TEXT NOT NULL,
+    public_key TEXT NOT NULL,            -- base64 Ed25519 key proven at handshake
+    status TEXT NOT NULL DEFAULT 'active', -- active, unreachable, banned
+    capabilities TEXT[] NOT NULL DEFAULT '{}',
+    last_seen TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    failures INT NOT NULL DEFAULT 0,     -- consecutive failed health probes
+    next_probe_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS idx_federation_peers_status ON federation_peers(status);
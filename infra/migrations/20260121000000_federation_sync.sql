@@ -0,0 +1,26 @@
+-- Federation Sync: per-peer manifest cursors and failed records
+
+CREATE TABLE IF NOT EXISTS federation_sync_state (
+    peer_id TEXT PRIMARY KEY,
+    cursor TEXT,                         -- position after the last manifest entry processed
+    synced BIGINT NOT NULL DEFAULT 0,    -- records downloaded, verified and stored
+    deduplicated BIGINT NOT NULL DEFAULT 0, -- stored as revisits of a local payload
+    skipped BIGINT NOT NULL DEFAULT 0,   -- captures already held
+    failed BIGINT NOT NULL DEFAULT 0,    -- record attempts that failed
+    last_error TEXT,                     -- why the last cycle for this peer stopped early
+    last_synced_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE IF NOT EXISTS federation_sync_failures (
+    peer_id TEXT NOT NULL,
+    remote_id UUID NOT NULL,             -- snapshot id on the peer
+    snapshot JSONB NOT NULL,             -- manifest entry, for retries
+    error TEXT NOT NULL,
+    attempts INT NOT NULL DEFAULT 1,
+    last_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (peer_id, remote_id)
+);
+
+-- Manifest pages walk captures in (timestamp, id) order
+CREATE INDEX IF NOT EXISTS idx_snapshots_timestamp_id ON snapshots(timestamp, id);
@@ -0,0 +1,24 @@
+-- Replication Policies: what is pulled from and pushed to each peer
+
+-- 1. One policy per peer and direction; peer_id '*' applies to peers without their own
+CREATE TABLE IF NOT EXISTS federation_policies (
+    peer_id TEXT NOT NULL,
+    direction TEXT NOT NULL CHECK (direction IN ('pull', 'push')),
+    filter JSONB NOT NULL DEFAULT '{}',  -- hosts, surt_prefixes, collections, mime_types, from, to, max_record_bytes
+    bandwidth_bytes_per_hour BIGINT,
+    storage_quota_bytes BIGINT,          -- pull only
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (peer_id, direction)
+);
+
+-- 2. Bytes transferred in the current hourly window
+CREATE TABLE IF NOT EXISTS federation_bandwidth (
+    peer_id TEXT NOT NULL,
+    direction TEXT NOT NULL,
+    window_start TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    bytes BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (peer_id, direction)
+);
+
+-- 3. Manifest entries left out by the pull policy
+ALTER TABLE federation_sync_state ADD COLUMN IF NOT EXISTS filtered BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,21 @@
+-- IPFS Storage: WARC files added to an IPFS node and the IPNS manifests announcing them
+
+-- 1. The CID of the WARC file a capture was read from, once uploaded
+ALTER TABLE snapshots ADD COLUMN IF NOT EXISTS ipfs_cid TEXT;
+
+-- 2. Latest upload of each WARC file; a grown file is re-added and the old CID unpinned
+CREATE TABLE IF NOT EXISTS ipfs_objects (
+    warc_file TEXT PRIMARY KEY,
+    cid TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    uploaded_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- 3. Manifests published to IPNS
+CREATE TABLE IF NOT EXISTS ipfs_publications (
+    id BIGSERIAL PRIMARY KEY,
+    name TEXT NOT NULL,   -- IPNS name
+    cid TEXT NOT NULL,    -- manifest CID the name points at
+    files INTEGER NOT NULL,
+    published_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
@@ -0,0 +1,40 @@
+-- Fixity: scheduled rehashing of stored captures and signed Merkle manifests over them
+
+-- 1. When a capture's stored bytes were last rehashed; revisits share their original's bytes
+ALTER TABLE snapshots ADD COLUMN IF NOT EXISTS fixity_checked_at TIMESTAMPTZ;
+CREATE INDEX IF NOT EXISTS idx_snapshots_fixity_due ON snapshots(fixity_checked_at NULLS FIRST) WHERE revisit_of IS NULL;
+
+-- 2. Every rehash and what it found
+CREATE TABLE IF NOT EXISTS fixity_events (
+    id BIGSERIAL PRIMARY KEY,
+    snapshot_id UUID NOT NULL REFERENCES snapshots(id) ON DELETE CASCADE,
+    outcome TEXT NOT NULL,          -- ok, mismatch or unreadable
+    expected_sha256 TEXT NOT NULL,
+    actual_sha256 TEXT,
+    detail TEXT,
+    checked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+CREATE INDEX IF NOT EXISTS idx_fixity_events_snapshot ON fixity_events(snapshot_id, id DESC);
+CREATE INDEX IF NOT EXISTS idx_fixity_events_failures ON fixity_events(id DESC) WHERE outcome <> 'ok';
+
+-- 3. Signed Merkle roots, each naming the root before it
+CREATE TABLE IF NOT EXISTS merkle_manifests (
+    id BIGSERIAL PRIMARY KEY,
+    root TEXT NOT NULL,
+    previous_root TEXT,
+    tree_size INTEGER NOT NULL,
+    node_id TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL
+);
+
+-- 4. The leaves of each manifest; a capture is covered by exactly one, and
+--    cannot be deleted while it is
+CREATE TABLE IF NOT EXISTS merkle_leaves (
+    snapshot_id UUID PRIMARY KEY REFERENCES snapshots(id),
+    manifest_id BIGINT NOT NULL REFERENCES merkle_manifests(id),
+    position INTEGER NOT NULL,
+    leaf_hash TEXT NOT NULL,
+    UNIQUE (manifest_id, position)
+);
@@ -0,0 +1,15 @@
+-- Trusted Timestamps: RFC 3161 tokens countersigning Merkle manifest roots
+
+-- 1. The token a Time Stamping Authority returned for each root; captures
+--    reach theirs through merkle_leaves
+ALTER TABLE merkle_manifests ADD COLUMN IF NOT EXISTS tsa_url TEXT;
+ALTER TABLE merkle_manifests ADD COLUMN IF NOT EXISTS tsa_token BYTEA;   -- DER TimeStampToken
+ALTER TABLE merkle_manifests ADD COLUMN IF NOT EXISTS tsa_time TIMESTAMPTZ; -- genTime of the token
+
+-- 2. Failed attempts to get a token, so a root the TSA keeps rejecting backs
+--    off instead of taking a batch slot every round
+ALTER TABLE merkle_manifests ADD COLUMN IF NOT EXISTS tsa_attempts INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE merkle_manifests ADD COLUMN IF NOT EXISTS tsa_attempted_at TIMESTAMPTZ;
+
+-- 3. Manifests still waiting for a token
+CREATE INDEX IF NOT EXISTS idx_merkle_manifests_unstamped ON merkle_manifests(id) WHERE tsa_token IS NULL;
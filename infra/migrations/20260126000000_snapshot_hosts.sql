@@ -0,0 +1,12 @@
+-- Snapshot Hosts: the lowercased host of each capture's URL, so host and
+-- domain listings read an index instead of parsing every URL
+
+-- 1. Host as parsed by the snapshots API
+ALTER TABLE snapshots ADD COLUMN IF NOT EXISTS host TEXT
+    GENERATED ALWAYS AS (
+        lower(substring(url from '^[A-Za-z][A-Za-z0-9+.-]*://(?:[^/@]*@)?([^/:?#]+)'))
+    ) STORED;
+
+-- 2. Exact host matches, and subdomain matches as a prefix of the reversed host
+CREATE INDEX IF NOT EXISTS idx_snapshots_host ON snapshots(host);
+CREATE INDEX IF NOT EXISTS idx_snapshots_host_reversed ON snapshots(reverse(host) text_pattern_ops);
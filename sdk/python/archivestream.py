@@ -1,5 +1,5 @@
 import requests
-from typing import List, Dict, Optional
+from typing import Dict, Iterator, List, Optional
 
 class ArchiveStream:
     """
@@ -20,6 +20,24 @@ class ArchiveStream:
         response.raise_for_status()
         return response.json()
 
+    def iter_snapshots(self, url: str, page_size: int = 500, **filters) -> Iterator[Dict]:
+        """Page through every matching snapshot, newest first.
+
+        Filters are passed through as query parameters, e.g. ``matchType="domain"``,
+        ``from_="2024"``, ``to="2024"``, ``status="2xx"``, ``mime="text/html"``
+        or ``collapse="digest"``.
+        """
+        params = {"url": url, "limit": page_size}
+        params.update({k.rstrip("_"): v for k, v in filters.items() if v is not None})
+        while True:
+            response = requests.get(f"{self.base_url}/snapshots", params=params)
+            response.raise_for_status()
+            yield from response.json()
+            cursor = response.headers.get("X-Next-Cursor")
+            if not cursor:
+                return
+            params["cursor"] = cursor
+
     def resolve(self, url: str, at: str) -> Dict:
         """Resolve the best snapshot for a URL at a given time."""
         response = requests.get(f"{self.base_url}/resolve", params={"url": url, "at": at})